@@ -0,0 +1,6 @@
+(method_declaration
+  (attribute_list
+    (attribute
+      name: [(identifier) (qualified_name)] @annotation.name))
+  name: (identifier) @func.name
+  (#match? @annotation.name "^([A-Za-z_][A-Za-z0-9_]*\\.)*Autometrics(Attribute)?$"))
@@ -0,0 +1,8 @@
+(method_declaration
+  (modifiers
+    [(marker_annotation
+       name: [(identifier) (scoped_identifier)] @annotation.name)
+     (annotation
+       name: [(identifier) (scoped_identifier)] @annotation.name)])
+  name: (identifier) @func.name
+  (#match? @annotation.name "^([a-z_][a-z0-9_]*\\.)*Autometrics$"))
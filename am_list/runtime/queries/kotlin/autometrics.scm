@@ -0,0 +1,10 @@
+(function_declaration
+  (modifiers
+    (annotation
+      [(user_type
+         (type_identifier) @annotation.name .)
+       (constructor_invocation
+         (user_type
+           (type_identifier) @annotation.name .))]))
+  (simple_identifier) @func.name
+  (#eq? @annotation.name "Autometrics"))
@@ -0,0 +1,4 @@
+[(method_declaration
+   name: (name) @func.name)
+ (function_definition
+   name: (name) @func.name)]
@@ -0,0 +1,13 @@
+([(method_declaration
+    attributes: (attribute_list
+      (attribute_group
+        (attribute
+          [(name) (qualified_name)] @annotation.name)))
+    name: (name) @func.name)
+  (function_definition
+    attributes: (attribute_list
+      (attribute_group
+        (attribute
+          [(name) (qualified_name)] @annotation.name)))
+    name: (name) @func.name)]
+ (#match? @annotation.name "(^|\\\\)Autometrics$"))
@@ -0,0 +1,4 @@
+[(method
+   name: (_) @func.name)
+ (singleton_method
+   name: (_) @func.name)]
@@ -0,0 +1,9 @@
+(call
+  method: (identifier) @decorator.name
+  arguments: (argument_list
+    [(method
+       name: (_) @func.name)
+     (singleton_method
+       name: (_) @func.name)
+     (simple_symbol) @func.name])
+  (#eq? @decorator.name "autometrics"))
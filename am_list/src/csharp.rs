@@ -0,0 +1,96 @@
+mod queries;
+
+use crate::{FunctionInfo, ListAmFunctions, Result};
+use queries::{AllFunctionsQuery, AmQuery};
+use rayon::prelude::*;
+use std::{
+    collections::HashSet,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+use walkdir::{DirEntry, WalkDir};
+
+/// Implementation of the C# support for listing autometricized functions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Impl {}
+
+impl Impl {
+    fn is_hidden(entry: &DirEntry) -> bool {
+        entry
+            .file_name()
+            .to_str()
+            .map(|s| s.starts_with('.'))
+            .unwrap_or(false)
+    }
+
+    /// The output directories of `dotnet build`.
+    fn is_build_output(entry: &DirEntry) -> bool {
+        entry.depth() != 0
+            && entry.file_type().is_dir()
+            && matches!(entry.file_name().to_str(), Some("bin" | "obj"))
+    }
+
+    fn is_valid(entry: &DirEntry) -> bool {
+        if Impl::is_hidden(entry) || Impl::is_build_output(entry) {
+            return false;
+        }
+        entry.file_type().is_dir() || entry.path().extension().is_some_and(|ext| ext == "cs")
+    }
+
+    fn list_functions(
+        project_root: &Path,
+        list: impl Fn(&str, &str) -> Option<Vec<FunctionInfo>> + Sync,
+    ) -> Vec<FunctionInfo> {
+        const PREALLOCATED_ELEMS: usize = 100;
+        let mut result_set = HashSet::with_capacity(PREALLOCATED_ELEMS);
+
+        let walker = WalkDir::new(project_root).into_iter();
+        let mut source_mod_pairs = Vec::with_capacity(PREALLOCATED_ELEMS);
+        source_mod_pairs.extend(walker.filter_entry(Self::is_valid).filter_map(|entry| {
+            let entry = entry.ok()?;
+            entry.file_type().is_file().then(|| entry.into_path())
+        }));
+
+        result_set.par_extend(source_mod_pairs.par_iter().filter_map(|path| {
+            let source = read_to_string(path).ok()?;
+            let file_name = PathBuf::from(path)
+                .strip_prefix(project_root)
+                .expect("path comes from a project_root WalkDir")
+                .to_str()
+                .expect("file_name is a valid path as it is part of `path`")
+                .to_string();
+            list(&file_name, &source)
+        }));
+
+        let mut result = Vec::with_capacity(PREALLOCATED_ELEMS);
+        result.extend(result_set.into_iter().flatten());
+        result
+    }
+}
+
+impl ListAmFunctions for Impl {
+    fn list_autometrics_functions(&mut self, project_root: &Path) -> Result<Vec<FunctionInfo>> {
+        Ok(Self::list_functions(project_root, |file_name, source| {
+            let query = AmQuery::try_new().ok()?;
+            Some(
+                query
+                    .list_function_names(file_name, source)
+                    .unwrap_or_default(),
+            )
+        }))
+    }
+
+    fn list_all_function_definitions(&mut self, project_root: &Path) -> Result<Vec<FunctionInfo>> {
+        Ok(Self::list_functions(project_root, |file_name, source| {
+            let query = AllFunctionsQuery::try_new().ok()?;
+            Some(
+                query
+                    .list_function_names(file_name, source)
+                    .unwrap_or_default(),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,121 @@
+//! These tests are mostly for the queries, to ensure that querying only
+//! autometricized functions, or all functions, give the correct set of
+//! [`FunctionInfo`] entries. It is up to the [`Impl`] structure for each
+//! language to then merge the sets so that functions that get detected by both
+//! queries have their information merged.
+
+use crate::{Location, Position, Range};
+
+use super::*;
+use pretty_assertions::assert_eq;
+
+const FILE_NAME: &str = "Api/UserService.cs";
+
+#[test]
+fn detect_simple() {
+    let source = r#"
+        namespace Example.Api
+        {
+            public class UserService
+            {
+                [Autometrics]
+                public User GetUser(string id) { return null; }
+
+                private void Helper() {}
+            }
+        }
+        "#;
+
+    let query = AmQuery::try_new().unwrap();
+    let list = query.list_function_names(FILE_NAME, source).unwrap();
+    let all_query = AllFunctionsQuery::try_new().unwrap();
+    let all_list = all_query.list_function_names(FILE_NAME, source).unwrap();
+
+    let get_user_location = Location {
+        file: FILE_NAME.to_string(),
+        range: Range {
+            start: Position {
+                line: 6,
+                column: 28,
+            },
+            end: Position {
+                line: 6,
+                column: 28 + "GetUser".len(),
+            },
+        },
+    };
+    let helper_location = Location {
+        file: FILE_NAME.to_string(),
+        range: Range {
+            start: Position {
+                line: 8,
+                column: 29,
+            },
+            end: Position {
+                line: 8,
+                column: 29 + "Helper".len(),
+            },
+        },
+    };
+
+    let get_user_instrumented = FunctionInfo {
+        id: ("Example.Api.UserService", "GetUser").into(),
+        instrumentation: Some(get_user_location.clone()),
+        definition: Some(get_user_location.clone()),
+    };
+    let get_user_all = FunctionInfo {
+        id: ("Example.Api.UserService", "GetUser").into(),
+        instrumentation: None,
+        definition: Some(get_user_location),
+    };
+    let helper = FunctionInfo {
+        id: ("Example.Api.UserService", "Helper").into(),
+        instrumentation: None,
+        definition: Some(helper_location),
+    };
+
+    assert_eq!(list, vec![get_user_instrumented]);
+    assert_eq!(all_list, vec![get_user_all, helper]);
+}
+
+#[test]
+fn detect_attribute_variants() {
+    let source = r#"
+        namespace Example.Api;
+
+        class Handlers
+        {
+            [HttpGet, Autometrics(Objective = "api")]
+            public async Task Index() {}
+
+            [Autometrics.Autometrics]
+            void Qualified() {}
+
+            [AutometricsAttribute]
+            void LongForm() {}
+
+            [NotAutometrics]
+            void Skipped() {}
+
+            struct Nested
+            {
+                [Autometrics]
+                void Inner() {}
+            }
+        }
+        "#;
+
+    let query = AmQuery::try_new().unwrap();
+    let list = query.list_function_names(FILE_NAME, source).unwrap();
+
+    let ids: Vec<_> = list.into_iter().map(|info| info.id).collect();
+    assert_eq!(
+        ids,
+        vec![
+            ("Example.Api.Handlers", "Index").into(),
+            ("Example.Api.Handlers", "Qualified").into(),
+            ("Example.Api.Handlers", "LongForm").into(),
+            ("Example.Api.Handlers.Nested", "Inner").into(),
+        ]
+    );
+}
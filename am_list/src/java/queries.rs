@@ -0,0 +1,234 @@
+use crate::{AmlError, FunctionInfo, Location, Result, FUNC_NAME_CAPTURE};
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser, Query};
+
+/// The JVM languages that are listed by the Java implementation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Dialect {
+    Java,
+    Kotlin,
+}
+
+impl Dialect {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "java" => Some(Self::Java),
+            "kt" => Some(Self::Kotlin),
+            _ => None,
+        }
+    }
+
+    fn language(self) -> Language {
+        match self {
+            Self::Java => tree_sitter_java::language(),
+            Self::Kotlin => tree_sitter_kotlin::language(),
+        }
+    }
+}
+
+fn new_parser(dialect: Dialect) -> Result<Parser> {
+    let mut parser = Parser::new();
+    parser.set_language(dialect.language())?;
+    Ok(parser)
+}
+
+fn node_text(node: Option<Node>, source: &str) -> Result<String> {
+    node.ok_or(AmlError::InvalidText)?
+        .utf8_text(source.as_bytes())
+        .map(ToString::to_string)
+        .map_err(|_| AmlError::InvalidText)
+}
+
+/// The fully qualified name of the class that contains the function `node`
+/// is the name of, like `com.example.UserService`. Kotlin functions that are
+/// declared at the top level only have their package as module, or the
+/// file name if there is no package.
+fn get_node_module(node: &Node, source: &str, file_name: &str, dialect: Dialect) -> Result<String> {
+    let mut parts = Vec::new();
+    let mut root = *node;
+    while let Some(parent) = root.parent() {
+        match (dialect, parent.kind()) {
+            (
+                Dialect::Java,
+                "class_declaration"
+                | "interface_declaration"
+                | "enum_declaration"
+                | "record_declaration",
+            ) => parts.push(node_text(parent.child_by_field_name("name"), source)?),
+            (Dialect::Kotlin, "class_declaration" | "object_declaration") => {
+                let name = parent
+                    .named_children(&mut parent.walk())
+                    .find(|child| child.kind() == "type_identifier");
+                parts.push(node_text(name, source)?);
+            }
+            (Dialect::Kotlin, "companion_object") => {
+                let name = parent
+                    .named_children(&mut parent.walk())
+                    .find(|child| child.kind() == "type_identifier");
+                match name {
+                    Some(name) => parts.push(node_text(Some(name), source)?),
+                    None => parts.push("Companion".to_string()),
+                }
+            }
+            _ => {}
+        }
+        root = parent;
+    }
+
+    let package = match dialect {
+        Dialect::Java => root
+            .named_children(&mut root.walk())
+            .find(|child| child.kind() == "package_declaration")
+            .and_then(|package| {
+                package
+                    .named_children(&mut package.walk())
+                    .find(|child| matches!(child.kind(), "identifier" | "scoped_identifier"))
+            }),
+        Dialect::Kotlin => root
+            .named_children(&mut root.walk())
+            .find(|child| child.kind() == "package_header")
+            .and_then(|package| {
+                package
+                    .named_children(&mut package.walk())
+                    .find(|child| child.kind() == "identifier")
+            }),
+    };
+    if let Some(package) = package {
+        parts.push(node_text(Some(package), source)?);
+    }
+
+    if parts.is_empty() {
+        return Ok(Path::new(file_name)
+            .with_extension("")
+            .to_string_lossy()
+            .to_string());
+    }
+
+    parts.reverse();
+    Ok(parts.join("."))
+}
+
+fn list_function_names(
+    query: &Query,
+    func_name_idx: u32,
+    dialect: Dialect,
+    file_name: &str,
+    source: &str,
+    instrumented: bool,
+) -> Result<Vec<FunctionInfo>> {
+    let mut parser = new_parser(dialect)?;
+    let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+
+    let mut cursor = tree_sitter::QueryCursor::new();
+    cursor
+        .matches(query, parsed_source.root_node(), source.as_bytes())
+        .filter_map(|m| -> Option<Result<FunctionInfo>> {
+            let node = m.nodes_for_capture_index(func_name_idx).next()?;
+            let start = node.start_position();
+            let end = node.end_position();
+            let definition = Some(Location::from((file_name, start, end)));
+            let instrumentation = instrumented.then(|| Location::from((file_name, start, end)));
+
+            let function = match node_text(Some(node), source) {
+                Ok(function) => function,
+                Err(err) => return Some(Err(err)),
+            };
+            let module = match get_node_module(&node, source, file_name, dialect) {
+                Ok(module) => module,
+                Err(err) => return Some(Err(err)),
+            };
+
+            Some(Ok(FunctionInfo {
+                id: (module, function).into(),
+                instrumentation,
+                definition,
+            }))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()
+}
+
+/// Query wrapper for "all autometrics functions in source"
+#[derive(Debug)]
+pub(super) struct AmQuery {
+    query: Query,
+    dialect: Dialect,
+    /// Index of the capture for a function name.
+    func_name_idx: u32,
+}
+
+impl AmQuery {
+    /// Failible constructor.
+    ///
+    /// The constructor only fails if the given tree-sitter query does not have the
+    /// necessary named captures.
+    pub fn try_new(dialect: Dialect) -> Result<Self> {
+        let source = match dialect {
+            Dialect::Java => include_str!("../../runtime/queries/java/autometrics.scm"),
+            Dialect::Kotlin => include_str!("../../runtime/queries/kotlin/autometrics.scm"),
+        };
+        let query = Query::new(dialect.language(), source)?;
+        let func_name_idx = query
+            .capture_index_for_name(FUNC_NAME_CAPTURE)
+            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
+
+        Ok(Self {
+            query,
+            dialect,
+            func_name_idx,
+        })
+    }
+
+    pub fn list_function_names(&self, file_name: &str, source: &str) -> Result<Vec<FunctionInfo>> {
+        list_function_names(
+            &self.query,
+            self.func_name_idx,
+            self.dialect,
+            file_name,
+            source,
+            true,
+        )
+    }
+}
+
+/// Query wrapper for "all functions in source"
+#[derive(Debug)]
+pub(super) struct AllFunctionsQuery {
+    query: Query,
+    dialect: Dialect,
+    /// Index of the capture for a function name.
+    func_name_idx: u32,
+}
+
+impl AllFunctionsQuery {
+    /// Failible constructor.
+    ///
+    /// The constructor only fails if the given tree-sitter query does not have the
+    /// necessary named captures.
+    pub fn try_new(dialect: Dialect) -> Result<Self> {
+        let source = match dialect {
+            Dialect::Java => include_str!("../../runtime/queries/java/all_functions.scm"),
+            Dialect::Kotlin => include_str!("../../runtime/queries/kotlin/all_functions.scm"),
+        };
+        let query = Query::new(dialect.language(), source)?;
+        let func_name_idx = query
+            .capture_index_for_name(FUNC_NAME_CAPTURE)
+            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
+
+        Ok(Self {
+            query,
+            dialect,
+            func_name_idx,
+        })
+    }
+
+    pub fn list_function_names(&self, file_name: &str, source: &str) -> Result<Vec<FunctionInfo>> {
+        list_function_names(
+            &self.query,
+            self.func_name_idx,
+            self.dialect,
+            file_name,
+            source,
+            false,
+        )
+    }
+}
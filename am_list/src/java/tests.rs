@@ -0,0 +1,172 @@
+//! These tests are mostly for the queries, to ensure that querying only
+//! autometricized functions, or all functions, give the correct set of
+//! [`FunctionInfo`] entries. It is up to the [`Impl`] structure for each
+//! language to then merge the sets so that functions that get detected by both
+//! queries have their information merged.
+
+use crate::{Location, Position, Range};
+
+use super::*;
+use pretty_assertions::assert_eq;
+
+const JAVA_FILE_NAME: &str = "src/main/java/com/example/UserService.java";
+const KOTLIN_FILE_NAME: &str = "src/main/kotlin/com/example/Users.kt";
+
+fn location(file: &str, line: usize, column: usize, name: &str) -> Location {
+    Location {
+        file: file.to_string(),
+        range: Range {
+            start: Position { line, column },
+            end: Position {
+                line,
+                column: column + name.len(),
+            },
+        },
+    }
+}
+
+#[test]
+fn detect_simple() {
+    let source = r#"
+        package com.example;
+
+        public class UserService {
+            @Autometrics
+            public User getUser(String id) {
+                return null;
+            }
+
+            public void helper() {}
+        }
+        "#;
+
+    let query = AmQuery::try_new(Dialect::Java).unwrap();
+    let list = query.list_function_names(JAVA_FILE_NAME, source).unwrap();
+    let all_query = AllFunctionsQuery::try_new(Dialect::Java).unwrap();
+    let all_list = all_query
+        .list_function_names(JAVA_FILE_NAME, source)
+        .unwrap();
+
+    let get_user_location = location(JAVA_FILE_NAME, 5, 24, "getUser");
+    let helper_location = location(JAVA_FILE_NAME, 9, 24, "helper");
+
+    let get_user_instrumented = FunctionInfo {
+        id: ("com.example.UserService", "getUser").into(),
+        instrumentation: Some(get_user_location.clone()),
+        definition: Some(get_user_location.clone()),
+    };
+    let get_user_all = FunctionInfo {
+        id: ("com.example.UserService", "getUser").into(),
+        instrumentation: None,
+        definition: Some(get_user_location),
+    };
+    let helper = FunctionInfo {
+        id: ("com.example.UserService", "helper").into(),
+        instrumentation: None,
+        definition: Some(helper_location),
+    };
+
+    assert_eq!(list, vec![get_user_instrumented]);
+    assert_eq!(all_list, vec![get_user_all, helper]);
+}
+
+#[test]
+fn detect_nested() {
+    let source = r#"
+        package com.example;
+
+        class Outer {
+            @Override
+            @dev.autometrics.Autometrics(objective = API_SLO)
+            void handle() {}
+
+            @NotAutometrics
+            void skipped() {}
+
+            static class Inner {
+                @Autometrics
+                int compute() { return 1; }
+            }
+        }
+        "#;
+
+    let query = AmQuery::try_new(Dialect::Java).unwrap();
+    let list = query.list_function_names(JAVA_FILE_NAME, source).unwrap();
+
+    let ids: Vec<_> = list.into_iter().map(|info| info.id).collect();
+    assert_eq!(
+        ids,
+        vec![
+            ("com.example.Outer", "handle").into(),
+            ("com.example.Outer.Inner", "compute").into(),
+        ]
+    );
+}
+
+#[test]
+fn detect_kotlin() {
+    let source = r#"
+        package com.example
+
+        @Autometrics
+        fun topLevel(): Int = 1
+
+        class Users {
+            @Autometrics(objective = API_SLO)
+            suspend fun fetch(id: String): User? = null
+
+            fun helper() {}
+
+            companion object {
+                @Autometrics
+                fun create(): Users = Users()
+            }
+        }
+        "#;
+
+    let query = AmQuery::try_new(Dialect::Kotlin).unwrap();
+    let list = query.list_function_names(KOTLIN_FILE_NAME, source).unwrap();
+    let all_query = AllFunctionsQuery::try_new(Dialect::Kotlin).unwrap();
+    let all_list = all_query
+        .list_function_names(KOTLIN_FILE_NAME, source)
+        .unwrap();
+
+    let top_level_location = location(KOTLIN_FILE_NAME, 4, 12, "topLevel");
+    assert_eq!(
+        list[0],
+        FunctionInfo {
+            id: ("com.example", "topLevel").into(),
+            instrumentation: Some(top_level_location.clone()),
+            definition: Some(top_level_location),
+        }
+    );
+
+    let ids: Vec<_> = list.into_iter().map(|info| info.id).collect();
+    assert_eq!(
+        ids,
+        vec![
+            ("com.example", "topLevel").into(),
+            ("com.example.Users", "fetch").into(),
+            ("com.example.Users.Companion", "create").into(),
+        ]
+    );
+
+    assert_eq!(all_list.len(), 4);
+    assert!(all_list
+        .iter()
+        .any(|info| info.id == ("com.example.Users", "helper").into()));
+}
+
+#[test]
+fn module_without_package() {
+    let source = r#"
+        @Autometrics
+        fun main() {}
+        "#;
+
+    let query = AmQuery::try_new(Dialect::Kotlin).unwrap();
+    let list = query.list_function_names("src/Main.kt", source).unwrap();
+
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].id, ("src/Main", "main").into());
+}
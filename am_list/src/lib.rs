@@ -1,6 +1,10 @@
+pub mod csharp;
 pub mod go;
+pub mod java;
+pub mod php;
 pub mod python;
 mod roots;
+pub mod ruby;
 pub mod rust;
 pub mod typescript;
 
@@ -202,6 +206,13 @@ pub enum Language {
     Go,
     Typescript,
     Python,
+    /// Java and Kotlin, which share the project layout of the JVM build tools.
+    Java,
+    #[serde(rename = "C#")]
+    CSharp,
+    Ruby,
+    #[serde(rename = "PHP")]
+    Php,
 }
 
 impl FromStr for Language {
@@ -225,6 +236,22 @@ impl FromStr for Language {
             return Ok(Self::Python);
         }
 
+        if ["java", "kotlin", "kt"].contains(&discriminant.as_str()) {
+            return Ok(Self::Java);
+        }
+
+        if ["csharp", "cs", "c#"].contains(&discriminant.as_str()) {
+            return Ok(Self::CSharp);
+        }
+
+        if ["ruby", "rb"].contains(&discriminant.as_str()) {
+            return Ok(Self::Ruby);
+        }
+
+        if discriminant == "php" {
+            return Ok(Self::Php);
+        }
+
         Err(format!("Unknown language: {s}"))
     }
 }
@@ -236,6 +263,10 @@ impl std::fmt::Display for Language {
             Language::Go => write!(f, "Golang"),
             Language::Typescript => write!(f, "Typescript"),
             Language::Python => write!(f, "Python"),
+            Language::Java => write!(f, "Java"),
+            Language::CSharp => write!(f, "C#"),
+            Language::Ruby => write!(f, "Ruby"),
+            Language::Php => write!(f, "PHP"),
         }
     }
 }
@@ -274,6 +305,10 @@ pub fn list_single_project_functions(
         Language::Go => Box::new(crate::go::Impl {}),
         Language::Typescript => Box::new(crate::typescript::Impl {}),
         Language::Python => Box::new(crate::python::Impl {}),
+        Language::Java => Box::new(crate::java::Impl {}),
+        Language::CSharp => Box::new(crate::csharp::Impl {}),
+        Language::Ruby => Box::new(crate::ruby::Impl {}),
+        Language::Php => Box::new(crate::php::Impl {}),
     };
     let mut res = if all_functions {
         implementor.list_all_functions(root)?
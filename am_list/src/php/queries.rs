@@ -0,0 +1,170 @@
+use crate::{AmlError, FunctionInfo, Location, Result, FUNC_NAME_CAPTURE};
+use std::path::Path;
+use tree_sitter::{Node, Parser, Query};
+use tree_sitter_php::language_php as language;
+
+fn new_parser() -> Result<Parser> {
+    let mut parser = Parser::new();
+    parser.set_language(language())?;
+    Ok(parser)
+}
+
+fn node_text(node: Option<Node>, source: &str) -> Result<String> {
+    node.ok_or(AmlError::InvalidText)?
+        .utf8_text(source.as_bytes())
+        .map(ToString::to_string)
+        .map_err(|_| AmlError::InvalidText)
+}
+
+/// The fully qualified name of the class that contains the method `node` is
+/// the name of, like `App\Http\UserController`. Functions outside of a class
+/// only have their namespace as module, or the file name if there is no
+/// namespace.
+fn get_node_module(node: &Node, source: &str, file_name: &str) -> Result<String> {
+    let mut parts = Vec::new();
+    let mut top_level = *node;
+    while let Some(parent) = top_level.parent() {
+        match parent.kind() {
+            "class_declaration"
+            | "trait_declaration"
+            | "interface_declaration"
+            | "enum_declaration" => {
+                parts.push(node_text(parent.child_by_field_name("name"), source)?)
+            }
+            // `namespace App { ... }`
+            "namespace_definition" => {
+                parts.push(node_text(parent.child_by_field_name("name"), source)?);
+                break;
+            }
+            "program" => break,
+            _ => {}
+        }
+        top_level = parent;
+    }
+
+    // `namespace App;` applies to the declarations that follow it.
+    if top_level.parent().map(|parent| parent.kind()) == Some("program") {
+        let mut sibling = top_level.prev_named_sibling();
+        while let Some(previous) = sibling {
+            if previous.kind() == "namespace_definition" {
+                parts.push(node_text(previous.child_by_field_name("name"), source)?);
+                break;
+            }
+            sibling = previous.prev_named_sibling();
+        }
+    }
+
+    if parts.is_empty() {
+        return Ok(Path::new(file_name)
+            .with_extension("")
+            .to_string_lossy()
+            .to_string());
+    }
+
+    parts.reverse();
+    Ok(parts.join("\\"))
+}
+
+fn list_function_names(
+    query: &Query,
+    func_name_idx: u32,
+    file_name: &str,
+    source: &str,
+    instrumented: bool,
+) -> Result<Vec<FunctionInfo>> {
+    let mut parser = new_parser()?;
+    let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+
+    let mut cursor = tree_sitter::QueryCursor::new();
+    cursor
+        .matches(query, parsed_source.root_node(), source.as_bytes())
+        .filter_map(|m| -> Option<Result<FunctionInfo>> {
+            let node = m.nodes_for_capture_index(func_name_idx).next()?;
+            let start = node.start_position();
+            let end = node.end_position();
+            let definition = Some(Location::from((file_name, start, end)));
+            let instrumentation = instrumented.then(|| Location::from((file_name, start, end)));
+
+            let function = match node_text(Some(node), source) {
+                Ok(function) => function,
+                Err(err) => return Some(Err(err)),
+            };
+            let module = match get_node_module(&node, source, file_name) {
+                Ok(module) => module,
+                Err(err) => return Some(Err(err)),
+            };
+
+            Some(Ok(FunctionInfo {
+                id: (module, function).into(),
+                instrumentation,
+                definition,
+            }))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()
+}
+
+/// Query wrapper for "all autometrics functions in source"
+#[derive(Debug)]
+pub(super) struct AmQuery {
+    query: Query,
+    /// Index of the capture for a function name.
+    func_name_idx: u32,
+}
+
+impl AmQuery {
+    /// Failible constructor.
+    ///
+    /// The constructor only fails if the given tree-sitter query does not have the
+    /// necessary named captures.
+    pub fn try_new() -> Result<Self> {
+        let query = Query::new(
+            language(),
+            include_str!("../../runtime/queries/php/autometrics.scm"),
+        )?;
+        let func_name_idx = query
+            .capture_index_for_name(FUNC_NAME_CAPTURE)
+            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
+
+        Ok(Self {
+            query,
+            func_name_idx,
+        })
+    }
+
+    pub fn list_function_names(&self, file_name: &str, source: &str) -> Result<Vec<FunctionInfo>> {
+        list_function_names(&self.query, self.func_name_idx, file_name, source, true)
+    }
+}
+
+/// Query wrapper for "all functions in source"
+#[derive(Debug)]
+pub(super) struct AllFunctionsQuery {
+    query: Query,
+    /// Index of the capture for a function name.
+    func_name_idx: u32,
+}
+
+impl AllFunctionsQuery {
+    /// Failible constructor.
+    ///
+    /// The constructor only fails if the given tree-sitter query does not have the
+    /// necessary named captures.
+    pub fn try_new() -> Result<Self> {
+        let query = Query::new(
+            language(),
+            include_str!("../../runtime/queries/php/all_functions.scm"),
+        )?;
+        let func_name_idx = query
+            .capture_index_for_name(FUNC_NAME_CAPTURE)
+            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
+
+        Ok(Self {
+            query,
+            func_name_idx,
+        })
+    }
+
+    pub fn list_function_names(&self, file_name: &str, source: &str) -> Result<Vec<FunctionInfo>> {
+        list_function_names(&self.query, self.func_name_idx, file_name, source, false)
+    }
+}
@@ -0,0 +1,114 @@
+//! These tests are mostly for the queries, to ensure that querying only
+//! autometricized functions, or all functions, give the correct set of
+//! [`FunctionInfo`] entries. It is up to the [`Impl`] structure for each
+//! language to then merge the sets so that functions that get detected by both
+//! queries have their information merged.
+
+use crate::{Location, Position, Range};
+
+use super::*;
+use pretty_assertions::assert_eq;
+
+const FILE_NAME: &str = "src/Http/UserController.php";
+
+#[test]
+fn detect_simple() {
+    let source = r#"<?php
+
+namespace App\Http;
+
+use Autometrics\Autometrics;
+
+class UserController
+{
+    #[Autometrics]
+    public function show($id) {}
+
+    private function helper() {}
+}
+"#;
+
+    let query = AmQuery::try_new().unwrap();
+    let list = query.list_function_names(FILE_NAME, source).unwrap();
+    let all_query = AllFunctionsQuery::try_new().unwrap();
+    let all_list = all_query.list_function_names(FILE_NAME, source).unwrap();
+
+    let show_location = Location {
+        file: FILE_NAME.to_string(),
+        range: Range {
+            start: Position {
+                line: 9,
+                column: 20,
+            },
+            end: Position {
+                line: 9,
+                column: 20 + "show".len(),
+            },
+        },
+    };
+    let helper_location = Location {
+        file: FILE_NAME.to_string(),
+        range: Range {
+            start: Position {
+                line: 11,
+                column: 21,
+            },
+            end: Position {
+                line: 11,
+                column: 21 + "helper".len(),
+            },
+        },
+    };
+
+    let show_instrumented = FunctionInfo {
+        id: ("App\\Http\\UserController", "show").into(),
+        instrumentation: Some(show_location.clone()),
+        definition: Some(show_location.clone()),
+    };
+    let show_all = FunctionInfo {
+        id: ("App\\Http\\UserController", "show").into(),
+        instrumentation: None,
+        definition: Some(show_location),
+    };
+    let helper = FunctionInfo {
+        id: ("App\\Http\\UserController", "helper").into(),
+        instrumentation: None,
+        definition: Some(helper_location),
+    };
+
+    assert_eq!(list, vec![show_instrumented]);
+    assert_eq!(all_list, vec![show_all, helper]);
+}
+
+#[test]
+fn detect_functions() {
+    let source = r#"<?php
+
+namespace App {
+    #[\Autometrics\Autometrics]
+    function handler() {}
+
+    trait Cached
+    {
+        #[Route('/'), Autometrics(objective: 'api')]
+        public static function index() {}
+
+        #[NotAutometrics]
+        public function skipped() {}
+    }
+}
+"#;
+
+    let query = AmQuery::try_new().unwrap();
+    let list = query.list_function_names(FILE_NAME, source).unwrap();
+
+    let ids: Vec<_> = list.into_iter().map(|info| info.id).collect();
+    assert_eq!(
+        ids,
+        vec![("App", "handler").into(), ("App\\Cached", "index").into(),]
+    );
+
+    let source = "<?php\n#[Autometrics]\nfunction main() {}\n";
+    let list = query.list_function_names("bin/main.php", source).unwrap();
+    assert_eq!(list[0].id, ("bin/main", "main").into());
+}
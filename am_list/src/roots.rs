@@ -17,8 +17,29 @@ pub fn find_project_roots(repo: &Path) -> Result<Vec<(PathBuf, Language)>, AmlEr
     let go_roots = find_go_roots(&abs_repo)
         .into_iter()
         .map(|project_root| (project_root, Language::Go));
-
-    Ok(rust_roots.chain(ts_roots).chain(go_roots).collect())
+    let java_roots = find_roots(&abs_repo, &["build", "target"], |name| {
+        matches!(name, "pom.xml" | "build.gradle" | "build.gradle.kts")
+    })
+    .into_iter()
+    .map(|project_root| (project_root, Language::Java));
+    let csharp_roots = find_roots(&abs_repo, &["bin", "obj"], |name| name.ends_with(".csproj"))
+        .into_iter()
+        .map(|project_root| (project_root, Language::CSharp));
+    let ruby_roots = find_roots(&abs_repo, &["vendor"], |name| name == "Gemfile")
+        .into_iter()
+        .map(|project_root| (project_root, Language::Ruby));
+    let php_roots = find_roots(&abs_repo, &["vendor"], |name| name == "composer.json")
+        .into_iter()
+        .map(|project_root| (project_root, Language::Php));
+
+    Ok(rust_roots
+        .chain(ts_roots)
+        .chain(go_roots)
+        .chain(java_roots)
+        .chain(csharp_roots)
+        .chain(ruby_roots)
+        .chain(php_roots)
+        .collect())
 }
 
 fn is_hidden(entry: &DirEntry) -> bool {
@@ -167,3 +188,31 @@ fn find_go_roots(repo: &Path) -> Vec<PathBuf> {
         })
         .collect()
 }
+
+/// Find the directories that contain a file for which `is_manifest` returns
+/// true, without descending into the `excluded` directories (ie. the ones
+/// that contain dependencies or build output).
+fn find_roots(repo: &Path, excluded: &[&str], is_manifest: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    let is_excluded = |entry: &DirEntry| {
+        entry.depth() != 0
+            && entry.file_type().is_dir()
+            && entry
+                .file_name()
+                .to_str()
+                .map(|name| excluded.contains(&name))
+                .unwrap_or(false)
+    };
+
+    let walker = WalkDir::new(repo).into_iter();
+    walker
+        .filter_entry(|e| !is_hidden(e) && !is_excluded(e))
+        .filter_map(|e| -> Option<PathBuf> {
+            let entry = e.ok()?;
+            if entry.file_type().is_file() && entry.file_name().to_str().is_some_and(&is_manifest) {
+                entry.path().parent().map(Path::to_path_buf)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,149 @@
+use crate::{AmlError, FunctionInfo, Location, Result, FUNC_NAME_CAPTURE};
+use std::path::Path;
+use tree_sitter::{Node, Parser, Query};
+use tree_sitter_ruby::language;
+
+fn new_parser() -> Result<Parser> {
+    let mut parser = Parser::new();
+    parser.set_language(language())?;
+    Ok(parser)
+}
+
+fn node_text(node: Option<Node>, source: &str) -> Result<String> {
+    node.ok_or(AmlError::InvalidText)?
+        .utf8_text(source.as_bytes())
+        .map(ToString::to_string)
+        .map_err(|_| AmlError::InvalidText)
+}
+
+/// The name of the class or module that contains the method `node` is the
+/// name of, like `Api::UsersController`. Methods that are defined at the top
+/// level have the file name as module.
+fn get_node_module(node: &Node, source: &str, file_name: &str) -> Result<String> {
+    let mut parts = Vec::new();
+    let mut current = node.parent();
+    while let Some(parent) = current {
+        if matches!(parent.kind(), "class" | "module") {
+            parts.push(node_text(parent.child_by_field_name("name"), source)?);
+        }
+        current = parent.parent();
+    }
+
+    if parts.is_empty() {
+        return Ok(Path::new(file_name)
+            .with_extension("")
+            .to_string_lossy()
+            .to_string());
+    }
+
+    parts.reverse();
+    Ok(parts.join("::"))
+}
+
+fn list_function_names(
+    query: &Query,
+    func_name_idx: u32,
+    file_name: &str,
+    source: &str,
+    instrumented: bool,
+) -> Result<Vec<FunctionInfo>> {
+    let mut parser = new_parser()?;
+    let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+
+    let mut cursor = tree_sitter::QueryCursor::new();
+    cursor
+        .matches(query, parsed_source.root_node(), source.as_bytes())
+        .filter_map(|m| -> Option<Result<FunctionInfo>> {
+            let node = m.nodes_for_capture_index(func_name_idx).next()?;
+            let start = node.start_position();
+            let end = node.end_position();
+            let instrumentation = instrumented.then(|| Location::from((file_name, start, end)));
+            // `autometrics :name` instruments a method that is defined
+            // elsewhere, so it doesn't say where the definition is.
+            let is_symbol = node.kind() == "simple_symbol";
+            let definition = (!is_symbol).then(|| Location::from((file_name, start, end)));
+
+            let function = match node_text(Some(node), source) {
+                Ok(function) if is_symbol => function.trim_start_matches(':').to_string(),
+                Ok(function) => function,
+                Err(err) => return Some(Err(err)),
+            };
+            let module = match get_node_module(&node, source, file_name) {
+                Ok(module) => module,
+                Err(err) => return Some(Err(err)),
+            };
+
+            Some(Ok(FunctionInfo {
+                id: (module, function).into(),
+                instrumentation,
+                definition,
+            }))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()
+}
+
+/// Query wrapper for "all autometrics functions in source"
+#[derive(Debug)]
+pub(super) struct AmQuery {
+    query: Query,
+    /// Index of the capture for a function name.
+    func_name_idx: u32,
+}
+
+impl AmQuery {
+    /// Failible constructor.
+    ///
+    /// The constructor only fails if the given tree-sitter query does not have the
+    /// necessary named captures.
+    pub fn try_new() -> Result<Self> {
+        let query = Query::new(
+            language(),
+            include_str!("../../runtime/queries/ruby/autometrics.scm"),
+        )?;
+        let func_name_idx = query
+            .capture_index_for_name(FUNC_NAME_CAPTURE)
+            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
+
+        Ok(Self {
+            query,
+            func_name_idx,
+        })
+    }
+
+    pub fn list_function_names(&self, file_name: &str, source: &str) -> Result<Vec<FunctionInfo>> {
+        list_function_names(&self.query, self.func_name_idx, file_name, source, true)
+    }
+}
+
+/// Query wrapper for "all functions in source"
+#[derive(Debug)]
+pub(super) struct AllFunctionsQuery {
+    query: Query,
+    /// Index of the capture for a function name.
+    func_name_idx: u32,
+}
+
+impl AllFunctionsQuery {
+    /// Failible constructor.
+    ///
+    /// The constructor only fails if the given tree-sitter query does not have the
+    /// necessary named captures.
+    pub fn try_new() -> Result<Self> {
+        let query = Query::new(
+            language(),
+            include_str!("../../runtime/queries/ruby/all_functions.scm"),
+        )?;
+        let func_name_idx = query
+            .capture_index_for_name(FUNC_NAME_CAPTURE)
+            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
+
+        Ok(Self {
+            query,
+            func_name_idx,
+        })
+    }
+
+    pub fn list_function_names(&self, file_name: &str, source: &str) -> Result<Vec<FunctionInfo>> {
+        list_function_names(&self.query, self.func_name_idx, file_name, source, false)
+    }
+}
@@ -0,0 +1,124 @@
+//! These tests are mostly for the queries, to ensure that querying only
+//! autometricized functions, or all functions, give the correct set of
+//! [`FunctionInfo`] entries. It is up to the [`Impl`] structure for each
+//! language to then merge the sets so that functions that get detected by both
+//! queries have their information merged.
+
+use crate::{Location, Position, Range};
+
+use super::*;
+use pretty_assertions::assert_eq;
+
+const FILE_NAME: &str = "app/controllers/users_controller.rb";
+
+#[test]
+fn detect_simple() {
+    let source = r#"
+        module Api
+          class UsersController
+            autometrics def show
+              1
+            end
+
+            def helper; end
+          end
+        end
+        "#;
+
+    let query = AmQuery::try_new().unwrap();
+    let list = query.list_function_names(FILE_NAME, source).unwrap();
+    let all_query = AllFunctionsQuery::try_new().unwrap();
+    let all_list = all_query.list_function_names(FILE_NAME, source).unwrap();
+
+    let show_location = Location {
+        file: FILE_NAME.to_string(),
+        range: Range {
+            start: Position {
+                line: 3,
+                column: 28,
+            },
+            end: Position {
+                line: 3,
+                column: 28 + "show".len(),
+            },
+        },
+    };
+    let helper_location = Location {
+        file: FILE_NAME.to_string(),
+        range: Range {
+            start: Position {
+                line: 7,
+                column: 16,
+            },
+            end: Position {
+                line: 7,
+                column: 16 + "helper".len(),
+            },
+        },
+    };
+
+    let show_instrumented = FunctionInfo {
+        id: ("Api::UsersController", "show").into(),
+        instrumentation: Some(show_location.clone()),
+        definition: Some(show_location.clone()),
+    };
+    let show_all = FunctionInfo {
+        id: ("Api::UsersController", "show").into(),
+        instrumentation: None,
+        definition: Some(show_location),
+    };
+    let helper = FunctionInfo {
+        id: ("Api::UsersController", "helper").into(),
+        instrumentation: None,
+        definition: Some(helper_location),
+    };
+
+    assert_eq!(list, vec![show_instrumented]);
+    assert_eq!(all_list, vec![show_all, helper]);
+}
+
+#[test]
+fn detect_symbol() {
+    let source = r#"
+        class Billing::Invoice
+          def self.create; end
+
+          def total; end
+
+          autometrics :total
+        end
+
+        autometrics def top_level; end
+        "#;
+
+    let query = AmQuery::try_new().unwrap();
+    let list = query.list_function_names(FILE_NAME, source).unwrap();
+
+    let total_instrumentation = Location {
+        file: FILE_NAME.to_string(),
+        range: Range {
+            start: Position {
+                line: 6,
+                column: 22,
+            },
+            end: Position {
+                line: 6,
+                column: 22 + ":total".len(),
+            },
+        },
+    };
+
+    assert_eq!(list.len(), 2);
+    assert_eq!(
+        list[0],
+        FunctionInfo {
+            id: ("Billing::Invoice", "total").into(),
+            instrumentation: Some(total_instrumentation),
+            definition: None,
+        }
+    );
+    assert_eq!(
+        list[1].id,
+        ("app/controllers/users_controller", "top_level").into()
+    );
+}
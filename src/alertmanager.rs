@@ -0,0 +1,33 @@
+use serde::Serialize;
+
+/// A minimal Alertmanager configuration. Alerts are routed to a single
+/// receiver without any notification integrations, which means that they will
+/// only be visible in the Alertmanager UI and API.
+#[derive(Debug, Serialize)]
+pub struct Config {
+    pub route: Route,
+    pub receivers: Vec<Receiver>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Route {
+    pub receiver: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Receiver {
+    pub name: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let receiver = "default".to_string();
+
+        Self {
+            route: Route {
+                receiver: receiver.clone(),
+            },
+            receivers: vec![Receiver { name: receiver }],
+        }
+    }
+}
@@ -1,17 +1,37 @@
 use anyhow::Result;
 use autometrics_am::config::AmConfig;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use indicatif::MultiProgress;
 use std::path::PathBuf;
 use tracing::info;
+use url::Url;
 
+mod analyze;
+mod bench;
+mod completions;
+mod demo;
+mod discover;
 mod explore;
+mod export;
+pub mod grafana;
+mod ingest;
 mod init;
+mod instrument;
 mod list;
+mod promql;
 mod proxy;
+mod query;
+mod record;
+mod replay;
+mod report;
+mod rules;
+mod slo;
 pub mod start;
+mod status;
+mod stop;
 pub mod system;
 pub mod update;
+mod validate;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None, bin_name = "am")]
@@ -29,17 +49,73 @@ pub struct Application {
     #[clap(long, short)]
     pub verbose: bool,
 
+    /// The format of the log messages. `json` writes one object per line, with
+    /// the `component` (ie. `prometheus`) that logged the message, so the logs
+    /// can be ingested by a log pipeline.
+    #[clap(
+        long,
+        env = "AM_LOG_FORMAT",
+        value_enum,
+        default_value_t,
+        global = true
+    )]
+    pub log_format: LogFormat,
+
     /// Use the following file to define defaults for am.
     #[clap(long, env)]
     pub config_file: Option<PathBuf>,
+
+    /// Download Prometheus, Pushgateway, etc. from this mirror of the GitHub
+    /// releases instead of github.com. The mirror needs to use the same
+    /// layout: `<mirror>/<org>/<repo>/releases/download/<version>/<file>`.
+    #[clap(long, env = "AM_DOWNLOAD_BASE_URL", global = true)]
+    pub download_mirror: Option<Url>,
+
+    /// Refuse to use downloaded programs of which the signature can't be
+    /// verified with cosign, because cosign is not installed or the release
+    /// is not signed.
+    ///
+    /// Signatures are always verified if possible.
+    #[clap(long, env = "AM_REQUIRE_SIGNATURE", global = true)]
+    pub require_signature: bool,
+
+    /// A PEM file with additional CA certificates to trust when downloading
+    /// and checking endpoints, for example the one of a proxy that intercepts
+    /// TLS traffic.
+    ///
+    /// Proxies are taken from the `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY`
+    /// environment variables, or the `[network]` section of am.toml.
+    #[clap(long, env = "AM_CA_BUNDLE", global = true)]
+    pub ca_bundle: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable messages, with details only if `--verbose` is set.
+    #[default]
+    Pretty,
+
+    /// A single line per message, including the time, level and component.
+    Compact,
+
+    /// A JSON object per message.
+    Json,
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 pub enum SubCommands {
     /// Start scraping the specified endpoint(s), while also providing a web
     /// interface to inspect the autometrics data.
     Start(start::CliArguments),
 
+    /// Stop the instance of am that was started with `am start --detach`
+    Stop(stop::Arguments),
+
+    /// Show the running instances of am, the versions of the processes they
+    /// manage and the health of their scrape targets
+    Status(status::Arguments),
+
     /// Manage am related system settings. Such as cleaning up downloaded
     /// Prometheus, Pushgateway installs.
     System(system::Arguments),
@@ -51,9 +127,66 @@ pub enum SubCommands {
     /// Use am as a proxy to another prometheus instance
     Proxy(proxy::CliArguments),
 
+    /// Run a PromQL query against the Prometheus from `am start`, or another
+    /// Prometheus
+    Query(query::Arguments),
+
+    /// Show the call rate, error rate, latency and SLO status of the
+    /// autometrics functions in a project
+    Report(report::Arguments),
+
+    /// Analyze the data in the Prometheus from `am start`, or another
+    /// Prometheus
+    Analyze(analyze::Arguments),
+
+    /// Record the samples that the Prometheus from `am start` scraped into a
+    /// file, so they can be shared in a bug report
+    Record(record::Arguments),
+
+    /// Push samples in the Prometheus text format from a file or stdin to the
+    /// Pushgateway or Prometheus of `am start`
+    Ingest(ingest::Arguments),
+
+    /// Generate load on the Pushgateway or Prometheus from `am start`
+    Bench(bench::Arguments),
+
+    /// Start Prometheus and the explorer with the samples of a recording made
+    /// with `am record`
+    Replay(replay::Arguments),
+
+    /// Start Grafana with the Autometrics dashboards, using the Prometheus from
+    /// `am start` as its datasource
+    Grafana(grafana::Arguments),
+
+    /// Generate Prometheus rules for Service Level Objectives
+    Slo(slo::Arguments),
+
+    /// Check Prometheus rule files for mistakes, without needing promtool, or
+    /// run their unit tests
+    Rules(rules::Arguments),
+
+    /// Build PromQL queries for the autometrics metrics, or check a PromQL
+    /// query for mistakes
+    Promql(promql::Arguments),
+
+    /// Start `am start` together with a demo service that is instrumented
+    /// with autometrics, to try out the explorer without writing code
+    Demo(demo::Arguments),
+
+    /// Look for metrics endpoints on the ports that are listening locally
+    Discover(discover::Arguments),
+
     /// Create a new `am.toml` file interactively with sensible defaults
     Init(init::Arguments),
 
+    /// Export the autometrics rules and dashboards, so they can be deployed
+    /// alongside your own Prometheus and Grafana
+    Export(export::Arguments),
+
+    /// Check the `am.toml` file and the Prometheus config that `am start`
+    /// would generate from it
+    Validate(validate::Arguments),
+
     /// Open the Fiberplane discord to receive help, send suggestions or
     /// discuss various things related to Autometrics and the `am` CLI
     Discord,
@@ -61,20 +194,44 @@ pub enum SubCommands {
     /// Run the updater
     Update(update::Arguments),
 
+    /// Print the completion script of am for a shell. The endpoints and
+    /// profiles of the config file are completed as well.
+    Completions(completions::Arguments),
+
     /// List the functions in a project
     List(list::Arguments),
 
+    /// Add autometrics annotations to the functions in a project
+    Instrument(instrument::Arguments),
+
     #[clap(hide = true)]
     MarkdownHelp,
 }
 
 pub async fn handle_command(app: Application, config: AmConfig, mp: MultiProgress) -> Result<()> {
     match app.command {
-        SubCommands::Start(args) => start::handle_command(args, config, mp).await,
-        SubCommands::System(args) => system::handle_command(args, mp).await,
+        SubCommands::Start(args) => start::handle_command(args, config, app.config_file, mp).await,
+        SubCommands::Stop(args) => stop::handle_command(args).await,
+        SubCommands::Status(args) => status::handle_command(args).await,
+        SubCommands::System(args) => system::handle_command(args, config, mp).await,
         SubCommands::Explore(args) => explore::handle_command(args).await,
-        SubCommands::Proxy(args) => proxy::handle_command(args).await,
+        SubCommands::Proxy(args) => proxy::handle_command(args, config).await,
+        SubCommands::Query(args) => query::handle_command(args).await,
+        SubCommands::Report(args) => report::handle_command(args, config).await,
+        SubCommands::Analyze(args) => analyze::handle_command(args).await,
+        SubCommands::Record(args) => record::handle_command(args).await,
+        SubCommands::Ingest(args) => ingest::handle_command(args).await,
+        SubCommands::Bench(args) => bench::handle_command(args).await,
+        SubCommands::Replay(args) => replay::handle_command(args, mp).await,
+        SubCommands::Grafana(args) => grafana::handle_command(args, mp).await,
+        SubCommands::Slo(args) => slo::handle_command(args, config),
+        SubCommands::Rules(args) => rules::handle_command(args, mp).await,
+        SubCommands::Promql(args) => promql::handle_command(args, config).await,
+        SubCommands::Demo(args) => demo::handle_command(args, config, app.config_file, mp).await,
         SubCommands::Init(args) => init::handle_command(args).await,
+        SubCommands::Discover(args) => discover::handle_command(args).await,
+        SubCommands::Export(args) => export::handle_command(args, config),
+        SubCommands::Validate(args) => validate::handle_command(args, app.config_file),
         SubCommands::Discord => {
             const URL: &str = "https://discord.gg/kHtwcH8As9";
 
@@ -85,7 +242,9 @@ pub async fn handle_command(app: Application, config: AmConfig, mp: MultiProgres
             Ok(())
         }
         SubCommands::Update(args) => update::handle_command(args, mp).await,
+        SubCommands::Completions(args) => completions::handle_command(args, config),
         SubCommands::List(args) => list::handle_command(args),
+        SubCommands::Instrument(args) => instrument::handle_command(args),
         SubCommands::MarkdownHelp => {
             let disable_toc = true;
             clap_markdown::print_help_markdown::<Application>(Some(disable_toc));
@@ -0,0 +1,24 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+pub mod cardinality;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct Arguments {
+    #[command(subcommand)]
+    pub command: SubCommands,
+}
+
+#[derive(Subcommand)]
+pub enum SubCommands {
+    /// Show which metrics and labels have the most series, and which labels of
+    /// the autometrics metrics have an unbounded amount of values.
+    Cardinality(cardinality::Arguments),
+}
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    match args.command {
+        SubCommands::Cardinality(args) => cardinality::handle_command(args).await,
+    }
+}
@@ -0,0 +1,314 @@
+use crate::commands::query::render_table;
+use crate::commands::start::CLIENT;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use url::Url;
+
+/// The labels of the autometrics metrics that should only have a few values.
+/// A value per user, request or memory address in one of these creates a new
+/// set of series every time.
+const AUTOMETRICS_LABELS: [&str; 5] = [
+    "function",
+    "module",
+    "caller_function",
+    "caller_module",
+    "objective_name",
+];
+
+/// Selects all the series of the autometrics metrics.
+const AUTOMETRICS_SELECTOR: &str = r#"{__name__=~"function_calls.*"}"#;
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// How many metrics, labels and label pairs are shown.
+    #[clap(long, default_value_t = 10)]
+    limit: usize,
+
+    /// Autometrics labels with more values than this are reported as
+    /// exploding.
+    #[clap(long, default_value_t = 100)]
+    max_label_values: usize,
+
+    /// How the report is printed.
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+
+    /// The Prometheus to query.
+    ///
+    /// This defaults to the Prometheus proxy of a running `am start`.
+    #[clap(
+        long,
+        env,
+        default_value = "http://127.0.0.1:6789/prometheus",
+        alias = "prometheus-address"
+    )]
+    prometheus_url: Url,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    status: String,
+    data: Option<T>,
+    error: Option<String>,
+}
+
+/// The response of `/api/v1/status/tsdb`, which only covers the head block
+/// (about the last two hours).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TsdbStatus {
+    head_stats: Option<HeadStats>,
+    series_count_by_metric_name: Vec<Count>,
+    label_value_count_by_label_name: Vec<Count>,
+    series_count_by_label_value_pair: Vec<Count>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HeadStats {
+    num_series: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Count {
+    name: String,
+    value: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    head_series: Option<u64>,
+    top_metrics: Vec<Count>,
+    top_labels: Vec<Count>,
+    top_label_pairs: Vec<Count>,
+    autometrics_series: u64,
+    autometrics_labels: Vec<LabelReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct LabelReport {
+    label: String,
+    values: usize,
+    /// Whether the label has more values than `--max-label-values`, or values
+    /// that look like they are unbounded.
+    exploding: bool,
+    /// A few of the values that look like IDs or addresses.
+    suspicious_values: Vec<String>,
+}
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    let tsdb: TsdbStatus = api_get(
+        &args.prometheus_url,
+        "api/v1/status/tsdb",
+        &[("limit", args.limit.to_string())],
+    )
+    .await
+    .context("Unable to get the TSDB status from Prometheus, is `am start` running?")?;
+
+    let mut autometrics_labels = vec![];
+    for label in AUTOMETRICS_LABELS {
+        let values: Vec<String> = api_get(
+            &args.prometheus_url,
+            &format!("api/v1/label/{label}/values"),
+            &[("match[]", AUTOMETRICS_SELECTOR.to_string())],
+        )
+        .await?;
+
+        if !values.is_empty() {
+            autometrics_labels.push(label_report(label, &values, args.max_label_values));
+        }
+    }
+
+    // Older versions of Prometheus ignore the limit.
+    let top = |counts: Vec<Count>| counts.into_iter().take(args.limit).collect::<Vec<_>>();
+    let report = Report {
+        head_series: tsdb.head_stats.map(|stats| stats.num_series),
+        autometrics_series: tsdb
+            .series_count_by_metric_name
+            .iter()
+            .filter(|count| count.name.starts_with("function_calls"))
+            .map(|count| count.value)
+            .sum(),
+        top_metrics: top(tsdb.series_count_by_metric_name),
+        top_labels: top(tsdb.label_value_count_by_label_name),
+        top_label_pairs: top(tsdb.series_count_by_label_value_pair),
+        autometrics_labels,
+    };
+
+    match args.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Table => print_report(&report),
+    }
+
+    for label in report
+        .autometrics_labels
+        .iter()
+        .filter(|label| label.exploding)
+    {
+        warn!(
+            "The `{}` label of the autometrics metrics has {} values{}, which creates a lot of series",
+            label.label,
+            label.values,
+            if label.suspicious_values.is_empty() {
+                String::new()
+            } else {
+                format!(" like {}", label.suspicious_values.join(", "))
+            }
+        );
+    }
+
+    Ok(())
+}
+
+fn print_report(report: &Report) {
+    if let Some(head_series) = report.head_series {
+        info!(
+            "Prometheus has {head_series} series in its head block, {} of which are autometrics series",
+            report.autometrics_series
+        );
+    }
+
+    let sections = [
+        ("Metric", "Series", &report.top_metrics),
+        ("Label", "Values", &report.top_labels),
+        ("Label pair", "Series", &report.top_label_pairs),
+    ];
+    for (name, value, counts) in sections {
+        let columns = [name.to_string(), value.to_string()];
+        let rows: Vec<Vec<String>> = counts
+            .iter()
+            .map(|count| vec![count.name.clone(), count.value.to_string()])
+            .collect();
+        println!("{}", render_table(&columns, &rows));
+    }
+
+    if !report.autometrics_labels.is_empty() {
+        let columns = [
+            "Autometrics label".to_string(),
+            "Values".to_string(),
+            "Exploding".to_string(),
+        ];
+        let rows: Vec<Vec<String>> = report
+            .autometrics_labels
+            .iter()
+            .map(|label| {
+                vec![
+                    label.label.clone(),
+                    label.values.to_string(),
+                    if label.exploding { "yes" } else { "no" }.to_string(),
+                ]
+            })
+            .collect();
+        print!("{}", render_table(&columns, &rows));
+    }
+}
+
+fn label_report(label: &str, values: &[String], max_values: usize) -> LabelReport {
+    let suspicious_values: Vec<String> = values
+        .iter()
+        .filter(|value| looks_unbounded(value))
+        .take(3)
+        .cloned()
+        .collect();
+
+    LabelReport {
+        label: label.to_string(),
+        values: values.len(),
+        exploding: values.len() > max_values || !suspicious_values.is_empty(),
+        suspicious_values,
+    }
+}
+
+/// Whether `value` looks like an ID, timestamp or memory address, which are
+/// different for every call instead of naming a function.
+fn looks_unbounded(value: &str) -> bool {
+    let longest_run = |predicate: fn(&char) -> bool| {
+        value
+            .chars()
+            .fold((0usize, 0usize), |(longest, current), c| {
+                let current = if predicate(&c) { current + 1 } else { 0 };
+                (longest.max(current), current)
+            })
+            .0
+    };
+
+    longest_run(char::is_ascii_digit) >= 5
+        || (longest_run(char::is_ascii_hexdigit) >= 12 && value.chars().any(|c| c.is_ascii_digit()))
+}
+
+/// Get `path` from the Prometheus HTTP API at `prometheus_url`.
+async fn api_get<T: DeserializeOwned>(
+    prometheus_url: &Url,
+    path: &str,
+    params: &[(&str, String)],
+) -> Result<T> {
+    // Make sure the path of the Prometheus URL is kept.
+    let mut url = prometheus_url.clone();
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    let url = url.join(path)?;
+
+    let response = CLIENT
+        .get(url.clone())
+        .query(params)
+        .send()
+        .await
+        .with_context(|| format!("Unable to reach Prometheus at {prometheus_url}"))?;
+    let status = response.status();
+    let body = response.text().await?;
+
+    let response: ApiResponse<T> = serde_json::from_str(&body)
+        .with_context(|| format!("Unexpected response from {url} ({status}): {body}"))?;
+
+    match (response.status.as_str(), response.data) {
+        ("success", Some(data)) => Ok(data),
+        _ => bail!(
+            "Request to {url} failed: {}",
+            response.error.as_deref().unwrap_or("unknown error")
+        ),
+    }
+}
+
+#[test]
+fn test_label_report() {
+    let values: Vec<String> = ["get_user", "list_users", "handler_v2"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let report = label_report("function", &values, 100);
+    assert!(!report.exploding);
+
+    let report = label_report("function", &values, 2);
+    assert!(report.exploding);
+    assert!(report.suspicious_values.is_empty());
+
+    let values: Vec<String> = [
+        "get_user",
+        "closure_7f3a9c2b10e4",
+        "request_1699999999",
+        "f47ac10b-58cc-4372-a567-0e02b2c3d479",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect();
+    let report = label_report("caller_function", &values, 100);
+    assert!(report.exploding);
+    assert_eq!(
+        report.suspicious_values,
+        vec![
+            "closure_7f3a9c2b10e4",
+            "request_1699999999",
+            "f47ac10b-58cc-4372-a567-0e02b2c3d479"
+        ]
+    );
+}
@@ -0,0 +1,25 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+pub mod push;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct Arguments {
+    #[command(subcommand)]
+    pub command: SubCommands,
+}
+
+#[derive(Subcommand)]
+pub enum SubCommands {
+    /// Push synthetic series to the Pushgateway or Prometheus of `am start` at
+    /// a fixed interval, to test dashboards, cardinality limits and the
+    /// capacity of the machine.
+    Push(push::Arguments),
+}
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    match args.command {
+        SubCommands::Push(args) => push::handle_command(args).await,
+    }
+}
@@ -0,0 +1,254 @@
+use crate::commands::ingest::{push, remote_write};
+use crate::server::otlp::proto::{Label, Sample, TimeSeries};
+use crate::shutdown;
+use anyhow::{bail, Result};
+use clap::Parser;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::select;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+use url::Url;
+
+/// The prefix of the names of the synthetic metrics.
+const METRIC_PREFIX: &str = "am_bench_metric";
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// The amount of series that are pushed every interval.
+    #[clap(long, default_value_t = 1000)]
+    series: usize,
+
+    /// The amount of metric names the series are spread over.
+    #[clap(long, default_value_t = 10)]
+    metrics: usize,
+
+    /// How often the series are pushed.
+    #[clap(long, default_value = "1s", value_parser = humantime::parse_duration)]
+    interval: Duration,
+
+    /// Stop after this long, instead of pushing until am is stopped.
+    #[clap(long, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+
+    /// The `job` label of the series.
+    #[clap(long, default_value = "am_bench")]
+    job: String,
+
+    /// Write the series into Prometheus through its remote-write receiver,
+    /// instead of pushing them to the Pushgateway.
+    #[clap(long)]
+    remote_write: bool,
+
+    /// The Pushgateway to push the series to.
+    ///
+    /// This defaults to the Pushgateway proxy of a running `am start`.
+    #[clap(long, env, default_value = "http://127.0.0.1:6789/pushgateway")]
+    pushgateway_url: Url,
+
+    /// The Prometheus to write the series to with `--remote-write`.
+    ///
+    /// This defaults to the Prometheus proxy of a running `am start`.
+    #[clap(long, env, default_value = "http://127.0.0.1:6789/prometheus")]
+    prometheus_url: Url,
+}
+
+/// The results of the pushes so far.
+#[derive(Debug, Default)]
+struct Stats {
+    pushes: u64,
+    failures: u64,
+    samples: u64,
+    total_duration: Duration,
+    max_duration: Duration,
+}
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    if args.series == 0 || args.metrics == 0 {
+        bail!("--series and --metrics need to be at least 1");
+    }
+    if args.interval.is_zero() {
+        bail!("--interval needs to be positive");
+    }
+
+    let target = if args.remote_write {
+        "Prometheus"
+    } else {
+        "the Pushgateway"
+    };
+    info!(
+        "Pushing {} series of {} metrics to {target} every {}",
+        args.series,
+        args.metrics,
+        humantime::format_duration(args.interval)
+    );
+
+    let mut stats = Stats::default();
+    let run = run(&args, &mut stats);
+    let stop = async {
+        match args.duration {
+            Some(duration) => tokio::time::sleep(duration).await,
+            None => {
+                let signal = shutdown::signal().await;
+                info!("{signal} signal received, stopping...");
+            }
+        }
+    };
+
+    select! {
+        result = run => result?,
+        _ = stop => {}
+    }
+
+    report(&stats);
+    Ok(())
+}
+
+async fn run(args: &Arguments, stats: &mut Stats) -> Result<()> {
+    let mut interval = tokio::time::interval(args.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        let values = random_values(args.series);
+        let start = Instant::now();
+        let result = if args.remote_write {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+            remote_write(
+                &args.prometheus_url,
+                timeseries(&values, args.metrics, &args.job, timestamp),
+            )
+            .await
+        } else {
+            push(
+                &args.pushgateway_url,
+                &args.job,
+                &BTreeMap::new(),
+                render(&values, args.metrics),
+            )
+            .await
+        };
+        let duration = start.elapsed();
+
+        stats.pushes += 1;
+        stats.total_duration += duration;
+        stats.max_duration = stats.max_duration.max(duration);
+        match result {
+            Ok(()) => {
+                stats.samples += values.len() as u64;
+                debug!("Pushed {} series in {duration:?}", values.len());
+            }
+            Err(err) => {
+                stats.failures += 1;
+                warn!("Push failed after {duration:?}: {err:#}");
+            }
+        }
+
+        if duration > args.interval {
+            warn!(
+                "Pushing took {duration:?}, which is longer than the interval of {}",
+                humantime::format_duration(args.interval)
+            );
+        }
+    }
+}
+
+fn report(stats: &Stats) {
+    if stats.pushes == 0 {
+        info!("No series were pushed");
+        return;
+    }
+
+    info!(
+        "{} pushes ({} failed) with {} samples in total, taking {:?} on average and {:?} at most",
+        stats.pushes,
+        stats.failures,
+        stats.samples,
+        stats.total_duration / stats.pushes as u32,
+        stats.max_duration
+    );
+}
+
+fn random_values(series: usize) -> Vec<f64> {
+    let mut rng = rand::thread_rng();
+    (0..series).map(|_| rng.gen_range(0.0..100.0)).collect()
+}
+
+/// The metric name and the value of the `series` label of the series with
+/// `index`, which are spread evenly over `metrics` metric names.
+fn series_name(index: usize, metrics: usize) -> (String, usize) {
+    (
+        format!("{METRIC_PREFIX}_{}", index % metrics),
+        index / metrics,
+    )
+}
+
+/// The series in the Prometheus text format, which needs the series of a
+/// metric to be grouped together.
+fn render(values: &[f64], metrics: usize) -> String {
+    let mut output = String::new();
+
+    for metric in 0..metrics.min(values.len()) {
+        let _ = writeln!(output, "# TYPE {METRIC_PREFIX}_{metric} gauge");
+        for index in (metric..values.len()).step_by(metrics) {
+            let (name, series) = series_name(index, metrics);
+            let _ = writeln!(output, "{name}{{series=\"{series}\"}} {}", values[index]);
+        }
+    }
+
+    output
+}
+
+fn timeseries(values: &[f64], metrics: usize, job: &str, timestamp: i64) -> Vec<TimeSeries> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, &value)| {
+            let (name, series) = series_name(index, metrics);
+
+            // Remote-write requires the labels to be sorted by name.
+            TimeSeries {
+                labels: vec![
+                    Label {
+                        name: "__name__".to_string(),
+                        value: name,
+                    },
+                    Label {
+                        name: "job".to_string(),
+                        value: job.to_string(),
+                    },
+                    Label {
+                        name: "series".to_string(),
+                        value: series.to_string(),
+                    },
+                ],
+                samples: vec![Sample { value, timestamp }],
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_render_series() {
+    let values = [1.0, 2.0, 3.0, 4.5, 5.0];
+
+    assert_eq!(
+        render(&values, 2),
+        "# TYPE am_bench_metric_0 gauge\n\
+         am_bench_metric_0{series=\"0\"} 1\n\
+         am_bench_metric_0{series=\"1\"} 3\n\
+         am_bench_metric_0{series=\"2\"} 5\n\
+         # TYPE am_bench_metric_1 gauge\n\
+         am_bench_metric_1{series=\"0\"} 2\n\
+         am_bench_metric_1{series=\"1\"} 4.5\n"
+    );
+
+    let timeseries = timeseries(&values, 2, "am_bench", 1700000000000);
+    assert_eq!(timeseries.len(), 5);
+    assert_eq!(timeseries[3].labels[0].value, "am_bench_metric_1");
+    assert_eq!(timeseries[3].labels[2].value, "1");
+    assert_eq!(timeseries[3].samples[0].value, 4.5);
+}
@@ -0,0 +1,86 @@
+use super::Application;
+use anyhow::Result;
+use autometrics_am::config::AmConfig;
+use clap::builder::{PossibleValue, PossibleValuesParser};
+use clap::{Args, Command, CommandFactory};
+use clap_complete::Shell;
+use std::io;
+
+/// The subcommands that accept the arguments of `am start`.
+const START_COMMANDS: [&str; 2] = ["start", "demo"];
+
+#[derive(Args)]
+pub struct Arguments {
+    /// The shell to generate the completion script for.
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+pub fn handle_command(args: Arguments, config: AmConfig) -> Result<()> {
+    let mut command = with_config_values(Application::command(), &config);
+    clap_complete::generate(args.shell, &mut command, "am", &mut io::stdout());
+    Ok(())
+}
+
+/// Let the endpoints and the `--profile` of `am start` complete to the
+/// endpoints and profiles that are defined in `config`. These are only used
+/// for the completions, `am start` still accepts any endpoint.
+fn with_config_values(command: Command, config: &AmConfig) -> Command {
+    let endpoints: Vec<PossibleValue> = config
+        .endpoints
+        .iter()
+        .flatten()
+        .map(|endpoint| {
+            let value = PossibleValue::new(endpoint.url.to_string());
+            match &endpoint.job_name {
+                Some(job_name) => value.help(job_name.clone()),
+                None => value,
+            }
+        })
+        .collect();
+    let profiles: Vec<PossibleValue> = config
+        .profiles
+        .iter()
+        .flatten()
+        .map(|(name, _)| PossibleValue::new(name.clone()))
+        .collect();
+
+    START_COMMANDS.into_iter().fold(command, |command, name| {
+        command.mut_subcommand(name, |mut subcommand| {
+            if !endpoints.is_empty() {
+                subcommand = subcommand.mut_arg("metrics_endpoints", |arg| {
+                    arg.value_parser(PossibleValuesParser::new(endpoints.clone()))
+                });
+            }
+            if !profiles.is_empty() {
+                subcommand = subcommand.mut_arg("profile", |arg| {
+                    arg.value_parser(PossibleValuesParser::new(profiles.clone()))
+                });
+            }
+            subcommand
+        })
+    })
+}
+
+#[test]
+fn test_complete_config_values() {
+    let config: AmConfig = toml::from_str(
+        r#"
+        [[endpoint]]
+        url = "http://localhost:3000/metrics"
+        job-name = "api"
+
+        [profile.staging]
+        "#,
+    )
+    .unwrap();
+
+    let mut command = with_config_values(Application::command(), &config);
+    let mut output = Vec::new();
+    clap_complete::generate(Shell::Zsh, &mut command, "am", &mut output);
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.contains(r#":((http\://localhost\:3000/metrics\:"api"))'"#));
+    assert!(output.contains(":PROFILE:(staging)'"));
+    assert!(output.contains("'--prometheus-version=["));
+}
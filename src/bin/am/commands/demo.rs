@@ -0,0 +1,340 @@
+use crate::commands::start;
+use crate::metrics::{Histogram, BUCKETS};
+use anyhow::{bail, Context, Result};
+use autometrics_am::config::AmConfig;
+use axum::routing::get;
+use axum::{Router, Server};
+use clap::Parser;
+use indicatif::MultiProgress;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fmt::Write;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::select;
+use tracing::info;
+use url::Url;
+
+/// The value of the `service_name` label of the demo metrics.
+const SERVICE_NAME: &str = "am-demo";
+
+/// Latencies are capped at this many seconds, so a wide distribution doesn't
+/// leave calls running forever.
+const MAX_LATENCY: f64 = 60.0;
+
+/// A function of the demo service.
+struct Function {
+    name: &'static str,
+    module: &'static str,
+    /// The function that calls this one, if any.
+    caller: Option<&'static str>,
+}
+
+const FUNCTIONS: [Function; 5] = [
+    Function {
+        name: "handle_request",
+        module: "demo::api",
+        caller: None,
+    },
+    Function {
+        name: "get_user",
+        module: "demo::users",
+        caller: Some("handle_request"),
+    },
+    Function {
+        name: "list_orders",
+        module: "demo::orders",
+        caller: Some("handle_request"),
+    },
+    Function {
+        name: "create_order",
+        module: "demo::orders",
+        caller: Some("handle_request"),
+    },
+    Function {
+        name: "charge_card",
+        module: "demo::payments",
+        caller: Some("create_order"),
+    },
+];
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// The port on which the demo service serves its metrics.
+    #[clap(long, env, default_value_t = 3030, help_heading = "Demo options")]
+    demo_port: u16,
+
+    /// The ratio of calls that return an error, between 0 and 1.
+    #[clap(long, default_value_t = 0.05, help_heading = "Demo options")]
+    error_rate: f64,
+
+    /// The median latency of the calls.
+    #[clap(
+        long,
+        default_value = "50ms",
+        value_parser = humantime::parse_duration,
+        help_heading = "Demo options"
+    )]
+    latency_median: Duration,
+
+    /// The 99th percentile of the latency of the calls. Together with the
+    /// median this determines how widely the latencies are spread.
+    #[clap(
+        long,
+        default_value = "500ms",
+        value_parser = humantime::parse_duration,
+        help_heading = "Demo options"
+    )]
+    latency_p99: Duration,
+
+    /// How many calls the demo service makes per second.
+    #[clap(long, default_value_t = 10.0, help_heading = "Demo options")]
+    calls_per_second: f64,
+
+    #[command(flatten)]
+    start: start::CliArguments,
+}
+
+pub async fn handle_command(
+    args: Arguments,
+    config: AmConfig,
+    config_file: Option<PathBuf>,
+    mp: MultiProgress,
+) -> Result<()> {
+    if !(0.0..=1.0).contains(&args.error_rate) {
+        bail!("--error-rate needs to be between 0 and 1");
+    }
+    if args.latency_median.is_zero() || args.latency_p99 < args.latency_median {
+        bail!("--latency-median needs to be positive and at most --latency-p99");
+    }
+    if args.calls_per_second <= 0.0 {
+        bail!("--calls-per-second needs to be positive");
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", args.demo_port))
+        .with_context(|| format!("Unable to listen on port {}", args.demo_port))?;
+    listener.set_nonblocking(true)?;
+
+    let demo = Arc::new(Demo::new(&args));
+    let app = Router::new().route(
+        "/metrics",
+        get({
+            let demo = demo.clone();
+            move || async move { demo.render() }
+        }),
+    );
+    let server = Server::from_tcp(listener)?.serve(app.into_make_service());
+
+    let url = Url::parse(&format!("http://127.0.0.1:{}/metrics", args.demo_port))?;
+    info!("Demo service listening on {url}");
+
+    tokio::spawn(generate_calls(demo, args.calls_per_second));
+
+    let mut start_args = args.start;
+    start_args.add_endpoint(url, "am_demo");
+
+    select! {
+        result = server => result.context("Demo service exited with an error"),
+        result = start::handle_command(start_args, config, config_file, mp) => result,
+    }
+}
+
+/// Start a call to a random function of the demo service `calls_per_second`
+/// times per second.
+async fn generate_calls(demo: Arc<Demo>, calls_per_second: f64) {
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / calls_per_second));
+
+    loop {
+        interval.tick().await;
+
+        let demo = demo.clone();
+        tokio::spawn(async move { demo.call().await });
+    }
+}
+
+#[derive(Debug, Default)]
+struct Calls {
+    /// The calls by function and whether they succeeded.
+    totals: BTreeMap<(usize, bool), u64>,
+    /// The duration of the calls by function.
+    durations: BTreeMap<usize, Histogram>,
+    /// The calls in progress by function.
+    concurrent: BTreeMap<usize, u64>,
+}
+
+/// The state of the simulated service, of which the metrics are in the
+/// format of the autometrics libraries.
+struct Demo {
+    error_rate: f64,
+    latency_median: Duration,
+    latency_p99: Duration,
+    calls: Mutex<Calls>,
+}
+
+impl Demo {
+    fn new(args: &Arguments) -> Self {
+        Self {
+            error_rate: args.error_rate,
+            latency_median: args.latency_median,
+            latency_p99: args.latency_p99,
+            calls: Mutex::default(),
+        }
+    }
+
+    /// Simulate a call to a random function, which takes a random amount of
+    /// time and fails at the configured error rate.
+    async fn call(&self) {
+        let (function, latency, ok) = {
+            let mut rng = rand::thread_rng();
+            (
+                rng.gen_range(0..FUNCTIONS.len()),
+                self.sample_latency(&mut rng),
+                !rng.gen_bool(self.error_rate),
+            )
+        };
+
+        *self
+            .calls
+            .lock()
+            .unwrap()
+            .concurrent
+            .entry(function)
+            .or_default() += 1;
+
+        tokio::time::sleep(latency).await;
+
+        *self
+            .calls
+            .lock()
+            .unwrap()
+            .concurrent
+            .entry(function)
+            .or_default() -= 1;
+        self.record(function, ok, latency);
+    }
+
+    /// Sample a latency from a log-normal distribution with the configured
+    /// median and 99th percentile.
+    fn sample_latency(&self, rng: &mut impl Rng) -> Duration {
+        // The 99th percentile of the standard normal distribution.
+        const Z_99: f64 = 2.326;
+
+        let median = self.latency_median.as_secs_f64();
+        let sigma = (self.latency_p99.as_secs_f64() / median).ln() / Z_99;
+
+        // A standard normal sample using the Box-Muller transform. `1 - u` is
+        // used as `gen` can return 0, but not 1.
+        let (u1, u2): (f64, f64) = (rng.gen(), rng.gen());
+        let z = (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * PI * u2).cos();
+
+        Duration::from_secs_f64((median * (sigma * z).exp()).min(MAX_LATENCY))
+    }
+
+    fn record(&self, function: usize, ok: bool, latency: Duration) {
+        let mut calls = self.calls.lock().unwrap();
+        *calls.totals.entry((function, ok)).or_default() += 1;
+        calls
+            .durations
+            .entry(function)
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    /// The metrics of the demo service in the Prometheus text format.
+    fn render(&self) -> String {
+        let calls = self.calls.lock().unwrap();
+        let mut output = String::new();
+
+        let _ = writeln!(
+            output,
+            "# TYPE build_info gauge\nbuild_info{{version=\"{}\",commit=\"\",branch=\"\",service_name=\"{SERVICE_NAME}\"}} 1",
+            env!("CARGO_PKG_VERSION")
+        );
+
+        let _ = writeln!(output, "# TYPE function_calls_total counter");
+        for ((index, ok), count) in &calls.totals {
+            let function = &FUNCTIONS[*index];
+            let caller = function.caller.unwrap_or_default();
+            let caller_module = FUNCTIONS
+                .iter()
+                .find(|f| Some(f.name) == function.caller)
+                .map(|f| f.module)
+                .unwrap_or_default();
+            let result = if *ok { "ok" } else { "error" };
+            let _ = writeln!(
+                output,
+                "function_calls_total{{{},caller_function=\"{caller}\",caller_module=\"{caller_module}\",result=\"{result}\"}} {count}",
+                labels(function)
+            );
+        }
+
+        let _ = writeln!(output, "# TYPE function_calls_duration_seconds histogram");
+        for (index, histogram) in &calls.durations {
+            let labels = labels(&FUNCTIONS[*index]);
+            for (bucket, le) in histogram.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    output,
+                    "function_calls_duration_seconds_bucket{{{labels},le=\"{le}\"}} {bucket}"
+                );
+            }
+            let _ = writeln!(
+                output,
+                "function_calls_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}\n\
+                 function_calls_duration_seconds_sum{{{labels}}} {}\n\
+                 function_calls_duration_seconds_count{{{labels}}} {}",
+                histogram.count, histogram.sum, histogram.count
+            );
+        }
+
+        let _ = writeln!(output, "# TYPE function_calls_concurrent gauge");
+        for (index, count) in &calls.concurrent {
+            let _ = writeln!(
+                output,
+                "function_calls_concurrent{{{}}} {count}",
+                labels(&FUNCTIONS[*index])
+            );
+        }
+
+        output
+    }
+}
+
+/// The labels that all metrics of `function` have.
+fn labels(function: &Function) -> String {
+    format!(
+        "function=\"{}\",module=\"{}\",service_name=\"{SERVICE_NAME}\"",
+        function.name, function.module
+    )
+}
+
+#[test]
+fn test_render_demo_metrics() {
+    let demo = Demo {
+        error_rate: 0.5,
+        latency_median: Duration::from_millis(50),
+        latency_p99: Duration::from_millis(500),
+        calls: Mutex::default(),
+    };
+    demo.record(4, true, Duration::from_millis(30));
+    demo.record(4, false, Duration::from_millis(300));
+
+    let output = demo.render();
+    assert!(output.contains(
+        "function_calls_total{function=\"charge_card\",module=\"demo::payments\",service_name=\"am-demo\",caller_function=\"create_order\",caller_module=\"demo::orders\",result=\"error\"} 1\n"
+    ));
+    assert!(output.contains(
+        "function_calls_duration_seconds_bucket{function=\"charge_card\",module=\"demo::payments\",service_name=\"am-demo\",le=\"0.05\"} 1\n"
+    ));
+    assert!(output.contains(
+        "function_calls_duration_seconds_count{function=\"charge_card\",module=\"demo::payments\",service_name=\"am-demo\"} 2\n"
+    ));
+
+    let mut rng = rand::thread_rng();
+    for _ in 0..100 {
+        let latency = demo.sample_latency(&mut rng);
+        assert!(latency > Duration::ZERO && latency.as_secs_f64() <= MAX_LATENCY);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::commands::start::CLIENT;
+use crate::instances;
+use anyhow::{Context, Result};
+use clap::Parser;
+use futures_util::future::join_all;
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tracing::{debug, info};
+use url::Url;
+
+/// The paths on which frameworks commonly expose their metrics, in the order
+/// in which they are tried.
+const METRICS_PATHS: [&str; 4] = [
+    "/metrics",
+    "/api/metrics",
+    "/actuator/prometheus",
+    "/metrics/prometheus",
+];
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// How long to wait for every probed port to respond.
+    #[clap(long, default_value = "500ms", value_parser = humantime::parse_duration)]
+    timeout: Duration,
+}
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    info!("Looking for metrics endpoints on the ports that are listening locally...");
+    let endpoints = discover_endpoints(args.timeout).await?;
+
+    if endpoints.is_empty() {
+        info!("No metrics endpoints found");
+        return Ok(());
+    }
+
+    for endpoint in &endpoints {
+        info!("Found metrics endpoint {endpoint}");
+    }
+
+    let urls: Vec<_> = endpoints.iter().map(Url::to_string).collect();
+    info!(
+        "Scrape them with `am start {}` or `am start --auto-discover`",
+        urls.join(" ")
+    );
+
+    Ok(())
+}
+
+/// Probe the TCP ports that are listening on this machine for metrics in the
+/// Prometheus text format, returning a single endpoint per port. The ports
+/// of the running am instances are skipped.
+pub(crate) async fn discover_endpoints(timeout: Duration) -> Result<Vec<Url>> {
+    let mut ports = listening_ports().await?;
+    for instance in instances::running()? {
+        ports.remove(&instance.listen_address.port());
+        ports.remove(&instance.prometheus_port);
+        if let Some(port) = instance.pushgateway_port {
+            ports.remove(&port);
+        }
+        if let Some(port) = instance.alertmanager_port {
+            ports.remove(&port);
+        }
+    }
+
+    debug!(?ports, "Probing listening ports for metrics");
+
+    let probes = ports
+        .into_iter()
+        .map(|port| async move { probe_port(port, timeout).await });
+
+    Ok(join_all(probes).await.into_iter().flatten().collect())
+}
+
+async fn probe_port(port: u16, timeout: Duration) -> Option<Url> {
+    for path in METRICS_PATHS {
+        let url = Url::parse(&format!("http://localhost:{port}{path}")).ok()?;
+
+        // Ports that don't speak http fail on the first request, after which
+        // the other paths don't need to be tried.
+        let response = CLIENT.get(url.clone()).timeout(timeout).send().await.ok()?;
+        if !response.status().is_success() {
+            continue;
+        }
+
+        match response.text().await {
+            Ok(body) if looks_like_metrics(&body) => return Some(url),
+            _ => continue,
+        }
+    }
+
+    None
+}
+
+/// Whether `body` is in the Prometheus text format, as opposed to any other
+/// page that happens to be served on `/metrics`.
+pub(crate) fn looks_like_metrics(body: &str) -> bool {
+    body.lines()
+        .any(|line| line.starts_with("# TYPE ") || line.starts_with("# HELP "))
+}
+
+/// The TCP ports on which a process is listening, read from `/proc/net`.
+#[cfg(target_os = "linux")]
+async fn listening_ports() -> Result<BTreeSet<u16>> {
+    let mut ports = BTreeSet::new();
+
+    for file in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        match tokio::fs::read_to_string(file).await {
+            Ok(contents) => ports.extend(parse_proc_net_tcp(&contents)),
+            // IPv6 might be disabled.
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err).with_context(|| format!("Unable to read {file}")),
+        }
+    }
+
+    Ok(ports)
+}
+
+/// The TCP ports on which a process is listening, as reported by `lsof`.
+#[cfg(target_os = "macos")]
+async fn listening_ports() -> Result<BTreeSet<u16>> {
+    let output = tokio::process::Command::new("lsof")
+        .args(["-nP", "-iTCP", "-sTCP:LISTEN", "-Fn"])
+        .output()
+        .await
+        .context("Unable to list the listening ports with lsof")?;
+
+    // Every address is written as `n<host>:<port>`, like `n*:3000` or
+    // `n[::1]:8080`.
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix('n'))
+        .filter_map(|address| address.rsplit_once(':')?.1.parse().ok())
+        .collect())
+}
+
+/// The TCP ports on which a process is listening, as reported by `netstat`.
+#[cfg(target_os = "windows")]
+async fn listening_ports() -> Result<BTreeSet<u16>> {
+    let output = tokio::process::Command::new("netstat")
+        .args(["-an", "-p", "TCP"])
+        .output()
+        .await
+        .context("Unable to list the listening ports with netstat")?;
+
+    // The lines look like `TCP    0.0.0.0:3000    0.0.0.0:0    LISTENING`.
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.trim_end().ends_with("LISTENING"))
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter_map(|address| address.rsplit_once(':')?.1.parse().ok())
+        .collect())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+async fn listening_ports() -> Result<BTreeSet<u16>> {
+    anyhow::bail!("Discovering endpoints is not supported on this OS")
+}
+
+/// Parse the ports in the `LISTEN` state (`0A`) from the contents of
+/// `/proc/net/tcp` or `/proc/net/tcp6`. The local address is written as
+/// `<hex ip>:<hex port>`.
+#[cfg(target_os = "linux")]
+fn parse_proc_net_tcp(contents: &str) -> impl Iterator<Item = u16> + '_ {
+    contents.lines().skip(1).filter_map(|line| {
+        let mut columns = line.split_whitespace().skip(1);
+        let local_address = columns.next()?;
+        let state = columns.nth(1)?;
+        if state != "0A" {
+            return None;
+        }
+
+        let (_, port) = local_address.rsplit_once(':')?;
+        u16::from_str_radix(port, 16).ok()
+    })
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_parse_proc_net_tcp() {
+    let contents = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0BB8 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 31337 1 0000000000000000 100 0 0 10 0
+   1: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 31338 1 0000000000000000 100 0 0 10 0
+   2: 0100007F:0BB8 0100007F:D431 01 00000000:00000000 00:00000000 00000000  1000        0 31339 1 0000000000000000 20 4 30 10 -1
+";
+
+    let ports: Vec<_> = parse_proc_net_tcp(contents).collect();
+    assert_eq!(ports, vec![3000, 8080]);
+}
+
+#[test]
+fn test_looks_like_metrics() {
+    assert!(looks_like_metrics(
+        "# HELP http_requests_total The total number of requests.\n# TYPE http_requests_total counter\nhttp_requests_total 3\n"
+    ));
+    assert!(!looks_like_metrics("<html><body>Not found</body></html>"));
+}
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use clap::Parser;
+use std::time::Duration;
 use tracing::info;
 use url::Url;
 
@@ -12,15 +13,23 @@ pub struct Arguments {
     /// Which endpoint to open in the browser
     #[clap(long, env, default_value = "https://explorer.autometrics.dev/")]
     explorer_endpoint: Url,
-}
 
-pub async fn handle_command(mut args: Arguments) -> Result<()> {
-    let url = &mut args.explorer_endpoint;
+    /// Open the metrics of the function with this name.
+    #[clap(long)]
+    function: Option<String>,
 
-    if let Some(prom_url) = args.prometheus_endpoint {
-        let query = format!("prometheusUrl={}", prom_url.as_str());
-        url.set_query(Some(&query));
-    }
+    /// The module of `--function`, for functions with the same name in
+    /// several modules.
+    #[clap(long, requires = "function")]
+    module: Option<String>,
+
+    /// Show the metrics of this period up to now, like `1h` or `7d`.
+    #[clap(long, value_parser = humantime::parse_duration)]
+    time_range: Option<Duration>,
+}
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    let url = explorer_url(&args);
 
     if open::that(url.as_str()).is_err() {
         info!(
@@ -31,3 +40,64 @@ pub async fn handle_command(mut args: Arguments) -> Result<()> {
 
     Ok(())
 }
+
+/// The explorer URL with the query parameters that make it open the
+/// requested view.
+fn explorer_url(args: &Arguments) -> Url {
+    let mut url = args.explorer_endpoint.clone();
+
+    {
+        let mut query = url.query_pairs_mut();
+
+        if let Some(prom_url) = &args.prometheus_endpoint {
+            query.append_pair("prometheusUrl", prom_url.as_str());
+        }
+
+        if let Some(function) = &args.function {
+            query.append_pair("function", function);
+        }
+
+        if let Some(module) = &args.module {
+            query.append_pair("module", module);
+        }
+
+        if let Some(time_range) = args.time_range {
+            query.append_pair(
+                "timeRange",
+                &humantime::format_duration(time_range).to_string(),
+            );
+        }
+    }
+
+    // Without any parameters the query would be left as an empty `?`.
+    if url.query() == Some("") {
+        url.set_query(None);
+    }
+
+    url
+}
+
+#[test]
+fn test_explorer_url() {
+    let args = Arguments::parse_from([
+        "explore",
+        "--prometheus-endpoint",
+        "http://localhost:9090/",
+        "--function",
+        "get_user",
+        "--module",
+        "api::users",
+        "--time-range",
+        "1h",
+    ]);
+    assert_eq!(
+        explorer_url(&args).as_str(),
+        "https://explorer.autometrics.dev/?prometheusUrl=http%3A%2F%2Flocalhost%3A9090%2F&function=get_user&module=api%3A%3Ausers&timeRange=1h"
+    );
+
+    let args = Arguments::parse_from(["explore"]);
+    assert_eq!(
+        explorer_url(&args).as_str(),
+        "https://explorer.autometrics.dev/"
+    );
+}
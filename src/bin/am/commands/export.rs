@@ -0,0 +1,129 @@
+use super::grafana::DASHBOARDS_DIR;
+use super::start::AUTOMETRICS_RULES;
+use anyhow::{Context, Result};
+use autometrics_am::config::AmConfig;
+use autometrics_am::slo;
+use clap::Parser;
+use serde_yaml::{Mapping, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// The bundled rules and dashboards are written to `rules/` and `dashboards/`
+/// in the output directory. Existing files with the same name are replaced, so
+/// exporting again updates them.
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// The directory to write the rules and dashboards to.
+    output_dir: PathBuf,
+
+    /// Replace the `${DS_PROMETHEUS}` datasource input of the dashboards with
+    /// the uid of an existing Prometheus datasource.
+    ///
+    /// By default the input is kept, so Grafana asks for the datasource when
+    /// the dashboards are imported.
+    #[clap(long, env)]
+    datasource_uid: Option<String>,
+
+    /// Add a `namespace` to the rule files, as used by `mimirtool rules` and
+    /// `cortextool rules`.
+    #[clap(long, env)]
+    rules_namespace: Option<String>,
+
+    /// Only export the dashboards.
+    #[clap(long, conflicts_with = "no_dashboards")]
+    no_rules: bool,
+
+    /// Only export the rules.
+    #[clap(long)]
+    no_dashboards: bool,
+}
+
+pub fn handle_command(args: Arguments, config: AmConfig) -> Result<()> {
+    if !args.no_rules {
+        let rules_dir = args.output_dir.join("rules");
+        fs::create_dir_all(&rules_dir)
+            .with_context(|| format!("Unable to create directory {}", rules_dir.display()))?;
+
+        let rules = serde_yaml::from_str(AUTOMETRICS_RULES)?;
+        write_rules(
+            &rules_dir.join("autometrics.rules.yml"),
+            rules,
+            args.rules_namespace.as_deref(),
+        )?;
+
+        // The rules for the SLOs in `am.toml` are exported as well, since
+        // `am start` would load them next to the autometrics rules.
+        let slos = slo::load(config.slos, config.slo_file.as_deref())?;
+        if !slos.is_empty() {
+            let rules = serde_yaml::to_value(slo::generate_rules(&slos))?;
+            write_rules(
+                &rules_dir.join("am-slo.rules.yml"),
+                rules,
+                args.rules_namespace.as_deref(),
+            )?;
+        }
+    }
+
+    if !args.no_dashboards {
+        let dashboards_dir = args.output_dir.join("dashboards");
+        fs::create_dir_all(&dashboards_dir)
+            .with_context(|| format!("Unable to create directory {}", dashboards_dir.display()))?;
+
+        for file in DASHBOARDS_DIR.files() {
+            let Some(contents) = file.contents_utf8() else {
+                continue;
+            };
+
+            let file_name = file
+                .path()
+                .file_name()
+                .context("dashboard file does not have a name")?;
+
+            let contents = match &args.datasource_uid {
+                Some(uid) => contents.replace("${DS_PROMETHEUS}", uid),
+                None => contents.to_string(),
+            };
+
+            write_file(&dashboards_dir.join(file_name), &contents)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the `rules` to `path`, with the `namespace` (if any) as the first key
+/// of the file.
+fn write_rules(path: &Path, rules: Value, namespace: Option<&str>) -> Result<()> {
+    let rules = match (namespace, rules) {
+        (Some(namespace), Value::Mapping(rules)) => {
+            let mut namespaced = Mapping::new();
+            namespaced.insert("namespace".into(), namespace.into());
+            namespaced.extend(rules.into_iter().filter(|(key, _)| key != "namespace"));
+            Value::Mapping(namespaced)
+        }
+        (_, rules) => rules,
+    };
+
+    write_file(path, &serde_yaml::to_string(&rules)?)
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    fs::write(path, contents).with_context(|| format!("Unable to write {}", path.display()))?;
+    info!("Wrote {}", path.display());
+    Ok(())
+}
+
+#[test]
+fn test_write_rules_with_namespace() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rules.yml");
+    let rules = serde_yaml::from_str("groups:\n- name: example\n  rules: []\n").unwrap();
+
+    write_rules(&path, rules, Some("autometrics")).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(path).unwrap(),
+        "namespace: autometrics\ngroups:\n- name: example\n  rules: []\n"
+    );
+}
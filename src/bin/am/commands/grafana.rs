@@ -0,0 +1,306 @@
+use crate::commands::start::{determine_os_and_arch, CLIENT};
+use crate::dir::AutoCleanupDir;
+use crate::downloader::{allow_unsigned, download_file, unpack, ARCHIVE_EXTENSION};
+use crate::error::AmError;
+use crate::installs;
+use crate::shutdown;
+use crate::versions;
+use anyhow::{bail, Context, Result};
+use autometrics_am::grafana;
+use clap::Parser;
+use directories::ProjectDirs;
+use include_dir::{include_dir, Dir};
+use indicatif::MultiProgress;
+use rand::distributions::{Alphanumeric, DistString};
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::Stdio;
+use tempfile::NamedTempFile;
+use tokio::{process, select};
+use tracing::{debug, error, info};
+use url::Url;
+
+/// The dashboards that are shipped with autometrics-shared. These will be
+/// provisioned into Grafana.
+pub(crate) static DASHBOARDS_DIR: Dir<'_> =
+    include_dir!("$CARGO_MANIFEST_DIR/files/autometrics-shared/dashboards");
+
+/// The uid of the Prometheus datasource that gets provisioned. The dashboards
+/// refer to their datasource through the `${DS_PROMETHEUS}` input, which will
+/// be replaced with this uid.
+const DATASOURCE_UID: &str = "am-prometheus";
+
+pub(crate) const DEFAULT_GRAFANA_VERSION: &str = "v10.1.5";
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// The Grafana version to use. It will be downloaded if am has not
+    /// downloaded it already. This can also be `latest` or a semver range like
+    /// `^10.1`.
+    #[clap(long, env, default_value = DEFAULT_GRAFANA_VERSION)]
+    grafana_version: String,
+
+    /// The listen address for the Grafana web server.
+    #[clap(long, env, default_value = "127.0.0.1:3001")]
+    grafana_listen_address: SocketAddr,
+
+    /// The Prometheus URL that will be provisioned as the default datasource.
+    ///
+    /// This defaults to the Prometheus proxy of a running `am start`.
+    #[clap(
+        long,
+        env,
+        default_value = "http://127.0.0.1:6789/prometheus",
+        alias = "prometheus-address"
+    )]
+    prometheus_url: Url,
+
+    /// Whenever to clean up files created by Grafana after successful execution
+    #[clap(short = 'd', long, env)]
+    ephemeral: bool,
+}
+
+pub async fn handle_command(args: Arguments, mp: MultiProgress) -> Result<()> {
+    // First let's retrieve the directory for our application to store data in.
+    let project_dirs =
+        ProjectDirs::from("", "autometrics", "am").context("Unable to determine home directory")?;
+    let local_data = project_dirs.data_local_dir().to_owned();
+
+    // Make sure that the local data directory exists for our application.
+    std::fs::create_dir_all(&local_data)
+        .with_context(|| format!("Unable to create data directory: {:?}", local_data))?;
+
+    let grafana_version = versions::resolve(
+        &versions::GRAFANA,
+        &args.grafana_version,
+        &local_data,
+        false,
+    )
+    .await?;
+    let grafana_version = grafana_version.trim_start_matches('v');
+
+    info!("Using Grafana version: {}", grafana_version);
+
+    let grafana_path = local_data.join(format!("grafana-{grafana_version}"));
+
+    // Check if grafana is available
+    if !grafana_path.exists() {
+        info!("Cached version of Grafana not found, downloading Grafana");
+        install_grafana(&grafana_path, grafana_version, mp).await?;
+        debug!("Downloaded Grafana to: {:?}", &grafana_path);
+    } else {
+        debug!("Found Grafana in: {:?}", &grafana_path);
+    }
+
+    installs::mark_used(&grafana_path);
+
+    let grafana_task = start_grafana(&grafana_path, &args);
+
+    select! {
+        biased;
+
+        signal = shutdown::signal() => {
+            info!("{signal} signal received, exiting...");
+            Ok(())
+        }
+
+        Err(err) = grafana_task => {
+            bail!("Grafana exited with an error: {err:?}");
+        }
+
+        else => {
+            Ok(())
+        }
+    }
+}
+
+/// Install the specified version of Grafana into `grafana_path`.
+///
+/// Grafana is not released through GitHub, so this downloads the archive and
+/// its checksum from `dl.grafana.com` instead.
+async fn install_grafana(
+    grafana_path: &Path,
+    grafana_version: &str,
+    multi_progress: MultiProgress,
+) -> Result<()> {
+    let (os, arch) = determine_os_and_arch()?;
+    let package = format!("grafana-{grafana_version}.{os}-{arch}.{ARCHIVE_EXTENSION}");
+    let prefix = format!("grafana-v{grafana_version}/");
+    let url = format!("https://dl.grafana.com/oss/release/{package}");
+
+    let mut grafana_archive = NamedTempFile::new()?;
+
+    let calculated_checksum = download_file(
+        grafana_archive.as_file(),
+        &url,
+        &format!("Downloading {package} from dl.grafana.com"),
+        &multi_progress,
+    )
+    .await?;
+
+    let expected_checksum = CLIENT
+        .get(format!("{url}.sha256"))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    if expected_checksum.trim() != calculated_checksum {
+        error!(
+            expected_checksum = ?expected_checksum.trim(),
+            ?calculated_checksum,
+            "Calculated checksum for downloaded archive did not match expected checksum",
+        );
+        return Err(AmError::ChecksumMismatch { package }.into());
+    }
+
+    // Grafana does not publish signatures for its archives.
+    allow_unsigned(&package, "Grafana releases are not signed")?;
+
+    // Make sure we set the position to the beginning of the file so that we can
+    // unpack it.
+    grafana_archive.as_file_mut().seek(SeekFrom::Start(0))?;
+
+    unpack(
+        grafana_archive.as_file(),
+        "grafana",
+        grafana_path,
+        &prefix,
+        &multi_progress,
+    )
+    .await
+}
+
+/// Write the datasource and dashboard provisioning files into
+/// `provisioning_dir`.
+fn write_provisioning(provisioning_dir: &Path, prometheus_url: &Url) -> Result<()> {
+    let datasources_dir = provisioning_dir.join("datasources");
+    let dashboards_dir = provisioning_dir.join("dashboards");
+    let dashboard_files_dir = provisioning_dir.join("dashboard-files");
+
+    fs::create_dir_all(&datasources_dir)?;
+    fs::create_dir_all(&dashboards_dir)?;
+    fs::create_dir_all(&dashboard_files_dir)?;
+
+    let datasources = grafana::DatasourcesConfig {
+        api_version: 1,
+        datasources: vec![grafana::Datasource {
+            name: "Prometheus".to_string(),
+            kind: "prometheus".to_string(),
+            uid: DATASOURCE_UID.to_string(),
+            access: "proxy".to_string(),
+            url: prometheus_url.to_string(),
+            is_default: true,
+            editable: false,
+        }],
+    };
+
+    serde_yaml::to_writer(File::create(datasources_dir.join("am.yml"))?, &datasources)?;
+
+    let dashboards = grafana::DashboardsConfig {
+        api_version: 1,
+        providers: vec![grafana::DashboardProvider {
+            name: "autometrics".to_string(),
+            kind: "file".to_string(),
+            folder: "Autometrics".to_string(),
+            allow_ui_updates: true,
+            options: grafana::DashboardProviderOptions {
+                path: dashboard_files_dir.clone(),
+            },
+        }],
+    };
+
+    serde_yaml::to_writer(File::create(dashboards_dir.join("am.yml"))?, &dashboards)?;
+
+    for file in DASHBOARDS_DIR.files() {
+        let Some(contents) = file.contents_utf8() else {
+            continue;
+        };
+
+        let file_name = file
+            .path()
+            .file_name()
+            .context("dashboard file does not have a name")?;
+
+        debug!(?file_name, "Provisioning dashboard");
+
+        fs::write(
+            dashboard_files_dir.join(file_name),
+            contents.replace("${DS_PROMETHEUS}", DATASOURCE_UID),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Start a Grafana process. This will block until the Grafana process stops.
+async fn start_grafana(grafana_path: &Path, args: &Arguments) -> Result<()> {
+    let runtime_dir = AutoCleanupDir::new(
+        &format!(
+            "am-grafana-{}",
+            Alphanumeric.sample_string(&mut rand::thread_rng(), 6)
+        ),
+        true,
+    )?;
+
+    write_provisioning(&runtime_dir, &args.prometheus_url)?;
+
+    let work_dir = AutoCleanupDir::new("grafana", args.ephemeral)?;
+
+    #[cfg(not(target_os = "windows"))]
+    let program = "grafana";
+    #[cfg(target_os = "windows")]
+    let program = "grafana.exe";
+
+    let grafana_bin = grafana_path.join("bin").join(program);
+
+    info!("Starting Grafana on http://{}", args.grafana_listen_address);
+
+    let child = process::Command::new(grafana_bin)
+        .arg("server")
+        .arg(format!("--homepath={}", grafana_path.display()))
+        .env("GF_PATHS_PROVISIONING", runtime_dir.as_os_str())
+        .env("GF_PATHS_DATA", work_dir.as_os_str())
+        .env("GF_PATHS_LOGS", work_dir.join("logs"))
+        .env(
+            "GF_SERVER_HTTP_ADDR",
+            args.grafana_listen_address.ip().to_string(),
+        )
+        .env(
+            "GF_SERVER_HTTP_PORT",
+            args.grafana_listen_address.port().to_string(),
+        )
+        // Allow anonymous access, since this is only meant for local usage.
+        .env("GF_AUTH_ANONYMOUS_ENABLED", "true")
+        .env("GF_AUTH_ANONYMOUS_ORG_ROLE", "Admin")
+        .env("GF_AUTH_DISABLE_LOGIN_FORM", "true")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .current_dir(&work_dir)
+        .spawn()
+        .context("Unable to start Grafana")?
+        .wait_with_output()
+        .await?;
+
+    if !child.status.success() {
+        if !child.stdout.is_empty() {
+            error!("Grafana stdout:\n{}", String::from_utf8(child.stdout)?);
+        }
+
+        if !child.stderr.is_empty() {
+            error!("Grafana stderr:\n{}", String::from_utf8(child.stderr)?);
+        }
+
+        return Err(AmError::ChildCrashed {
+            process: "Grafana".to_string(),
+            status: child.status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
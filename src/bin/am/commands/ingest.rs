@@ -0,0 +1,290 @@
+use crate::commands::start::CLIENT;
+use crate::server::otlp::proto::{Label, Sample, TimeSeries, WriteRequest};
+use anyhow::{bail, Context, Result};
+use autometrics_am::parser::label_parser;
+use clap::Parser;
+use http::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use prost::Message;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::info;
+use url::Url;
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// The file with the samples in the Prometheus text format, or `-` to
+    /// read them from stdin.
+    input: PathBuf,
+
+    /// The `job` label of the samples.
+    #[clap(long, default_value = "ingest")]
+    job: String,
+
+    /// An extra label (`key=value`) for all samples. For the Pushgateway this
+    /// is part of the grouping key. Can be specified multiple times.
+    #[clap(long = "label", value_parser = label_parser)]
+    labels: Vec<(String, String)>,
+
+    /// Write the samples into Prometheus through its remote-write receiver,
+    /// instead of pushing them to the Pushgateway.
+    ///
+    /// This keeps the timestamps of the samples, which the Pushgateway
+    /// rejects.
+    #[clap(long)]
+    remote_write: bool,
+
+    /// The Pushgateway to push the samples to.
+    ///
+    /// This defaults to the Pushgateway proxy of a running `am start`.
+    #[clap(long, env, default_value = "http://127.0.0.1:6789/pushgateway")]
+    pushgateway_url: Url,
+
+    /// The Prometheus to write the samples to with `--remote-write`.
+    ///
+    /// This defaults to the Prometheus proxy of a running `am start`.
+    #[clap(long, env, default_value = "http://127.0.0.1:6789/prometheus")]
+    prometheus_url: Url,
+}
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    let input = if args.input.as_os_str() == "-" {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .context("Unable to read the samples from stdin")?;
+        input
+    } else {
+        std::fs::read_to_string(&args.input)
+            .with_context(|| format!("Unable to read {}", args.input.display()))?
+    };
+
+    let labels: BTreeMap<String, String> = args.labels.into_iter().collect();
+
+    if args.remote_write {
+        let timeseries = parse_samples(&input, &args.job, &labels)?;
+        let count = timeseries.len();
+        remote_write(&args.prometheus_url, timeseries).await?;
+        info!("Wrote {count} samples into Prometheus");
+    } else {
+        push(&args.pushgateway_url, &args.job, &labels, input).await?;
+        info!("Pushed the samples to the Pushgateway as job {}", args.job);
+    }
+
+    Ok(())
+}
+
+/// Push the metrics in `body` to the Pushgateway, which replaces the metrics
+/// with the same name in the group of `job` and `labels`.
+pub(super) async fn push(
+    pushgateway_url: &Url,
+    job: &str,
+    labels: &BTreeMap<String, String>,
+    body: String,
+) -> Result<()> {
+    let mut url = pushgateway_url.clone();
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("Invalid Pushgateway URL {pushgateway_url}"))?
+        .pop_if_empty()
+        .extend(["metrics", "job", job]);
+    for (key, value) in labels {
+        url.path_segments_mut().unwrap().extend([key, value]);
+    }
+
+    let response = CLIENT
+        .post(url)
+        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(body)
+        .send()
+        .await
+        .context("Unable to reach the Pushgateway, is `am start --pushgateway-enabled` running?")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = response.text().await.unwrap_or_default();
+        bail!(
+            "The Pushgateway rejected the samples ({status}): {}",
+            message.trim()
+        );
+    }
+
+    Ok(())
+}
+
+pub(super) async fn remote_write(prometheus_url: &Url, timeseries: Vec<TimeSeries>) -> Result<()> {
+    let body = snap::raw::Encoder::new()
+        .compress_vec(&WriteRequest { timeseries }.encode_to_vec())
+        .context("Unable to compress remote-write request")?;
+
+    let url = format!(
+        "{}/api/v1/write",
+        prometheus_url.as_str().trim_end_matches('/')
+    );
+    let response = CLIENT
+        .post(url)
+        .header(CONTENT_TYPE, "application/x-protobuf")
+        .header(CONTENT_ENCODING, "snappy")
+        .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+        .body(body)
+        .send()
+        .await
+        .context("Unable to reach Prometheus, is `am start` running?")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = response.text().await.unwrap_or_default();
+        bail!(
+            "Prometheus rejected the samples ({status}): {}",
+            message.trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Parse the samples in the Prometheus text format into a series per sample.
+/// The `job` and `extra_labels` are added, unless a sample sets them itself.
+/// Samples without a timestamp get the current time.
+fn parse_samples(
+    input: &str,
+    job: &str,
+    extra_labels: &BTreeMap<String, String>,
+) -> Result<Vec<TimeSeries>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+
+    input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            let (name, mut labels, rest) = parse_series(line)
+                .with_context(|| format!("Invalid sample on line {}: {line}", index + 1))?;
+
+            let mut values = rest.split_whitespace();
+            let value = values
+                .next()
+                .and_then(parse_value)
+                .with_context(|| format!("Invalid value on line {}: {line}", index + 1))?;
+            let timestamp = match values.next() {
+                Some(timestamp) => timestamp
+                    .parse()
+                    .with_context(|| format!("Invalid timestamp on line {}: {line}", index + 1))?,
+                None => now,
+            };
+
+            labels.insert("__name__".to_string(), name);
+            labels
+                .entry("job".to_string())
+                .or_insert_with(|| job.to_string());
+            for (key, value) in extra_labels {
+                labels.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+
+            // A BTreeMap keeps the labels sorted, as remote-write requires.
+            Ok(TimeSeries {
+                labels: labels
+                    .into_iter()
+                    .map(|(name, value)| Label { name, value })
+                    .collect(),
+                samples: vec![Sample { value, timestamp }],
+            })
+        })
+        .collect()
+}
+
+/// Split a sample line into its metric name, labels and the remainder with
+/// the value and optional timestamp.
+fn parse_series(line: &str) -> Option<(String, BTreeMap<String, String>, &str)> {
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let name = &line[..name_end];
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut labels = BTreeMap::new();
+    let mut rest = &line[name_end..];
+
+    if let Some(label_list) = rest.strip_prefix('{') {
+        rest = label_list;
+        loop {
+            rest = rest.trim_start_matches([' ', ',']);
+            if let Some(remainder) = rest.strip_prefix('}') {
+                rest = remainder;
+                break;
+            }
+
+            let (key, remainder) = rest.split_once('=')?;
+            let remainder = remainder.trim_start().strip_prefix('"')?;
+
+            // Label values are quoted, with `\\`, `\"` and `\n` escaped.
+            let mut value = String::new();
+            let mut chars = remainder.char_indices();
+            let end = loop {
+                match chars.next()? {
+                    (index, '"') => break index,
+                    (_, '\\') => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        escaped => value.push(escaped),
+                    },
+                    (_, c) => value.push(c),
+                }
+            };
+
+            labels.insert(key.trim().to_string(), value);
+            rest = &remainder[end + 1..];
+        }
+    }
+
+    Some((name.to_string(), labels, rest))
+}
+
+fn parse_value(value: &str) -> Option<f64> {
+    match value {
+        "+Inf" => Some(f64::INFINITY),
+        "-Inf" => Some(f64::NEG_INFINITY),
+        "NaN" => Some(f64::NAN),
+        value => value.parse().ok(),
+    }
+}
+
+#[test]
+fn test_parse_samples() {
+    let input = r#"
+# HELP backup_duration_seconds How long the backup took.
+# TYPE backup_duration_seconds gauge
+backup_duration_seconds 12.5
+backup_files_total{kind="full", path="C:\\backup \"daily\""} 3 1700000000000
+backup_last_success{job="nightly"} +Inf
+"#;
+    let extra_labels = BTreeMap::from([("host".to_string(), "db-1".to_string())]);
+
+    let timeseries = parse_samples(input, "backup", &extra_labels).unwrap();
+    assert_eq!(timeseries.len(), 3);
+
+    let labels = |series: &TimeSeries| -> Vec<(String, String)> {
+        series
+            .labels
+            .iter()
+            .map(|label| (label.name.clone(), label.value.clone()))
+            .collect()
+    };
+    assert_eq!(
+        labels(&timeseries[1]),
+        vec![
+            ("__name__".to_string(), "backup_files_total".to_string()),
+            ("host".to_string(), "db-1".to_string()),
+            ("job".to_string(), "backup".to_string()),
+            ("kind".to_string(), "full".to_string()),
+            ("path".to_string(), r#"C:\backup "daily""#.to_string()),
+        ]
+    );
+    assert_eq!(timeseries[1].samples[0].value, 3.0);
+    assert_eq!(timeseries[1].samples[0].timestamp, 1700000000000);
+    assert!(labels(&timeseries[2]).contains(&("job".to_string(), "nightly".to_string())));
+    assert_eq!(timeseries[2].samples[0].value, f64::INFINITY);
+
+    assert!(parse_samples("backup_files_total{kind=\"full} 3", "backup", &extra_labels).is_err());
+    assert!(parse_samples("backup_files_total three", "backup", &extra_labels).is_err());
+}
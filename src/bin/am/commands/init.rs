@@ -1,13 +1,23 @@
-use crate::interactive::{confirm, confirm_optional, user_input, user_input_optional};
+use super::discover::looks_like_metrics;
+use crate::commands::start::CLIENT;
+use crate::interactive::{
+    confirm, confirm_optional, select_all_by_default, user_input, user_input_optional,
+};
 use anyhow::{bail, Context, Result};
 use autometrics_am::config::{AmConfig, Endpoint};
+use autometrics_am::parser::endpoint_parser;
 use clap::Parser;
+use futures_util::future::join_all;
 use std::fs;
 use std::path::PathBuf;
 use std::time::Duration;
 use tracing::info;
 use url::Url;
 
+/// The ports on which applications commonly expose their metrics during local
+/// development, which are probed by `am init --autodetect`.
+const COMMON_PORTS: [u16; 10] = [3000, 3001, 4000, 5000, 8000, 8080, 8081, 8888, 9090, 9464];
+
 #[derive(Parser, Clone)]
 pub struct Arguments {
     /// Where the file should be outputted to. Defaults to current directory
@@ -17,6 +27,34 @@ pub struct Arguments {
     /// Whenever to forcefully override an existing `am.toml` file, if it already exists
     #[clap(long, env)]
     force: bool,
+
+    /// A metrics endpoint to add, in the same formats that `am start` accepts
+    /// (ie. `:3000`). Can be specified multiple times, in which case am won't
+    /// ask for more endpoints.
+    #[clap(long = "endpoint", value_parser = endpoint_parser)]
+    endpoints: Vec<Url>,
+
+    /// The default scrape interval for all endpoints, like `5s`.
+    #[clap(long, value_parser = humantime::parse_duration)]
+    scrape_interval: Option<Duration>,
+
+    /// Enable the Pushgateway.
+    #[clap(long)]
+    pushgateway_enabled: Option<bool>,
+
+    /// Enable the Alertmanager.
+    #[clap(long)]
+    alertmanager_enabled: Option<bool>,
+
+    /// Look for metrics endpoints on common local ports (3000, 8000, 8080,
+    /// 9090, ...) and offer the ones that respond as endpoints.
+    #[clap(long)]
+    autodetect: bool,
+
+    /// Don't ask anything and only use the options that are given, so the
+    /// config can be generated from a script.
+    #[clap(long, short = 'y')]
+    non_interactive: bool,
 }
 
 pub async fn handle_command(args: Arguments) -> Result<()> {
@@ -24,15 +62,54 @@ pub async fn handle_command(args: Arguments) -> Result<()> {
         bail!("Output file already exists. Supply --force to override");
     }
 
-    let mut endpoints = vec![];
+    let ask_for_endpoints = !args.non_interactive && args.endpoints.is_empty();
+    let mut endpoints: Vec<_> = args.endpoints.into_iter().map(Endpoint::new).collect();
+
+    if args.autodetect {
+        let detected = detect_endpoints().await;
 
-    while confirm("Do you want to add (more) endpoints?")? {
-        endpoints.push(prompt_endpoint()?);
+        if detected.is_empty() {
+            info!("No metrics endpoints found on the common ports");
+        } else if args.non_interactive {
+            for url in detected {
+                info!("Found metrics endpoint {url}");
+                endpoints.push(Endpoint::new(url));
+            }
+        } else {
+            let items: Vec<_> = detected.iter().map(Url::to_string).collect();
+            let selected = select_all_by_default(
+                "Which of the detected endpoints do you want to add?",
+                &items,
+            )?;
+            endpoints.extend(
+                selected
+                    .into_iter()
+                    .map(|index| Endpoint::new(detected[index].clone())),
+            );
+        }
     }
 
-    let pushgateway_enabled =
-        confirm_optional("Do you want to enable the Pushgateway (optional)?")?;
-    let scrape_interval = prompt_scrape_interval()?;
+    if ask_for_endpoints {
+        while confirm("Do you want to add (more) endpoints?")? {
+            endpoints.push(prompt_endpoint()?);
+        }
+    }
+
+    let pushgateway_enabled = match args.pushgateway_enabled {
+        Some(enabled) => Some(enabled),
+        None if args.non_interactive => None,
+        None => confirm_optional("Do you want to enable the Pushgateway (optional)?")?,
+    };
+    let alertmanager_enabled = match args.alertmanager_enabled {
+        Some(enabled) => Some(enabled),
+        None if args.non_interactive => None,
+        None => confirm_optional("Do you want to enable the Alertmanager (optional)?")?,
+    };
+    let scrape_interval = match args.scrape_interval {
+        Some(scrape_interval) => Some(scrape_interval),
+        None if args.non_interactive => None,
+        None => prompt_scrape_interval()?,
+    };
 
     let cfg = AmConfig {
         endpoints: if endpoints.is_empty() {
@@ -41,7 +118,9 @@ pub async fn handle_command(args: Arguments) -> Result<()> {
             Some(endpoints)
         },
         pushgateway_enabled,
+        alertmanager_enabled,
         prometheus_scrape_interval: scrape_interval,
+        ..Default::default()
     };
 
     let config = toml::to_string(&cfg)?;
@@ -51,6 +130,29 @@ pub async fn handle_command(args: Arguments) -> Result<()> {
     Ok(())
 }
 
+/// Probe the common ports on localhost for a `/metrics` endpoint, returning
+/// the ones that respond with metrics.
+async fn detect_endpoints() -> Vec<Url> {
+    let probes = COMMON_PORTS.iter().map(|port| async move {
+        let url = Url::parse(&format!("http://localhost:{port}/metrics")).ok()?;
+        let body = CLIENT
+            .get(url.clone())
+            .timeout(Duration::from_millis(500))
+            .send()
+            .await
+            .ok()?
+            .error_for_status()
+            .ok()?
+            .text()
+            .await
+            .ok()?;
+
+        looks_like_metrics(&body).then_some(url)
+    });
+
+    join_all(probes).await.into_iter().flatten().collect()
+}
+
 fn prompt_endpoint() -> Result<Endpoint> {
     let endpoint = user_input("Enter a metrics endpoint URL")?;
     let job_name = user_input_optional("Enter job name (optional)")?;
@@ -58,10 +160,10 @@ fn prompt_endpoint() -> Result<Endpoint> {
     let scrape_interval = prompt_scrape_interval()?;
 
     Ok(Endpoint {
-        url: Url::parse(&endpoint)?,
         job_name,
         honor_labels,
         prometheus_scrape_interval: scrape_interval,
+        ..Endpoint::new(Url::parse(&endpoint)?)
     })
 }
 
@@ -0,0 +1,372 @@
+use am_list::{FunctionInfo, Language};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// The amount of unchanged lines that are shown around every change in the
+/// diff output.
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Args)]
+pub struct Arguments {
+    /// Main directory to start the subprojects search on. Languages are
+    /// detected the same way as `am list all` does.
+    #[arg(value_name = "ROOT", default_value = ".")]
+    root: PathBuf,
+
+    /// Only instrument functions with this name. Methods are named
+    /// `Type::method` in Rust and `Class.method` in Typescript.
+    #[arg(short, long = "function", value_name = "FUNCTION")]
+    functions: Vec<String>,
+
+    /// Only instrument functions in this module.
+    #[arg(short, long = "module", value_name = "MODULE")]
+    modules: Vec<String>,
+
+    /// Instrument all functions that are not instrumented yet.
+    #[arg(long, conflicts_with_all = ["functions", "modules"])]
+    all: bool,
+
+    /// Print the changes as a diff, instead of writing them to the source
+    /// files.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+pub fn handle_command(args: Arguments) -> Result<()> {
+    if !args.all && args.functions.is_empty() && args.modules.is_empty() {
+        bail!("Select the functions to instrument with `--function` or `--module`, or use `--all`");
+    }
+
+    let projects = am_list::list_all_project_functions(&args.root)?;
+
+    let mut total = 0;
+    for (project_root, (language, functions)) in projects {
+        let mut files: BTreeMap<String, Vec<FunctionInfo>> = BTreeMap::new();
+
+        for function in functions {
+            if function.instrumentation.is_some() || !args.matches(&function) {
+                continue;
+            }
+
+            let Some(definition) = &function.definition else {
+                continue;
+            };
+
+            if language == Language::Typescript && !function.id.function.contains('.') {
+                warn!(
+                    "Skipping {}: Typescript functions need to be wrapped with `autometrics()` manually",
+                    function.id.function
+                );
+                continue;
+            }
+
+            if language == Language::Ruby {
+                warn!(
+                    "Skipping {}: Ruby methods need to be prefixed with `autometrics` manually",
+                    function.id.function
+                );
+                continue;
+            }
+
+            files
+                .entry(definition.file.clone())
+                .or_default()
+                .push(function);
+        }
+
+        for (file, functions) in files {
+            let path = project_root.join(&file);
+            let source = fs::read_to_string(&path)
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+
+            let insertions = instrument(language, &source, &functions);
+            if insertions.is_empty() {
+                continue;
+            }
+
+            total += functions.len();
+
+            if args.dry_run {
+                print!("{}", unified_diff(&path, &source, &insertions));
+            } else {
+                fs::write(&path, apply(&source, &insertions))
+                    .with_context(|| format!("Unable to write {}", path.display()))?;
+                info!(
+                    "Instrumented {} function(s) in {}",
+                    functions.len(),
+                    path.display()
+                );
+            }
+        }
+    }
+
+    if args.dry_run {
+        info!("Total: {total} functions would be instrumented");
+    } else {
+        info!("Total: {total} functions instrumented");
+        if total > 0 {
+            info!("Make sure the autometrics library is added as a dependency of your project(s)");
+        }
+    }
+
+    Ok(())
+}
+
+impl Arguments {
+    fn matches(&self, function: &FunctionInfo) -> bool {
+        if self.all {
+            return true;
+        }
+
+        let function_matches =
+            self.functions.is_empty() || self.functions.contains(&function.id.function);
+        let module_matches = self.modules.is_empty() || self.modules.contains(&function.id.module);
+
+        function_matches && module_matches
+    }
+}
+
+/// The line that marks a function as instrumented, placed right above the
+/// function definition.
+fn annotation(language: Language) -> &'static str {
+    match language {
+        Language::Rust => "#[autometrics]",
+        Language::Go => "//autometrics:inst",
+        Language::Typescript => "@Autometrics()",
+        Language::Python => "@autometrics",
+        Language::Java => "@Autometrics",
+        Language::CSharp => "[Autometrics]",
+        Language::Php => "#[Autometrics]",
+        // Ruby methods are wrapped on the line of the definition instead, which
+        // `am instrument` skips.
+        Language::Ruby => "autometrics",
+    }
+}
+
+/// The import that is needed for the annotation, together with a snippet that
+/// indicates that the import is already present.
+fn import(language: Language) -> Option<(&'static str, &'static str)> {
+    match language {
+        Language::Rust => Some(("use autometrics::autometrics;", "autometrics::autometrics")),
+        // The Go annotation is a directive for `go generate`, which doesn't
+        // require an import.
+        Language::Go => None,
+        // The package of the annotation depends on the autometrics library
+        // that is used, so the import is left to the IDE.
+        Language::Java | Language::CSharp | Language::Ruby | Language::Php => None,
+        Language::Typescript => Some((
+            "import { Autometrics } from \"@autometrics/autometrics\";",
+            "Autometrics }",
+        )),
+        Language::Python => Some((
+            "from autometrics import autometrics",
+            "from autometrics import",
+        )),
+    }
+}
+
+/// Determine the lines that need to be inserted in `source` to instrument
+/// `functions`. The result maps the (0-based) line number before which the
+/// lines need to be inserted, to the lines themselves.
+fn instrument(
+    language: Language,
+    source: &str,
+    functions: &[FunctionInfo],
+) -> BTreeMap<usize, Vec<String>> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut insertions: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+
+    for function in functions {
+        let Some(definition) = &function.definition else {
+            continue;
+        };
+
+        let line_number = definition.range.start.line;
+        let Some(line) = lines.get(line_number) else {
+            continue;
+        };
+
+        // Guard against instrumenting the same function twice, in case the
+        // annotation isn't picked up by `am_list`.
+        if line_number > 0 && lines[line_number - 1].trim() == annotation(language) {
+            continue;
+        }
+
+        let indentation = &line[..line.len() - line.trim_start().len()];
+        insertions
+            .entry(line_number)
+            .or_default()
+            .push(format!("{indentation}{}", annotation(language)));
+    }
+
+    if insertions.is_empty() {
+        return insertions;
+    }
+
+    if let Some((import, marker)) = import(language) {
+        if !source.contains(marker) {
+            let line_number = import_line(language, &lines);
+            insertions
+                .entry(line_number)
+                .or_default()
+                .insert(0, import.to_string());
+        }
+    }
+
+    insertions
+}
+
+/// The line before which a new import can be inserted. This skips over the
+/// parts of the file that need to stay at the top, like module documentation.
+fn import_line(language: Language, lines: &[&str]) -> usize {
+    let mut line_number = 0;
+
+    match language {
+        Language::Rust => {
+            while line_number < lines.len()
+                && (lines[line_number].starts_with("//!") || lines[line_number].starts_with("#!["))
+            {
+                line_number += 1;
+            }
+        }
+        Language::Python => {
+            let mut in_docstring = false;
+            while line_number < lines.len() {
+                let line = lines[line_number].trim();
+                if in_docstring {
+                    in_docstring = !(line.ends_with("\"\"\"") || line.ends_with("'''"));
+                } else if line.starts_with("\"\"\"") || line.starts_with("'''") {
+                    in_docstring = line.len() < 6
+                        || !(line[3..].ends_with("\"\"\"") || line[3..].ends_with("'''"));
+                } else if !(line.starts_with('#') || line.starts_with("from __future__")) {
+                    break;
+                }
+                line_number += 1;
+            }
+        }
+        Language::Go
+        | Language::Typescript
+        | Language::Java
+        | Language::CSharp
+        | Language::Ruby
+        | Language::Php => {}
+    }
+
+    line_number
+}
+
+/// Insert the lines into `source`.
+fn apply(source: &str, insertions: &BTreeMap<usize, Vec<String>>) -> String {
+    let mut result = String::with_capacity(source.len());
+
+    for (line_number, line) in source.lines().enumerate() {
+        for inserted in insertions.get(&line_number).into_iter().flatten() {
+            result.push_str(inserted);
+            result.push('\n');
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+
+    result
+}
+
+/// Create a unified diff of the insertions into `source`.
+fn unified_diff(path: &Path, source: &str, insertions: &BTreeMap<usize, Vec<String>>) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+
+    // Determine the ranges of the original file that are shown, merging the
+    // ranges that overlap.
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for &line_number in insertions.keys() {
+        let start = line_number.saturating_sub(DIFF_CONTEXT);
+        let end = (line_number + DIFF_CONTEXT).min(lines.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if *last_end >= start => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut diff = format!("--- {0}\n+++ {0}\n", path.display());
+    let mut offset = 0;
+
+    for (start, end) in hunks {
+        let inserted: usize = insertions
+            .range(start..end)
+            .map(|(_, lines)| lines.len())
+            .sum();
+
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            start + 1,
+            end - start,
+            start + offset + 1,
+            end - start + inserted
+        ));
+
+        for (line_number, line) in lines.iter().enumerate().take(end).skip(start) {
+            for inserted in insertions.get(&line_number).into_iter().flatten() {
+                diff.push_str(&format!("+{inserted}\n"));
+            }
+            diff.push_str(&format!(" {line}\n"));
+        }
+
+        offset += inserted;
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use am_list::Location;
+
+    fn function_at(line: usize) -> FunctionInfo {
+        let mut function = FunctionInfo::default();
+        let mut location = Location::default();
+        location.range.start.line = line;
+        function.definition = Some(location);
+        function
+    }
+
+    #[test]
+    fn instrument_rust() {
+        let source = "//! Module docs\n\nstruct Foo;\n\nimpl Foo {\n    pub fn bar() {}\n}\n";
+
+        let insertions = instrument(Language::Rust, source, &[function_at(5)]);
+
+        assert_eq!(
+            apply(source, &insertions),
+            "//! Module docs\nuse autometrics::autometrics;\n\nstruct Foo;\n\nimpl Foo {\n    #[autometrics]\n    pub fn bar() {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn instrument_python_after_docstring() {
+        let source = "\"\"\"\nModule docs\n\"\"\"\nimport os\n\ndef foo():\n    pass\n";
+
+        let insertions = instrument(Language::Python, source, &[function_at(5)]);
+
+        assert_eq!(
+            apply(source, &insertions),
+            "\"\"\"\nModule docs\n\"\"\"\nfrom autometrics import autometrics\nimport os\n\n@autometrics\ndef foo():\n    pass\n"
+        );
+    }
+
+    #[test]
+    fn diff_hunk_header() {
+        let source = "package main\n\nfunc main() {}\n";
+
+        let insertions = instrument(Language::Go, source, &[function_at(2)]);
+
+        assert_eq!(
+            unified_diff(Path::new("main.go"), source, &insertions),
+            "--- main.go\n+++ main.go\n@@ -1,3 +1,4 @@\n package main\n \n+//autometrics:inst\n func main() {}\n"
+        );
+    }
+}
@@ -1,5 +1,8 @@
-use am_list::Language;
+use am_list::{FunctionInfo, Language};
+use anyhow::{bail, Result};
 use clap::{Args, Subcommand};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use tracing::info;
 
@@ -27,34 +30,132 @@ struct SingleProject {
     /// - 'rust' or 'rs' for Rust,
     /// - 'go' for Golang,
     /// - 'typescript', 'ts', 'javascript', or 'js' for Typescript/Javascript,
-    /// - 'python' or 'py' for Python.
+    /// - 'python' or 'py' for Python,
+    /// - 'java', 'kotlin', or 'kt' for Java/Kotlin,
+    /// - 'csharp', 'cs', or 'c#' for C#,
+    /// - 'ruby' or 'rb' for Ruby,
+    /// - 'php' for PHP.
     #[arg(short, long, value_name = "LANGUAGE", verbatim_doc_comment)]
     language: Language,
     /// Root of the project to start the search on:
     /// - For Rust projects it must be where the Cargo.toml lie,
     /// - For Go projects it must be the root of the repository,
     /// - For Python projects it must be the root of the library,
-    /// - For Typescript projects it must be where the package.json lie.
+    /// - For Typescript projects it must be where the package.json lie,
+    /// - For Java/Kotlin projects it must be where the pom.xml or build.gradle lie,
+    /// - For C# projects it must be where the .csproj lie,
+    /// - For Ruby projects it must be where the Gemfile lie,
+    /// - For PHP projects it must be where the composer.json lie.
     #[arg(value_name = "ROOT", verbatim_doc_comment)]
     root: PathBuf,
     /// List all functions instead of only the autometricized ones (defaults to false)
-    #[arg(short, long, default_value = "false")]
+    #[arg(short, long, default_value = "false", conflicts_with = "diff")]
     all_functions: bool,
     /// Pretty print the resulting JSON (defaults to false)
     #[arg(short, long, default_value = "false")]
     pretty: bool,
+    #[command(flatten)]
+    diff: DiffArgs,
 }
 
 #[derive(Args)]
 struct AllProjects {
     /// Main directory to start the subprojects search on. am currently detects
-    /// Rust (Cargo.toml), Typescript (package.json), and Golang (go.mod)
-    /// projects.
+    /// Rust (Cargo.toml), Typescript (package.json), Golang (go.mod),
+    /// Java/Kotlin (pom.xml, build.gradle), C# (.csproj), Ruby (Gemfile), and
+    /// PHP (composer.json) projects.
     #[arg(value_name = "ROOT")]
     root: PathBuf,
     /// Pretty print the resulting JSON (defaults to false)
     #[arg(short, long, default_value = "false")]
     pretty: bool,
+    #[command(flatten)]
+    diff: DiffArgs,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// Compare all functions against the autometricized ones, and print the
+    /// coverage together with the functions that are not instrumented yet.
+    #[arg(long)]
+    diff: bool,
+    /// Exit with an error if less than this percentage of the functions is
+    /// autometricized, ie. to enforce the coverage in CI.
+    #[arg(long, value_name = "PERCENT", requires = "diff", value_parser = percentage_parser)]
+    fail_under: Option<f64>,
+}
+
+/// The result of `--diff`, where `uninstrumented` is either a list of
+/// functions, or the functions per project.
+#[derive(Debug, PartialEq, Serialize)]
+struct Coverage<T> {
+    instrumented: usize,
+    total: usize,
+    percentage: f64,
+    uninstrumented: T,
+}
+
+impl Coverage<Vec<FunctionInfo>> {
+    fn new(functions: Vec<FunctionInfo>) -> Self {
+        let total = functions.len();
+        let uninstrumented: Vec<FunctionInfo> = functions
+            .into_iter()
+            .filter(|function| function.instrumentation.is_none())
+            .collect();
+        let instrumented = total - uninstrumented.len();
+
+        Self {
+            instrumented,
+            total,
+            percentage: percentage(instrumented, total),
+            uninstrumented,
+        }
+    }
+}
+
+impl<T> Coverage<T> {
+    /// Log the coverage, and fail if it is below `fail_under`.
+    fn check(&self, fail_under: Option<f64>) -> Result<()> {
+        info!(
+            "Coverage: {}/{} functions autometricized ({:.1}%)",
+            self.instrumented, self.total, self.percentage
+        );
+
+        match fail_under {
+            Some(fail_under) if self.percentage < fail_under => bail!(
+                "Coverage of {:.1}% is below the required {fail_under}%",
+                self.percentage
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A project without any functions doesn't have anything left to instrument,
+/// so it is fully covered.
+fn percentage(instrumented: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        instrumented as f64 * 100.0 / total as f64
+    }
+}
+
+fn percentage_parser(arg: &str) -> Result<f64> {
+    let percentage: f64 = arg.trim_end_matches('%').parse()?;
+    if !(0.0..=100.0).contains(&percentage) {
+        bail!("percentage must be between 0 and 100");
+    }
+    Ok(percentage)
+}
+
+fn print_json(value: &impl Serialize, pretty: bool) -> Result<()> {
+    if pretty {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    } else {
+        println!("{}", serde_json::to_string(value)?);
+    }
+    Ok(())
 }
 
 pub fn handle_command(args: Arguments) -> anyhow::Result<()> {
@@ -64,16 +165,33 @@ pub fn handle_command(args: Arguments) -> anyhow::Result<()> {
     }
 }
 
-fn handle_all_projects(args: AllProjects) -> Result<(), anyhow::Error> {
+fn handle_all_projects(args: AllProjects) -> Result<()> {
     let root = args.root;
     info!("Listing functions in {}:", root.display());
     let res = am_list::list_all_project_functions(&root)?;
 
-    if args.pretty {
-        println!("{}", serde_json::to_string_pretty(&res)?);
-    } else {
-        println!("{}", serde_json::to_string(&res)?);
+    if args.diff.diff {
+        let projects: BTreeMap<PathBuf, Coverage<Vec<FunctionInfo>>> = res
+            .into_iter()
+            .map(|(path, (_, functions))| (path, Coverage::new(functions)))
+            .collect();
+        let instrumented = projects.values().map(|c| c.instrumented).sum();
+        let total = projects.values().map(|c| c.total).sum();
+        let coverage = Coverage {
+            instrumented,
+            total,
+            percentage: percentage(instrumented, total),
+            uninstrumented: projects
+                .into_iter()
+                .map(|(path, coverage)| (path, coverage.uninstrumented))
+                .collect::<BTreeMap<_, _>>(),
+        };
+
+        print_json(&coverage, args.pretty)?;
+        return coverage.check(args.diff.fail_under);
     }
+
+    print_json(&res, args.pretty)?;
     info!(
         "Total: {} functions",
         res.values().map(|list| list.1.len()).sum::<usize>()
@@ -82,18 +200,56 @@ fn handle_all_projects(args: AllProjects) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-fn handle_single_project(args: SingleProject) -> Result<(), anyhow::Error> {
+fn handle_single_project(args: SingleProject) -> Result<()> {
     let root = args.root;
     info!("Autometrics functions in {}:", root.display());
 
-    let res = am_list::list_single_project_functions(&root, args.language, args.all_functions)?;
+    if args.diff.diff {
+        let res = am_list::list_single_project_functions(&root, args.language, true)?;
+        let coverage = Coverage::new(res);
 
-    if args.pretty {
-        println!("{}", serde_json::to_string_pretty(&res)?);
-    } else {
-        println!("{}", serde_json::to_string(&res)?);
+        print_json(&coverage, args.pretty)?;
+        return coverage.check(args.diff.fail_under);
     }
+
+    let res = am_list::list_single_project_functions(&root, args.language, args.all_functions)?;
+
+    print_json(&res, args.pretty)?;
     info!("Total: {} functions", res.len());
 
     Ok(())
 }
+
+#[test]
+fn test_coverage() {
+    let function = |name: &str, instrumented: bool| FunctionInfo {
+        id: ("api", name).into(),
+        definition: None,
+        instrumentation: instrumented.then(Default::default),
+    };
+
+    let coverage = Coverage::new(vec![
+        function("get_user", true),
+        function("list_users", false),
+        function("delete_user", true),
+        function("helper", true),
+    ]);
+    assert_eq!(
+        coverage,
+        Coverage {
+            instrumented: 3,
+            total: 4,
+            percentage: 75.0,
+            uninstrumented: vec![function("list_users", false)],
+        }
+    );
+    assert!(coverage.check(None).is_ok());
+    assert!(coverage.check(Some(75.0)).is_ok());
+    assert!(coverage.check(Some(80.0)).is_err());
+
+    assert_eq!(Coverage::new(vec![]).percentage, 100.0);
+
+    assert_eq!(percentage_parser("80").unwrap(), 80.0);
+    assert_eq!(percentage_parser("92.5%").unwrap(), 92.5);
+    assert!(percentage_parser("120").is_err());
+}
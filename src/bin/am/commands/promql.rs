@@ -0,0 +1,144 @@
+use super::query::{execute, function_matchers, metric_query, render_table, tabulate, Metric};
+use anyhow::{bail, Context, Result};
+use autometrics_am::config::AmConfig;
+use autometrics_am::{promql, slo};
+use clap::{Args, Subcommand};
+use tracing::{error, info};
+use url::Url;
+
+#[derive(Args)]
+pub struct Arguments {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build the PromQL query for an autometrics metric of a function, module
+    /// or SLO.
+    Build(BuildArguments),
+
+    /// Check a PromQL query for syntax errors and common mistakes, like taking
+    /// the rate of a gauge or aggregating away the `le` label of a histogram.
+    Lint(LintArguments),
+}
+
+#[derive(Args)]
+struct BuildArguments {
+    /// The metric to build the query for.
+    #[arg(value_enum)]
+    metric: Metric,
+
+    /// Only include the calls to this function.
+    #[arg(long)]
+    function: Option<String>,
+
+    /// Only include the calls to functions in this module.
+    #[arg(long)]
+    module: Option<String>,
+
+    /// Only include the calls to the functions of this SLO, as defined in the
+    /// config file.
+    #[arg(long, conflicts_with_all = ["function", "module"])]
+    slo: Option<String>,
+
+    /// The quantile that is used for the `latency` metric.
+    #[arg(long, default_value_t = 0.95)]
+    quantile: f64,
+
+    /// The window over which the rates are calculated.
+    #[arg(long, default_value = "5m")]
+    window: String,
+
+    #[command(flatten)]
+    run: RunArguments,
+}
+
+#[derive(Args)]
+struct LintArguments {
+    /// The PromQL query to check.
+    query: String,
+
+    #[command(flatten)]
+    run: RunArguments,
+}
+
+#[derive(Args)]
+struct RunArguments {
+    /// Run the query and print the results, instead of printing the query.
+    #[arg(long)]
+    run: bool,
+
+    /// The Prometheus to run the query against.
+    ///
+    /// This defaults to the Prometheus proxy of a running `am start`.
+    #[arg(
+        long,
+        env,
+        default_value = "http://127.0.0.1:6789/prometheus",
+        alias = "prometheus-address"
+    )]
+    prometheus_url: Url,
+}
+
+pub async fn handle_command(args: Arguments, config: AmConfig) -> Result<()> {
+    match args.command {
+        Command::Build(args) => build(args, config).await,
+        Command::Lint(args) => lint(args).await,
+    }
+}
+
+async fn build(args: BuildArguments, config: AmConfig) -> Result<()> {
+    if !promql::is_valid_duration(&args.window) {
+        bail!("`{}` is not a valid PromQL duration", args.window);
+    }
+
+    let matchers = match &args.slo {
+        Some(name) => {
+            let slos = slo::load(config.slos, config.slo_file.as_deref())?;
+            let Some(slo) = slos.iter().find(|slo| &slo.name == name) else {
+                bail!("No SLO named `{name}` is defined");
+            };
+            function_matchers(slo.function.as_deref(), slo.module.as_deref())
+        }
+        None => function_matchers(args.function.as_deref(), args.module.as_deref()),
+    };
+
+    let query = metric_query(&matchers, args.metric, args.quantile, &args.window);
+    output(query, &args.run).await
+}
+
+async fn lint(args: LintArguments) -> Result<()> {
+    let lints = promql::lint(&args.query).context("Invalid PromQL query")?;
+
+    for lint in &lints {
+        error!("{lint}");
+    }
+    if !lints.is_empty() {
+        bail!("Found {} problem(s)", lints.len());
+    }
+
+    output(args.query, &args.run).await
+}
+
+/// Print `query`, or the results of running it with `--run`.
+async fn output(query: String, args: &RunArguments) -> Result<()> {
+    if !args.run {
+        println!("{query}");
+        return Ok(());
+    }
+
+    info!("Running query: {query}");
+    let data = execute(&args.prometheus_url, query, None)
+        .await
+        .context("Unable to query Prometheus, is `am start` running?")?;
+
+    let (columns, rows) = tabulate(&data);
+    if rows.is_empty() {
+        info!("The query did not return any results");
+    } else {
+        print!("{}", render_table(&columns, &rows));
+    }
+
+    Ok(())
+}
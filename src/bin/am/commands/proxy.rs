@@ -1,11 +1,26 @@
-use crate::server::start_web_server;
+use crate::server::auth::{authorization_header, AccessControl};
+use crate::server::prometheus::{self, Upstream};
+use crate::server::promql_compat::PromqlCompat;
+use crate::server::query_cache::QueryCache;
+use crate::server::tls::TlsConfig;
+use crate::server::{access_log, explorer};
+use crate::server::{bind_listeners, start_web_server};
+use crate::shutdown;
+use crate::unix_bridge;
 use anyhow::{bail, Context, Result};
+use autometrics_am::config::{AmConfig, BasicAuth, UpstreamFlavor};
+use autometrics_am::parser::{basic_auth_parser, cors_origin_parser, header_parser};
 use clap::Parser;
 use directories::ProjectDirs;
+use http::header::{HeaderName, HeaderValue};
+use http::HeaderMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::select;
 use tokio::sync::watch;
-use tracing::info;
+use tracing::{debug, info, warn};
 use url::Url;
 
 #[derive(Parser, Clone)]
@@ -13,37 +28,231 @@ pub struct CliArguments {
     /// The listen address for the web server of am.
     ///
     /// This includes am's HTTP API, the explorer and the proxy to the Prometheus, Gateway, etc.
+    /// Can be specified multiple times, and port `0` picks a free port.
     #[clap(
         short,
-        long,
-        env,
+        long = "listen-address",
+        env = "LISTEN_ADDRESS",
         default_value = "127.0.0.1:6789",
+        value_delimiter = ',',
         alias = "explorer-address"
     )]
-    listen_address: SocketAddr,
+    listen_addresses: Vec<SocketAddr>,
 
     /// The upstream Prometheus URL
     #[clap(long, env, alias = "prometheus-address")]
     prometheus_url: Option<Url>,
+
+    /// Send this token as a `Authorization: Bearer` header to the upstream
+    /// Prometheus.
+    #[clap(long, env, conflicts_with = "prometheus_basic_auth")]
+    prometheus_bearer_token: Option<String>,
+
+    /// Use these credentials (`user:pass`) for HTTP basic authentication with
+    /// the upstream Prometheus.
+    ///
+    /// Credentials that are part of the Prometheus URL are used as well.
+    #[clap(long, env, value_parser = basic_auth_parser)]
+    prometheus_basic_auth: Option<BasicAuth>,
+
+    /// Send this header with every request to the upstream Prometheus, in
+    /// the `Name: value` format. Can be specified multiple times.
+    ///
+    /// These replace the headers with the same name in the `[proxy]` section
+    /// of `am.toml`.
+    #[clap(long = "upstream-header", value_parser = header_parser)]
+    upstream_headers: Vec<(String, String)>,
+
+    /// Send this tenant as the `X-Scope-OrgID` header to the upstream, which
+    /// Cortex, Mimir and Loki use for multi-tenancy.
+    #[clap(long, env)]
+    tenant_id: Option<String>,
+
+    /// Only accept requests that send this token as a `Authorization: Bearer`
+    /// header.
+    #[clap(long, env)]
+    require_bearer_token: Option<String>,
+
+    /// Only accept requests that use these credentials (`user:pass`) for HTTP
+    /// basic authentication.
+    ///
+    /// If `--require-bearer-token` is set as well, either one is accepted.
+    #[clap(long, env, value_parser = basic_auth_parser)]
+    require_basic_auth: Option<BasicAuth>,
+
+    /// Append a line with the method, path, status and latency of every
+    /// request to this file.
+    #[clap(long, env)]
+    access_log: Option<PathBuf>,
+
+    /// Allow browsers to call am from this origin, like
+    /// `http://localhost:3000`, or from any origin with `*`. Can be specified
+    /// multiple times.
+    #[clap(
+        long = "cors-allow-origin",
+        env = "CORS_ALLOW_ORIGINS",
+        value_delimiter = ',',
+        value_parser = cors_origin_parser
+    )]
+    cors_allow_origins: Vec<String>,
+
+    /// Serve am over HTTPS. Without `--tls-cert` and `--tls-key`, a
+    /// self-signed certificate for localhost is generated.
+    #[clap(long, env)]
+    tls: bool,
+
+    /// The PEM encoded certificate to serve HTTPS with, instead of the
+    /// generated one. Implies `--tls`.
+    #[clap(long, env, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The PEM encoded private key of `--tls-cert`.
+    #[clap(long, env, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// The kind of backend of the upstream, which determines where its API is
+    /// served and which of its quirks are handled. Detected if not specified.
+    #[clap(long, env, value_enum)]
+    upstream_flavor: Option<UpstreamFlavor>,
+
+    /// The Prometheus version of the upstream, like `2.24.0`, for backends
+    /// that don't report it themselves. Queries that use PromQL functions
+    /// which this version doesn't support are rewritten.
+    #[clap(long, env)]
+    upstream_prometheus_version: Option<String>,
+
+    /// Cache the responses to the queries that are sent to the upstream
+    /// Prometheus for this long, like `30s`, which speeds up dashboards that
+    /// refresh often when the upstream is slow.
+    #[clap(long, env, value_parser = humantime::parse_duration)]
+    query_cache_ttl: Option<Duration>,
+
+    /// The maximum number of responses in the query cache.
+    #[clap(long, env, default_value_t = 1000, requires = "query_cache_ttl")]
+    query_cache_max_entries: usize,
+
+    /// Serve the explorer from this URL, like a specific version of the
+    /// explorer or a development server, or from a directory with a build of
+    /// the explorer.
+    #[clap(long, env)]
+    explorer_url: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 struct Arguments {
-    listen_address: SocketAddr,
-    prometheus_url: Option<Url>,
+    listen_addresses: Vec<SocketAddr>,
+    prometheus_upstream: Option<Upstream>,
+    /// Whether the flavor of the upstream was specified, or still needs to be
+    /// detected.
+    upstream_flavor_specified: bool,
+    access_control: AccessControl,
+    access_log: Option<PathBuf>,
+    tls: Option<TlsConfig>,
+    explorer_url: Option<String>,
 }
 
 impl Arguments {
-    fn new(args: CliArguments) -> Self {
-        Arguments {
-            listen_address: args.listen_address,
-            prometheus_url: args.prometheus_url,
+    fn new(args: CliArguments, config: AmConfig) -> Result<Self> {
+        let proxy_config = config.proxy.unwrap_or_default();
+        let tenant_id = args.tenant_id.or(proxy_config.tenant_id);
+        let upstream_flavor = args.upstream_flavor.or(proxy_config.flavor);
+
+        // The headers on the command line take precedence over the ones in
+        // the config file.
+        let mut headers = HeaderMap::new();
+        for (name, value) in proxy_config
+            .headers
+            .unwrap_or_default()
+            .into_iter()
+            .chain(tenant_id.map(|tenant_id| ("X-Scope-OrgID".to_string(), tenant_id)))
+            .chain(args.upstream_headers)
+        {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid upstream header name {name}"))?;
+            let value = HeaderValue::from_str(&value)
+                .with_context(|| format!("Invalid value for upstream header {name}"))?;
+            headers.insert(name, value);
         }
+
+        let prometheus_upstream = match args.prometheus_url {
+            Some(mut url) => {
+                // Credentials that are part of the URL are used for basic
+                // auth, unless other credentials were passed explicitly.
+                let url_basic_auth = (!url.username().is_empty()).then(|| BasicAuth {
+                    username: url.username().to_string(),
+                    password: url.password().unwrap_or_default().to_string(),
+                });
+                let _ = url.set_username("");
+                let _ = url.set_password(None);
+
+                let basic_auth = args.prometheus_basic_auth.or(url_basic_auth);
+                let authorization = authorization_header(
+                    args.prometheus_bearer_token.as_deref(),
+                    basic_auth.as_ref(),
+                )?;
+
+                let mut upstream = Upstream {
+                    url: unix_bridge::local_url(&url, "/")?,
+                    flavor: upstream_flavor.unwrap_or(UpstreamFlavor::Prometheus),
+                    authorization,
+                    headers,
+                    promql_compat: match &args.upstream_prometheus_version {
+                        Some(version) => PromqlCompat::new(version)?,
+                        None => PromqlCompat::default(),
+                    },
+                    query_cache: args
+                        .query_cache_ttl
+                        .map(|ttl| Arc::new(QueryCache::new(ttl, args.query_cache_max_entries))),
+                };
+                prometheus::set_api_path(&mut upstream);
+
+                Some(upstream)
+            }
+            None => None,
+        };
+
+        Ok(Arguments {
+            listen_addresses: args.listen_addresses,
+            prometheus_upstream,
+            upstream_flavor_specified: upstream_flavor.is_some(),
+            access_control: AccessControl {
+                bearer_token: args.require_bearer_token,
+                basic_auth: args.require_basic_auth,
+                cors_allow_origins: args.cors_allow_origins,
+            },
+            access_log: args.access_log,
+            tls: TlsConfig::new(args.tls, args.tls_cert, args.tls_key)?,
+            explorer_url: args.explorer_url,
+        })
     }
 }
 
-pub async fn handle_command(args: CliArguments) -> Result<()> {
-    let args = Arguments::new(args);
+pub async fn handle_command(args: CliArguments, config: AmConfig) -> Result<()> {
+    let detect_version = args.upstream_prometheus_version.is_none();
+    let mut args = Arguments::new(args, config)?;
+
+    if let Some(upstream) = args.prometheus_upstream.as_mut() {
+        detect_upstream(upstream, !args.upstream_flavor_specified, detect_version).await;
+    }
+
+    if !args.access_control.is_enabled() {
+        for listen_address in &args.listen_addresses {
+            if !listen_address.ip().is_loopback() {
+                warn!(
+                    "Listening on {} without authentication, use --require-bearer-token or --require-basic-auth to restrict access",
+                    listen_address
+                );
+            }
+        }
+    }
+
+    if let Some(path) = &args.access_log {
+        access_log::open(path)?;
+    }
+
+    if let Some(explorer_url) = &args.explorer_url {
+        explorer::serve_from(explorer_url)?;
+    }
 
     // First let's retrieve the directory for our application to store data in.
     let project_dirs =
@@ -54,18 +263,32 @@ pub async fn handle_command(args: CliArguments) -> Result<()> {
     std::fs::create_dir_all(&local_data)
         .with_context(|| format!("Unable to create data directory: {:?}", local_data))?;
 
+    let listeners = bind_listeners(&args.listen_addresses)?;
     let (tx, _) = watch::channel(None);
 
     // Start web server for hosting the explorer, am api and proxies to the enabled services.
     let web_server_task = async move {
-        start_web_server(&args.listen_address, false, false, args.prometheus_url, tx).await
+        start_web_server(
+            listeners,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            args.prometheus_upstream,
+            args.access_control,
+            args.tls,
+            tx,
+        )
+        .await
     };
 
     select! {
         biased;
 
-        _ = tokio::signal::ctrl_c() => {
-            info!("SIGINT signal received, exiting...");
+        signal = shutdown::signal() => {
+            info!("{signal} signal received, exiting...");
             Ok(())
         }
 
@@ -78,3 +301,115 @@ pub async fn handle_command(args: CliArguments) -> Result<()> {
         }
     }
 }
+
+/// Complete `upstream` with what it reports about itself: which kind of
+/// backend it is and, for Prometheus, its version. Older versions of
+/// Prometheus don't support all the functions that the explorer uses, so the
+/// queries are translated for them.
+async fn detect_upstream(upstream: &mut Upstream, detect_flavor: bool, detect_version: bool) {
+    // Other backends report their own version, instead of the one of the
+    // Prometheus engine that they use.
+    let detect_version =
+        detect_version && (detect_flavor || upstream.flavor == UpstreamFlavor::Prometheus);
+    if !detect_flavor && !detect_version {
+        return;
+    }
+
+    let build_info = match prometheus::build_info(upstream).await {
+        Ok(build_info) => Some(build_info),
+        Err(err) => {
+            warn!(
+                ?err,
+                "Unable to detect the upstream, use --upstream-flavor and --upstream-prometheus-version if queries fail on it"
+            );
+            None
+        }
+    };
+
+    if detect_flavor {
+        upstream.flavor = prometheus::detect_flavor(&upstream.url, build_info.as_ref());
+        prometheus::set_api_path(upstream);
+        debug!(flavor = ?upstream.flavor, "Detected the flavor of the upstream");
+    }
+
+    let Some(version) = build_info
+        .filter(|_| detect_version && upstream.flavor == UpstreamFlavor::Prometheus)
+        .map(|build_info| build_info.version)
+    else {
+        return;
+    };
+
+    match PromqlCompat::new(&version) {
+        Ok(promql_compat) => {
+            info!("Upstream reports Prometheus version {version}");
+            upstream.promql_compat = promql_compat;
+        }
+        Err(err) => {
+            warn!("{err:#}, use --upstream-prometheus-version if queries fail on the upstream")
+        }
+    }
+}
+
+#[test]
+fn test_upstream_headers() {
+    let args = CliArguments::parse_from([
+        "proxy",
+        "--prometheus-url",
+        "http://localhost:9090",
+        "--tenant-id",
+        "team-a",
+        "--upstream-header",
+        "X-Gateway-Key: from-cli",
+    ]);
+    let config: AmConfig = toml::from_str(
+        r#"
+        [proxy.headers]
+        X-Gateway-Key = "from-config"
+        X-Source = "am"
+        "#,
+    )
+    .unwrap();
+
+    let upstream = Arguments::new(args, config)
+        .unwrap()
+        .prometheus_upstream
+        .unwrap();
+    assert_eq!(upstream.headers["x-scope-orgid"], "team-a");
+    assert_eq!(upstream.headers["x-gateway-key"], "from-cli");
+    assert_eq!(upstream.headers["x-source"], "am");
+}
+
+#[test]
+fn test_upstream_flavor() {
+    let args = CliArguments::parse_from([
+        "proxy",
+        "--prometheus-url",
+        "http://vmselect:8481",
+        "--upstream-flavor",
+        "victoriametrics",
+        "--tenant-id",
+        "42",
+    ]);
+    let upstream = Arguments::new(args, AmConfig::default())
+        .unwrap()
+        .prometheus_upstream
+        .unwrap();
+    assert_eq!(
+        upstream.url.as_str(),
+        "http://vmselect:8481/select/42/prometheus/"
+    );
+
+    // A path in the URL is used as is.
+    let args = CliArguments::parse_from([
+        "proxy",
+        "--prometheus-url",
+        "http://mimir:8080/api/prom/",
+        "--upstream-flavor",
+        "mimir",
+    ]);
+    let upstream = Arguments::new(args, AmConfig::default())
+        .unwrap()
+        .prometheus_upstream
+        .unwrap();
+    assert_eq!(upstream.url.as_str(), "http://mimir:8080/api/prom/");
+}
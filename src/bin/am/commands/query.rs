@@ -0,0 +1,421 @@
+use crate::commands::start::CLIENT;
+use anyhow::{bail, Context, Result};
+use autometrics_am::promql;
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::info;
+use url::Url;
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// The PromQL query to run.
+    #[clap(required_unless_present = "function", conflicts_with = "function")]
+    query: Option<String>,
+
+    /// Query an autometrics metric of this function, instead of passing a
+    /// PromQL query.
+    #[clap(long, help_heading = "Autometrics options")]
+    function: Option<String>,
+
+    /// Only include the function from this module.
+    #[clap(long, requires = "function", help_heading = "Autometrics options")]
+    module: Option<String>,
+
+    /// The metric to query for `--function`.
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Metric::CallRate,
+        help_heading = "Autometrics options"
+    )]
+    metric: Metric,
+
+    /// The quantile that is used for `--metric latency`.
+    #[clap(long, default_value_t = 0.95, help_heading = "Autometrics options")]
+    quantile: f64,
+
+    /// The window over which the rates for `--function` are calculated.
+    #[clap(long, default_value = "5m", help_heading = "Autometrics options")]
+    window: String,
+
+    /// Run a range query over this duration (ie. `1h`) up until now, instead
+    /// of an instant query.
+    #[clap(long, value_parser = humantime::parse_duration)]
+    range: Option<Duration>,
+
+    /// The resolution of a range query. Defaults to a 60th of the range.
+    #[clap(long, requires = "range", value_parser = humantime::parse_duration)]
+    step: Option<Duration>,
+
+    /// How the results are printed.
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+
+    /// The Prometheus to query.
+    ///
+    /// This defaults to the Prometheus proxy of a running `am start`.
+    #[clap(
+        long,
+        env,
+        default_value = "http://127.0.0.1:6789/prometheus",
+        alias = "prometheus-address"
+    )]
+    prometheus_url: Url,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub(super) enum Metric {
+    /// The amount of calls per second.
+    CallRate,
+    /// The ratio of calls that returned an error.
+    ErrorRate,
+    /// The ratio of calls that returned successfully.
+    SuccessRate,
+    /// The latency of the calls in seconds, at `--quantile`.
+    Latency,
+    /// The amount of calls that are in progress.
+    Concurrency,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+struct QueryResponse {
+    status: String,
+    data: Option<QueryData>,
+    error: Option<String>,
+}
+
+/// The result of a query, in the format of the Prometheus HTTP API.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "resultType", content = "result", rename_all = "lowercase")]
+pub(super) enum QueryData {
+    Vector(Vec<Sample>),
+    Matrix(Vec<Series>),
+    Scalar(SamplePair),
+    String(SamplePair),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct Sample {
+    pub metric: BTreeMap<String, String>,
+    pub value: SamplePair,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct Series {
+    pub metric: BTreeMap<String, String>,
+    pub values: Vec<SamplePair>,
+}
+
+/// A unix timestamp in seconds and a value, which Prometheus encodes as a
+/// string.
+pub(super) type SamplePair = (f64, String);
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    let query = match (&args.query, &args.function) {
+        (Some(query), _) => query.clone(),
+        (None, Some(function)) => {
+            if !promql::is_valid_duration(&args.window) {
+                bail!("`{}` is not a valid PromQL duration", args.window);
+            }
+            function_query(
+                function,
+                args.module.as_deref(),
+                args.metric,
+                args.quantile,
+                &args.window,
+            )
+        }
+        // Enforced by clap.
+        (None, None) => unreachable!(),
+    };
+
+    info!("Running query: {query}");
+
+    let range = args
+        .range
+        .map(|range| (range, args.step.unwrap_or(range / 60)));
+    let data = execute(&args.prometheus_url, query, range).await?;
+
+    match args.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&data)?),
+        OutputFormat::Csv => {
+            let (columns, rows) = tabulate(&data);
+            print!("{}", render_csv(&columns, &rows));
+        }
+        OutputFormat::Table => {
+            let (columns, rows) = tabulate(&data);
+            if rows.is_empty() {
+                info!("The query did not return any results");
+            } else {
+                print!("{}", render_table(&columns, &rows));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Run `query` against the Prometheus at `prometheus_url`. This is an instant
+/// query, unless a `range` and step are passed, in which case the range ends
+/// now.
+pub(super) async fn execute(
+    prometheus_url: &Url,
+    query: String,
+    range: Option<(Duration, Duration)>,
+) -> Result<QueryData> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+    let (path, params) = match range {
+        Some((range, step)) => {
+            let step = step.max(Duration::from_secs(1));
+            (
+                "api/v1/query_range",
+                vec![
+                    ("query", query),
+                    ("start", (now - range.as_secs_f64()).to_string()),
+                    ("end", now.to_string()),
+                    ("step", step.as_secs_f64().to_string()),
+                ],
+            )
+        }
+        None => (
+            "api/v1/query",
+            vec![("query", query), ("time", now.to_string())],
+        ),
+    };
+
+    // Make sure the path of the Prometheus URL is kept.
+    let mut url = prometheus_url.clone();
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    let url = url.join(path)?;
+
+    // Prometheus returns a JSON body with the error for invalid queries, so
+    // the status code is only checked if the body is not a valid response.
+    let response = CLIENT
+        .get(url.clone())
+        .query(&params)
+        .send()
+        .await
+        .with_context(|| format!("Unable to reach Prometheus at {prometheus_url}"))?;
+    let status = response.status();
+    let body = response.text().await?;
+
+    let response: QueryResponse = serde_json::from_str(&body)
+        .with_context(|| format!("Unexpected response from {url} ({status}): {body}"))?;
+
+    match (response.status.as_str(), response.data) {
+        ("success", Some(data)) => Ok(data),
+        _ => bail!(
+            "Query failed: {}",
+            response.error.as_deref().unwrap_or("unknown error")
+        ),
+    }
+}
+
+/// Build the PromQL query for an autometrics `metric` of `function`.
+fn function_query(
+    function: &str,
+    module: Option<&str>,
+    metric: Metric,
+    quantile: f64,
+    window: &str,
+) -> String {
+    let matchers = function_matchers(Some(function), module);
+    metric_query(&matchers, metric, quantile, window)
+}
+
+/// The label matchers that select the calls to `function` in `module`.
+pub(super) fn function_matchers(function: Option<&str>, module: Option<&str>) -> Vec<String> {
+    let function = function.map(|function| format!("function=\"{}\"", escape(function)));
+    let module = module.map(|module| format!("module=\"{}\"", escape(module)));
+    function.into_iter().chain(module).collect()
+}
+
+/// Build the PromQL query for an autometrics `metric`, aggregated by function
+/// and module, of the functions that match all `matchers`.
+pub(super) fn metric_query(
+    matchers: &[String],
+    metric: Metric,
+    quantile: f64,
+    window: &str,
+) -> String {
+    let selector = |extra: Option<&str>| {
+        let matchers: Vec<&str> = matchers.iter().map(String::as_str).chain(extra).collect();
+        matchers.join(",")
+    };
+    let calls = |extra: Option<&str>| {
+        format!(
+            "sum by (function, module) (rate(function_calls_total{{{}}}[{window}]))",
+            selector(extra)
+        )
+    };
+
+    match metric {
+        Metric::CallRate => calls(None),
+        Metric::ErrorRate => format!("{} / {}", calls(Some("result=\"error\"")), calls(None)),
+        Metric::SuccessRate => format!("{} / {}", calls(Some("result=\"ok\"")), calls(None)),
+        Metric::Latency => format!(
+            "histogram_quantile({quantile}, sum by (le, function, module) (rate(function_calls_duration_seconds_bucket{{{}}}[{window}])))",
+            selector(None)
+        ),
+        Metric::Concurrency => format!(
+            "sum by (function, module) (function_calls_concurrent{{{}}})",
+            selector(None)
+        ),
+    }
+}
+
+/// Escape `value` for use in a double quoted PromQL string.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Turn the result of a query into a header and rows, with a column for every
+/// label that is used in the result. A time column is only added for range
+/// queries, which return multiple values per series.
+pub(super) fn tabulate(data: &QueryData) -> (Vec<String>, Vec<Vec<String>>) {
+    let with_time = matches!(data, QueryData::Matrix(_));
+    let series: Vec<(&BTreeMap<String, String>, &[SamplePair])> = match data {
+        QueryData::Vector(samples) => samples
+            .iter()
+            .map(|sample| (&sample.metric, std::slice::from_ref(&sample.value)))
+            .collect(),
+        QueryData::Matrix(series) => series
+            .iter()
+            .map(|series| (&series.metric, series.values.as_slice()))
+            .collect(),
+        QueryData::Scalar(value) | QueryData::String(value) => {
+            return (vec!["value".to_string()], vec![vec![value.1.clone()]]);
+        }
+    };
+
+    let labels: BTreeSet<&String> = series
+        .iter()
+        .flat_map(|(metric, _)| metric.keys())
+        .collect();
+
+    let mut columns: Vec<String> = labels.iter().map(|label| label.to_string()).collect();
+    if with_time {
+        columns.push("time".to_string());
+    }
+    columns.push("value".to_string());
+
+    let mut rows = vec![];
+    for (metric, values) in series {
+        for (timestamp, value) in values {
+            let mut row: Vec<String> = labels
+                .iter()
+                .map(|label| metric.get(*label).cloned().unwrap_or_default())
+                .collect();
+            if with_time {
+                let time = UNIX_EPOCH + Duration::from_secs_f64(*timestamp);
+                row.push(humantime::format_rfc3339_seconds(time).to_string());
+            }
+            row.push(value.clone());
+            rows.push(row);
+        }
+    }
+
+    (columns, rows)
+}
+
+pub(super) fn render_table(columns: &[String], rows: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            rows.iter()
+                .map(|row| row[index].chars().count())
+                .chain([column.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut output = String::new();
+    for row in std::iter::once(columns).chain(rows.iter().map(Vec::as_slice)) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        output.push_str(line.join("  ").trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+fn render_csv(columns: &[String], rows: &[Vec<String>]) -> String {
+    let mut output = String::new();
+
+    for row in std::iter::once(columns).chain(rows.iter().map(Vec::as_slice)) {
+        let line: Vec<String> = row
+            .iter()
+            .map(|cell| {
+                if cell.contains([',', '"', '\n']) {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell.clone()
+                }
+            })
+            .collect();
+        output.push_str(&line.join(","));
+        output.push('\n');
+    }
+
+    output
+}
+
+#[test]
+fn test_function_query() {
+    assert_eq!(
+        function_query("handler", Some("api"), Metric::ErrorRate, 0.95, "5m"),
+        "sum by (function, module) (rate(function_calls_total{function=\"handler\",module=\"api\",result=\"error\"}[5m])) / sum by (function, module) (rate(function_calls_total{function=\"handler\",module=\"api\"}[5m]))"
+    );
+    assert_eq!(
+        function_query("handler", None, Metric::Latency, 0.99, "1h"),
+        "histogram_quantile(0.99, sum by (le, function, module) (rate(function_calls_duration_seconds_bucket{function=\"handler\"}[1h])))"
+    );
+    assert_eq!(
+        metric_query(
+            &function_matchers(None, Some("api")),
+            Metric::Concurrency,
+            0.95,
+            "5m"
+        ),
+        "sum by (function, module) (function_calls_concurrent{module=\"api\"})"
+    );
+}
+
+#[test]
+fn test_tabulate_and_render() {
+    let data: QueryData = serde_json::from_str(
+        r#"{"resultType":"vector","result":[
+            {"metric":{"function":"handler","module":"api"},"value":[1700000000,"0.5"]},
+            {"metric":{"function":"list, all"},"value":[1700000000,"12"]}
+        ]}"#,
+    )
+    .unwrap();
+
+    let (columns, rows) = tabulate(&data);
+
+    assert_eq!(
+        render_table(&columns, &rows),
+        "function   module  value\nhandler    api     0.5\nlist, all          12\n"
+    );
+    assert_eq!(
+        render_csv(&columns, &rows),
+        "function,module,value\nhandler,api,0.5\n\"list, all\",,12\n"
+    );
+}
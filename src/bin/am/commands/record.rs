@@ -0,0 +1,163 @@
+use super::query::{execute, QueryData, Series};
+use crate::commands::start::CLIENT;
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+use url::Url;
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// The file to write the recording to, in the OpenMetrics text format.
+    #[clap(default_value = "am-recording.om")]
+    output: PathBuf,
+
+    /// Record the samples of this duration (ie. `30m`) up until now.
+    #[clap(long, default_value = "1h", value_parser = humantime::parse_duration)]
+    range: Duration,
+
+    /// The Prometheus to record the samples from.
+    ///
+    /// This defaults to the Prometheus proxy of a running `am start`.
+    #[clap(
+        long,
+        env,
+        default_value = "http://127.0.0.1:6789/prometheus",
+        alias = "prometheus-address"
+    )]
+    prometheus_url: Url,
+}
+
+#[derive(Deserialize)]
+struct LabelValuesResponse {
+    status: String,
+    #[serde(default)]
+    data: Vec<String>,
+    error: Option<String>,
+}
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+    let metric_names = metric_names(&args.prometheus_url, now - args.range.as_secs_f64()).await?;
+
+    let file = File::create(&args.output)
+        .with_context(|| format!("Unable to create {}", args.output.display()))?;
+    let mut writer = BufWriter::new(file);
+
+    // A range vector selector returns the raw samples, instead of the
+    // evaluated ones that a range query would return.
+    let mut samples = 0;
+    for name in &metric_names {
+        let query = format!("{{__name__=\"{name}\"}}[{}s]", args.range.as_secs());
+        let QueryData::Matrix(series) = execute(&args.prometheus_url, query, None).await? else {
+            bail!("Prometheus returned an unexpected result for {name}");
+        };
+
+        debug!(name, series = series.len(), "Recording metric");
+        for series in &series {
+            samples += series.values.len();
+            write_series(&mut writer, name, series)?;
+        }
+    }
+
+    writeln!(writer, "# EOF")?;
+    writer.flush()?;
+
+    info!(
+        "Recorded {samples} samples of {} metrics to {}",
+        metric_names.len(),
+        args.output.display()
+    );
+    info!("Use `am replay {}` to explore them", args.output.display());
+
+    Ok(())
+}
+
+/// The names of all metrics that have samples after `start`.
+async fn metric_names(prometheus_url: &Url, start: f64) -> Result<Vec<String>> {
+    // Make sure the path of the Prometheus URL is kept.
+    let mut url = prometheus_url.clone();
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    let url = url.join("api/v1/label/__name__/values")?;
+
+    let response: LabelValuesResponse = CLIENT
+        .get(url)
+        .query(&[("start", start.to_string())])
+        .send()
+        .await
+        .with_context(|| format!("Unable to reach Prometheus at {prometheus_url}"))?
+        .json()
+        .await
+        .context("Unexpected response from Prometheus")?;
+
+    if response.status != "success" {
+        bail!(
+            "Unable to list the metrics: {}",
+            response.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+
+    Ok(response.data)
+}
+
+/// Write the samples of `series` in the OpenMetrics text format, which
+/// `promtool tsdb create-blocks-from openmetrics` can import.
+fn write_series(writer: &mut impl Write, name: &str, series: &Series) -> Result<()> {
+    let labels: Vec<String> = series
+        .metric
+        .iter()
+        .filter(|(label, _)| *label != "__name__")
+        .map(|(label, value)| format!("{label}=\"{}\"", escape(value)))
+        .collect();
+    let labels = if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    };
+
+    for (timestamp, value) in &series.values {
+        writeln!(writer, "{name}{labels} {value} {timestamp:.3}")?;
+    }
+
+    Ok(())
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[test]
+fn test_write_series() {
+    let series = Series {
+        metric: [
+            ("__name__", "function_calls_total"),
+            ("function", "handler"),
+            ("module", "api \"v2\""),
+        ]
+        .into_iter()
+        .map(|(label, value)| (label.to_string(), value.to_string()))
+        .collect(),
+        values: vec![
+            (1700000000.0, "3".to_string()),
+            (1700000015.5, "NaN".to_string()),
+        ],
+    };
+
+    let mut output = vec![];
+    write_series(&mut output, "function_calls_total", &series).unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "function_calls_total{function=\"handler\",module=\"api \\\"v2\\\"\"} 3 1700000000.000\n\
+         function_calls_total{function=\"handler\",module=\"api \\\"v2\\\"\"} NaN 1700000015.500\n"
+    );
+}
@@ -0,0 +1,277 @@
+use crate::commands::start::{install_prometheus, DEFAULT_PROMETHEUS_VERSION};
+use crate::error::AmError;
+use crate::installs;
+use crate::server::auth::AccessControl;
+use crate::server::{bind_listeners, start_web_server};
+use crate::shutdown;
+use crate::versions;
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use directories::ProjectDirs;
+use indicatif::MultiProgress;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::sync::watch;
+use tokio::{process, select};
+use tracing::{debug, error, info};
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// The recording that was created by `am record`.
+    recording: PathBuf,
+
+    /// The Prometheus version to use. It will be downloaded if am has not
+    /// downloaded it already. This can also be `latest` or a semver range like
+    /// `^2.48`.
+    #[clap(long, env, default_value = DEFAULT_PROMETHEUS_VERSION)]
+    prometheus_version: String,
+
+    /// The port on which Prometheus listens.
+    #[clap(long, env, default_value_t = 9090)]
+    prometheus_port: u16,
+
+    /// The listen address for the web server of am.
+    #[clap(
+        short,
+        long,
+        env,
+        default_value = "127.0.0.1:6789",
+        alias = "explorer-address"
+    )]
+    listen_address: SocketAddr,
+
+    /// Store the imported data in this directory, instead of a temporary
+    /// directory that is removed afterwards.
+    #[clap(long, env)]
+    data_dir: Option<PathBuf>,
+}
+
+pub async fn handle_command(args: Arguments, mp: MultiProgress) -> Result<()> {
+    let Some((start, end)) = time_range(&args.recording)? else {
+        bail!("{} does not contain any samples", args.recording.display());
+    };
+
+    // First let's retrieve the directory for our application to store data in.
+    let project_dirs =
+        ProjectDirs::from("", "autometrics", "am").context("Unable to determine home directory")?;
+    let local_data = project_dirs.data_local_dir().to_owned();
+
+    // Make sure that the local data directory exists for our application.
+    fs::create_dir_all(&local_data)
+        .with_context(|| format!("Unable to create data directory: {:?}", local_data))?;
+
+    let prometheus_version = versions::resolve(
+        &versions::PROMETHEUS,
+        &args.prometheus_version,
+        &local_data,
+        false,
+    )
+    .await?;
+    let prometheus_version = prometheus_version.trim_start_matches('v');
+    let prometheus_path = local_data.join(format!("prometheus-{prometheus_version}"));
+
+    if !prometheus_path.exists() {
+        info!("Cached version of Prometheus not found, downloading Prometheus");
+        install_prometheus(&prometheus_path, prometheus_version, mp).await?;
+        debug!("Downloaded Prometheus to: {:?}", &prometheus_path);
+    }
+
+    installs::mark_used(&prometheus_path);
+
+    let temp_dir;
+    let data_dir = match &args.data_dir {
+        Some(data_dir) => {
+            fs::create_dir_all(data_dir)?;
+            data_dir.clone()
+        }
+        None => {
+            temp_dir = tempfile::tempdir()?;
+            temp_dir.path().to_owned()
+        }
+    };
+
+    create_blocks(&prometheus_path, &args.recording, &data_dir).await?;
+
+    info!(
+        "The recording contains samples from {} to {}",
+        humantime::format_rfc3339_seconds(UNIX_EPOCH + start),
+        humantime::format_rfc3339_seconds(UNIX_EPOCH + end)
+    );
+
+    let (tx, rx) = watch::channel(None);
+
+    let web_server_task = start_web_server(
+        bind_listeners(std::slice::from_ref(&args.listen_address))?,
+        Some(args.prometheus_port),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        AccessControl::default(),
+        None,
+        tx,
+    );
+
+    let prometheus_task = async {
+        // Wait for the web server, so Prometheus knows its external URL.
+        let listen_address = rx.clone().wait_for(Option::is_some).await?.unwrap();
+        start_prometheus(&prometheus_path, &data_dir, &args, listen_address).await
+    };
+
+    select! {
+        biased;
+
+        signal = shutdown::signal() => {
+            info!("{signal} signal received, exiting...");
+            Ok(())
+        }
+
+        Err(err) = prometheus_task => {
+            bail!("Prometheus exited with an error: {err:?}");
+        }
+
+        Err(err) = web_server_task => {
+            bail!("Web server exited with an error: {err:?}");
+        }
+
+        else => {
+            Ok(())
+        }
+    }
+}
+
+/// Import the recording into TSDB blocks in `data_dir`.
+async fn create_blocks(prometheus_path: &Path, recording: &Path, data_dir: &Path) -> Result<()> {
+    #[cfg(not(target_os = "windows"))]
+    let program = "promtool";
+    #[cfg(target_os = "windows")]
+    let program = "promtool.exe";
+
+    info!("Importing {}", recording.display());
+
+    let output = process::Command::new(prometheus_path.join(program))
+        .args(["tsdb", "create-blocks-from", "openmetrics"])
+        .arg(recording)
+        .arg(data_dir)
+        .output()
+        .await
+        .context("Unable to run promtool")?;
+
+    if !output.status.success() {
+        bail!(
+            "promtool was unable to import the recording:\n{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    debug!("{}", String::from_utf8_lossy(&output.stdout));
+    Ok(())
+}
+
+async fn start_prometheus(
+    prometheus_path: &Path,
+    data_dir: &Path,
+    args: &Arguments,
+    listen_address: SocketAddr,
+) -> Result<()> {
+    #[cfg(not(target_os = "windows"))]
+    let program = "prometheus";
+    #[cfg(target_os = "windows")]
+    let program = "prometheus.exe";
+
+    // Nothing is scraped while replaying, so an empty config is enough.
+    let config_file = data_dir.join("prometheus.yml");
+    fs::write(&config_file, "")?;
+
+    let child = process::Command::new(prometheus_path.join(program))
+        .arg(format!("--config.file={}", config_file.display()))
+        .arg(format!("--storage.tsdb.path={}", data_dir.display()))
+        .arg(format!("--web.listen-address=:{}", args.prometheus_port))
+        .arg(format!(
+            "--web.external-url=http://{listen_address}/prometheus"
+        ))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Unable to start Prometheus")?
+        .wait_with_output()
+        .await?;
+
+    if !child.status.success() {
+        if !child.stderr.is_empty() {
+            error!(
+                "Prometheus stderr:\n{}",
+                String::from_utf8_lossy(&child.stderr)
+            );
+        }
+
+        return Err(AmError::ChildCrashed {
+            process: "Prometheus".to_string(),
+            status: child.status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// The timestamps of the first and last sample in the recording.
+fn time_range(recording: &Path) -> Result<Option<(Duration, Duration)>> {
+    let file =
+        File::open(recording).with_context(|| format!("Unable to open {}", recording.display()))?;
+
+    let mut range: Option<(f64, f64)> = None;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let Some(timestamp) = line
+            .rsplit(' ')
+            .next()
+            .and_then(|timestamp| timestamp.parse::<f64>().ok())
+        else {
+            continue;
+        };
+
+        range = Some(match range {
+            Some((start, end)) => (start.min(timestamp), end.max(timestamp)),
+            None => (timestamp, timestamp),
+        });
+    }
+
+    Ok(range.map(|(start, end)| {
+        (
+            Duration::from_secs_f64(start.max(0.0)),
+            Duration::from_secs_f64(end.max(0.0)),
+        )
+    }))
+}
+
+#[test]
+fn test_time_range() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(
+        &mut file,
+        b"up{job=\"api\"} 1 1700000015.000\nup{job=\"api\"} 1 1700000000.000\nup{job=\"web\"} 0 1700000030.500\n# EOF\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        time_range(file.path()).unwrap(),
+        Some((
+            Duration::from_secs(1700000000),
+            Duration::from_secs_f64(1700000030.5)
+        ))
+    );
+}
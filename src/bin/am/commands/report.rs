@@ -0,0 +1,300 @@
+use super::query::{execute, metric_query, render_table, Metric, QueryData};
+use am_list::{FunctionId, Language};
+use anyhow::{bail, Context, Result};
+use autometrics_am::config::AmConfig;
+use autometrics_am::promql;
+use autometrics_am::slo::{self, SloDefinition};
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use tracing::info;
+use url::Url;
+
+/// The window of the recorded SLO error ratio that is compared against the
+/// error budget.
+const SLO_WINDOW: &str = "1h";
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// Root of the project. Subprojects are detected the same way as
+    /// `am list all` does, unless `--language` is used.
+    #[clap(default_value = ".")]
+    root: PathBuf,
+
+    /// Only look for functions of this language, with ROOT as the root of the
+    /// project. See `am list single` for the valid values.
+    #[clap(short, long)]
+    language: Option<Language>,
+
+    /// The window over which the rates are calculated.
+    #[clap(long, default_value = "5m")]
+    window: String,
+
+    /// How the report is printed.
+    #[clap(short, long, value_enum, default_value_t = ReportFormat::Table)]
+    format: ReportFormat,
+
+    /// The Prometheus to query.
+    ///
+    /// This defaults to the Prometheus proxy of a running `am start`.
+    #[clap(
+        long,
+        env,
+        default_value = "http://127.0.0.1:6789/prometheus",
+        alias = "prometheus-address"
+    )]
+    prometheus_url: Url,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Table,
+    Json,
+    Markdown,
+}
+
+#[derive(Debug, Serialize)]
+struct FunctionReport {
+    function: String,
+    module: String,
+    calls_per_second: Option<f64>,
+    error_ratio: Option<f64>,
+    latency_p95_seconds: Option<f64>,
+    latency_p99_seconds: Option<f64>,
+    slos: Vec<SloStatus>,
+}
+
+#[derive(Debug, Serialize)]
+struct SloStatus {
+    name: String,
+    objective: f64,
+    /// The ratio of failed calls over the last hour, as recorded by the rules
+    /// that `am start` loads for the SLO.
+    error_ratio: Option<f64>,
+    met: Option<bool>,
+}
+
+pub async fn handle_command(args: Arguments, config: AmConfig) -> Result<()> {
+    if !promql::is_valid_duration(&args.window) {
+        bail!("`{}` is not a valid PromQL duration", args.window);
+    }
+
+    let functions = find_functions(&args)?;
+    if functions.is_empty() {
+        info!("No autometrics functions found in {}", args.root.display());
+        return Ok(());
+    }
+
+    let slos = slo::load(config.slos, config.slo_file.as_deref())?;
+
+    let prometheus_url = &args.prometheus_url;
+    let query = |metric, quantile| {
+        let query = metric_query(&[], metric, quantile, &args.window);
+        async move {
+            let data = execute(prometheus_url, query, None)
+                .await
+                .context("Unable to query Prometheus, is `am start` running?")?;
+            anyhow::Ok(by_function(data))
+        }
+    };
+
+    let calls = query(Metric::CallRate, 0.0).await?;
+    let errors = query(Metric::ErrorRate, 0.0).await?;
+    let p95 = query(Metric::Latency, 0.95).await?;
+    let p99 = query(Metric::Latency, 0.99).await?;
+
+    let slo_error_ratios = if slos.is_empty() {
+        HashMap::new()
+    } else {
+        let data = execute(
+            &args.prometheus_url,
+            format!("slo:sli_error:ratio_rate{SLO_WINDOW}"),
+            None,
+        )
+        .await?;
+        by_label(data, "slo")
+    };
+
+    let reports: Vec<FunctionReport> = functions
+        .into_iter()
+        .map(|id| FunctionReport {
+            calls_per_second: calls.get(&id).copied(),
+            error_ratio: errors.get(&id).copied(),
+            latency_p95_seconds: p95.get(&id).copied(),
+            latency_p99_seconds: p99.get(&id).copied(),
+            slos: slos
+                .iter()
+                .filter(|slo| slo.matches(&id.function, &id.module))
+                .map(|slo| slo_status(slo, slo_error_ratios.get(&slo.name).copied()))
+                .collect(),
+            function: id.function,
+            module: id.module,
+        })
+        .collect();
+
+    match args.format {
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&reports)?),
+        ReportFormat::Table => {
+            let (columns, rows) = tabulate(&reports);
+            print!("{}", render_table(&columns, &rows));
+        }
+        ReportFormat::Markdown => {
+            let (columns, rows) = tabulate(&reports);
+            print!("{}", render_markdown(&columns, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the autometrics functions in the project(s).
+fn find_functions(args: &Arguments) -> Result<BTreeSet<FunctionId>> {
+    let projects = match args.language {
+        Some(language) => vec![(args.root.clone(), language)],
+        None => am_list::find_project_roots(&args.root)?,
+    };
+
+    let mut functions = BTreeSet::new();
+    for (root, language) in projects {
+        functions.extend(
+            am_list::list_single_project_functions(&root, language, false)?
+                .into_iter()
+                .map(|info| info.id),
+        );
+    }
+
+    Ok(functions)
+}
+
+fn slo_status(slo: &SloDefinition, error_ratio: Option<f64>) -> SloStatus {
+    SloStatus {
+        name: slo.name.clone(),
+        objective: slo.objective,
+        error_ratio,
+        met: error_ratio.map(|ratio| ratio <= slo.error_budget()),
+    }
+}
+
+/// The value of every series in the result, keyed by the function and module.
+/// Missing and `NaN` values (ie. for a function without any calls) are left
+/// out.
+fn by_function(data: QueryData) -> HashMap<FunctionId, f64> {
+    samples(data)
+        .filter_map(|(metric, value)| {
+            let function = metric.get("function")?;
+            let module = metric.get("module").map_or("", String::as_str);
+            Some(((module, function).into(), value))
+        })
+        .collect()
+}
+
+fn by_label(data: QueryData, label: &str) -> HashMap<String, f64> {
+    samples(data)
+        .filter_map(|(metric, value)| Some((metric.get(label)?.clone(), value)))
+        .collect()
+}
+
+fn samples(data: QueryData) -> impl Iterator<Item = (BTreeMap<String, String>, f64)> {
+    let samples = match data {
+        QueryData::Vector(samples) => samples,
+        _ => vec![],
+    };
+
+    samples.into_iter().filter_map(|sample| {
+        let value: f64 = sample.value.1.parse().ok()?;
+        value.is_finite().then_some((sample.metric, value))
+    })
+}
+
+fn tabulate(reports: &[FunctionReport]) -> (Vec<String>, Vec<Vec<String>>) {
+    let columns = [
+        "function", "module", "calls/s", "errors", "p95", "p99", "SLOs",
+    ]
+    .map(String::from)
+    .to_vec();
+
+    let rows = reports
+        .iter()
+        .map(|report| {
+            let slos: Vec<String> = report
+                .slos
+                .iter()
+                .map(|slo| {
+                    let status = match slo.met {
+                        Some(true) => "met",
+                        Some(false) => "missed",
+                        None => "no data",
+                    };
+                    format!("{} ({status})", slo.name)
+                })
+                .collect();
+
+            vec![
+                report.function.clone(),
+                report.module.clone(),
+                format_value(report.calls_per_second, |value| format!("{value:.2}")),
+                format_value(report.error_ratio, |value| format!("{:.2}%", value * 100.0)),
+                format_value(report.latency_p95_seconds, format_latency),
+                format_value(report.latency_p99_seconds, format_latency),
+                slos.join(", "),
+            ]
+        })
+        .collect();
+
+    (columns, rows)
+}
+
+fn format_value(value: Option<f64>, format: impl Fn(f64) -> String) -> String {
+    value.map_or_else(|| "-".to_string(), format)
+}
+
+fn format_latency(seconds: f64) -> String {
+    if seconds < 1.0 {
+        format!("{:.1}ms", seconds * 1000.0)
+    } else {
+        format!("{seconds:.2}s")
+    }
+}
+
+fn render_markdown(columns: &[String], rows: &[Vec<String>]) -> String {
+    let line = |cells: &[String]| format!("| {} |\n", cells.join(" | "));
+
+    let mut output = line(columns);
+    output.push_str(&line(&vec!["---".to_string(); columns.len()]));
+    for row in rows {
+        output.push_str(&line(row));
+    }
+
+    output
+}
+
+#[test]
+fn test_report_rows() {
+    let slo = SloDefinition {
+        name: "api".to_string(),
+        objective: 99.0,
+        function: Some("handler".to_string()),
+        module: None,
+        latency_threshold: None,
+        description: None,
+    };
+    let report = FunctionReport {
+        function: "handler".to_string(),
+        module: "api".to_string(),
+        calls_per_second: Some(12.5),
+        error_ratio: Some(0.015),
+        latency_p95_seconds: Some(0.0421),
+        latency_p99_seconds: None,
+        slos: vec![slo_status(&slo, Some(0.015))],
+    };
+
+    let (columns, rows) = tabulate(&[report]);
+
+    assert_eq!(
+        render_markdown(&columns, &rows),
+        "| function | module | calls/s | errors | p95 | p99 | SLOs |\n\
+         | --- | --- | --- | --- | --- | --- | --- |\n\
+         | handler | api | 12.50 | 1.50% | 42.1ms | - | api (missed) |\n"
+    );
+}
@@ -0,0 +1,166 @@
+use crate::commands::start::{install_prometheus, DEFAULT_PROMETHEUS_VERSION};
+use crate::installs;
+use crate::versions;
+use anyhow::{bail, Context, Result};
+use autometrics_am::rules;
+use clap::{Args, Subcommand};
+use directories::ProjectDirs;
+use indicatif::MultiProgress;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::process;
+use tracing::{debug, error, info};
+
+#[derive(Args)]
+pub struct Arguments {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check the syntax of the rules, their PromQL expressions and labels, and
+    /// look for duplicate rules.
+    Check(CheckArguments),
+
+    /// Run the unit tests for rules in the format of `promtool test rules`:
+    /// input series and the alerts and recording rule results that are
+    /// expected at certain times.
+    Test(TestArguments),
+}
+
+#[derive(Args)]
+struct CheckArguments {
+    /// The rule files to check.
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+struct TestArguments {
+    /// The test files to run.
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// The version of Prometheus whose promtool evaluates the rules. It will
+    /// be downloaded if am has not downloaded it already. This can also be
+    /// `latest` or a semver range like `^2.48`.
+    #[arg(long, env, default_value = DEFAULT_PROMETHEUS_VERSION)]
+    prometheus_version: String,
+}
+
+pub async fn handle_command(args: Arguments, mp: MultiProgress) -> Result<()> {
+    match args.command {
+        Command::Check(args) => check(args),
+        Command::Test(args) => test(args, mp).await,
+    }
+}
+
+fn check(args: CheckArguments) -> Result<()> {
+    let mut problems = 0;
+
+    for path in &args.files {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read rule file {}", path.display()))?;
+
+        let report = match rules::check(&contents) {
+            Ok(report) => report,
+            Err(err) => {
+                error!("{}: {err}", path.display());
+                problems += 1;
+                continue;
+            }
+        };
+
+        if report.problems.is_empty() {
+            info!("{}: {} rule(s) found", path.display(), report.rules);
+        }
+
+        for problem in &report.problems {
+            error!("{}: {problem}", path.display());
+        }
+        problems += report.problems.len();
+    }
+
+    if problems > 0 {
+        bail!("Found {problems} problem(s)");
+    }
+
+    Ok(())
+}
+
+async fn test(args: TestArguments, mp: MultiProgress) -> Result<()> {
+    let project_dirs =
+        ProjectDirs::from("", "autometrics", "am").context("Unable to determine home directory")?;
+    let local_data = project_dirs.data_local_dir().to_owned();
+
+    fs::create_dir_all(&local_data)
+        .with_context(|| format!("Unable to create data directory: {:?}", local_data))?;
+
+    let prometheus_version = versions::resolve(
+        &versions::PROMETHEUS,
+        &args.prometheus_version,
+        &local_data,
+        false,
+    )
+    .await?;
+    let prometheus_version = prometheus_version.trim_start_matches('v');
+    let prometheus_path = local_data.join(format!("prometheus-{prometheus_version}"));
+
+    if !prometheus_path.exists() {
+        info!("Cached version of Prometheus not found, downloading Prometheus");
+        install_prometheus(&prometheus_path, prometheus_version, mp).await?;
+        debug!("Downloaded Prometheus to: {:?}", &prometheus_path);
+    }
+
+    installs::mark_used(&prometheus_path);
+
+    let mut failed = 0;
+    for path in &args.files {
+        if !run_tests(&prometheus_path, path).await? {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {} test file(s) failed", args.files.len());
+    }
+
+    Ok(())
+}
+
+/// Run the tests in `path` with the promtool of the Prometheus in
+/// `prometheus_path`, and report whether they passed. The rule files are
+/// resolved relative to the test file by promtool.
+async fn run_tests(prometheus_path: &Path, path: &Path) -> Result<bool> {
+    #[cfg(not(target_os = "windows"))]
+    let program = "promtool";
+    #[cfg(target_os = "windows")]
+    let program = "promtool.exe";
+
+    if !path.exists() {
+        bail!("Test file {} does not exist", path.display());
+    }
+
+    let output = process::Command::new(prometheus_path.join(program))
+        .args(["test", "rules"])
+        .arg(path)
+        .output()
+        .await
+        .context("Unable to run promtool")?;
+
+    debug!("{}", String::from_utf8_lossy(&output.stdout));
+
+    if output.status.success() {
+        info!("{}: passed", path.display());
+        return Ok(true);
+    }
+
+    error!(
+        "{}: failed\n{}{}",
+        path.display(),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(false)
+}
@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use autometrics_am::config::AmConfig;
+use autometrics_am::slo;
+use clap::{Args, Subcommand};
+use std::path::PathBuf;
+use tracing::info;
+
+/// Service Level Objectives are read from the `[[slo]]` tables in `am.toml`
+/// and from the file passed with `--slo-file` (or `slo-file` in `am.toml`),
+/// which contains a `slos` list with the same fields.
+#[derive(Args)]
+pub struct Arguments {
+    #[command(subcommand)]
+    command: Command,
+
+    /// A YAML file with Service Level Objectives.
+    #[arg(long, env, global = true)]
+    slo_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the Service Level Objectives that are defined.
+    List,
+
+    /// Generate the Prometheus recording and alerting rules for the Service
+    /// Level Objectives. `am start` loads these rules automatically.
+    Generate(GenerateArguments),
+}
+
+#[derive(Args)]
+struct GenerateArguments {
+    /// Write the rules to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub fn handle_command(args: Arguments, config: AmConfig) -> Result<()> {
+    let slo_file = args.slo_file.or(config.slo_file);
+    let slos = slo::load(config.slos, slo_file.as_deref())?;
+
+    match args.command {
+        Command::List => {
+            if slos.is_empty() {
+                info!("No SLOs are defined");
+            }
+
+            for slo in &slos {
+                let kind = match slo.latency_threshold {
+                    Some(threshold) => format!("latency below {threshold}s"),
+                    None => "success rate".to_string(),
+                };
+                let function = slo.function.as_deref().unwrap_or("*");
+                let module = slo.module.as_deref().unwrap_or("*");
+                info!(
+                    "{}: {}% {kind} (function: {function}, module: {module})",
+                    slo.name, slo.objective
+                );
+            }
+        }
+        Command::Generate(generate) => {
+            let rules = serde_yaml::to_string(&slo::generate_rules(&slos))?;
+
+            match generate.output {
+                Some(path) => {
+                    std::fs::write(&path, rules)
+                        .with_context(|| format!("Unable to write {}", path.display()))?;
+                    info!(
+                        "Wrote rules for {} SLO(s) to {}",
+                        slos.len(),
+                        path.display()
+                    );
+                }
+                None => print!("{rules}"),
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,42 +1,115 @@
+use super::discover;
+use crate::daemon;
 use crate::dir::AutoCleanupDir;
-use crate::downloader::{download_github_release, unpack, verify_checksum};
+use crate::downloader::{install_release_archive, ARCHIVE_EXTENSION, SHA256SUMS_FILE};
+use crate::error::AmError;
+use crate::installs;
+use crate::instances::{self, Instance};
 use crate::interactive;
-use crate::server::start_web_server;
+use crate::logs;
+use crate::network;
+use crate::server::access_log;
+use crate::server::auth::AccessControl;
+use crate::server::tls::TlsConfig;
+use crate::server::{bind_listeners, explorer, functions, metrics, start_web_server};
+use crate::shutdown;
+use crate::status::{self, ProcessState, STATUS};
+use crate::targets::TARGETS;
+use crate::unix_bridge;
+use crate::versions;
 use anyhow::{anyhow, bail, Context, Result};
-use autometrics_am::config::{endpoints_from_first_input, AmConfig};
-use autometrics_am::parser::endpoint_parser;
+use app::App;
+use autometrics_am::alertmanager;
+use autometrics_am::config::{
+    endpoints_from_first_input, AmConfig, BasicAuth, ContainerMetrics, FederateConfig,
+    GraphiteMapping, MetricRelabelConfig, RemoteWriteConfig, RestartPolicy, WebAuth,
+};
+use autometrics_am::downsampling;
+use autometrics_am::parser::{
+    basic_auth_parser, cors_origin_parser, endpoint_parser, label_parser, prometheus_arg_parser,
+    prometheus_feature_parser, web_auth_parser,
+};
 use autometrics_am::prometheus;
-use autometrics_am::prometheus::ScrapeConfig;
+use autometrics_am::prometheus::{ScrapeConfig, ScrapeProtocol};
+use autometrics_am::slo::{self, SloDefinition};
+use blackbox::Probe;
 use clap::Parser;
 use directories::ProjectDirs;
+use docker::DockerDiscovery;
+use futures_util::future::FusedFuture;
 use futures_util::FutureExt;
 use indicatif::MultiProgress;
+use kubernetes::{resolve_kubeconfig, KubernetesDiscovery};
 use once_cell::sync::Lazy;
+use port_forward::PortForward;
 use rand::distributions::{Alphanumeric, DistString};
+use semver_rs::Version;
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{Seek, SeekFrom};
+use std::future::Future;
+use std::io::Write;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use std::{env, fs, vec};
+use supervisor::Supervisor;
 use tempfile::NamedTempFile;
+use tokio::select;
 use tokio::sync::watch;
 use tokio::sync::watch::Receiver;
-use tokio::{process, select};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use url::Url;
 
+mod app;
+mod blackbox;
+mod container_metrics;
+mod docker;
+mod feature_flags;
+pub(crate) mod gateway;
+pub(crate) mod graphite;
+pub(crate) mod host_metrics;
+mod kubernetes;
+pub(crate) mod loki;
+mod mdns;
+pub(crate) mod notifications;
+mod otel_collector;
+mod port_forward;
+mod reload;
+pub(crate) mod statsd;
+mod supervisor;
+pub(crate) mod tempo;
+
 // Create a reqwest client that will be used to make HTTP requests. This allows
 // for keep-alives if we are making multiple requests to the same host.
 pub(crate) static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
-    reqwest::Client::builder()
-        .user_agent(concat!("am/", env!("CARGO_PKG_VERSION")))
-        .connect_timeout(Duration::from_secs(5))
+    network::client_builder()
         .build()
         .expect("Unable to create reqwest client")
 });
 
+pub(crate) const DEFAULT_PROMETHEUS_VERSION: &str = "v2.45.0";
+pub(crate) const DEFAULT_PUSHGATEWAY_VERSION: &str = "v1.6.0";
+pub(crate) const DEFAULT_ALERTMANAGER_VERSION: &str = "v0.26.0";
+pub(crate) const DEFAULT_OTEL_COLLECTOR_VERSION: &str = "v0.88.0";
+pub(crate) const DEFAULT_BLACKBOX_VERSION: &str = "v0.24.0";
+
+/// The recording and alerting rules that are shipped with autometrics-shared.
+pub(crate) const AUTOMETRICS_RULES: &str =
+    include_str!("../../../../files/autometrics-shared/autometrics.rules.yml");
+
+const DEFAULT_PROMETHEUS_PORT: u16 = 9090;
+const DEFAULT_PUSHGATEWAY_PORT: u16 = 9091;
+const DEFAULT_ALERTMANAGER_PORT: u16 = 9093;
+const DEFAULT_LISTEN_PORT: u16 = 6789;
+
+/// The job that scrapes the targets from [`FILE_SD_TARGETS_FILE`].
+const FILE_SD_JOB_NAME: &str = "am_file_sd";
+
+/// The file with the targets for file-based service discovery. It is written
+/// next to the Prometheus config, which is where Prometheus looks for it.
+const FILE_SD_TARGETS_FILE: &str = "targets.json";
+
 #[derive(Parser, Clone)]
 pub struct CliArguments {
     /// The endpoint(s) that Prometheus will scrape.
@@ -50,15 +123,85 @@ pub struct CliArguments {
     #[clap(value_parser = endpoint_parser, verbatim_doc_comment)]
     metrics_endpoints: Vec<Url>,
 
-    /// The Prometheus version to use. It will be downloaded if am has not
-    /// downloaded it already.
+    /// Send this token as a `Authorization: Bearer` header when scraping the
+    /// endpoint(s).
+    ///
+    /// This applies to all endpoints that don't specify their own credentials.
     #[clap(
         long,
         env,
-        default_value = "v2.45.0",
-        help_heading = "Prometheus options"
+        conflicts_with = "basic_auth",
+        help_heading = "Endpoint options"
     )]
-    prometheus_version: String,
+    bearer_token: Option<String>,
+
+    /// Use these credentials (`user:pass`) for HTTP basic authentication when
+    /// scraping the endpoint(s).
+    ///
+    /// This applies to all endpoints that don't specify their own credentials.
+    #[clap(long, env, value_parser = basic_auth_parser, help_heading = "Endpoint options")]
+    basic_auth: Option<BasicAuth>,
+
+    /// Disable validation of the server certificate of the endpoint(s).
+    #[clap(long, env, help_heading = "Endpoint options")]
+    tls_skip_verify: bool,
+
+    /// The CA certificate used to validate the server certificate of the
+    /// endpoint(s).
+    #[clap(long, env, help_heading = "Endpoint options")]
+    ca_cert: Option<PathBuf>,
+
+    /// How long a scrape of the endpoint(s) may take, ie. `5s`. This can't be
+    /// longer than the scrape interval.
+    ///
+    /// This applies to all endpoints that don't specify their own timeout.
+    #[clap(long, env, value_parser = humantime::parse_duration, help_heading = "Endpoint options")]
+    scrape_timeout: Option<Duration>,
+
+    /// Fail scrapes of the endpoint(s) that return more samples than this, so
+    /// a misbehaving target can't flood Prometheus.
+    ///
+    /// This applies to all endpoints that don't specify their own limit.
+    #[clap(long, env, help_heading = "Endpoint options")]
+    sample_limit: Option<u64>,
+
+    /// Fail scrapes of the endpoint(s) in which a sample has more labels than
+    /// this.
+    ///
+    /// This applies to all endpoints that don't specify their own limit.
+    #[clap(long, env, help_heading = "Endpoint options")]
+    label_limit: Option<u64>,
+
+    /// Whether Prometheus uses the timestamps that the endpoint(s) expose,
+    /// instead of the time of the scrape. Defaults to `true`.
+    ///
+    /// This applies to all endpoints that don't specify it themselves.
+    #[clap(long, env, help_heading = "Endpoint options")]
+    honor_timestamps: Option<bool>,
+
+    /// Wait up to this long for the endpoint(s) to respond before starting
+    /// Prometheus, ie. `2m` when am is started before the application.
+    ///
+    /// By default the endpoints are only checked once.
+    #[clap(long, env, value_parser = humantime::parse_duration, help_heading = "Endpoint options")]
+    wait_for_endpoints: Option<Duration>,
+
+    /// Look for metrics endpoints on the ports that are listening locally, like
+    /// `am discover`, and offer to scrape them as well.
+    ///
+    /// With `--ci` all of the discovered endpoints are scraped.
+    #[clap(long, env, help_heading = "Endpoint options")]
+    auto_discover: bool,
+
+    /// The Prometheus version to use. It will be downloaded if am has not
+    /// downloaded it already.
+    ///
+    /// Besides an exact version, this can be `latest` or a semver range like
+    /// `^2.48`, which get resolved with the releases on GitHub.
+    ///
+    /// Defaults to `v2.45.0`.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    prometheus_version: Option<String>,
 
     /// The default scrape interval for all Prometheus jobs.
     ///
@@ -66,17 +209,132 @@ pub struct CliArguments {
     #[clap(long, env, help_heading = "Prometheus options", value_parser = humantime::parse_duration)]
     scrape_interval: Option<Duration>,
 
+    /// The port on which Prometheus listens. Use `0` to pick a free port.
+    ///
+    /// Defaults to `9090`.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    prometheus_port: Option<u16>,
+
     /// The listen address for the web server of am.
     ///
     /// This includes am's HTTP API, the explorer and the proxy to the Prometheus, Gateway, etc.
+    /// Can be specified multiple times, in which case Prometheus and the other
+    /// processes use the first one, and port `0` picks a free port.
     #[clap(
         short,
-        long,
-        env,
+        long = "listen-address",
+        env = "LISTEN_ADDRESS",
         default_value = "127.0.0.1:6789",
+        value_delimiter = ',',
         alias = "explorer-address"
     )]
-    listen_address: SocketAddr,
+    listen_addresses: Vec<SocketAddr>,
+
+    /// Append a line with the method, path, status and latency of every
+    /// request to the web server of am to this file.
+    #[clap(long, env)]
+    access_log: Option<PathBuf>,
+
+    /// Allow browsers to call the web server of am from this origin, like
+    /// `http://localhost:3000`, or from any origin with `*`. Can be specified
+    /// multiple times, and replaces the `cors-allow-origins` of the config
+    /// file.
+    #[clap(
+        long = "cors-allow-origin",
+        env = "CORS_ALLOW_ORIGINS",
+        value_delimiter = ',',
+        value_parser = cors_origin_parser
+    )]
+    cors_allow_origins: Vec<String>,
+
+    /// Require these credentials for every request to the web server of am,
+    /// in the `token:<secret>` format for a bearer token or the
+    /// `basic:<user>:<pass>` format for HTTP basic authentication.
+    ///
+    /// The health check on `/api/health` is always allowed.
+    #[clap(long, env, value_parser = web_auth_parser)]
+    web_auth: Option<WebAuth>,
+
+    /// Serve the web server of am over HTTPS, which is needed to embed the
+    /// explorer in pages that require a secure context. Without `--tls-cert`
+    /// and `--tls-key`, a self-signed certificate for localhost is generated.
+    #[clap(long, env)]
+    tls: bool,
+
+    /// The PEM encoded certificate to serve HTTPS with, instead of the
+    /// generated one. Implies `--tls`.
+    #[clap(long, env, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The PEM encoded private key of `--tls-cert`.
+    #[clap(long, env, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Serve the assets of the explorer that were downloaded with
+    /// `am system download-explorer`, instead of loading them from
+    /// explorer.autometrics.dev.
+    #[clap(long, env)]
+    explorer_offline: bool,
+
+    /// Serve the explorer from this URL, like a specific version of the
+    /// explorer or a development server, or from a directory with a build of
+    /// the explorer.
+    #[clap(long, env, conflicts_with = "explorer_offline")]
+    explorer_url: Option<String>,
+
+    /// Announce the web server of am through mDNS as a `_autometrics._tcp`
+    /// service, so it can be discovered on the local network. This requires a
+    /// listen address other than localhost.
+    #[clap(long, env)]
+    mdns: bool,
+
+    /// Forward all scraped metrics to this remote write URL, such as Grafana
+    /// Cloud, Mimir or Thanos.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    remote_write_url: Option<Url>,
+
+    /// Send this token as a `Authorization: Bearer` header to the remote write
+    /// URL.
+    #[clap(
+        long,
+        env,
+        conflicts_with = "remote_write_basic_auth",
+        help_heading = "Prometheus options"
+    )]
+    remote_write_bearer_token: Option<String>,
+
+    /// Use these credentials (`user:pass`) for HTTP basic authentication with
+    /// the remote write URL.
+    #[clap(long, env, value_parser = basic_auth_parser, help_heading = "Prometheus options")]
+    remote_write_basic_auth: Option<BasicAuth>,
+
+    /// A label that Prometheus adds to everything it remote writes, ie.
+    /// `--external-label env=dev`, so the data can be attributed to this
+    /// environment. Can be used multiple times.
+    #[clap(
+        long = "external-label",
+        value_name = "KEY=VALUE",
+        value_parser = label_parser,
+        help_heading = "Prometheus options"
+    )]
+    external_labels: Vec<(String, String)>,
+
+    /// A format in which Prometheus asks the endpoints for their metrics, in
+    /// order of preference. Can be used multiple times.
+    ///
+    /// This requires Prometheus 2.49 or newer.
+    #[clap(
+        long = "scrape-protocol",
+        value_enum,
+        help_heading = "Prometheus options"
+    )]
+    scrape_protocols: Vec<ScrapeProtocol>,
+
+    /// Let Prometheus ingest native histograms, by starting it with
+    /// `--enable-feature=native-histograms`. These are scraped in the protobuf
+    /// format.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    native_histograms: bool,
 
     /// Enable pushgateway.
     ///
@@ -87,14 +345,333 @@ pub struct CliArguments {
     #[clap(short, long, env, help_heading = "Pushgateway options")]
     pushgateway_enabled: Option<bool>,
 
-    /// The pushgateway version to use.
+    /// The pushgateway version to use. Like the Prometheus version, this can
+    /// be `latest` or a semver range.
+    ///
+    /// Defaults to `v1.6.0`.
+    #[clap(long, env, help_heading = "Pushgateway options")]
+    pushgateway_version: Option<String>,
+
+    /// The port on which the pushgateway listens. Use `0` to pick a free port.
+    ///
+    /// Defaults to `9091`.
+    #[clap(long, env, help_heading = "Pushgateway options")]
+    pushgateway_port: Option<u16>,
+
+    /// Enable the autometrics gateway.
+    ///
+    /// The gateway accepts metrics that applications push to it, through OTLP
+    /// or in the Prometheus format, and aggregates them for Prometheus to
+    /// scrape. Unlike the Pushgateway, counters and histograms of multiple
+    /// instances (like functions) are summed up. It is proxied at `/gateway`.
     #[clap(
-        long,
-        env,
-        default_value = "v1.6.0",
-        help_heading = "Pushgateway options"
+        long = "gateway",
+        env = "GATEWAY_ENABLED",
+        help_heading = "Gateway options"
     )]
-    pushgateway_version: String,
+    gateway_enabled: bool,
+
+    /// The autometrics gateway version to use. Like the Prometheus version,
+    /// this can be `latest` or a semver range.
+    ///
+    /// Defaults to `v0.1.0`.
+    #[clap(long, env, help_heading = "Gateway options")]
+    gateway_version: Option<String>,
+
+    /// The port on which the gateway listens. Use `0` to pick a free port.
+    ///
+    /// Defaults to `9092`.
+    #[clap(long, env, help_heading = "Gateway options")]
+    gateway_port: Option<u16>,
+
+    /// Listen for StatsD metrics and expose them to Prometheus.
+    ///
+    /// Counters, gauges and timers (as histograms in seconds) are aggregated
+    /// by am, so applications that only speak StatsD can be explored without
+    /// running a statsd_exporter. DogStatsD tags become labels.
+    #[clap(long, env, help_heading = "StatsD options")]
+    statsd_enabled: Option<bool>,
+
+    /// The UDP port on which StatsD metrics are received.
+    ///
+    /// Defaults to `8125`.
+    #[clap(long, env, help_heading = "StatsD options")]
+    statsd_port: Option<u16>,
+
+    /// Listen for metrics in the Graphite plaintext protocol and expose them
+    /// to Prometheus.
+    ///
+    /// The dotted paths are turned into metrics with labels by the `mappings`
+    /// in the `[graphite]` section of the am.toml file.
+    #[clap(long, env, help_heading = "Graphite options")]
+    graphite_enabled: Option<bool>,
+
+    /// The TCP port on which the Graphite metrics are received.
+    ///
+    /// Defaults to `2003`.
+    #[clap(long, env, help_heading = "Graphite options")]
+    graphite_port: Option<u16>,
+
+    /// Enable alertmanager.
+    ///
+    /// Alertmanager receives the alerts fired by the Prometheus alerting rules
+    /// and makes them available through its web interface and API.
+    #[clap(long, env, help_heading = "Alertmanager options")]
+    alertmanager_enabled: Option<bool>,
+
+    /// The alertmanager version to use. Like the Prometheus version, this can
+    /// be `latest` or a semver range.
+    ///
+    /// Defaults to `v0.26.0`.
+    #[clap(long, env, help_heading = "Alertmanager options")]
+    alertmanager_version: Option<String>,
+
+    /// The port on which alertmanager listens. Use `0` to pick a free port.
+    ///
+    /// Defaults to `9093`.
+    #[clap(long, env, help_heading = "Alertmanager options")]
+    alertmanager_port: Option<u16>,
+
+    /// Enable the OpenTelemetry Collector.
+    ///
+    /// The collector receives metrics through OTLP (on port 4317 for gRPC and
+    /// 4318 for HTTP) and exposes them to Prometheus. This is useful for
+    /// applications that only support pushing metrics through OTLP.
+    #[clap(
+        long = "otel-collector",
+        env = "OTEL_COLLECTOR_ENABLED",
+        help_heading = "OpenTelemetry Collector options"
+    )]
+    otel_collector_enabled: bool,
+
+    /// The OpenTelemetry Collector (contrib) version to use. Like the
+    /// Prometheus version, this can be `latest` or a semver range.
+    ///
+    /// Defaults to `v0.88.0`.
+    #[clap(long, env, help_heading = "OpenTelemetry Collector options")]
+    otel_collector_version: Option<String>,
+
+    /// Run Tempo to store traces locally.
+    ///
+    /// Tempo receives traces through OTLP (on port 4317 for gRPC and 4318 for
+    /// HTTP, or through the OpenTelemetry Collector if it is enabled) and its
+    /// API is proxied at `/tempo`. Prometheus stores exemplars, so the traces
+    /// of a metric can be looked up from the explorer.
+    ///
+    /// Since the OTLP ports are fixed, only one am at a time can run Tempo.
+    #[clap(long, env, help_heading = "Tracing options")]
+    traces_enabled: bool,
+
+    /// The Tempo version to use. Like the Prometheus version, this can be
+    /// `latest` or a semver range.
+    ///
+    /// Defaults to `v2.3.1`.
+    #[clap(long, env, help_heading = "Tracing options")]
+    tempo_version: Option<String>,
+
+    /// The port on which Tempo serves its API. Use `0` to pick a free port.
+    ///
+    /// Defaults to `3200`.
+    #[clap(long, env, help_heading = "Tracing options")]
+    tempo_port: Option<u16>,
+
+    /// Run Loki to store logs locally.
+    ///
+    /// Applications push their logs to `/loki/api/v1/push` (on the Loki port
+    /// or through the web server of am), and the API is proxied at `/loki`, so
+    /// the logs can be queried next to the metrics.
+    #[clap(long, env, help_heading = "Logging options")]
+    loki_enabled: bool,
+
+    /// Send the lines of this log file to Loki, like `--loki-log-file
+    /// app.log`. The path can be a glob. This runs Promtail, and implies
+    /// `--loki-enabled`. Can be used multiple times.
+    #[clap(
+        long = "loki-log-file",
+        env = "LOKI_LOG_FILES",
+        value_delimiter = ',',
+        help_heading = "Logging options"
+    )]
+    loki_log_files: Vec<PathBuf>,
+
+    /// The Loki version to use, which is also used for Promtail. Like the
+    /// Prometheus version, this can be `latest` or a semver range.
+    ///
+    /// Defaults to `v2.9.3`.
+    #[clap(long, env, help_heading = "Logging options")]
+    loki_version: Option<String>,
+
+    /// The port on which Loki serves its API. Use `0` to pick a free port.
+    ///
+    /// Defaults to `3100`.
+    #[clap(long, env, help_heading = "Logging options")]
+    loki_port: Option<u16>,
+
+    /// Scrape the CPU, memory, disk and network metrics of this machine.
+    ///
+    /// This runs node_exporter, or windows_exporter on Windows, so the
+    /// resource usage of the machine can be compared with the metrics of the
+    /// application.
+    #[clap(long, env, help_heading = "Host metrics options")]
+    host_metrics: bool,
+
+    /// The node_exporter (or windows_exporter on Windows) version to use. Like
+    /// the Prometheus version, this can be `latest` or a semver range.
+    ///
+    /// Defaults to `v1.6.1` (`v0.24.0` for windows_exporter).
+    #[clap(long, env, help_heading = "Host metrics options")]
+    host_metrics_version: Option<String>,
+
+    /// Enable the blackbox_exporter.
+    ///
+    /// It probes services that don't expose metrics themselves, so their
+    /// uptime and latency show up as `probe_success` and
+    /// `probe_duration_seconds`. Defaults to `true` if there are any probes.
+    #[clap(long, env, help_heading = "Blackbox options")]
+    blackbox_enabled: Option<bool>,
+
+    /// A URL to probe, like `https://example.com` or `tcp://localhost:5432`.
+    /// Can be specified multiple times, and replaces the `probes` of the
+    /// config file.
+    #[clap(long = "probe", help_heading = "Blackbox options")]
+    probes: Vec<Probe>,
+
+    /// The blackbox_exporter version to use. Like the Prometheus version, this
+    /// can be `latest` or a semver range.
+    ///
+    /// Defaults to `v0.24.0`.
+    #[clap(long, env, help_heading = "Blackbox options")]
+    blackbox_version: Option<String>,
+
+    /// Discover scrape targets through the Kubernetes API.
+    ///
+    /// Only pods with the `autometrics.dev/scrape: "true"` annotation will be
+    /// scraped. The `autometrics.dev/path` and `autometrics.dev/port`
+    /// annotations can be used to change the default path and port.
+    #[clap(long, env, help_heading = "Kubernetes options")]
+    kubernetes: bool,
+
+    /// Only discover pods in these namespaces. Defaults to all namespaces.
+    #[clap(
+        long = "kubernetes-namespace",
+        env = "KUBERNETES_NAMESPACES",
+        value_delimiter = ',',
+        help_heading = "Kubernetes options"
+    )]
+    kubernetes_namespaces: Vec<String>,
+
+    /// Only discover pods matching this label selector, e.g. `app=my-app`.
+    #[clap(long, env, help_heading = "Kubernetes options")]
+    kubernetes_label_selector: Option<String>,
+
+    /// The kubeconfig file that Prometheus and kubectl use to connect to the
+    /// cluster.
+    ///
+    /// Defaults to `$KUBECONFIG` or `~/.kube/config`.
+    #[clap(long, help_heading = "Kubernetes options")]
+    kubeconfig: Option<PathBuf>,
+
+    /// Forward a port of a pod or service with `kubectl port-forward` and
+    /// scrape it, like `pod/api:9464` or `staging/svc/api:http`.
+    ///
+    /// The format is `[namespace/]kind/name:port[/path]`, where the path
+    /// defaults to `/metrics`. Can be specified multiple times.
+    #[clap(long = "k8s-port-forward", help_heading = "Kubernetes options")]
+    k8s_port_forwards: Vec<PortForward>,
+
+    /// Discover scrape targets through the Docker daemon.
+    ///
+    /// Containers with the `autometrics.dev/scrape=true` label, or that expose
+    /// one of the `--docker-metrics-port` ports, will be scraped. The
+    /// `autometrics.dev/path` and `autometrics.dev/port` labels can be used to
+    /// change the default path and port.
+    #[clap(long, env, help_heading = "Docker options")]
+    docker_discovery: bool,
+
+    /// The address of the Docker daemon.
+    #[clap(long, env, help_heading = "Docker options")]
+    docker_host: Option<String>,
+
+    /// Containers exposing this port will be scraped, even without the
+    /// `autometrics.dev/scrape` label. Defaults to 9464.
+    #[clap(
+        long = "docker-metrics-port",
+        env = "DOCKER_METRICS_PORTS",
+        value_delimiter = ',',
+        help_heading = "Docker options"
+    )]
+    docker_metrics_ports: Vec<u16>,
+
+    /// How often the list of Docker containers gets refreshed.
+    #[clap(long, env, help_heading = "Docker options", value_parser = humantime::parse_duration)]
+    docker_refresh_interval: Option<Duration>,
+
+    /// Scrape the CPU, memory and network usage of the Docker containers.
+    ///
+    /// `docker` scrapes the metrics endpoint of the Docker daemon, which needs
+    /// `"metrics-addr": "127.0.0.1:9323"` in its `daemon.json`. `cadvisor` runs
+    /// cAdvisor as a container, which has more detailed metrics per container.
+    #[clap(long, env, value_enum, help_heading = "Docker options")]
+    container_metrics: Option<ContainerMetrics>,
+
+    /// When to restart Prometheus, Pushgateway, etc. after they exit. Defaults
+    /// to `on-failure`.
+    #[clap(long, env, value_enum, help_heading = "Process options")]
+    restart_policy: Option<RestartPolicy>,
+
+    /// How many times in a row a process will be restarted before am gives up.
+    /// Defaults to 5.
+    #[clap(long, env, help_heading = "Process options")]
+    max_restarts: Option<u32>,
+
+    /// Show a desktop notification when Prometheus, Pushgateway, etc. exit
+    /// unexpectedly, or when one of the scrape targets goes down.
+    #[clap(long, env, help_heading = "Process options")]
+    notifications: bool,
+
+    /// Use the settings of this profile from the config file (ie.
+    /// `[profile.staging]`) on top of the top-level settings.
+    #[clap(long, env = "AM_PROFILE")]
+    profile: Option<String>,
+
+    /// Don't download anything, only use the binaries that have been
+    /// downloaded by a previous run of `am start`.
+    #[clap(long, env = "AM_OFFLINE")]
+    pub(crate) offline: bool,
+
+    /// Resolve the versions, check the endpoints and print the Prometheus
+    /// config and the command lines that would be run, without downloading or
+    /// starting anything.
+    #[clap(long, conflicts_with = "detach")]
+    dry_run: bool,
+
+    /// Print the generated Prometheus config (`prometheus.yml`) and exit,
+    /// without starting anything. Use `GET /api/prometheus-config` to get the
+    /// config of a running instance.
+    #[clap(long, conflicts_with_all = ["detach", "dry_run"])]
+    print_config: bool,
+
+    /// Never ask for input, fail instead. This is enabled automatically when
+    /// the `CI` environment variable is set.
+    #[clap(long, env = "CI", alias = "no-interactive")]
+    ci: bool,
+
+    /// Stop everything after this duration (ie. `10m`), for example when am
+    /// is only needed for the duration of an integration test.
+    #[clap(long, env = "AM_TIMEOUT", value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// The root of the codebase in which the explorer looks for functions
+    /// (see `am list all`).
+    ///
+    /// Defaults to the current directory.
+    #[clap(long, env = "AM_PROJECT_ROOT")]
+    project_root: Option<PathBuf>,
+
+    /// Run am in the background. Use `am status` to inspect it and `am stop` to
+    /// stop it.
+    #[clap(long)]
+    detach: bool,
 
     /// Whenever to clean up files created by Prometheus/Pushgateway after successful execution
     #[clap(short = 'd', long, env)]
@@ -103,79 +680,1078 @@ pub struct CliArguments {
     /// Whenever to *NOT* load the autometrics rules file into Prometheus
     #[clap(long, env)]
     no_rules: bool,
+
+    /// A YAML file with Service Level Objectives, for which recording and
+    /// alerting rules will be loaded into Prometheus. See `am slo` for more
+    /// details.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    slo_file: Option<PathBuf>,
+
+    /// Load the recording and alerting rules in this file into Prometheus, next
+    /// to the autometrics rules (use `--no-rules` to only load these). Can be
+    /// used multiple times.
+    #[clap(
+        long = "rules-file",
+        env = "RULES_FILES",
+        value_delimiter = ',',
+        help_heading = "Prometheus options"
+    )]
+    rules_files: Vec<PathBuf>,
+
+    /// The directory in which Prometheus stores its data, so it is kept
+    /// across restarts of am. This directory is never removed, not even when
+    /// `--ephemeral` is passed.
+    ///
+    /// Defaults to `.autometrics/prometheus/data` in the current directory.
+    #[clap(long, env = "PROMETHEUS_DATA_DIR", help_heading = "Prometheus options")]
+    data_dir: Option<PathBuf>,
+
+    /// How long Prometheus keeps its data, ie. `15d`.
+    ///
+    /// Defaults to the Prometheus default of `15d`.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    retention_time: Option<String>,
+
+    /// Pass the endpoints to Prometheus through a `targets.json` file
+    /// (`file_sd_configs`) instead of the Prometheus config.
+    ///
+    /// Changes to the endpoints will then only update that file, instead of
+    /// reloading Prometheus. Endpoints with credentials, TLS settings or
+    /// `honor_labels` are still part of the Prometheus config.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    file_sd: bool,
+
+    /// The maximum amount of disk space Prometheus uses for its data, ie.
+    /// `10GB`. There is no limit by default.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    retention_size: Option<String>,
+
+    /// Record the autometrics metrics with fewer labels at 1m and 5m
+    /// resolutions, which speeds up the queries over long running sessions.
+    ///
+    /// The recorded series are named like
+    /// `function_module_service_name:function_calls_total:rate5m`.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    downsampling: bool,
+
+    /// Run Prometheus in agent mode, which only forwards the scraped metrics to
+    /// the remote write URL.
+    ///
+    /// The metrics can't be queried locally and no rules are evaluated, which
+    /// makes am a lightweight collector, ie. on edge machines.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    agent: bool,
+
+    /// Enable the admin API of Prometheus, which `am system snapshot` needs.
+    ///
+    /// The admin API can delete series, so it is disabled unless it is needed.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    enable_admin_api: bool,
+
+    /// Pass an extra flag to Prometheus, ie.
+    /// `--prometheus-arg storage.tsdb.wal-compression=true`. Can be used
+    /// multiple times.
+    #[clap(
+        long = "prometheus-arg",
+        value_name = "KEY=VALUE",
+        value_parser = prometheus_arg_parser,
+        help_heading = "Prometheus options"
+    )]
+    prometheus_args: Vec<String>,
+
+    /// Enable an experimental Prometheus feature, ie.
+    /// `--prometheus-feature exemplar-storage`, which is passed to Prometheus
+    /// as `--enable-feature`. Can be used multiple times.
+    ///
+    /// Features that the selected Prometheus version doesn't have yet are
+    /// refused.
+    #[clap(
+        long = "prometheus-feature",
+        value_name = "FEATURE",
+        value_parser = prometheus_feature_parser,
+        help_heading = "Prometheus options"
+    )]
+    prometheus_features: Vec<String>,
+
+    /// A YAML file that is merged into the generated Prometheus config.
+    ///
+    /// Mappings are merged and lists are appended to, so it can add extra
+    /// `scrape_configs`, `remote_read` or `alerting` settings. Other values
+    /// replace the ones that am generates.
+    #[clap(long, env, help_heading = "Prometheus options")]
+    prometheus_config_extend: Option<PathBuf>,
+
+    /// The command that starts your application, which am runs and stops
+    /// together with Prometheus (ie. `am start --app-endpoint :3000 -- cargo
+    /// run`). This can also be set with `command` in the `[app]` section of
+    /// the config file.
+    #[clap(last = true, help_heading = "Application options")]
+    app_command: Vec<String>,
+
+    /// The metrics endpoint of the application that am runs. am waits for it
+    /// to respond and then scrapes it.
+    #[clap(long, env, value_parser = endpoint_parser, help_heading = "Application options")]
+    app_endpoint: Option<Url>,
+
+    /// Endpoints that are scraped on top of the ones from the command line or
+    /// the config file, with their job name.
+    #[clap(skip)]
+    extra_endpoints: Vec<(Url, String)>,
+}
+
+impl CliArguments {
+    /// Scrape `url` as `job_name`, next to the endpoints that were passed.
+    pub(crate) fn add_endpoint(&mut self, url: Url, job_name: &str) {
+        self.extra_endpoints.push((url, job_name.to_string()));
+    }
 }
 
 #[derive(Debug, Clone)]
 struct Arguments {
     metrics_endpoints: Vec<Endpoint>,
+    /// The endpoints that were discovered or entered when am started, rather
+    /// than configured. They are kept when the config is reloaded.
+    startup_endpoints: Vec<Endpoint>,
+    /// How long to wait for the endpoints to respond at startup.
+    wait_for_endpoints: Option<Duration>,
+    auto_discover: bool,
     prometheus_version: String,
     prometheus_scrape_interval: Duration,
     listen_address: SocketAddr,
+    /// The addresses on which the web server listens as well, like the IP of
+    /// a VPN.
+    extra_listen_addresses: Vec<SocketAddr>,
+    access_log: Option<PathBuf>,
+    cors_allow_origins: Vec<String>,
+    web_auth: Option<WebAuth>,
+    tls: Option<TlsConfig>,
+    explorer_offline: bool,
+    explorer_url: Option<String>,
+    mdns: bool,
+    ports: Ports,
+    remote_write: Option<RemoteWriteConfig>,
+    external_labels: BTreeMap<String, String>,
+    scrape_protocols: Vec<ScrapeProtocol>,
     pushgateway_enabled: bool,
     pushgateway_version: String,
+    gateway_enabled: bool,
+    gateway_version: String,
+    gateway_port: u16,
+    statsd_enabled: bool,
+    statsd_port: u16,
+    graphite_enabled: bool,
+    graphite_port: u16,
+    graphite_mappings: Vec<GraphiteMapping>,
+    alertmanager_enabled: bool,
+    alertmanager_version: String,
+    otel_collector_enabled: bool,
+    otel_collector_version: String,
+    traces_enabled: bool,
+    tempo_version: String,
+    tempo_port: u16,
+    loki_enabled: bool,
+    loki_log_files: Vec<PathBuf>,
+    loki_version: String,
+    loki_port: u16,
+    host_metrics: bool,
+    host_metrics_version: String,
+    blackbox_enabled: bool,
+    blackbox_version: String,
+    probes: Vec<Probe>,
+    kubernetes: Option<KubernetesDiscovery>,
+    port_forwards: Vec<PortForward>,
+    /// The kubeconfig file that kubectl uses for the port forwards, if it was
+    /// set explicitly.
+    kubeconfig: Option<PathBuf>,
+    docker: Option<DockerDiscovery>,
+    container_metrics: Option<ContainerMetrics>,
+    supervisor: Supervisor,
     ephemeral_working_directory: bool,
+    prometheus_data_dir: Option<PathBuf>,
+    /// The extra flags passed to Prometheus, including the retention flags.
+    prometheus_args: Vec<String>,
+    /// The features that are passed to Prometheus with `--enable-feature`.
+    prometheus_features: Vec<String>,
+    /// Run Prometheus in agent mode.
+    agent: bool,
+    enable_admin_api: bool,
+    prometheus_config_extend: Option<PathBuf>,
+    file_sd: bool,
     no_rules: bool,
+    rule_files: Vec<PathBuf>,
+    slos: Vec<SloDefinition>,
+    /// Load the downsampling recording rules into Prometheus.
+    downsampling: bool,
+    project_root: Option<PathBuf>,
+    app: Option<App>,
 }
 
 impl Arguments {
-    fn new(args: CliArguments, config: AmConfig) -> Self {
-        Arguments {
+    fn new(args: CliArguments, config: AmConfig) -> Result<Self> {
+        let agent = args.agent || config.agent.unwrap_or(false);
+
+        let slo_file = args.slo_file.clone().or(config.slo_file);
+        let slos = slo::load(config.slos, slo_file.as_deref())?;
+
+        let (&listen_address, extra_listen_addresses) = args
+            .listen_addresses
+            .split_first()
+            .context("At least one listen address is required")?;
+        let extra_listen_addresses = extra_listen_addresses.to_vec();
+
+        let cors_allow_origins = if args.cors_allow_origins.is_empty() {
+            config
+                .cors_allow_origins
+                .unwrap_or_default()
+                .iter()
+                .map(|origin| cors_origin_parser(origin))
+                .collect::<Result<_>>()
+                .context("Invalid `cors-allow-origins` in the config file")?
+        } else {
+            args.cors_allow_origins
+        };
+
+        let rule_files = if args.rules_files.is_empty() {
+            config.rule_files.unwrap_or_default()
+        } else {
+            args.rules_files
+        };
+        let rule_files = rule_files
+            .into_iter()
+            .map(|path| {
+                if !path.is_file() {
+                    bail!("Rule file {} does not exist", path.display());
+                }
+                Ok(env::current_dir()?.join(path))
+            })
+            .collect::<Result<_>>()?;
+
+        // Prometheus runs in its own working directory, so relative paths need
+        // to be resolved here.
+        let prometheus_data_dir = match args.data_dir.or(config.prometheus_data_dir) {
+            Some(dir) => Some(env::current_dir()?.join(dir)),
+            None => None,
+        };
+
+        let retention_time = args.retention_time.or(config.prometheus_retention_time);
+        let retention_size = args.retention_size.or(config.prometheus_retention_size);
+        if agent && (retention_time.is_some() || retention_size.is_some()) {
+            bail!("The retention options can't be used in agent mode, since Prometheus only keeps the data until it has been sent");
+        }
+
+        let mut prometheus_args = vec![];
+        if let Some(time) = retention_time {
+            prometheus_args.push(format!("--storage.tsdb.retention.time={time}"));
+        }
+        if let Some(size) = retention_size {
+            prometheus_args.push(format!("--storage.tsdb.retention.size={size}"));
+        }
+        if args.prometheus_args.is_empty() {
+            for arg in config.prometheus_args.unwrap_or_default() {
+                prometheus_args.push(prometheus_arg_parser(&arg)?);
+            }
+        } else {
+            prometheus_args.extend(args.prometheus_args);
+        }
+
+        let mut prometheus_features = if args.prometheus_features.is_empty() {
+            config
+                .prometheus_features
+                .unwrap_or_default()
+                .iter()
+                .map(|feature| prometheus_feature_parser(feature))
+                .collect::<Result<_>>()
+                .context("Invalid `prometheus-features` in the config file")?
+        } else {
+            args.prometheus_features
+        };
+        if args.native_histograms || config.native_histograms.unwrap_or(false) {
+            prometheus_features.push("native-histograms".to_string());
+        }
+        // The exemplars link the metrics to the traces in Tempo.
+        let traces_enabled = args.traces_enabled || config.traces_enabled.unwrap_or(false);
+        if traces_enabled {
+            prometheus_features.push("exemplar-storage".to_string());
+        }
+        if prometheus_features.iter().any(|feature| feature == "agent") {
+            bail!("Use --agent (or `agent = true`) to run Prometheus in agent mode");
+        }
+        prometheus_features.sort();
+        prometheus_features.dedup();
+
+        let loki_log_files: Vec<_> = if args.loki_log_files.is_empty() {
+            config.loki_log_files.unwrap_or_default()
+        } else {
+            args.loki_log_files
+        };
+        // Promtail runs in its own working directory, so relative paths need
+        // to be resolved here.
+        let loki_log_files = loki_log_files
+            .into_iter()
+            .map(|path| Ok(env::current_dir()?.join(path)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let kubernetes_config = config.kubernetes.unwrap_or_default();
+        let kubeconfig = args.kubeconfig.or(kubernetes_config.kubeconfig);
+        let port_forwards = if args.k8s_port_forwards.is_empty() {
+            kubernetes_config
+                .port_forwards
+                .unwrap_or_default()
+                .iter()
+                .map(|forward| forward.parse())
+                .collect::<Result<_>>()?
+        } else {
+            args.k8s_port_forwards
+        };
+        let kubernetes =
+            (args.kubernetes || kubernetes_config.enabled.unwrap_or(false)).then(|| {
+                KubernetesDiscovery {
+                    namespaces: if args.kubernetes_namespaces.is_empty() {
+                        kubernetes_config.namespaces.unwrap_or_default()
+                    } else {
+                        args.kubernetes_namespaces
+                    },
+                    label_selector: args
+                        .kubernetes_label_selector
+                        .or(kubernetes_config.label_selector),
+                    kubeconfig: resolve_kubeconfig(kubeconfig.clone()),
+                }
+            });
+
+        let docker_config = config.docker.unwrap_or_default();
+        let container_metrics = args.container_metrics.or(docker_config.container_metrics);
+        let docker = (args.docker_discovery || docker_config.enabled.unwrap_or(false)).then(|| {
+            DockerDiscovery {
+                host: args
+                    .docker_host
+                    .or(docker_config.host)
+                    .unwrap_or_else(|| "unix:///var/run/docker.sock".to_string()),
+                metrics_ports: if args.docker_metrics_ports.is_empty() {
+                    docker_config.metrics_ports.unwrap_or_else(|| vec![9464])
+                } else {
+                    args.docker_metrics_ports
+                },
+                refresh_interval: args
+                    .docker_refresh_interval
+                    .or(docker_config.refresh_interval)
+                    .unwrap_or_else(|| Duration::from_secs(15)),
+            }
+        });
+
+        let app = App::new(args.app_command, args.app_endpoint, config.app);
+
+        let probes: Vec<Probe> = if args.probes.is_empty() {
+            config
+                .probes
+                .unwrap_or_default()
+                .iter()
+                .map(|probe| probe.parse())
+                .collect::<Result<_>>()?
+        } else {
+            args.probes
+        };
+
+        // Federated servers are scraped in addition to the endpoints, even if
+        // those are passed on the command line.
+        let federate_endpoints: Vec<Endpoint> = config
+            .federate
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(num, federate)| {
+                let url = federate.url.clone();
+                let federate = FederateConfig {
+                    job_name: Some(
+                        federate
+                            .job_name
+                            .unwrap_or_else(|| format!("federate_{num}")),
+                    ),
+                    ..federate
+                };
+                match federate.try_into() {
+                    Ok(endpoint) => Some(endpoint),
+                    Err(err) => {
+                        warn!("Ignoring federation from {url}: {err}");
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        let graphite_config = config.graphite.unwrap_or_default();
+        let honor_timestamps = args.honor_timestamps.or(config.honor_timestamps);
+
+        let arguments = Arguments {
             metrics_endpoints: endpoints_from_first_input(args.metrics_endpoints, config.endpoints)
                 .into_iter()
-                .filter_map(|e| e.try_into().ok())
+                .map(|mut endpoint| {
+                    // Credentials passed through the CLI act as defaults for
+                    // all endpoints.
+                    if endpoint.bearer_token.is_none() && endpoint.basic_auth.is_none() {
+                        endpoint.bearer_token = args.bearer_token.clone();
+                        endpoint.basic_auth = args.basic_auth.clone();
+                    }
+                    if args.tls_skip_verify {
+                        endpoint.tls_skip_verify.get_or_insert(true);
+                    }
+                    if endpoint.ca_cert.is_none() {
+                        endpoint.ca_cert = args.ca_cert.clone();
+                    }
+                    endpoint.scrape_timeout = endpoint.scrape_timeout.or(args.scrape_timeout);
+                    endpoint.sample_limit = endpoint.sample_limit.or(args.sample_limit);
+                    endpoint.label_limit = endpoint.label_limit.or(args.label_limit);
+                    endpoint.honor_timestamps = endpoint.honor_timestamps.or(honor_timestamps);
+                    endpoint
+                })
+                .filter_map(|endpoint| {
+                    let url = endpoint.url.clone();
+                    match endpoint.try_into() {
+                        Ok(endpoint) => Some(endpoint),
+                        Err(err) => {
+                            warn!("Ignoring endpoint {url}: {err}");
+                            None
+                        }
+                    }
+                })
+                .chain(federate_endpoints)
+                .chain(args.extra_endpoints.iter().map(|(url, job_name)| {
+                    Endpoint::new(url.clone(), job_name.clone(), false, None)
+                }))
+                .collect(),
+            startup_endpoints: vec![],
+            prometheus_version: args
+                .prometheus_version
+                .or(config.prometheus_version)
+                .unwrap_or_else(|| DEFAULT_PROMETHEUS_VERSION.to_string()),
+            listen_address,
+            extra_listen_addresses,
+            access_log: args.access_log.or(config.access_log),
+            cors_allow_origins,
+            web_auth: args.web_auth.or(config.web_auth),
+            tls: TlsConfig::new(
+                args.tls || config.tls.unwrap_or(false),
+                args.tls_cert.or(config.tls_cert),
+                args.tls_key.or(config.tls_key),
+            )?,
+            explorer_offline: args.explorer_offline || config.explorer_offline.unwrap_or(false),
+            explorer_url: args.explorer_url.or(config.explorer_url),
+            mdns: args.mdns || config.mdns.unwrap_or(false),
+            ports: Ports {
+                prometheus: args
+                    .prometheus_port
+                    .or(config.prometheus_port)
+                    .unwrap_or(DEFAULT_PROMETHEUS_PORT),
+                pushgateway: args
+                    .pushgateway_port
+                    .or(config.pushgateway_port)
+                    .unwrap_or(DEFAULT_PUSHGATEWAY_PORT),
+                alertmanager: args
+                    .alertmanager_port
+                    .or(config.alertmanager_port)
+                    .unwrap_or(DEFAULT_ALERTMANAGER_PORT),
+            },
+            remote_write: match args.remote_write_url {
+                Some(url) => Some(RemoteWriteConfig {
+                    url,
+                    bearer_token: args.remote_write_bearer_token,
+                    basic_auth: args.remote_write_basic_auth,
+                }),
+                None => config.remote_write,
+            },
+            external_labels: config
+                .external_labels
+                .unwrap_or_default()
+                .into_iter()
+                .chain(args.external_labels)
                 .collect(),
-            prometheus_version: args.prometheus_version,
-            listen_address: args.listen_address,
+            scrape_protocols: if args.scrape_protocols.is_empty() {
+                config.scrape_protocols.unwrap_or_default()
+            } else {
+                args.scrape_protocols
+            },
             pushgateway_enabled: args
                 .pushgateway_enabled
                 .or(config.pushgateway_enabled)
                 .unwrap_or(false),
-            pushgateway_version: args.pushgateway_version,
+            statsd_enabled: args
+                .statsd_enabled
+                .or(config.statsd_enabled)
+                .unwrap_or(false),
+            statsd_port: args
+                .statsd_port
+                .or(config.statsd_port)
+                .unwrap_or(statsd::DEFAULT_PORT),
+            graphite_enabled: args
+                .graphite_enabled
+                .or(graphite_config.enabled)
+                .unwrap_or(false),
+            graphite_port: args
+                .graphite_port
+                .or(graphite_config.port)
+                .unwrap_or(graphite::DEFAULT_PORT),
+            graphite_mappings: graphite_config.mappings,
+            pushgateway_version: args
+                .pushgateway_version
+                .or(config.pushgateway_version)
+                .unwrap_or_else(|| DEFAULT_PUSHGATEWAY_VERSION.to_string()),
+            gateway_enabled: args.gateway_enabled || config.gateway_enabled.unwrap_or(false),
+            gateway_version: args
+                .gateway_version
+                .or(config.gateway_version)
+                .unwrap_or_else(|| gateway::DEFAULT_VERSION.to_string()),
+            gateway_port: args
+                .gateway_port
+                .or(config.gateway_port)
+                .unwrap_or(gateway::DEFAULT_PORT),
+            alertmanager_enabled: args
+                .alertmanager_enabled
+                .or(config.alertmanager_enabled)
+                .unwrap_or(false),
+            alertmanager_version: args
+                .alertmanager_version
+                .or(config.alertmanager_version)
+                .unwrap_or_else(|| DEFAULT_ALERTMANAGER_VERSION.to_string()),
+            otel_collector_enabled: args.otel_collector_enabled
+                || config.otel_collector_enabled.unwrap_or(false),
+            traces_enabled,
+            tempo_version: args
+                .tempo_version
+                .or(config.tempo_version)
+                .unwrap_or_else(|| tempo::DEFAULT_VERSION.to_string()),
+            tempo_port: args
+                .tempo_port
+                .or(config.tempo_port)
+                .unwrap_or(tempo::DEFAULT_PORT),
+            loki_enabled: args.loki_enabled
+                || config.loki_enabled.unwrap_or(false)
+                || !loki_log_files.is_empty(),
+            loki_log_files,
+            loki_version: args
+                .loki_version
+                .or(config.loki_version)
+                .unwrap_or_else(|| loki::DEFAULT_VERSION.to_string()),
+            loki_port: args
+                .loki_port
+                .or(config.loki_port)
+                .unwrap_or(loki::DEFAULT_PORT),
+            otel_collector_version: args
+                .otel_collector_version
+                .or(config.otel_collector_version)
+                .unwrap_or_else(|| DEFAULT_OTEL_COLLECTOR_VERSION.to_string()),
+            host_metrics: args.host_metrics || config.host_metrics.unwrap_or(false),
+            host_metrics_version: args
+                .host_metrics_version
+                .or(config.host_metrics_version)
+                .unwrap_or_else(|| host_metrics::DEFAULT_VERSION.to_string()),
+            blackbox_enabled: args
+                .blackbox_enabled
+                .or(config.blackbox_enabled)
+                .unwrap_or(!probes.is_empty()),
+            blackbox_version: args
+                .blackbox_version
+                .or(config.blackbox_version)
+                .unwrap_or_else(|| DEFAULT_BLACKBOX_VERSION.to_string()),
+            probes,
+            kubernetes,
+            port_forwards,
+            kubeconfig,
+            docker,
+            container_metrics,
+            supervisor: Supervisor {
+                policy: args
+                    .restart_policy
+                    .or(config.restart_policy)
+                    .unwrap_or_default(),
+                max_restarts: args.max_restarts.or(config.max_restarts).unwrap_or(5),
+                notify: args.notifications || config.notifications.unwrap_or(false),
+            },
             ephemeral_working_directory: args.ephemeral,
+            prometheus_data_dir,
+            prometheus_args,
+            prometheus_features,
+            prometheus_config_extend: args
+                .prometheus_config_extend
+                .or(config.prometheus_config_extend),
             prometheus_scrape_interval: args
                 .scrape_interval
                 .or(config.prometheus_scrape_interval)
                 .unwrap_or_else(|| Duration::from_secs(5)),
-            no_rules: args.no_rules,
+            wait_for_endpoints: args.wait_for_endpoints.or(config.wait_for_endpoints),
+            auto_discover: args.auto_discover || config.auto_discover.unwrap_or(false),
+            file_sd: args.file_sd || config.file_sd.unwrap_or(false),
+            rule_files,
+            slos,
+            downsampling: args.downsampling || config.downsampling.unwrap_or(false),
+            project_root: args.project_root.or(config.project_root),
+            app,
+            agent,
+            enable_admin_api: args.enable_admin_api || config.enable_admin_api.unwrap_or(false),
+            // Prometheus doesn't evaluate any rules in agent mode.
+            no_rules: args.no_rules || agent,
+        };
+
+        if arguments.agent {
+            arguments.check_agent_mode()?;
         }
+
+        Ok(arguments)
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Endpoint {
-    url: Url,
-    job_name: String,
-    honor_labels: bool,
-    scrape_interval: Option<Duration>,
-}
+    /// Check that the settings can be used with Prometheus in agent mode,
+    /// which refuses to start with rules or an Alertmanager.
+    fn check_agent_mode(&self) -> Result<()> {
+        if self.remote_write.is_none() {
+            bail!("Agent mode requires a remote write URL, set it with --remote-write-url or the `remote-write` section in am.toml");
+        }
 
-impl Endpoint {
-    fn new(
-        url: Url,
-        job_name: String,
-        honor_labels: bool,
+        if self.alertmanager_enabled {
+            bail!("Alertmanager can't be used in agent mode, since Prometheus doesn't evaluate alerting rules");
+        }
+
+        if !self.rule_files.is_empty() || !self.slos.is_empty() {
+            bail!("Rule files and SLOs can't be used in agent mode, since Prometheus doesn't evaluate rules");
+        }
+
+        if self.downsampling {
+            bail!("Downsampling can't be used in agent mode, since Prometheus doesn't evaluate recording rules");
+        }
+
+        Ok(())
+    }
+
+    /// The job names of the endpoints that are scraped, not counting the
+    /// targets registered through the API.
+    fn job_names(&self) -> Vec<String> {
+        self.metrics_endpoints
+            .iter()
+            .map(|endpoint| endpoint.job_name.clone())
+            .collect()
+    }
+
+    /// Scrape `endpoint`, which was found or entered when am started. Unlike
+    /// the configured endpoints, it is kept when the config is reloaded.
+    fn add_startup_endpoint(&mut self, endpoint: Endpoint) {
+        self.startup_endpoints.push(endpoint.clone());
+        self.metrics_endpoints.push(endpoint);
+    }
+
+    /// The address on which a managed process listens on `port`. With web
+    /// authentication the processes only listen on the loopback interface, so
+    /// they can't be reached without going through the web server of am.
+    fn process_listen_address(&self, port: u16) -> String {
+        format!("{}:{port}", self.process_listen_host())
+    }
+
+    /// The host part of [`Self::process_listen_address`], for the processes
+    /// that configure the address and the port separately. It is empty when
+    /// the processes listen on all interfaces.
+    fn process_listen_host(&self) -> &'static str {
+        if self.web_auth.is_some() {
+            "127.0.0.1"
+        } else {
+            ""
+        }
+    }
+
+    /// All the addresses on which the web server listens, starting with the
+    /// one that is used by the managed processes.
+    fn listen_addresses(&self) -> Vec<SocketAddr> {
+        let mut listen_addresses = vec![self.listen_address];
+        listen_addresses.extend(&self.extra_listen_addresses);
+        listen_addresses
+    }
+
+    /// The scheme on which the web server of am is served.
+    fn web_scheme(&self) -> &'static str {
+        if self.tls.is_some() {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// Resolve the versions of the programs that will be used to exact
+    /// versions, as they can also be `latest` or a semver range.
+    async fn resolve_versions(&mut self, local_data: &Path, offline: bool) -> Result<()> {
+        self.prometheus_version = versions::resolve(
+            &versions::PROMETHEUS,
+            &self.prometheus_version,
+            local_data,
+            offline,
+        )
+        .await?;
+
+        if self.pushgateway_enabled {
+            self.pushgateway_version = versions::resolve(
+                &versions::PUSHGATEWAY,
+                &self.pushgateway_version,
+                local_data,
+                offline,
+            )
+            .await?;
+        }
+
+        if self.gateway_enabled {
+            self.gateway_version = versions::resolve(
+                &versions::GATEWAY,
+                &self.gateway_version,
+                local_data,
+                offline,
+            )
+            .await?;
+        }
+
+        if self.alertmanager_enabled {
+            self.alertmanager_version = versions::resolve(
+                &versions::ALERTMANAGER,
+                &self.alertmanager_version,
+                local_data,
+                offline,
+            )
+            .await?;
+        }
+
+        if self.traces_enabled {
+            self.tempo_version =
+                versions::resolve(&versions::TEMPO, &self.tempo_version, local_data, offline)
+                    .await?;
+        }
+
+        if self.loki_enabled {
+            self.loki_version =
+                versions::resolve(&versions::LOKI, &self.loki_version, local_data, offline).await?;
+        }
+
+        if self.otel_collector_enabled {
+            self.otel_collector_version = versions::resolve(
+                &versions::OTEL_COLLECTOR,
+                &self.otel_collector_version,
+                local_data,
+                offline,
+            )
+            .await?;
+        }
+
+        if self.host_metrics {
+            self.host_metrics_version = versions::resolve(
+                &host_metrics::EXPORTER,
+                &self.host_metrics_version,
+                local_data,
+                offline,
+            )
+            .await?;
+        }
+
+        if self.blackbox_enabled {
+            self.blackbox_version = versions::resolve(
+                &versions::BLACKBOX_EXPORTER,
+                &self.blackbox_version,
+                local_data,
+                offline,
+            )
+            .await?;
+        }
+
+        self.check_prometheus_version()
+    }
+
+    /// Check that the resolved Prometheus version supports the settings that
+    /// are used, as older versions refuse to start with unknown fields in
+    /// their config.
+    fn check_prometheus_version(&self) -> Result<()> {
+        feature_flags::check(&self.prometheus_features, &self.prometheus_version)?;
+
+        let uses_scrape_protocols = !self.scrape_protocols.is_empty()
+            || self
+                .metrics_endpoints
+                .iter()
+                .any(|endpoint| !endpoint.scrape_protocols.is_empty());
+        if !uses_scrape_protocols {
+            return Ok(());
+        }
+
+        let since = Version::new(ScrapeProtocol::SINCE)
+            .parse()
+            .expect("valid version");
+        match Version::new(self.prometheus_version.trim_start_matches('v')).parse() {
+            Ok(version) if version < since => bail!(
+                "Scrape protocols require Prometheus {} or newer, but {} is used. Select a newer version with --prometheus-version",
+                ScrapeProtocol::SINCE,
+                self.prometheus_version
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The ports on which the processes that am manages listen.
+#[derive(Debug, Clone, Copy)]
+struct Ports {
+    prometheus: u16,
+    pushgateway: u16,
+    alertmanager: u16,
+}
+
+impl Ports {
+    /// Replace the ports that are set to `0` with free ports.
+    fn resolve(self, pushgateway_enabled: bool, alertmanager_enabled: bool) -> Result<Self> {
+        Ok(Ports {
+            prometheus: resolve_port(self.prometheus, "Prometheus")?,
+            pushgateway: if pushgateway_enabled {
+                resolve_port(self.pushgateway, "Pushgateway")?
+            } else {
+                self.pushgateway
+            },
+            alertmanager: if alertmanager_enabled {
+                resolve_port(self.alertmanager, "Alertmanager")?
+            } else {
+                self.alertmanager
+            },
+        })
+    }
+}
+
+/// Make sure that the ports don't clash with the other `am start` instances
+/// that are running. Default ports that are taken are replaced with free ones,
+/// while ports that were chosen explicitly result in an error that points at
+/// the instance which uses them.
+fn avoid_running_instances(
+    listen_address: &mut SocketAddr,
+    ports: &mut Ports,
+    pushgateway_enabled: bool,
+    alertmanager_enabled: bool,
+    running: &[Instance],
+) -> Result<()> {
+    let check = |port: u16, default: u16, process: &str, flag: &str| -> Result<bool> {
+        let Some(instance) = running.iter().find(|instance| instance.uses_port(port)) else {
+            return Ok(false);
+        };
+
+        if port != default {
+            return Err(anyhow::Error::new(AmError::PortInUse { port }).context(format!(
+                "Port {port} for {process} is already used by another am (pid {}, explorer on http://{}). Stop it with Ctrl+C or `am stop`, or pick another port with {flag}",
+                instance.pid,
+                instance.listen_address
+            )));
+        }
+
+        info!(
+            "Port {port} is already used by another am (pid {}), picking a free port for {process}",
+            instance.pid
+        );
+        Ok(true)
+    };
+
+    if check(
+        listen_address.port(),
+        DEFAULT_LISTEN_PORT,
+        "the web server",
+        "--listen-address",
+    )? {
+        listen_address.set_port(0);
+    }
+
+    if check(
+        ports.prometheus,
+        DEFAULT_PROMETHEUS_PORT,
+        "Prometheus",
+        "--prometheus-port",
+    )? {
+        ports.prometheus = 0;
+    }
+
+    if pushgateway_enabled
+        && check(
+            ports.pushgateway,
+            DEFAULT_PUSHGATEWAY_PORT,
+            "Pushgateway",
+            "--pushgateway-port",
+        )?
+    {
+        ports.pushgateway = 0;
+    }
+
+    if alertmanager_enabled
+        && check(
+            ports.alertmanager,
+            DEFAULT_ALERTMANAGER_PORT,
+            "Alertmanager",
+            "--alertmanager-port",
+        )?
+    {
+        ports.alertmanager = 0;
+    }
+
+    Ok(())
+}
+
+fn resolve_port(port: u16, process: &str) -> Result<u16> {
+    if port != 0 {
+        return Ok(port);
+    }
+
+    // Let the OS pick a free port. There is a small window in which another
+    // process could take the port before it gets used, but that is unlikely.
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0))
+        .with_context(|| format!("Unable to find a free port for {process}"))?;
+    let port = listener.local_addr()?.port();
+
+    info!("Using port {port} for {process}");
+    Ok(port)
+}
+
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    url: Url,
+    job_name: String,
+    honor_labels: bool,
+    honor_timestamps: Option<bool>,
+    scrape_protocols: Vec<ScrapeProtocol>,
+    scrape_interval: Option<Duration>,
+    scrape_timeout: Option<Duration>,
+    sample_limit: Option<u64>,
+    label_limit: Option<u64>,
+    labels: BTreeMap<String, String>,
+    bearer_token: Option<String>,
+    basic_auth: Option<BasicAuth>,
+    tls_skip_verify: bool,
+    ca_cert: Option<PathBuf>,
+    metric_relabel_configs: Vec<MetricRelabelConfig>,
+    /// The query parameters that are sent with every scrape, like the
+    /// `match[]` selectors of a federation endpoint.
+    params: BTreeMap<String, Vec<String>>,
+}
+
+impl Endpoint {
+    fn new(
+        url: Url,
+        job_name: String,
+        honor_labels: bool,
         scrape_interval: Option<Duration>,
     ) -> Self {
         Self {
             url,
             job_name,
             honor_labels,
+            honor_timestamps: None,
+            scrape_protocols: vec![],
             scrape_interval,
+            scrape_timeout: None,
+            sample_limit: None,
+            label_limit: None,
+            labels: BTreeMap::new(),
+            bearer_token: None,
+            basic_auth: None,
+            tls_skip_verify: false,
+            ca_cert: None,
+            metric_relabel_configs: vec![],
+            params: BTreeMap::new(),
+        }
+    }
+
+    /// The `host:port` that Prometheus scrapes.
+    fn target(&self) -> String {
+        match self.url.port() {
+            Some(port) => format!("{}:{}", self.url.host_str().unwrap(), port),
+            None => self.url.host_str().unwrap().to_string(),
+        }
+    }
+
+    fn metrics_path(&self) -> &str {
+        match self.url.path() {
+            "" => "/metrics",
+            path => path,
         }
     }
+
+    /// Whether the endpoint can be scraped through file-based service
+    /// discovery. Only the settings that can be set through labels are
+    /// supported there.
+    fn supports_file_sd(&self) -> bool {
+        !self.honor_labels
+            && self.honor_timestamps.is_none()
+            && self.scrape_protocols.is_empty()
+            && self.scrape_timeout.is_none()
+            && self.sample_limit.is_none()
+            && self.label_limit.is_none()
+            && self.bearer_token.is_none()
+            && self.basic_auth.is_none()
+            && !self.tls_skip_verify
+            && self.ca_cert.is_none()
+            && self.metric_relabel_configs.is_empty()
+            && self.params.is_empty()
+    }
 }
 
 impl TryFrom<autometrics_am::config::Endpoint> for Endpoint {
     type Error = anyhow::Error;
 
     fn try_from(value: autometrics_am::config::Endpoint) -> Result<Self, Self::Error> {
+        let mut url = value.url;
+
+        // Credentials that are part of the URL are used for basic auth. They
+        // are removed from the URL so they won't end up in any logs.
+        let url_basic_auth = (!url.username().is_empty()).then(|| BasicAuth {
+            username: url.username().to_string(),
+            password: url.password().unwrap_or_default().to_string(),
+        });
+        let _ = url.set_username("");
+        let _ = url.set_password(None);
+
+        let url = unix_bridge::local_url(&url, "/metrics")?;
+
+        let basic_auth = value.basic_auth.or(url_basic_auth);
+        if basic_auth.is_some() && value.bearer_token.is_some() {
+            bail!("bearer token and basic auth cannot be used at the same time");
+        }
+
         Ok(Self {
-            url: value.url,
+            url,
             job_name: value
                 .job_name
                 .ok_or_else(|| anyhow!("TryFrom requires job_name"))?,
             honor_labels: value.honor_labels.unwrap_or(false),
+            honor_timestamps: value.honor_timestamps,
+            scrape_protocols: value.scrape_protocols.unwrap_or_default(),
             scrape_interval: value.prometheus_scrape_interval,
+            scrape_timeout: value.scrape_timeout,
+            sample_limit: value.sample_limit,
+            label_limit: value.label_limit,
+            labels: value.labels,
+            bearer_token: value.bearer_token,
+            basic_auth,
+            tls_skip_verify: value.tls_skip_verify.unwrap_or(false),
+            ca_cert: value.ca_cert,
+            metric_relabel_configs: value.metric_relabel_configs.unwrap_or_default(),
+            params: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<FederateConfig> for Endpoint {
+    type Error = anyhow::Error;
+
+    /// The series of a federated server keep their own labels, like `job` and
+    /// `instance`, so `honor_labels` is always enabled.
+    fn try_from(value: FederateConfig) -> Result<Self, Self::Error> {
+        if value.matches.is_empty() {
+            bail!("at least one `match` selector is required to federate from a Prometheus server");
+        }
+
+        let endpoint = autometrics_am::config::Endpoint {
+            job_name: value.job_name,
+            honor_labels: Some(true),
+            prometheus_scrape_interval: value.prometheus_scrape_interval,
+            bearer_token: value.bearer_token,
+            basic_auth: value.basic_auth,
+            tls_skip_verify: value.tls_skip_verify,
+            ca_cert: value.ca_cert,
+            ..autometrics_am::config::Endpoint::new(value.url)
+        };
+
+        Ok(Self {
+            params: BTreeMap::from([("match[]".to_string(), value.matches)]),
+            ..endpoint.try_into()?
         })
     }
 }
@@ -191,42 +1767,201 @@ impl From<Endpoint> for ScrapeConfig {
             _ => None,
         };
 
-        let mut metrics_path = endpoint.url.path();
-        if metrics_path.is_empty() {
-            metrics_path = "/metrics";
-        }
-
-        let host = match endpoint.url.port() {
-            Some(port) => format!("{}:{}", endpoint.url.host_str().unwrap(), port),
-            None => endpoint.url.host_str().unwrap().to_string(),
-        };
-
         ScrapeConfig {
-            job_name: endpoint.job_name,
             static_configs: vec![prometheus::StaticScrapeConfig {
-                targets: vec![host],
+                targets: vec![endpoint.target()],
+                labels: endpoint.labels,
             }],
-            metrics_path: Some(metrics_path.to_string()),
+            metrics_path: Some(endpoint.metrics_path().to_string()),
+            params: endpoint.params,
+            job_name: endpoint.job_name,
             scheme,
             honor_labels: Some(endpoint.honor_labels),
+            honor_timestamps: endpoint.honor_timestamps,
+            scrape_protocols: endpoint.scrape_protocols,
             scrape_interval: endpoint.scrape_interval,
+            scrape_timeout: endpoint.scrape_timeout,
+            sample_limit: endpoint.sample_limit,
+            label_limit: endpoint.label_limit,
+            basic_auth: endpoint.basic_auth.map(|auth| prometheus::BasicAuth {
+                username: auth.username,
+                password: auth.password,
+            }),
+            authorization: endpoint
+                .bearer_token
+                .map(|token| prometheus::Authorization {
+                    kind: "Bearer".to_string(),
+                    credentials: token,
+                }),
+            tls_config: (endpoint.tls_skip_verify || endpoint.ca_cert.is_some()).then(|| {
+                prometheus::TlsConfig {
+                    ca_file: endpoint
+                        .ca_cert
+                        .map(|path| path.to_string_lossy().into_owned()),
+                    insecure_skip_verify: endpoint.tls_skip_verify.then_some(true),
+                }
+            }),
+            metric_relabel_configs: endpoint
+                .metric_relabel_configs
+                .iter()
+                .flat_map(relabel_configs)
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The Prometheus relabel configs that implement `config`.
+fn relabel_configs(config: &MetricRelabelConfig) -> Vec<prometheus::RelabelConfig> {
+    let filter = |source_labels: &Vec<String>, regex: &str, action| {
+        let source_labels = if source_labels.is_empty() {
+            vec!["__name__".to_string()]
+        } else {
+            source_labels.clone()
+        };
+
+        prometheus::RelabelConfig {
+            source_labels,
+            regex: Some(regex.to_string()),
+            action: Some(action),
+            ..Default::default()
+        }
+    };
+
+    match config {
+        MetricRelabelConfig::Keep {
+            source_labels,
+            regex,
+        } => vec![filter(
+            source_labels,
+            regex,
+            prometheus::RelabelAction::Keep,
+        )],
+        MetricRelabelConfig::Drop {
+            source_labels,
+            regex,
+        } => vec![filter(
+            source_labels,
+            regex,
+            prometheus::RelabelAction::Drop,
+        )],
+        // Prometheus can't rename a label, so the value is copied to the new
+        // label before the old one is removed.
+        MetricRelabelConfig::RenameLabel { from, to } => vec![
+            prometheus::RelabelConfig {
+                source_labels: vec![from.clone()],
+                regex: Some("(.+)".to_string()),
+                target_label: Some(to.clone()),
+                replacement: Some("$1".to_string()),
+                action: Some(prometheus::RelabelAction::Replace),
+                ..Default::default()
+            },
+            prometheus::RelabelConfig {
+                regex: Some(from.clone()),
+                action: Some(prometheus::RelabelAction::LabelDrop),
+                ..Default::default()
+            },
+        ],
+        MetricRelabelConfig::LabelDrop { regex } => vec![prometheus::RelabelConfig {
+            regex: Some(regex.clone()),
+            action: Some(prometheus::RelabelAction::LabelDrop),
+            ..Default::default()
+        }],
+    }
+}
+
+impl From<Endpoint> for prometheus::TargetGroup {
+    /// The `job` label replaces the job name of the file-based service
+    /// discovery job, so the endpoint keeps its own job name.
+    fn from(endpoint: Endpoint) -> Self {
+        let mut labels = endpoint.labels.clone();
+        labels.extend([
+            ("job".to_string(), endpoint.job_name.clone()),
+            ("__scheme__".to_string(), endpoint.url.scheme().to_string()),
+            (
+                "__metrics_path__".to_string(),
+                endpoint.metrics_path().to_string(),
+            ),
+        ]);
+
+        if let Some(scrape_interval) = endpoint.scrape_interval {
+            labels.insert(
+                "__scrape_interval__".to_string(),
+                format!("{}ms", scrape_interval.as_millis()),
+            );
+        }
+
+        prometheus::TargetGroup {
+            targets: vec![endpoint.target()],
+            labels,
         }
     }
 }
 
-pub async fn handle_command(args: CliArguments, config: AmConfig, mp: MultiProgress) -> Result<()> {
-    let mut args = Arguments::new(args, config);
+pub async fn handle_command(
+    cli_args: CliArguments,
+    config: AmConfig,
+    config_file: Option<PathBuf>,
+    mp: MultiProgress,
+) -> Result<()> {
+    let config = config.with_profile(cli_args.profile.as_deref())?;
+    let mut args = Arguments::new(cli_args.clone(), config)?;
+
+    if cli_args.detach {
+        let state = daemon::detach(args.listen_address)?;
+        info!("am is running in the background (pid {})", state.pid);
+        info!(
+            "Explorer endpoint: {}://{}",
+            args.web_scheme(),
+            state.listen_address
+        );
+        info!("Logs are written to: {}", state.log_file.display());
+        info!("Use `am status` to inspect it and `am stop` to stop it");
+        return Ok(());
+    }
+
+    if let Some(project_root) = args.project_root.clone() {
+        let _ = functions::PROJECT_ROOT.set(project_root);
+    }
+
+    if args.auto_discover {
+        discover_endpoints(&mut args, cli_args.ci).await?;
+    }
+
+    if cli_args.print_config {
+        return print_config(args);
+    }
+
+    if args.metrics_endpoints.is_empty()
+        && args.app.as_ref().and_then(App::endpoint).is_none()
+        && !args.pushgateway_enabled
+        && !args.gateway_enabled
+        && !args.statsd_enabled
+        && !args.graphite_enabled
+        && !args.otel_collector_enabled
+        && !args.traces_enabled
+        && !args.loki_enabled
+        && !args.alertmanager_enabled
+        && !args.host_metrics
+        && (!args.blackbox_enabled || args.probes.is_empty())
+        && args.kubernetes.is_none()
+        && args.port_forwards.is_empty()
+        && args.docker.is_none()
+        && args.container_metrics.is_none()
+    {
+        if cli_args.ci || cli_args.dry_run {
+            bail!("No metrics endpoints provided and pushgateway is not enabled. Provide an endpoint as an argument or in the config file.");
+        }
 
-    if args.metrics_endpoints.is_empty() && !args.pushgateway_enabled {
         info!("No metrics endpoints provided and pushgateway is not enabled. Please provide an endpoint.");
 
         // Ask for a metric endpoint and parse the input like a regular CLI argument
         let url = interactive::user_input("Metric endpoint")?;
-        let url = endpoint_parser(&url)?;
+        let url = unix_bridge::local_url(&endpoint_parser(&url)?, "/metrics")?;
 
         // Add the provided URL with the job name am_0
         let endpoint = Endpoint::new(url, "am_0".to_string(), false, None);
-        args.metrics_endpoints.push(endpoint);
+        args.add_startup_endpoint(endpoint);
     }
 
     // First let's retrieve the directory for our application to store data in.
@@ -238,121 +1973,641 @@ pub async fn handle_command(args: CliArguments, config: AmConfig, mp: MultiProgr
     std::fs::create_dir_all(&local_data)
         .with_context(|| format!("Unable to create data directory: {:?}", local_data))?;
 
+    args.resolve_versions(&local_data, cli_args.offline).await?;
+
+    if cli_args.dry_run {
+        return dry_run(args, &local_data).await;
+    }
+
+    if let Some(explorer_url) = &args.explorer_url {
+        explorer::serve_from(explorer_url)?;
+    } else if args.explorer_offline {
+        explorer::serve_offline(local_data.join(explorer::OFFLINE_DIR))?;
+    }
+
+    if cli_args.offline {
+        let missing: Vec<String> = required_binaries(&args)
+            .into_iter()
+            .filter(|(_, directory)| !local_data.join(directory).exists())
+            .map(|(name, _)| name)
+            .collect();
+
+        if !missing.is_empty() {
+            bail!(
+                "Running in offline mode, but the following are not available in {}: {}. Run `am start` without `--offline` to download them.",
+                local_data.display(),
+                missing.join(", ")
+            );
+        }
+    }
+
     if !args.metrics_endpoints.is_empty() {
-        info!("Checking if provided metrics endpoints work...");
+        match args.wait_for_endpoints {
+            Some(deadline) => info!(
+                "Waiting up to {} for the metrics endpoints to respond...",
+                humantime::format_duration(deadline)
+            ),
+            None => info!("Checking if provided metrics endpoints work..."),
+        }
 
-        // check if the provided endpoint works
-        for endpoint in &args.metrics_endpoints {
-            if let Err(err) = check_endpoint(&endpoint.url).await {
+        // check if the provided endpoints work, which happens concurrently
+        // since they might need to be retried.
+        let checks = args.metrics_endpoints.iter().map(|endpoint| async move {
+            if let Err(err) = wait_for_endpoint(endpoint, args.wait_for_endpoints).await {
                 warn!(
                     ?err,
                     "Failed to make request to {} (job {})", endpoint.url, endpoint.job_name
                 );
             }
-        }
-    }
-
-    if args.pushgateway_enabled {
-        let url = Url::parse("http://localhost:9091/pushgateway/metrics").unwrap();
-        let endpoint = Endpoint::new(url, "am_pushgateway".to_string(), true, None);
-        args.metrics_endpoints.push(endpoint);
+        });
+        futures_util::future::join_all(checks).await;
     }
 
-    let (tx, rx) = watch::channel(None);
+    // Prometheus scrapes the web server, so it is bound before the Prometheus
+    // config is generated. Its port is then known and can't be taken by
+    // another process in the meantime.
+    resolve_ports(&mut args)?;
+    let web_server_listeners = bind_listeners(&args.listen_addresses())?;
+    let local_addrs = web_server_listeners
+        .iter()
+        .map(std::net::TcpListener::local_addr)
+        .collect::<std::io::Result<Vec<_>>>()?;
+    args.listen_address = local_addrs[0];
+    args.extra_listen_addresses = local_addrs[1..].to_vec();
+    let _registration = instances::register(Instance {
+        pid: std::process::id(),
+        listen_address: args.listen_address,
+        prometheus_port: args.ports.prometheus,
+        pushgateway_port: args.pushgateway_enabled.then_some(args.ports.pushgateway),
+        alertmanager_port: args.alertmanager_enabled.then_some(args.ports.alertmanager),
+        started_at: SystemTime::now(),
+    })?;
+    add_internal_endpoints(&mut args);
+    TARGETS.set_configured_jobs(args.job_names());
 
-    // Start web server for hosting the explorer, am api and proxies to the enabled services.
-    let web_server_task = async move {
-        start_web_server(
-            &args.listen_address,
-            true,
-            args.pushgateway_enabled,
-            None,
-            tx,
-        )
-        .await
-    };
+    if args.web_auth.is_none() {
+        for listen_address in args.listen_addresses() {
+            if !listen_address.ip().is_loopback() {
+                warn!(
+                    "Listening on {} without authentication, use --web-auth to restrict access",
+                    listen_address
+                );
+            }
+        }
+    }
 
-    // Start Prometheus server
-    let prometheus_args = args.clone();
-    let prometheus_local_data = local_data.clone();
-    let prometheus_multi_progress = mp.clone();
+    if let Some(path) = &args.access_log {
+        access_log::open(path)?;
+    }
 
-    let prom_rx = rx.clone();
+    // The Prometheus config gets written to a unique, temporary location. This
+    // location is shared with the config watcher, so it can rewrite the config.
+    let prometheus_runtime_dir = AutoCleanupDir::new(
+        &format!(
+            "am-prometheus-{}",
+            Alphanumeric.sample_string(&mut rand::thread_rng(), 6)
+        ),
+        true,
+    )?;
+    let prometheus_config_path = prometheus_runtime_dir.join("prometheus.yml");
 
-    let prometheus_task = async move {
-        let prometheus_version = prometheus_args.prometheus_version.trim_start_matches('v');
+    // The config file is watched for changes, so that the Prometheus config can
+    // be reloaded without restarting am.
+    let config_file =
+        config_file.or_else(|| Some(PathBuf::from("./am.toml")).filter(|path| path.exists()));
 
-        info!("Using Prometheus version: {}", prometheus_version);
+    STATUS.set_config_files(config_file.clone(), prometheus_config_path.clone());
 
-        let prometheus_path =
-            prometheus_local_data.join(format!("prometheus-{prometheus_version}"));
+    let otel_collector_task = if args.otel_collector_enabled {
+        let otel_collector_args = args.clone();
+        let otel_collector_local_data = local_data.clone();
+        let otel_collector_multi_progress = mp.clone();
+        async move {
+            let otel_collector_version = otel_collector_args
+                .otel_collector_version
+                .trim_start_matches('v');
+            let otel_collector_path =
+                otel_collector_local_data.join(format!("otelcol-contrib-{otel_collector_version}"));
 
-        // Check if prometheus is available
-        if !prometheus_path.exists() {
-            info!("Cached version of Prometheus not found, downloading Prometheus");
-            install_prometheus(
-                &prometheus_path,
-                prometheus_version,
-                prometheus_multi_progress,
+            install_and_supervise(
+                otel_collector_args.supervisor,
+                "otel-collector",
+                "OpenTelemetry Collector",
+                otel_collector_version,
+                &otel_collector_path,
+                || {
+                    otel_collector::install_otel_collector(
+                        &otel_collector_path,
+                        otel_collector_version,
+                        otel_collector_multi_progress,
+                    )
+                },
+                || {
+                    otel_collector::start_otel_collector(
+                        &otel_collector_path,
+                        otel_collector_args.ephemeral_working_directory,
+                        otel_collector_args
+                            .traces_enabled
+                            .then_some(tempo::FORWARDED_OTLP_GRPC_ENDPOINT),
+                    )
+                },
             )
-            .await?;
-            debug!("Downloaded Prometheus to: {:?}", &prometheus_path);
-        } else {
-            debug!("Found prometheus in: {:?}", prometheus_path);
+            .await
         }
-
-        let prometheus_config = generate_prom_config(
-            prometheus_args.prometheus_scrape_interval,
-            prometheus_args.metrics_endpoints,
-            !args.no_rules,
-        )?;
-
-        start_prometheus(
-            &prometheus_path,
-            &prometheus_config,
-            args.ephemeral_working_directory,
-            !args.no_rules,
-            prom_rx,
-        )
-        .await
+        .instrument(info_span!("otel-collector", component = "otel-collector"))
+        .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
     };
 
-    let pushgateway_task = if args.pushgateway_enabled {
-        let pushgateway_args = args.clone();
-        let pushgateway_local_data = local_data.clone();
-        let pushgateway_multi_progress = mp.clone();
+    let tempo_task = if args.traces_enabled {
+        let tempo_args = args.clone();
+        let tempo_local_data = local_data.clone();
+        let tempo_multi_progress = mp.clone();
         async move {
-            let pushgateway_version = pushgateway_args.pushgateway_version.trim_start_matches('v');
-
-            info!("Using pushgateway version: {}", pushgateway_version);
-
-            let pushgateway_path =
-                pushgateway_local_data.join(format!("pushgateway-{pushgateway_version}"));
+            let tempo_version = tempo_args.tempo_version.trim_start_matches('v');
+            let tempo_path =
+                tempo_local_data.join(format!("{}-{tempo_version}", versions::TEMPO.directory));
 
-            // Check if pushgateway is available
-            if !pushgateway_path.exists() {
-                info!("Cached version of pushgateway not found, downloading pushgateway");
-                install_pushgateway(
-                    &pushgateway_path,
-                    pushgateway_version,
-                    pushgateway_multi_progress,
-                )
-                .await?;
-                debug!("Downloaded pushgateway to: {:?}", &pushgateway_path);
-            } else {
-                debug!("Found pushgateway in: {:?}", &pushgateway_path);
-            }
-
-            start_pushgateway(&pushgateway_path, args.ephemeral_working_directory, rx).await
+            install_and_supervise(
+                tempo_args.supervisor,
+                "tempo",
+                "Tempo",
+                tempo_version,
+                &tempo_path,
+                || tempo::install_tempo(&tempo_path, tempo_version, tempo_multi_progress),
+                || {
+                    tempo::start_tempo(
+                        &tempo_path,
+                        tempo_args.process_listen_host(),
+                        tempo_args.tempo_port,
+                        tempo_args.otel_collector_enabled,
+                        tempo_args.ephemeral_working_directory,
+                    )
+                },
+            )
+            .await
         }
+        .instrument(info_span!("tempo", component = "tempo"))
         .boxed()
     } else {
         async move { anyhow::Ok(()) }.boxed()
     };
 
-    if !args.metrics_endpoints.is_empty() {
-        let endpoints = args
-            .metrics_endpoints
+    let loki_task = if args.loki_enabled {
+        let loki_args = args.clone();
+        let loki_local_data = local_data.clone();
+        let loki_multi_progress = mp.clone();
+        async move {
+            let loki_version = loki_args.loki_version.trim_start_matches('v');
+            let loki_path =
+                loki_local_data.join(format!("{}-{loki_version}", versions::LOKI.directory));
+
+            install_and_supervise(
+                loki_args.supervisor,
+                "loki",
+                "Loki",
+                loki_version,
+                &loki_path,
+                || loki::install("loki", &loki_path, loki_version, loki_multi_progress),
+                || {
+                    loki::start_loki(
+                        &loki_path,
+                        loki_args.process_listen_host(),
+                        loki_args.loki_port,
+                        loki_args.ephemeral_working_directory,
+                    )
+                },
+            )
+            .await
+        }
+        .instrument(info_span!("loki", component = "loki"))
+        .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let promtail_task = if !args.loki_log_files.is_empty() {
+        let promtail_args = args.clone();
+        let promtail_local_data = local_data.clone();
+        let promtail_multi_progress = mp.clone();
+        async move {
+            let promtail_version = promtail_args.loki_version.trim_start_matches('v');
+            let promtail_path = promtail_local_data.join(format!(
+                "{}-{promtail_version}",
+                versions::PROMTAIL.directory
+            ));
+
+            install_and_supervise(
+                promtail_args.supervisor,
+                "promtail",
+                "Promtail",
+                promtail_version,
+                &promtail_path,
+                || {
+                    loki::install(
+                        "promtail",
+                        &promtail_path,
+                        promtail_version,
+                        promtail_multi_progress,
+                    )
+                },
+                || {
+                    loki::start_promtail(
+                        &promtail_path,
+                        &promtail_args.loki_log_files,
+                        promtail_args.loki_port,
+                        promtail_args.ephemeral_working_directory,
+                    )
+                },
+            )
+            .await
+        }
+        .instrument(info_span!("promtail", component = "promtail"))
+        .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let host_metrics_task = if args.host_metrics {
+        let host_metrics_args = args.clone();
+        let host_metrics_local_data = local_data.clone();
+        let host_metrics_multi_progress = mp.clone();
+        async move {
+            let exporter = host_metrics::EXPORTER;
+            let exporter_version = host_metrics_args
+                .host_metrics_version
+                .trim_start_matches('v');
+            let exporter_path =
+                host_metrics_local_data.join(format!("{}-{exporter_version}", exporter.directory));
+
+            install_and_supervise(
+                host_metrics_args.supervisor,
+                "host-metrics",
+                exporter.name,
+                exporter_version,
+                &exporter_path,
+                || {
+                    host_metrics::install_exporter(
+                        &exporter_path,
+                        exporter_version,
+                        host_metrics_multi_progress,
+                    )
+                },
+                || host_metrics::start_exporter(&exporter_path),
+            )
+            .await
+        }
+        .instrument(info_span!("host-metrics", component = "host-metrics"))
+        .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let cadvisor_task = if args.container_metrics == Some(ContainerMetrics::Cadvisor) {
+        let supervisor = args.supervisor;
+        async move {
+            supervisor
+                .supervise("cadvisor", container_metrics::start_cadvisor)
+                .await
+        }
+        .instrument(info_span!("cadvisor", component = "cadvisor"))
+        .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let statsd_task = if args.statsd_enabled {
+        statsd::listen(args.statsd_port)
+            .instrument(info_span!("statsd", component = "statsd"))
+            .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let mdns_task = if args.mdns {
+        mdns::announce(args.listen_addresses(), args.web_scheme())
+            .instrument(info_span!("mdns", component = "mdns"))
+            .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let graphite_task = if args.graphite_enabled {
+        graphite::listen(args.graphite_port, args.graphite_mappings.clone())
+            .instrument(info_span!("graphite", component = "graphite"))
+            .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let blackbox_task = if args.blackbox_enabled {
+        let blackbox_args = args.clone();
+        let blackbox_local_data = local_data.clone();
+        let blackbox_multi_progress = mp.clone();
+        async move {
+            let blackbox_version = blackbox_args.blackbox_version.trim_start_matches('v');
+            let blackbox_path =
+                blackbox_local_data.join(format!("blackbox_exporter-{blackbox_version}"));
+
+            install_and_supervise(
+                blackbox_args.supervisor,
+                "blackbox",
+                "blackbox_exporter",
+                blackbox_version,
+                &blackbox_path,
+                || {
+                    blackbox::install_blackbox(
+                        &blackbox_path,
+                        blackbox_version,
+                        blackbox_multi_progress,
+                    )
+                },
+                || blackbox::start_blackbox(&blackbox_path),
+            )
+            .await
+        }
+        .instrument(info_span!("blackbox", component = "blackbox"))
+        .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let app_task = match args.app.clone() {
+        Some(app) => {
+            let supervisor = args.supervisor;
+            async move { supervisor.supervise("app", || app::start_app(&app)).await }
+                .instrument(info_span!("app", component = "app"))
+                .boxed()
+        }
+        None => async move { anyhow::Ok(()) }.boxed(),
+    };
+
+    let port_forward_tasks = args
+        .port_forwards
+        .iter()
+        .map(|forward| {
+            // The local port is picked once, so it stays the same when kubectl
+            // gets restarted.
+            let local_port = resolve_port(0, &format!("the port forward of {forward}"))?;
+            let forward = forward.clone();
+            let kubeconfig = args.kubeconfig.clone();
+            let supervisor = args.supervisor;
+            let span = info_span!("port-forward", component = "port-forward", %forward);
+
+            Ok(async move {
+                supervisor
+                    .supervise(&forward.process_name(), || {
+                        port_forward::start_port_forward(
+                            &forward,
+                            local_port,
+                            kubeconfig.as_deref(),
+                        )
+                    })
+                    .await
+            }
+            .instrument(span))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let port_forward_task = async move {
+        futures_util::future::try_join_all(port_forward_tasks)
+            .await
+            .map(|_| ())
+    }
+    .boxed();
+
+    let timeout = cli_args.timeout;
+    let timeout_task = async move {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let reload_task = reload::watch_config(
+        config_file,
+        cli_args,
+        args.clone(),
+        prometheus_config_path.clone(),
+    )
+    .boxed();
+
+    let notifications_task =
+        notifications::watch_targets(args.ports.prometheus, args.supervisor.notify).boxed();
+
+    let (tx, rx) = watch::channel(None);
+
+    // Start web server for hosting the explorer, am api and proxies to the enabled services.
+    let web_server_task = async move {
+        start_web_server(
+            web_server_listeners,
+            Some(args.ports.prometheus),
+            args.pushgateway_enabled.then_some(args.ports.pushgateway),
+            args.gateway_enabled.then_some(args.gateway_port),
+            args.traces_enabled.then_some(args.tempo_port),
+            args.loki_enabled.then_some(args.loki_port),
+            args.alertmanager_enabled.then_some(args.ports.alertmanager),
+            None,
+            AccessControl {
+                cors_allow_origins: args.cors_allow_origins.clone(),
+                ..AccessControl::with_web_auth(args.web_auth.as_ref())
+            },
+            args.tls.clone(),
+            tx,
+        )
+        .await
+    }
+    .instrument(info_span!("web", component = "web"));
+
+    // Start Prometheus server
+    let prometheus_args = args.clone();
+    let prometheus_local_data = local_data.clone();
+    let prometheus_multi_progress = mp.clone();
+
+    let prom_rx = rx.clone();
+    let alertmanager_rx = rx.clone();
+    let gateway_rx = rx.clone();
+
+    let prometheus_task = async move {
+        let prometheus_version = prometheus_args.prometheus_version.trim_start_matches('v');
+
+        info!("Using Prometheus version: {}", prometheus_version);
+        STATUS.register("prometheus", prometheus_version);
+
+        let prometheus_path =
+            prometheus_local_data.join(format!("prometheus-{prometheus_version}"));
+
+        // Check if prometheus is available
+        if !prometheus_path.exists() {
+            info!("Cached version of Prometheus not found, downloading Prometheus");
+            STATUS.set_state("prometheus", ProcessState::Downloading);
+            install_prometheus(
+                &prometheus_path,
+                prometheus_version,
+                prometheus_multi_progress,
+            )
+            .await?;
+            debug!("Downloaded Prometheus to: {:?}", &prometheus_path);
+            STATUS.set_state("prometheus", ProcessState::Starting);
+        } else {
+            debug!("Found prometheus in: {:?}", prometheus_path);
+        }
+
+        installs::mark_used(&prometheus_path);
+
+        let (prometheus_config, file_sd_targets) = generate_prom_config(&prometheus_args)?;
+        write_prom_config(
+            &prometheus_config_path,
+            &render_prom_config(&prometheus_args, &prometheus_config)?,
+        )?;
+        write_slo_rules(&prometheus_args)?;
+
+        if prometheus_args.file_sd {
+            write_file_sd_targets(
+                &prometheus_config_path.with_file_name(FILE_SD_TARGETS_FILE),
+                &file_sd_targets,
+            )?;
+        }
+
+        prometheus_args
+            .supervisor
+            .supervise("prometheus", || {
+                start_prometheus(
+                    &prometheus_path,
+                    &prometheus_config_path,
+                    &prometheus_args,
+                    prom_rx.clone(),
+                )
+            })
+            .await
+    }
+    .instrument(info_span!("prometheus", component = "prometheus"));
+
+    let pushgateway_task = if args.pushgateway_enabled {
+        let pushgateway_args = args.clone();
+        let pushgateway_local_data = local_data.clone();
+        let pushgateway_multi_progress = mp.clone();
+        async move {
+            let pushgateway_version = pushgateway_args.pushgateway_version.trim_start_matches('v');
+            let pushgateway_path =
+                pushgateway_local_data.join(format!("pushgateway-{pushgateway_version}"));
+            let pushgateway_listen_address =
+                pushgateway_args.process_listen_address(pushgateway_args.ports.pushgateway);
+
+            install_and_supervise(
+                pushgateway_args.supervisor,
+                "pushgateway",
+                "pushgateway",
+                pushgateway_version,
+                &pushgateway_path,
+                || {
+                    install_pushgateway(
+                        &pushgateway_path,
+                        pushgateway_version,
+                        pushgateway_multi_progress,
+                    )
+                },
+                || {
+                    start_pushgateway(
+                        &pushgateway_path,
+                        &pushgateway_listen_address,
+                        pushgateway_args.ephemeral_working_directory,
+                        pushgateway_args.web_scheme(),
+                        rx.clone(),
+                    )
+                },
+            )
+            .await
+        }
+        .instrument(info_span!("pushgateway", component = "pushgateway"))
+        .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let gateway_task = if args.gateway_enabled {
+        let gateway_args = args.clone();
+        let gateway_local_data = local_data.clone();
+        let gateway_multi_progress = mp.clone();
+        async move {
+            let gateway_version = gateway_args.gateway_version.trim_start_matches('v');
+            let gateway_path = gateway_local_data
+                .join(format!("{}-{gateway_version}", versions::GATEWAY.directory));
+            let gateway_listen_address =
+                gateway_args.process_listen_address(gateway_args.gateway_port);
+
+            install_and_supervise(
+                gateway_args.supervisor,
+                "gateway",
+                "autometrics gateway",
+                gateway_version,
+                &gateway_path,
+                || gateway::install_gateway(&gateway_path, gateway_version, gateway_multi_progress),
+                || {
+                    gateway::start_gateway(
+                        &gateway_path,
+                        &gateway_listen_address,
+                        gateway_args.ephemeral_working_directory,
+                        gateway_args.web_scheme(),
+                        gateway_rx.clone(),
+                    )
+                },
+            )
+            .await
+        }
+        .instrument(info_span!("gateway", component = "gateway"))
+        .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    let alertmanager_task = if args.alertmanager_enabled {
+        let alertmanager_args = args.clone();
+        let alertmanager_local_data = local_data.clone();
+        let alertmanager_multi_progress = mp.clone();
+        async move {
+            let alertmanager_version = alertmanager_args
+                .alertmanager_version
+                .trim_start_matches('v');
+            let alertmanager_path =
+                alertmanager_local_data.join(format!("alertmanager-{alertmanager_version}"));
+            let alertmanager_listen_address =
+                alertmanager_args.process_listen_address(alertmanager_args.ports.alertmanager);
+
+            install_and_supervise(
+                alertmanager_args.supervisor,
+                "alertmanager",
+                "alertmanager",
+                alertmanager_version,
+                &alertmanager_path,
+                || {
+                    install_alertmanager(
+                        &alertmanager_path,
+                        alertmanager_version,
+                        alertmanager_multi_progress,
+                    )
+                },
+                || {
+                    start_alertmanager(
+                        &alertmanager_path,
+                        &alertmanager_listen_address,
+                        alertmanager_args.ephemeral_working_directory,
+                        alertmanager_args.web_scheme(),
+                        alertmanager_rx.clone(),
+                    )
+                },
+            )
+            .await
+        }
+        .instrument(info_span!("alertmanager", component = "alertmanager"))
+        .boxed()
+    } else {
+        async move { anyhow::Ok(()) }.boxed()
+    };
+
+    if !args.metrics_endpoints.is_empty() {
+        let endpoints = args
+            .metrics_endpoints
             .iter()
             .map(|endpoint| endpoint.url.to_string())
             .collect::<Vec<String>>()
@@ -360,30 +2615,596 @@ pub async fn handle_command(args: CliArguments, config: AmConfig, mp: MultiProgr
         info!("Now sampling the following endpoints for metrics: {endpoints}");
     }
 
-    select! {
-        biased;
+    if let Some(remote_write) = &args.remote_write {
+        info!("Forwarding metrics to: {}", remote_write.url);
+    }
+
+    if args.agent {
+        info!("Prometheus runs in agent mode, the metrics can't be queried locally");
+    }
+
+    if args.blackbox_enabled && !args.probes.is_empty() {
+        let probes = args
+            .probes
+            .iter()
+            .map(Probe::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!("Probing the following services: {probes}");
+    }
+
+    if args.kubernetes.is_some() {
+        info!("Discovering pods annotated with `autometrics.dev/scrape: \"true\"` through the Kubernetes API");
+    }
+
+    if let Some(docker) = &args.docker {
+        info!(
+            "Discovering Docker containers through {} every {}",
+            docker.host,
+            humantime::format_duration(docker.refresh_interval)
+        );
+    }
+
+    let mut prometheus_task = prometheus_task.boxed().fuse();
+    let mut pushgateway_task = pushgateway_task.fuse();
+    let mut gateway_task = gateway_task.fuse();
+    let mut alertmanager_task = alertmanager_task.fuse();
+    let mut otel_collector_task = otel_collector_task.fuse();
+    let mut tempo_task = tempo_task.fuse();
+    let mut loki_task = loki_task.fuse();
+    let mut promtail_task = promtail_task.fuse();
+    let mut host_metrics_task = host_metrics_task.fuse();
+    let mut cadvisor_task = cadvisor_task.fuse();
+    let mut blackbox_task = blackbox_task.fuse();
+    let mut app_task = app_task.fuse();
+    let mut port_forward_task = port_forward_task.fuse();
+
+    let result = select! {
+        biased;
+
+        signal = shutdown::signal() => {
+            info!("{signal} signal received, exiting...");
+            Ok(())
+        }
+
+        _ = timeout_task => {
+            info!("Timeout reached, exiting...");
+            Ok(())
+        }
+
+        Err(err) = web_server_task => {
+            Err(err.context("Web server exited with an error"))
+        }
+
+        Err(err) = &mut prometheus_task => {
+            Err(err.context("Prometheus exited with an error"))
+        }
+
+        Err(err) = &mut pushgateway_task => {
+            Err(err.context("Pushgateway exited with an error"))
+        }
+
+        Err(err) = &mut gateway_task => {
+            Err(err.context("The autometrics gateway exited with an error"))
+        }
+
+        Err(err) = &mut alertmanager_task => {
+            Err(err.context("Alertmanager exited with an error"))
+        }
+
+        Err(err) = &mut otel_collector_task => {
+            Err(err.context("OpenTelemetry Collector exited with an error"))
+        }
+
+        Err(err) = &mut tempo_task => {
+            Err(err.context("Tempo exited with an error"))
+        }
+
+        Err(err) = &mut loki_task => {
+            Err(err.context("Loki exited with an error"))
+        }
+
+        Err(err) = &mut promtail_task => {
+            Err(err.context("Promtail exited with an error"))
+        }
+
+        Err(err) = &mut host_metrics_task => {
+            Err(err.context(format!("{} exited with an error", host_metrics::EXPORTER.name)))
+        }
+
+        Err(err) = &mut cadvisor_task => {
+            Err(err.context("cAdvisor exited with an error"))
+        }
+
+        Err(err) = &mut blackbox_task => {
+            Err(err.context("blackbox_exporter exited with an error"))
+        }
+
+        Err(err) = &mut app_task => {
+            Err(err.context("The application exited with an error"))
+        }
+
+        Err(err) = &mut port_forward_task => {
+            Err(err.context("kubectl port-forward exited with an error"))
+        }
+
+        Err(err) = reload_task => {
+            Err(err.context("Config watcher exited with an error"))
+        }
+
+        Err(err) = statsd_task => {
+            Err(err.context("StatsD listener exited with an error"))
+        }
+
+        Err(err) = graphite_task => {
+            Err(err.context("Graphite listener exited with an error"))
+        }
+
+        Err(err) = mdns_task => {
+            Err(err.context("mDNS announcement exited with an error"))
+        }
+
+        Err(err) = notifications_task => {
+            Err(err.context("Target health watcher exited with an error"))
+        }
+
+        else => {
+            Ok(())
+        }
+    };
+
+    // The processes need to exit before their working directories are
+    // cleaned up, which happens once the tasks are dropped.
+    let processes = [
+        prometheus_task,
+        pushgateway_task,
+        gateway_task,
+        alertmanager_task,
+        otel_collector_task,
+        tempo_task,
+        loki_task,
+        promtail_task,
+        host_metrics_task,
+        cadvisor_task,
+        blackbox_task,
+        app_task,
+        port_forward_task,
+    ];
+    shutdown::stop(processes.into_iter().filter(|task| !task.is_terminated())).await;
+
+    result
+}
+
+/// Install `version` of a program that am runs into `path`, unless an earlier
+/// run already downloaded it, and then keep it running with `supervisor`.
+/// `process` is the name of the program in the status API, `name` the one
+/// that is logged.
+async fn install_and_supervise<I, IFut, R, RFut>(
+    supervisor: Supervisor,
+    process: &str,
+    name: &str,
+    version: &str,
+    path: &Path,
+    install: I,
+    run: R,
+) -> Result<()>
+where
+    I: FnOnce() -> IFut,
+    IFut: Future<Output = Result<()>>,
+    R: FnMut() -> RFut,
+    RFut: Future<Output = Result<()>>,
+{
+    info!("Using {name} version: {version}");
+    STATUS.register(process, version);
+
+    if !path.exists() {
+        info!("Cached version of {name} not found, downloading {name}");
+        STATUS.set_state(process, ProcessState::Downloading);
+        install().await?;
+        debug!("Downloaded {name} to: {path:?}");
+        STATUS.set_state(process, ProcessState::Starting);
+    } else {
+        debug!("Found {name} in: {path:?}");
+    }
+
+    installs::mark_used(path);
+
+    supervisor.supervise(process, run).await
+}
+
+/// The path of `binary` in the install in `path`, with the executable suffix
+/// of the platform.
+pub(super) fn binary_path(path: &Path, binary: &str) -> PathBuf {
+    path.join(format!("{binary}{}", env::consts::EXE_SUFFIX))
+}
+
+/// Write `config` to a temporary location and run `binary` of the install in
+/// `path` with the `command_args` for that file in `work_dir`, until the
+/// process stops. `process` is the name of the program in the status API,
+/// `name` the one that is logged.
+pub(super) async fn run_with_config(
+    path: &Path,
+    binary: &str,
+    process: &str,
+    name: &str,
+    config: &impl serde::Serialize,
+    command_args: impl FnOnce(&Path) -> Vec<String>,
+    work_dir: &Path,
+) -> Result<()> {
+    // First write the config file to a temporary location
+    let runtime_dir = AutoCleanupDir::new(
+        &format!(
+            "am-{process}-{}",
+            Alphanumeric.sample_string(&mut rand::thread_rng(), 6)
+        ),
+        true,
+    )?;
+
+    let config_file_path = runtime_dir.join(format!("{process}.yml"));
+    let config_file = File::create(&config_file_path)?;
+
+    debug!(
+        path = ?config_file_path,
+        "Created temporary file for {name} config serialization"
+    );
+
+    serde_yaml::to_writer(&config_file, config)?;
+
+    let child = shutdown::command(binary_path(path, binary))
+        .args(command_args(&config_file_path))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .current_dir(work_dir)
+        .spawn()
+        .with_context(|| format!("Unable to start {name}"))?;
+
+    let status = status::wait(child, process).await?;
+
+    if !status.success() && !shutdown::is_requested() {
+        let output = logs::dump(process);
+        if !output.is_empty() {
+            error!("{name} output:\n{output}");
+        }
+
+        return Err(AmError::ChildCrashed {
+            process: name.to_string(),
+            status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Pick the ports of Prometheus and the other managed processes, moving out
+/// of the way of other running instances of am. A web server port of `0` is
+/// left for the OS to pick when the web server gets bound.
+fn resolve_ports(args: &mut Arguments) -> Result<()> {
+    avoid_running_instances(
+        &mut args.listen_address,
+        &mut args.ports,
+        args.pushgateway_enabled,
+        args.alertmanager_enabled,
+        &instances::running()?,
+    )?;
+    args.ports = args
+        .ports
+        .resolve(args.pushgateway_enabled, args.alertmanager_enabled)?;
+    if args.gateway_enabled {
+        args.gateway_port = resolve_port(args.gateway_port, "the autometrics gateway")?;
+    }
+    if args.traces_enabled {
+        args.tempo_port = resolve_port(args.tempo_port, "Tempo")?;
+    }
+    if args.loki_enabled {
+        args.loki_port = resolve_port(args.loki_port, "Loki")?;
+    }
+    Ok(())
+}
+
+/// Print the `prometheus.yml` that `am start` would generate for `args`, so
+/// it can be used with a standalone Prometheus.
+fn print_config(mut args: Arguments) -> Result<()> {
+    resolve_ports(&mut args)?;
+    let web_server_port = resolve_port(args.listen_address.port(), "the web server")?;
+    args.listen_address.set_port(web_server_port);
+    add_internal_endpoints(&mut args);
+
+    let (prometheus_config, _) = generate_prom_config(&args)?;
+    for problem in prometheus_config.validate() {
+        warn!("generated Prometheus config: {problem}");
+    }
+
+    print!("{}", render_prom_config(&args, &prometheus_config)?);
+    Ok(())
+}
+
+/// Print what `am start` would run with `args`: the Prometheus config and the
+/// command lines of the processes, without downloading or starting anything.
+async fn dry_run(mut args: Arguments, local_data: &Path) -> Result<()> {
+    for endpoint in &args.metrics_endpoints {
+        match wait_for_endpoint(endpoint, None).await {
+            Ok(()) => info!("{} (job {}) responded", endpoint.url, endpoint.job_name),
+            Err(err) => warn!(
+                ?err,
+                "Failed to make request to {} (job {})", endpoint.url, endpoint.job_name
+            ),
+        }
+    }
+
+    resolve_ports(&mut args)?;
+    let web_server_port = resolve_port(args.listen_address.port(), "the web server")?;
+    args.listen_address.set_port(web_server_port);
+    add_internal_endpoints(&mut args);
+
+    let (prometheus_config, _) = generate_prom_config(&args)?;
+    for problem in prometheus_config.validate() {
+        warn!("generated Prometheus config: {problem}");
+    }
+
+    // The runtime directories get a random suffix when am actually starts.
+    let config_file_path = env::temp_dir()
+        .join("am-prometheus-XXXXXX")
+        .join("prometheus.yml");
+    println!("# {}", config_file_path.display());
+    println!("{}", render_prom_config(&args, &prometheus_config)?);
+
+    let program = |directory: &str, version: &str, binary: &str| {
+        binary_path(
+            &local_data.join(format!("{directory}-{}", version.trim_start_matches('v'))),
+            binary,
+        )
+    };
+    let work_dir = |process: &str| -> Result<PathBuf> {
+        let start_dir = if args.ephemeral_working_directory {
+            env::temp_dir()
+        } else {
+            env::current_dir()?
+        };
+        Ok(start_dir.join(".autometrics").join(process))
+    };
+    let external_url = args.listen_address.to_string();
+
+    let mut commands = vec![(
+        program("prometheus", &args.prometheus_version, "prometheus"),
+        prometheus_command_args(&args, &config_file_path, &external_url),
+    )];
+    if args.pushgateway_enabled {
+        commands.push((
+            program("pushgateway", &args.pushgateway_version, "pushgateway"),
+            pushgateway_command_args(
+                &args.process_listen_address(args.ports.pushgateway),
+                args.web_scheme(),
+                &external_url,
+            ),
+        ));
+    }
+    if args.gateway_enabled {
+        commands.push((
+            program(
+                versions::GATEWAY.directory,
+                &args.gateway_version,
+                versions::GATEWAY.directory,
+            ),
+            gateway::gateway_command_args(
+                &args.process_listen_address(args.gateway_port),
+                args.web_scheme(),
+                &external_url,
+            ),
+        ));
+    }
+    if args.otel_collector_enabled {
+        commands.push((
+            program(
+                versions::OTEL_COLLECTOR.directory,
+                &args.otel_collector_version,
+                "otelcol-contrib",
+            ),
+            otel_collector::otel_collector_command_args(
+                &env::temp_dir()
+                    .join("am-otel-collector-XXXXXX")
+                    .join("otel-collector.yml"),
+            ),
+        ));
+    }
+    if args.traces_enabled {
+        commands.push((
+            program(versions::TEMPO.directory, &args.tempo_version, "tempo"),
+            tempo::tempo_command_args(&env::temp_dir().join("am-tempo-XXXXXX").join("tempo.yml")),
+        ));
+    }
+    if args.loki_enabled {
+        commands.push((
+            program(versions::LOKI.directory, &args.loki_version, "loki"),
+            loki::command_args(&env::temp_dir().join("am-loki-XXXXXX").join("loki.yml")),
+        ));
+    }
+    if !args.loki_log_files.is_empty() {
+        commands.push((
+            program(versions::PROMTAIL.directory, &args.loki_version, "promtail"),
+            loki::command_args(
+                &env::temp_dir()
+                    .join("am-promtail-XXXXXX")
+                    .join("promtail.yml"),
+            ),
+        ));
+    }
+    if args.host_metrics {
+        commands.push((
+            program(
+                host_metrics::EXPORTER.directory,
+                &args.host_metrics_version,
+                host_metrics::EXPORTER.directory,
+            ),
+            host_metrics::exporter_command_args(),
+        ));
+    }
+    if args.container_metrics == Some(ContainerMetrics::Cadvisor) {
+        commands.push((
+            PathBuf::from("docker"),
+            container_metrics::cadvisor_command_args("am-cadvisor-xxxxxx"),
+        ));
+    }
+    if args.blackbox_enabled {
+        commands.push((
+            program(
+                versions::BLACKBOX_EXPORTER.directory,
+                &args.blackbox_version,
+                "blackbox_exporter",
+            ),
+            blackbox::blackbox_command_args(
+                &env::temp_dir()
+                    .join("am-blackbox-XXXXXX")
+                    .join("blackbox.yml"),
+            ),
+        ));
+    }
+    if args.alertmanager_enabled {
+        commands.push((
+            program("alertmanager", &args.alertmanager_version, "alertmanager"),
+            alertmanager_command_args(
+                &env::temp_dir()
+                    .join("am-alertmanager-XXXXXX")
+                    .join("alertmanager.yml"),
+                &work_dir("alertmanager")?,
+                &args.process_listen_address(args.ports.alertmanager),
+                args.web_scheme(),
+                &external_url,
+            ),
+        ));
+    }
+
+    for forward in &args.port_forwards {
+        let local_port = resolve_port(0, &format!("the port forward of {forward}"))?;
+        commands.push((
+            PathBuf::from("kubectl"),
+            port_forward::port_forward_command_args(
+                forward,
+                local_port,
+                args.kubeconfig.as_deref(),
+            ),
+        ));
+    }
 
-        _ = tokio::signal::ctrl_c() => {
-            info!("SIGINT signal received, exiting...");
-            Ok(())
+    println!("# Commands");
+    for (program, command_args) in &commands {
+        println!("{}", command_line(&program.to_string_lossy(), command_args));
+    }
+    if let Some(app) = &args.app {
+        if let Some((program, command_args)) = app.command().split_first() {
+            println!("{}", command_line(program, command_args));
         }
+    }
 
-        Err(err) = web_server_task => {
-            bail!("Web server exited with an error: {err:?}");
+    for (name, directory) in required_binaries(&args) {
+        if !local_data.join(directory).exists() {
+            info!("{name} is not downloaded yet, `am start` would download it");
         }
+    }
 
-        Err(err) = prometheus_task => {
-            bail!("Prometheus exited with an error: {err:?}");
-        }
+    Ok(())
+}
 
-        Err(err) = pushgateway_task => {
-            bail!("Pushgateway exited with an error: {err:?}");
-        }
+/// Format a command line so it can be copied into a shell, quoting the
+/// arguments that need it.
+fn command_line(program: &str, command_args: &[String]) -> String {
+    std::iter::once(program)
+        .chain(command_args.iter().map(String::as_str))
+        .map(|arg| {
+            if !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_=./:,@%+".contains(c))
+            {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-        else => {
-            Ok(())
-        }
+/// The binaries that are needed to run with `args`, together with the directory
+/// (relative to the local data directory) that they are installed in.
+fn required_binaries(args: &Arguments) -> Vec<(String, String)> {
+    let mut binaries = vec![("Prometheus", "prometheus", &args.prometheus_version)];
+
+    if args.pushgateway_enabled {
+        binaries.push(("Pushgateway", "pushgateway", &args.pushgateway_version));
+    }
+
+    if args.gateway_enabled {
+        binaries.push((
+            versions::GATEWAY.name,
+            versions::GATEWAY.directory,
+            &args.gateway_version,
+        ));
+    }
+
+    if args.alertmanager_enabled {
+        binaries.push(("Alertmanager", "alertmanager", &args.alertmanager_version));
+    }
+
+    if args.otel_collector_enabled {
+        binaries.push((
+            "OpenTelemetry Collector",
+            "otelcol-contrib",
+            &args.otel_collector_version,
+        ));
+    }
+
+    if args.traces_enabled {
+        binaries.push((
+            versions::TEMPO.name,
+            versions::TEMPO.directory,
+            &args.tempo_version,
+        ));
+    }
+
+    if args.loki_enabled {
+        binaries.push((
+            versions::LOKI.name,
+            versions::LOKI.directory,
+            &args.loki_version,
+        ));
     }
+
+    if !args.loki_log_files.is_empty() {
+        binaries.push((
+            versions::PROMTAIL.name,
+            versions::PROMTAIL.directory,
+            &args.loki_version,
+        ));
+    }
+
+    if args.host_metrics {
+        binaries.push((
+            host_metrics::EXPORTER.name,
+            host_metrics::EXPORTER.directory,
+            &args.host_metrics_version,
+        ));
+    }
+
+    if args.blackbox_enabled {
+        binaries.push((
+            "blackbox_exporter",
+            "blackbox_exporter",
+            &args.blackbox_version,
+        ));
+    }
+
+    binaries
+        .into_iter()
+        .map(|(name, directory, version)| {
+            let version = version.trim_start_matches('v');
+            (
+                format!("{name} v{version}"),
+                format!("{directory}-{version}"),
+            )
+        })
+        .collect()
 }
 
 /// Install the specified version of Prometheus into `prometheus_path`.
@@ -392,46 +3213,24 @@ pub async fn handle_command(args: CliArguments, config: AmConfig, mp: MultiProgr
 /// archive into. Then it will verify the downloaded archive against the
 /// downloaded checksum. Finally it will unpack the archive into
 /// `prometheus_path`.
-async fn install_prometheus(
+pub(crate) async fn install_prometheus(
     prometheus_path: &Path,
     prometheus_version: &str,
     multi_progress: MultiProgress,
 ) -> Result<()> {
     let (os, arch) = determine_os_and_arch()?;
     let base = format!("prometheus-{prometheus_version}.{os}-{arch}");
-    let package = format!("{base}.tar.gz");
+    let package = format!("{base}.{ARCHIVE_EXTENSION}");
     let prefix = format!("{base}/");
 
-    let mut prometheus_archive = NamedTempFile::new()?;
-
-    let calculated_checksum = download_github_release(
-        prometheus_archive.as_file(),
-        "prometheus",
-        "prometheus",
-        prometheus_version,
-        &package,
-        &multi_progress,
-    )
-    .await?;
-
-    verify_checksum(
-        &calculated_checksum,
+    install_release_archive(
         "prometheus",
         "prometheus",
         prometheus_version,
         &package,
-    )
-    .await?;
-
-    // Make sure we set the position to the beginning of the file so that we can
-    // unpack it.
-    prometheus_archive.as_file_mut().seek(SeekFrom::Start(0))?;
-
-    unpack(
-        prometheus_archive.as_file(),
-        "prometheus",
-        prometheus_path,
+        SHA256SUMS_FILE,
         &prefix,
+        prometheus_path,
         &multi_progress,
     )
     .await
@@ -451,114 +3250,570 @@ async fn install_pushgateway(
     let (os, arch) = determine_os_and_arch()?;
 
     let base = format!("pushgateway-{pushgateway_version}.{os}-{arch}");
-    let package = format!("{base}.tar.gz");
+    let package = format!("{base}.{ARCHIVE_EXTENSION}");
     let prefix = format!("{base}/");
 
-    let mut pushgateway_archive = NamedTempFile::new()?;
-
-    let calculated_checksum = download_github_release(
-        pushgateway_archive.as_file(),
+    install_release_archive(
         "prometheus",
         "pushgateway",
         pushgateway_version,
         &package,
+        SHA256SUMS_FILE,
+        &prefix,
+        pushgateway_path,
         &multi_progress,
     )
-    .await?;
+    .await
+}
 
-    verify_checksum(
-        &calculated_checksum,
-        "prometheus",
-        "pushgateway",
-        pushgateway_version,
-        &package,
-    )
-    .await?;
+/// Install the specified version of Alertmanager into `alertmanager_path`.
+///
+/// This function will first create a temporary file to download the
+/// Alertmanager archive into. Then it will verify the downloaded archive
+/// against the downloaded checksum. Finally it will unpack the archive into
+/// `alertmanager_path`.
+async fn install_alertmanager(
+    alertmanager_path: &Path,
+    alertmanager_version: &str,
+    multi_progress: MultiProgress,
+) -> Result<()> {
+    let (os, arch) = determine_os_and_arch()?;
 
-    // Make sure we set the position to the beginning of the file so that we can
-    // unpack it.
-    pushgateway_archive.as_file_mut().seek(SeekFrom::Start(0))?;
+    let base = format!("alertmanager-{alertmanager_version}.{os}-{arch}");
+    let package = format!("{base}.{ARCHIVE_EXTENSION}");
+    let prefix = format!("{base}/");
 
-    unpack(
-        pushgateway_archive.as_file(),
-        "pushgateway",
-        pushgateway_path,
+    install_release_archive(
+        "prometheus",
+        "alertmanager",
+        alertmanager_version,
+        &package,
+        SHA256SUMS_FILE,
         &prefix,
+        alertmanager_path,
         &multi_progress,
     )
     .await
 }
 
-/// Translates the OS and arch provided by Rust to the convention used by
-/// Prometheus.
-fn determine_os_and_arch() -> Result<(&'static str, &'static str)> {
-    use std::env::consts::{ARCH, OS};
+/// Translates the OS and arch provided by Rust to the convention used by
+/// Prometheus.
+pub(crate) fn determine_os_and_arch() -> Result<(&'static str, &'static str)> {
+    use std::env::consts::{ARCH, OS};
+
+    let os = match OS {
+        "linux" => "linux",
+        "macos" => "darwin",
+        "windows" => "windows",
+        "freebsd" => "freebsd",
+        "netbsd" => "netbsd",
+        "openbsd" => "openbsd",
+        "dragonfly" => "dragonfly",
+        _ => bail!(format!("Unsupported OS: {}", ARCH)),
+    };
+
+    let arch = match ARCH {
+        "x86" => "386",
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "s390x" => "s390x",
+        "powerpc64" => "powerpc64", // NOTE: Do we use this one, or the le one?
+        // "mips" => "mips", // NOTE: Not sure which mips to pick in this situation
+        // "arm" => "arm", // NOTE: Not sure which arm to pick in this situation
+        _ => bail!(format!("Unsupported architecture: {}", ARCH)),
+    };
+
+    Ok((os, arch))
+}
+
+/// Generate a Prometheus configuration file.
+///
+/// For now this will expand a simple template and only has support for a single
+/// endpoint.
+///
+/// The targets for file-based service discovery are returned as well, these
+/// are only used with `--file-sd`.
+fn generate_prom_config(
+    args: &Arguments,
+) -> Result<(prometheus::Config, Vec<prometheus::TargetGroup>)> {
+    let mut endpoints = args.metrics_endpoints.clone();
+
+    // Targets registered through the API can't replace the jobs that am was
+    // started with. The jobs of am itself all start with `am_`, which isn't
+    // allowed for registered targets.
+    for target in TARGETS.list() {
+        if endpoints
+            .iter()
+            .any(|endpoint| endpoint.job_name == target.job_name)
+        {
+            warn!(
+                job_name = %target.job_name,
+                "Ignoring registered target, a job with the same name already exists"
+            );
+            continue;
+        }
+
+        endpoints.push(Endpoint::new(
+            target.url,
+            target.job_name,
+            target.honor_labels,
+            target.scrape_interval,
+        ));
+    }
+
+    let (file_sd_endpoints, static_endpoints): (Vec<_>, Vec<_>) = endpoints
+        .into_iter()
+        .partition(|endpoint| args.file_sd && endpoint.supports_file_sd());
+
+    let mut scrape_configs: Vec<ScrapeConfig> =
+        static_endpoints.into_iter().map(Into::into).collect();
+
+    // This job is added even if there are no targets yet, so that targets can
+    // be added later on without changing the config.
+    if args.file_sd {
+        scrape_configs.push(ScrapeConfig {
+            job_name: FILE_SD_JOB_NAME.to_string(),
+            file_sd_configs: vec![prometheus::FileSdConfig {
+                files: vec![FILE_SD_TARGETS_FILE.to_string()],
+            }],
+            ..Default::default()
+        });
+    }
+
+    if let Some(kubernetes) = &args.kubernetes {
+        scrape_configs.push(kubernetes.clone().into());
+    }
+
+    if let Some(docker) = &args.docker {
+        scrape_configs.push(docker.clone().into());
+    }
+
+    if args.blackbox_enabled {
+        scrape_configs.extend(blackbox::scrape_configs(&args.probes));
+    }
+
+    let mut rule_files = Vec::new();
+
+    if !args.no_rules {
+        let path = env::temp_dir().join("autometrics.rules.yml");
+        let path_str = path
+            .into_os_string()
+            .into_string()
+            .map_err(|_| anyhow!("failed to convert OsString into String"))?;
+
+        rule_files.push(path_str);
+    }
+
+    for path in &args.rule_files {
+        let path_str = path
+            .clone()
+            .into_os_string()
+            .into_string()
+            .map_err(|_| anyhow!("failed to convert OsString into String"))?;
+
+        rule_files.push(path_str);
+    }
+
+    if args.downsampling {
+        let path_str = downsampling_rules_path()
+            .into_os_string()
+            .into_string()
+            .map_err(|_| anyhow!("failed to convert OsString into String"))?;
+
+        rule_files.push(path_str);
+    }
+
+    if !args.slos.is_empty() {
+        let path_str = slo_rules_path()
+            .into_os_string()
+            .into_string()
+            .map_err(|_| anyhow!("failed to convert OsString into String"))?;
+
+        rule_files.push(path_str);
+    }
+
+    // Alertmanager is served with the `/alertmanager` route prefix, since it
+    // gets the same external url as the one used by am's proxy.
+    let alerting = args
+        .alertmanager_enabled
+        .then(|| prometheus::AlertingConfig {
+            alertmanagers: vec![prometheus::AlertmanagerConfig {
+                static_configs: vec![prometheus::StaticScrapeConfig {
+                    targets: vec![format!("localhost:{}", args.ports.alertmanager)],
+                    labels: BTreeMap::new(),
+                }],
+                path_prefix: Some("/alertmanager".to_string()),
+                scheme: Some(prometheus::Scheme::Http),
+            }],
+        });
+
+    let remote_write = args
+        .remote_write
+        .iter()
+        .map(|remote_write| prometheus::RemoteWriteConfig {
+            url: remote_write.url.to_string(),
+            basic_auth: remote_write
+                .basic_auth
+                .clone()
+                .map(|auth| prometheus::BasicAuth {
+                    username: auth.username,
+                    password: auth.password,
+                }),
+            authorization: remote_write.bearer_token.clone().map(|token| {
+                prometheus::Authorization {
+                    kind: "Bearer".to_string(),
+                    credentials: token,
+                }
+            }),
+        })
+        .collect();
+
+    let prometheus_config = prometheus::Config {
+        global: prometheus::GlobalConfig {
+            scrape_interval: args.prometheus_scrape_interval,
+            evaluation_interval: "15s".to_string(),
+            external_labels: args.external_labels.clone(),
+            scrape_protocols: args.scrape_protocols.clone(),
+        },
+        scrape_configs,
+        rule_files,
+        alerting,
+        remote_write,
+    };
+    let file_sd_targets = file_sd_endpoints.into_iter().map(Into::into).collect();
+
+    Ok((prometheus_config, file_sd_targets))
+}
+
+/// The location of the rules generated for the SLOs.
+fn slo_rules_path() -> PathBuf {
+    env::temp_dir().join("am-slo.rules.yml")
+}
+
+/// Write the rules for the SLOs to [`slo_rules_path`]. Returns whether the
+/// file changed.
+fn write_slo_rules(args: &Arguments) -> Result<bool> {
+    if args.slos.is_empty() {
+        return Ok(false);
+    }
+
+    let path = slo_rules_path();
+    let rules = serde_yaml::to_string(&slo::generate_rules(&args.slos))?;
+
+    if fs::read_to_string(&path).ok().as_deref() == Some(rules.as_str()) {
+        return Ok(false);
+    }
+
+    fs::write(&path, rules)
+        .with_context(|| format!("Unable to write SLO rules to {}", path.display()))?;
+    debug!(?path, "Wrote SLO rules");
+
+    Ok(true)
+}
+
+/// The location of the downsampling recording rules.
+fn downsampling_rules_path() -> PathBuf {
+    env::temp_dir().join("am-downsampling.rules.yml")
+}
+
+/// The Prometheus config that `am start` would generate for `config`, both
+/// as generated and as rendered. Settings that are passed through environment
+/// variables are taken into account.
+pub(crate) fn preview_prom_config(config: AmConfig) -> Result<(prometheus::Config, String)> {
+    let cli_args = CliArguments::try_parse_from(["start"])?;
+    let mut args = Arguments::new(cli_args, config)?;
+    add_internal_endpoints(&mut args);
+    let (prometheus_config, _) = generate_prom_config(&args)?;
+    let rendered = render_prom_config(&args, &prometheus_config)?;
+    Ok((prometheus_config, rendered))
+}
+
+/// Serialize the Prometheus configuration, with the file of
+/// `--prometheus-config-extend` merged into it.
+fn render_prom_config(args: &Arguments, prometheus_config: &prometheus::Config) -> Result<String> {
+    let Some(path) = &args.prometheus_config_extend else {
+        return Ok(serde_yaml::to_string(prometheus_config)?);
+    };
+
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Unable to read {}", path.display()))?;
+    let extension: serde_yaml::Value = serde_yaml::from_str(&contents)
+        .with_context(|| format!("{} is not a valid YAML file", path.display()))?;
+
+    let mut config = serde_yaml::to_value(prometheus_config)?;
+    match extension {
+        serde_yaml::Value::Null => {}
+        serde_yaml::Value::Mapping(_) => merge_yaml(&mut config, extension),
+        _ => bail!("{} needs to contain a YAML mapping", path.display()),
+    }
+
+    Ok(serde_yaml::to_string(&config)?)
+}
+
+/// Merge `extension` into `base`. Mappings are merged key by key and sequences
+/// are appended to, any other value replaces the one in `base`.
+fn merge_yaml(base: &mut serde_yaml::Value, extension: serde_yaml::Value) {
+    use serde_yaml::Value;
+
+    match (base, extension) {
+        (Value::Mapping(base), Value::Mapping(extension)) => {
+            for (key, value) in extension {
+                match base.get_mut(&key) {
+                    Some(base) => merge_yaml(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Sequence(base), Value::Sequence(extension)) => base.extend(extension),
+        (base, extension) => *base = extension,
+    }
+}
+
+/// Write the rendered Prometheus configuration to `path`.
+fn write_prom_config(path: &Path, rendered: &str) -> Result<()> {
+    fs::write(path, rendered).with_context(|| {
+        format!(
+            "Unable to write the Prometheus config to {}",
+            path.display()
+        )
+    })?;
+
+    debug!(?path, "Wrote Prometheus config");
+    Ok(())
+}
+
+/// Write the targets for file-based service discovery to `path`. Returns
+/// whether the file changed.
+///
+/// Prometheus picks up changes to the file by itself, so the file is replaced
+/// at once to make sure Prometheus never reads a partially written file.
+fn write_file_sd_targets(path: &Path, targets: &[prometheus::TargetGroup]) -> Result<bool> {
+    let contents = serde_json::to_string_pretty(targets)?;
+
+    if fs::read_to_string(path).ok().as_deref() == Some(contents.as_str()) {
+        return Ok(false);
+    }
+
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("targets file does not have a parent directory"))?;
+    let mut file = NamedTempFile::new_in(dir)?;
+    file.write_all(contents.as_bytes())?;
+    file.persist(path)
+        .with_context(|| format!("Unable to write targets to {}", path.display()))?;
+    debug!(?path, "Wrote file-based service discovery targets");
+
+    Ok(true)
+}
+
+/// Add the endpoints of the services that am manages itself to the list of
+/// endpoints that Prometheus will scrape.
+fn add_internal_endpoints(args: &mut Arguments) {
+    if args.pushgateway_enabled {
+        let url = Url::parse(&format!(
+            "http://localhost:{}/pushgateway/metrics",
+            args.ports.pushgateway
+        ))
+        .unwrap();
+        let endpoint = Endpoint::new(url, "am_pushgateway".to_string(), true, None);
+        args.metrics_endpoints.push(endpoint);
+    }
+
+    if args.gateway_enabled {
+        let url = Url::parse(&format!(
+            "http://localhost:{}/gateway/metrics",
+            args.gateway_port
+        ))
+        .unwrap();
+        let endpoint = Endpoint::new(url, "am_gateway".to_string(), true, None);
+        args.metrics_endpoints.push(endpoint);
+    }
+
+    // The metrics of am itself, as well as the StatsD and Graphite metrics,
+    // are served by the web server of am.
+    // The Display of a SocketAddr puts IPv6 addresses in brackets, as URLs
+    // require.
+    let authority = if args.listen_address.ip().is_unspecified() {
+        format!("localhost:{}", args.listen_address.port())
+    } else {
+        args.listen_address.to_string()
+    };
+    let web_server = format!("{}://{authority}", args.web_scheme());
+    // The certificate of the web server is usually self-signed, and the
+    // connection never leaves this machine anyway.
+    let tls_skip_verify = args.tls.is_some();
+    let web_auth = args.web_auth.clone();
+    let mut web_server_endpoint = |path: &str, job_name: &str| {
+        let url = Url::parse(&format!("{web_server}{path}")).unwrap();
+        let mut endpoint = Endpoint::new(url, job_name.to_string(), false, None);
+        endpoint.tls_skip_verify = tls_skip_verify;
+        match &web_auth {
+            Some(WebAuth::BearerToken(token)) => endpoint.bearer_token = Some(token.clone()),
+            Some(WebAuth::Basic(basic_auth)) => endpoint.basic_auth = Some(basic_auth.clone()),
+            None => {}
+        }
+        args.metrics_endpoints.push(endpoint);
+    };
+
+    web_server_endpoint(metrics::METRICS_PATH, "am");
+
+    if args.statsd_enabled {
+        web_server_endpoint(statsd::METRICS_PATH, "am_statsd");
+    }
+
+    if args.graphite_enabled {
+        web_server_endpoint(graphite::METRICS_PATH, "am_graphite");
+    }
+
+    if args.otel_collector_enabled {
+        let url = Url::parse(&format!(
+            "http://{}/metrics",
+            otel_collector::PROMETHEUS_ENDPOINT
+        ))
+        .unwrap();
+        let endpoint = Endpoint::new(url, "am_otel_collector".to_string(), true, None);
+        args.metrics_endpoints.push(endpoint);
+    }
+
+    if args.host_metrics {
+        let url = Url::parse(&format!("http://{}/metrics", host_metrics::LISTEN_ADDRESS)).unwrap();
+        let endpoint = Endpoint::new(url, "am_host_metrics".to_string(), false, None);
+        args.metrics_endpoints.push(endpoint);
+    }
+
+    match args.container_metrics {
+        Some(ContainerMetrics::Docker) => {
+            let url = Url::parse(&format!(
+                "http://{}/metrics",
+                container_metrics::DOCKER_METRICS_ADDRESS
+            ))
+            .unwrap();
+            let endpoint = Endpoint::new(url, "am_docker".to_string(), false, None);
+            args.metrics_endpoints.push(endpoint);
+        }
+        Some(ContainerMetrics::Cadvisor) => {
+            let url = Url::parse(&format!(
+                "http://localhost:{}/metrics",
+                container_metrics::CADVISOR_PORT
+            ))
+            .unwrap();
+            // cAdvisor reports the metrics of other containers, so their
+            // labels are kept as they are.
+            let endpoint = Endpoint::new(url, "am_cadvisor".to_string(), true, None);
+            args.metrics_endpoints.push(endpoint);
+        }
+        None => {}
+    }
+}
+
+/// Add the endpoints that are found on the listening ports to the endpoints
+/// that are scraped, after letting the user pick them unless `ci` is set.
+async fn discover_endpoints(args: &mut Arguments, ci: bool) -> Result<()> {
+    info!("Looking for metrics endpoints on the ports that are listening locally...");
+    let discovered: Vec<Url> = discover::discover_endpoints(Duration::from_millis(500))
+        .await?
+        .into_iter()
+        // Skip the ports that are already scraped.
+        .filter(|url| {
+            !args.metrics_endpoints.iter().any(|endpoint| {
+                matches!(endpoint.url.host_str(), Some("localhost" | "127.0.0.1"))
+                    && endpoint.url.port_or_known_default() == url.port_or_known_default()
+            })
+        })
+        .collect();
 
-    let os = match OS {
-        "linux" => "linux",
-        "macos" => "darwin",
-        "windows" => "windows",
-        "freebsd" => "freebsd",
-        "netbsd" => "netbsd",
-        "openbsd" => "openbsd",
-        "dragonfly" => "dragonfly",
-        _ => bail!(format!("Unsupported OS: {}", ARCH)),
-    };
+    if discovered.is_empty() {
+        info!("No new metrics endpoints found");
+        return Ok(());
+    }
 
-    let arch = match ARCH {
-        "x86" => "386",
-        "x86_64" => "amd64",
-        "aarch64" => "arm64",
-        "s390x" => "s390x",
-        "powerpc64" => "powerpc64", // NOTE: Do we use this one, or the le one?
-        // "mips" => "mips", // NOTE: Not sure which mips to pick in this situation
-        // "arm" => "arm", // NOTE: Not sure which arm to pick in this situation
-        _ => bail!(format!("Unsupported architecture: {}", ARCH)),
+    let selected: Vec<usize> = if ci {
+        (0..discovered.len()).collect()
+    } else {
+        let items: Vec<_> = discovered.iter().map(Url::to_string).collect();
+        interactive::select_all_by_default(
+            "Which of the discovered endpoints should be scraped?",
+            &items,
+        )?
     };
 
-    Ok((os, arch))
+    for index in selected {
+        let url = discovered[index].clone();
+        let job_name = format!("discovered_{}", url.port().unwrap_or_default());
+        info!("Scraping discovered endpoint {url} as job {job_name}");
+        args.add_startup_endpoint(Endpoint::new(url, job_name, false, None));
+    }
+
+    Ok(())
 }
 
-/// Generate a Prometheus configuration file.
-///
-/// For now this will expand a simple template and only has support for a single
-/// endpoint.
-fn generate_prom_config(
-    scrape_interval: Duration,
-    metric_endpoints: Vec<Endpoint>,
-    enable_rules: bool,
-) -> Result<prometheus::Config> {
-    let scrape_configs = metric_endpoints.into_iter().map(Into::into).collect();
+/// Check `endpoint` until it responds, backing off between the attempts. If
+/// it doesn't respond within `deadline` the last error is returned. Without a
+/// deadline the endpoint is only checked once.
+async fn wait_for_endpoint(endpoint: &Endpoint, deadline: Option<Duration>) -> Result<()> {
+    let Some(deadline) = deadline else {
+        return check_endpoint(endpoint).await;
+    };
 
-    let mut rule_files = Vec::new();
+    let deadline = tokio::time::Instant::now() + deadline;
+    let mut backoff = Duration::from_millis(250);
 
-    if enable_rules {
-        let path = env::temp_dir().join("autometrics.rules.yml");
-        let path_str = path
-            .into_os_string()
-            .into_string()
-            .map_err(|_| anyhow!("failed to convert OsString into String"))?;
+    loop {
+        let result = check_endpoint(endpoint).await;
+        if result.is_ok() || tokio::time::Instant::now() + backoff > deadline {
+            return result;
+        }
 
-        rule_files.push(path_str);
+        debug!(
+            url = %endpoint.url,
+            "Endpoint is not responding yet, retrying in {}",
+            humantime::format_duration(backoff)
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(Duration::from_secs(5));
     }
-
-    Ok(prometheus::Config {
-        global: prometheus::GlobalConfig {
-            scrape_interval,
-            evaluation_interval: "15s".to_string(),
-        },
-        scrape_configs,
-        rule_files,
-    })
 }
 
 /// Checks whenever the endpoint works
-async fn check_endpoint(url: &Url) -> Result<()> {
-    let response = CLIENT
-        .get(url.as_str())
-        .timeout(Duration::from_secs(5))
-        .send()
-        .await?;
+async fn check_endpoint(endpoint: &Endpoint) -> Result<()> {
+    // The shared client cannot be used if the endpoint needs custom TLS
+    // settings.
+    let client = if endpoint.tls_skip_verify || endpoint.ca_cert.is_some() {
+        let mut builder =
+            network::client_builder().danger_accept_invalid_certs(endpoint.tls_skip_verify);
+
+        if let Some(ca_cert) = &endpoint.ca_cert {
+            let pem = fs::read(ca_cert)
+                .with_context(|| format!("Unable to read CA certificate {}", ca_cert.display()))?;
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+        }
+
+        builder.build()?
+    } else {
+        CLIENT.clone()
+    };
+
+    let mut request = client
+        .get(endpoint.url.as_str())
+        .timeout(Duration::from_secs(5));
+
+    if let Some(token) = &endpoint.bearer_token {
+        request = request.bearer_auth(token);
+    }
+
+    if let Some(auth) = &endpoint.basic_auth {
+        request = request.basic_auth(&auth.username, Some(&auth.password));
+    }
+
+    for (name, values) in &endpoint.params {
+        for value in values {
+            request = request.query(&[(name, value)]);
+        }
+    }
+
+    let response = request.send().await?;
 
     if !response.status().is_success() {
         bail!("endpoint did not return 2xx status code");
@@ -571,49 +3826,24 @@ async fn check_endpoint(url: &Url) -> Result<()> {
 /// stops.
 async fn start_prometheus(
     prometheus_path: &Path,
-    prometheus_config: &prometheus::Config,
-    ephemeral: bool,
-    enable_rules: bool,
+    config_file_path: &Path,
+    args: &Arguments,
     mut rx: Receiver<Option<SocketAddr>>,
 ) -> Result<()> {
     // First write needed files to temp
-    let runtime_dir = AutoCleanupDir::new(
-        &format!(
-            "am-prometheus-{}",
-            Alphanumeric.sample_string(&mut rand::thread_rng(), 6)
-        ),
-        true,
-    )?;
-
-    let config_file_path = runtime_dir.join("prometheus.yml");
-    let config_file = File::create(&config_file_path)?;
-
-    debug!(
-        path = ?config_file_path,
-        "Created temporary file for Prometheus config serialization"
-    );
-
-    serde_yaml::to_writer(&config_file, &prometheus_config)?;
-
-    if enable_rules {
+    if !args.no_rules {
         let rule_file = env::temp_dir().join("autometrics.rules.yml");
-        fs::write(
-            rule_file,
-            include_bytes!("../../../../files/autometrics-shared/autometrics.rules.yml"),
-        )?;
+        fs::write(rule_file, AUTOMETRICS_RULES)?;
     }
 
-    // TODO: Capture prometheus output into a internal buffer and expose it
-    // through an api.
-
-    let work_dir = AutoCleanupDir::new("prometheus", ephemeral)?;
+    if args.downsampling {
+        let rules = serde_yaml::to_string(&downsampling::generate_rules())?;
+        fs::write(downsampling_rules_path(), rules)?;
+    }
 
-    #[cfg(not(target_os = "windows"))]
-    let program = "prometheus";
-    #[cfg(target_os = "windows")]
-    let program = "prometheus.exe";
+    let work_dir = AutoCleanupDir::new("prometheus", args.ephemeral_working_directory)?;
 
-    let prometheus_path = prometheus_path.join(program);
+    let prometheus_path = binary_path(prometheus_path, "prometheus");
 
     info!(bin_path = ?prometheus_path.display(), "Starting prometheus");
 
@@ -622,43 +3852,97 @@ async fn start_prometheus(
         |address| address.unwrap().to_string(),
     );
 
-    let child = process::Command::new(prometheus_path)
-        .arg(format!("--config.file={}", config_file_path.display()))
-        .arg("--web.listen-address=:9090")
-        .arg("--web.enable-lifecycle")
-        .arg(format!(
-            "--web.external-url=http://{external_url}/prometheus"
+    if let Some(data_dir) = &args.prometheus_data_dir {
+        fs::create_dir_all(data_dir).with_context(|| {
+            format!(
+                "Unable to create Prometheus data directory {}",
+                data_dir.display()
+            )
+        })?;
+        debug!(data_dir = ?data_dir.display(), "Using persistent Prometheus data directory");
+    }
+
+    let child = shutdown::command(prometheus_path)
+        .args(prometheus_command_args(
+            args,
+            config_file_path,
+            &external_url,
         ))
-        .arg("--web.enable-remote-write-receiver")
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .current_dir(&work_dir)
         .spawn()
-        .context("Unable to start Prometheus")?
-        .wait_with_output()
-        .await?;
+        .context("Unable to start Prometheus")?;
 
-    if !child.status.success() {
-        if !child.stdout.is_empty() {
-            error!("Prometheus stdout:\n{}", String::from_utf8(child.stdout)?);
-        }
+    let status = status::wait(child, "prometheus").await?;
 
-        if !child.stderr.is_empty() {
-            error!("Prometheus stderr:\n{}", String::from_utf8(child.stderr)?);
+    if !status.success() && !shutdown::is_requested() {
+        let output = logs::dump("prometheus");
+        if !output.is_empty() {
+            error!("Prometheus output:\n{output}");
         }
 
-        bail!("Prometheus exited with status {}", child.status)
+        return Err(AmError::ChildCrashed {
+            process: "Prometheus".to_string(),
+            status,
+        }
+        .into());
     }
 
     Ok(())
 }
 
+/// The command line arguments of Prometheus, which is reachable through the
+/// web server of am at `external_url`.
+fn prometheus_command_args(
+    args: &Arguments,
+    config_file_path: &Path,
+    external_url: &str,
+) -> Vec<String> {
+    let mut command_args = vec![];
+    if args.agent {
+        command_args.push("--enable-feature=agent".to_string());
+    }
+    for feature in &args.prometheus_features {
+        command_args.push(format!("--enable-feature={feature}"));
+    }
+    command_args.extend([
+        format!("--config.file={}", config_file_path.display()),
+        format!(
+            "--web.listen-address={}",
+            args.process_listen_address(args.ports.prometheus)
+        ),
+        "--web.enable-lifecycle".to_string(),
+        format!(
+            "--web.external-url={}://{external_url}/prometheus",
+            args.web_scheme()
+        ),
+        "--web.enable-remote-write-receiver".to_string(),
+    ]);
+    if args.enable_admin_api {
+        command_args.push("--web.enable-admin-api".to_string());
+    }
+
+    // Without an explicit data directory, Prometheus stores its data in the
+    // `data` directory of its working directory.
+    if let Some(data_dir) = &args.prometheus_data_dir {
+        // The agent keeps its write-ahead log in a different directory.
+        let storage = if args.agent { "agent" } else { "tsdb" };
+        command_args.push(format!("--storage.{storage}.path={}", data_dir.display()));
+    }
+
+    command_args.extend(args.prometheus_args.iter().cloned());
+    command_args
+}
+
 /// Start a prometheus process. This will block until the Prometheus process
 /// stops.
 async fn start_pushgateway(
     pushgateway_path: &Path,
+    listen_address: &str,
     ephemeral: bool,
+    web_scheme: &str,
     mut rx: Receiver<Option<SocketAddr>>,
 ) -> Result<()> {
     let work_dir = AutoCleanupDir::new("pushgateway", ephemeral)?;
@@ -669,37 +3953,140 @@ async fn start_pushgateway(
     );
 
     info!("Starting Pushgateway");
-    let child = process::Command::new(pushgateway_path.join("pushgateway"))
-        .arg("--web.listen-address=:9091")
-        .arg(format!(
-            "--web.external-url=http://{external_url}/pushgateway"
+    let child = shutdown::command(binary_path(pushgateway_path, "pushgateway"))
+        .args(pushgateway_command_args(
+            listen_address,
+            web_scheme,
+            &external_url,
         ))
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .current_dir(&work_dir)
         .spawn()
-        .context("Unable to start Pushgateway")?
-        .wait_with_output()
-        .await?;
+        .context("Unable to start Pushgateway")?;
+
+    let status = status::wait(child, "pushgateway").await?;
+
+    if !status.success() && !shutdown::is_requested() {
+        let output = logs::dump("pushgateway");
+        if !output.is_empty() {
+            error!("Pushgateway output:\n{output}");
+        }
 
-    if !child.status.success() {
-        if !child.stdout.is_empty() {
-            error!("Pushgateway stdout:\n{}", String::from_utf8(child.stdout)?);
+        return Err(AmError::ChildCrashed {
+            process: "Pushgateway".to_string(),
+            status,
         }
+        .into());
+    }
+
+    Ok(())
+}
+
+fn pushgateway_command_args(
+    listen_address: &str,
+    web_scheme: &str,
+    external_url: &str,
+) -> Vec<String> {
+    vec![
+        format!("--web.listen-address={listen_address}"),
+        format!("--web.external-url={web_scheme}://{external_url}/pushgateway"),
+    ]
+}
+
+/// Start a alertmanager process. This will block until the Alertmanager
+/// process stops.
+async fn start_alertmanager(
+    alertmanager_path: &Path,
+    listen_address: &str,
+    ephemeral: bool,
+    web_scheme: &str,
+    mut rx: Receiver<Option<SocketAddr>>,
+) -> Result<()> {
+    // First write the config file to a temporary location
+    let runtime_dir = AutoCleanupDir::new(
+        &format!(
+            "am-alertmanager-{}",
+            Alphanumeric.sample_string(&mut rand::thread_rng(), 6)
+        ),
+        true,
+    )?;
+
+    let config_file_path = runtime_dir.join("alertmanager.yml");
+    let config_file = File::create(&config_file_path)?;
+
+    debug!(
+        path = ?config_file_path,
+        "Created temporary file for Alertmanager config serialization"
+    );
+
+    serde_yaml::to_writer(&config_file, &alertmanager::Config::default())?;
+
+    let work_dir = AutoCleanupDir::new("alertmanager", ephemeral)?;
+
+    let alertmanager_path = binary_path(alertmanager_path, "alertmanager");
+
+    let external_url = rx.wait_for(Option::is_some).await.map_or_else(
+        |_| "localhost:6789".to_string(),
+        |address| address.unwrap().to_string(),
+    );
+
+    info!("Starting Alertmanager");
+    let child = shutdown::command(alertmanager_path)
+        .args(alertmanager_command_args(
+            &config_file_path,
+            &work_dir,
+            listen_address,
+            web_scheme,
+            &external_url,
+        ))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .current_dir(&work_dir)
+        .spawn()
+        .context("Unable to start Alertmanager")?;
+
+    let status = status::wait(child, "alertmanager").await?;
 
-        if !child.stderr.is_empty() {
-            error!("Pushgateway stderr:\n{}", String::from_utf8(child.stderr)?);
+    if !status.success() && !shutdown::is_requested() {
+        let output = logs::dump("alertmanager");
+        if !output.is_empty() {
+            error!("Alertmanager output:\n{output}");
         }
 
-        bail!("Pushgateway exited with status {}", child.status)
+        return Err(AmError::ChildCrashed {
+            process: "Alertmanager".to_string(),
+            status,
+        }
+        .into());
     }
 
     Ok(())
 }
 
+fn alertmanager_command_args(
+    config_file_path: &Path,
+    storage_path: &Path,
+    listen_address: &str,
+    web_scheme: &str,
+    external_url: &str,
+) -> Vec<String> {
+    vec![
+        format!("--config.file={}", config_file_path.display()),
+        format!("--storage.path={}", storage_path.display()),
+        format!("--web.listen-address={listen_address}"),
+        format!("--web.external-url={web_scheme}://{external_url}/alertmanager"),
+        // Disable high availability clustering, since we only run a single
+        // instance.
+        "--cluster.listen-address=".to_string(),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{BasicAuth, WebAuth};
     use rstest::rstest;
 
     #[rstest]
@@ -712,6 +4099,11 @@ mod tests {
         "http://localhost:3030/api/observability"
     )]
     #[case(":3000", "http://localhost:3000/metrics")]
+    #[case("unix:///run/app.sock", "unix:///run/app.sock")]
+    #[case(
+        "unix:///run/app.sock?path=/api/metrics",
+        "unix:///run/app.sock?path=/api/metrics"
+    )]
     #[case(":3030/api/observability", "http://localhost:3030/api/observability")]
     fn endpoint_parser_ok(#[case] input: &str, #[case] expected: url::Url) {
         let result = super::endpoint_parser(input).expect("expected no error");
@@ -721,9 +4113,489 @@ mod tests {
     #[rstest]
     #[case("ftp://localhost")]
     #[case("not a valid url at all")]
+    #[case("unix://")]
+    #[case("unix://localhost/run/app.sock")]
     fn endpoint_parser_error(#[case] input: &str) {
         let _ = super::endpoint_parser(input).expect_err("expected a error");
         // We're not checking which specific error occurred, just that a error
         // occurred.
     }
+
+    #[rstest]
+    #[case("user:pass", "user", "pass")]
+    #[case("user:", "user", "")]
+    #[case("user:pass:with:colons", "user", "pass:with:colons")]
+    fn basic_auth_parser_ok(#[case] input: &str, #[case] username: &str, #[case] password: &str) {
+        let result = super::basic_auth_parser(input).expect("expected no error");
+        assert_eq!(username, result.username);
+        assert_eq!(password, result.password);
+    }
+
+    #[rstest]
+    #[case("user")]
+    #[case(":pass")]
+    fn basic_auth_parser_error(#[case] input: &str) {
+        let _ = super::basic_auth_parser(input).expect_err("expected a error");
+    }
+
+    #[rstest]
+    #[case("token:secret", WebAuth::BearerToken("secret".to_string()))]
+    #[case("token:with:colons", WebAuth::BearerToken("with:colons".to_string()))]
+    #[case(
+        "basic:user:pass",
+        WebAuth::Basic(BasicAuth { username: "user".to_string(), password: "pass".to_string() })
+    )]
+    fn web_auth_parser_ok(#[case] input: &str, #[case] expected: WebAuth) {
+        let result = super::web_auth_parser(input).expect("expected no error");
+        assert_eq!(expected, result);
+    }
+
+    #[rstest]
+    #[case("secret")]
+    #[case("token:")]
+    #[case("basic:user")]
+    #[case("bearer:secret")]
+    fn web_auth_parser_error(#[case] input: &str) {
+        let _ = super::web_auth_parser(input).expect_err("expected a error");
+    }
+
+    #[rstest]
+    #[case("query.timeout=1m", "--query.timeout=1m")]
+    #[case("--query.timeout=1m", "--query.timeout=1m")]
+    #[case("web.enable-admin-api", "--web.enable-admin-api")]
+    fn prometheus_arg_parser_ok(#[case] input: &str, #[case] expected: &str) {
+        let result = super::prometheus_arg_parser(input).expect("expected no error");
+        assert_eq!(expected, result);
+    }
+
+    #[rstest]
+    #[case("=value")]
+    #[case("--")]
+    #[case("query timeout=1m")]
+    fn prometheus_arg_parser_error(#[case] input: &str) {
+        let _ = super::prometheus_arg_parser(input).expect_err("expected a error");
+    }
+
+    #[test]
+    fn file_sd_target_group() {
+        let url = super::endpoint_parser("localhost:3030").unwrap();
+        let endpoint = super::Endpoint::new(
+            url,
+            "api".to_string(),
+            false,
+            Some(std::time::Duration::from_millis(1500)),
+        );
+        assert!(endpoint.supports_file_sd());
+
+        let target_group: autometrics_am::prometheus::TargetGroup = endpoint.into();
+
+        assert_eq!(target_group.targets, vec!["localhost:3030"]);
+        assert_eq!(
+            serde_json::to_value(&target_group.labels).unwrap(),
+            serde_json::json!({
+                "job": "api",
+                "__scheme__": "http",
+                "__metrics_path__": "/metrics",
+                "__scrape_interval__": "1500ms",
+            })
+        );
+    }
+
+    #[test]
+    fn scrape_timeout_and_limits() {
+        let config: autometrics_am::config::AmConfig = toml::from_str(
+            r#"
+            [[endpoint]]
+            url = "http://localhost:3030/metrics"
+            job-name = "api"
+            scrape-timeout = "5s"
+            sample-limit = 10000
+            label-limit = 30
+            "#,
+        )
+        .unwrap();
+
+        let endpoint: super::Endpoint = config.endpoints.unwrap().remove(0).try_into().unwrap();
+        assert!(!endpoint.supports_file_sd());
+
+        let scrape_config: autometrics_am::prometheus::ScrapeConfig = endpoint.into();
+        assert_eq!(
+            scrape_config.scrape_timeout,
+            Some(std::time::Duration::from_secs(5))
+        );
+        assert_eq!(scrape_config.sample_limit, Some(10000));
+        assert_eq!(scrape_config.label_limit, Some(30));
+    }
+
+    #[test]
+    fn honor_timestamps_and_scrape_protocols() {
+        let config: autometrics_am::config::AmConfig = toml::from_str(
+            r#"
+            [[endpoint]]
+            url = "http://localhost:3030/metrics"
+            job-name = "api"
+            honor-timestamps = false
+            scrape-protocols = ["PrometheusProto", "OpenMetricsText1.0.0"]
+            "#,
+        )
+        .unwrap();
+
+        let endpoint: super::Endpoint = config.endpoints.unwrap().remove(0).try_into().unwrap();
+        assert!(!endpoint.supports_file_sd());
+
+        let scrape_config: autometrics_am::prometheus::ScrapeConfig = endpoint.into();
+        assert_eq!(scrape_config.honor_timestamps, Some(false));
+        assert_eq!(
+            scrape_config.scrape_protocols,
+            vec![
+                super::ScrapeProtocol::PrometheusProto,
+                super::ScrapeProtocol::OpenMetricsText1_0_0
+            ]
+        );
+    }
+
+    #[test]
+    fn endpoint_labels() {
+        let config: autometrics_am::config::AmConfig = toml::from_str(
+            r#"
+            [[endpoint]]
+            url = "http://localhost:3030/metrics"
+            job-name = "api"
+            labels = { env = "dev", team = "billing" }
+            "#,
+        )
+        .unwrap();
+
+        let endpoint: super::Endpoint = config.endpoints.unwrap().remove(0).try_into().unwrap();
+        assert!(endpoint.supports_file_sd());
+
+        let target_group: autometrics_am::prometheus::TargetGroup = endpoint.clone().into();
+        assert_eq!(target_group.labels["team"], "billing");
+        assert_eq!(target_group.labels["job"], "api");
+
+        let scrape_config: autometrics_am::prometheus::ScrapeConfig = endpoint.into();
+        assert_eq!(
+            serde_json::to_value(&scrape_config.static_configs[0].labels).unwrap(),
+            serde_json::json!({ "env": "dev", "team": "billing" })
+        );
+    }
+
+    #[test]
+    fn merge_yaml() {
+        let mut config: serde_yaml::Value = serde_yaml::from_str(
+            "global:\n  scrape_interval: 5s\n  evaluation_interval: 30s\nscrape_configs:\n- job_name: am_0\n",
+        )
+        .unwrap();
+        let extension = serde_yaml::from_str(
+            "global:\n  scrape_interval: 15s\nscrape_configs:\n- job_name: node\nremote_read:\n- url: http://thanos:10901\n",
+        )
+        .unwrap();
+
+        super::merge_yaml(&mut config, extension);
+
+        assert_eq!(
+            serde_yaml::to_string(&config).unwrap(),
+            "global:\n  scrape_interval: 15s\n  evaluation_interval: 30s\nscrape_configs:\n- job_name: am_0\n- job_name: node\nremote_read:\n- url: http://thanos:10901\n"
+        );
+    }
+
+    #[test]
+    fn metric_relabel_configs() {
+        use autometrics_am::config::MetricRelabelConfig;
+
+        let mut endpoint = super::Endpoint::new(
+            super::endpoint_parser(":3000").unwrap(),
+            "api".to_string(),
+            false,
+            None,
+        );
+        endpoint.metric_relabel_configs = vec![
+            MetricRelabelConfig::Drop {
+                source_labels: vec![],
+                regex: "go_.*".to_string(),
+            },
+            MetricRelabelConfig::RenameLabel {
+                from: "pod".to_string(),
+                to: "instance".to_string(),
+            },
+        ];
+        assert!(!endpoint.supports_file_sd());
+
+        let scrape_config: autometrics_am::prometheus::ScrapeConfig = endpoint.into();
+
+        assert_eq!(
+            serde_json::to_value(&scrape_config.metric_relabel_configs).unwrap(),
+            serde_json::json!([
+                { "source_labels": ["__name__"], "regex": "go_.*", "action": "drop" },
+                {
+                    "source_labels": ["pod"],
+                    "regex": "(.+)",
+                    "target_label": "instance",
+                    "replacement": "$1",
+                    "action": "replace",
+                },
+                { "regex": "pod", "action": "labeldrop" },
+            ])
+        );
+    }
+
+    #[test]
+    fn federate_scrape_config() {
+        let config: autometrics_am::config::AmConfig = toml::from_str(
+            r#"
+            [[federate]]
+            url = "https://prometheus.example.com/prometheus"
+            job-name = "production"
+            match = ['{job="api"}', 'up']
+            "#,
+        )
+        .unwrap();
+
+        let federate = config.federate.unwrap().remove(0);
+        let endpoint: super::Endpoint = federate.clone().try_into().unwrap();
+        assert!(!endpoint.supports_file_sd());
+
+        let scrape_config: autometrics_am::prometheus::ScrapeConfig = endpoint.into();
+        assert_eq!(scrape_config.job_name, "production");
+        assert_eq!(
+            scrape_config.metrics_path.as_deref(),
+            Some("/prometheus/federate")
+        );
+        assert_eq!(scrape_config.honor_labels, Some(true));
+        assert_eq!(
+            serde_json::to_value(&scrape_config.params).unwrap(),
+            serde_json::json!({ "match[]": ["{job=\"api\"}", "up"] })
+        );
+
+        let without_matches = autometrics_am::config::FederateConfig {
+            matches: vec![],
+            ..federate
+        };
+        assert!(super::Endpoint::try_from(without_matches).is_err());
+    }
+
+    #[test]
+    fn avoid_running_instances() {
+        let running = vec![super::Instance {
+            pid: 1,
+            listen_address: "127.0.0.1:6789".parse().unwrap(),
+            prometheus_port: 9090,
+            pushgateway_port: Some(9095),
+            alertmanager_port: None,
+            started_at: std::time::SystemTime::now(),
+        }];
+
+        let mut listen_address = "127.0.0.1:6789".parse().unwrap();
+        let mut ports = super::Ports {
+            prometheus: 9090,
+            pushgateway: 9091,
+            alertmanager: 9093,
+        };
+        super::avoid_running_instances(&mut listen_address, &mut ports, true, true, &running)
+            .unwrap();
+        assert_eq!(listen_address.port(), 0);
+        assert_eq!(ports.prometheus, 0);
+        assert_eq!(ports.pushgateway, 9091);
+        assert_eq!(ports.alertmanager, 9093);
+
+        // Explicitly chosen ports are never moved.
+        let mut ports = super::Ports {
+            prometheus: 9092,
+            pushgateway: 9095,
+            alertmanager: 9093,
+        };
+        assert!(super::avoid_running_instances(
+            &mut listen_address,
+            &mut ports,
+            true,
+            false,
+            &running
+        )
+        .is_err());
+        assert!(super::avoid_running_instances(
+            &mut listen_address,
+            &mut ports,
+            false,
+            false,
+            &running
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn command_line() {
+        let command_args = vec![
+            "--config.file=/tmp/prometheus.yml".to_string(),
+            "--web.external-url=http://127.0.0.1:6789/prometheus".to_string(),
+            "--cluster.listen-address=".to_string(),
+            "it's here".to_string(),
+            "".to_string(),
+        ];
+
+        assert_eq!(
+            super::command_line("/opt/prometheus", &command_args),
+            r#"/opt/prometheus --config.file=/tmp/prometheus.yml --web.external-url=http://127.0.0.1:6789/prometheus --cluster.listen-address= 'it'\''s here' ''"#
+        );
+    }
+
+    #[test]
+    fn loki_log_files() {
+        use clap::Parser;
+
+        let cli_args =
+            super::CliArguments::try_parse_from(["start", "--loki-log-file", "logs/app.log"])
+                .unwrap();
+        let args = super::Arguments::new(cli_args, Default::default()).unwrap();
+
+        // Log files are sent to Loki by Promtail, so they enable Loki.
+        assert!(args.loki_enabled);
+        assert_eq!(
+            args.loki_log_files,
+            vec![std::env::current_dir().unwrap().join("logs/app.log")]
+        );
+        let version = super::loki::DEFAULT_VERSION.trim_start_matches('v');
+        let directories: Vec<_> = super::required_binaries(&args)
+            .into_iter()
+            .map(|(_, directory)| directory)
+            .collect();
+        assert!(directories.contains(&format!("loki-{version}")));
+        assert!(directories.contains(&format!("promtail-{version}")));
+    }
+
+    #[test]
+    fn admin_api_is_opt_in() {
+        use clap::Parser;
+
+        let command_args = |flags: &[&str]| {
+            let cli_args =
+                super::CliArguments::try_parse_from(["start"].iter().chain(flags)).unwrap();
+            let args = super::Arguments::new(cli_args, Default::default()).unwrap();
+            super::prometheus_command_args(
+                &args,
+                std::path::Path::new("prometheus.yml"),
+                "localhost:6789",
+            )
+        };
+
+        let admin_api = "--web.enable-admin-api".to_string();
+        assert!(!command_args(&[]).contains(&admin_api));
+        assert!(command_args(&["--enable-admin-api"]).contains(&admin_api));
+    }
+
+    #[test]
+    fn web_auth_binds_processes_to_loopback() {
+        use clap::Parser;
+
+        let args = |flags: &[&str]| {
+            let cli_args =
+                super::CliArguments::try_parse_from(["start"].iter().chain(flags)).unwrap();
+            super::Arguments::new(cli_args, Default::default()).unwrap()
+        };
+        let listen_address = |command_args: Vec<String>| {
+            command_args
+                .into_iter()
+                .find(|arg| arg.starts_with("--web.listen-address="))
+                .unwrap()
+        };
+
+        let open = args(&[]);
+        assert_eq!(
+            listen_address(super::prometheus_command_args(
+                &open,
+                std::path::Path::new("prometheus.yml"),
+                "localhost:6789",
+            )),
+            "--web.listen-address=:9090"
+        );
+
+        let protected = args(&["--web-auth", "token:secret"]);
+        assert_eq!(
+            listen_address(super::prometheus_command_args(
+                &protected,
+                std::path::Path::new("prometheus.yml"),
+                "localhost:6789",
+            )),
+            "--web.listen-address=127.0.0.1:9090"
+        );
+        assert_eq!(
+            listen_address(super::pushgateway_command_args(
+                &protected.process_listen_address(protected.ports.pushgateway),
+                "http",
+                "localhost:6789",
+            )),
+            "--web.listen-address=127.0.0.1:9091"
+        );
+
+        let tempo_config = super::tempo::tempo_config(
+            protected.process_listen_host(),
+            protected.tempo_port,
+            false,
+        );
+        assert_eq!(tempo_config.server.http_listen_address, "127.0.0.1");
+        assert_eq!(tempo_config.server.grpc_listen_address, "127.0.0.1");
+        let tempo_config =
+            super::tempo::tempo_config(open.process_listen_host(), open.tempo_port, false);
+        assert_eq!(tempo_config.server.http_listen_address, "");
+
+        let data_dir = std::path::Path::new("data");
+        let loki_config = super::loki::loki_config(
+            protected.process_listen_host(),
+            protected.loki_port,
+            data_dir,
+        );
+        assert_eq!(loki_config.server.http_listen_address, "127.0.0.1");
+        assert_eq!(loki_config.server.grpc_listen_address, "127.0.0.1");
+        let loki_config =
+            super::loki::loki_config(open.process_listen_host(), open.loki_port, data_dir);
+        assert_eq!(loki_config.server.http_listen_address, "");
+    }
+
+    #[test]
+    fn internal_endpoints_ipv6_listen_address() {
+        use clap::Parser;
+
+        let cli_args =
+            super::CliArguments::try_parse_from(["start", "--listen-address", "[::1]:6789"])
+                .unwrap();
+        let mut args = super::Arguments::new(cli_args, Default::default()).unwrap();
+        super::add_internal_endpoints(&mut args);
+
+        let am = args
+            .metrics_endpoints
+            .iter()
+            .find(|endpoint| endpoint.job_name == "am")
+            .unwrap();
+        assert_eq!(am.url.as_str(), "http://[::1]:6789/api/metrics");
+    }
+
+    #[test]
+    fn alertmanager_port() {
+        use clap::Parser;
+
+        let cli_args = super::CliArguments::try_parse_from([
+            "start",
+            "--alertmanager-enabled",
+            "true",
+            "--alertmanager-port",
+            "9193",
+        ])
+        .unwrap();
+        let args = super::Arguments::new(cli_args, Default::default()).unwrap();
+
+        let command_args = super::alertmanager_command_args(
+            std::path::Path::new("alertmanager.yml"),
+            std::path::Path::new("data"),
+            &args.process_listen_address(args.ports.alertmanager),
+            "http",
+            "localhost:6789",
+        );
+        assert!(command_args.contains(&"--web.listen-address=:9193".to_string()));
+
+        let (prometheus_config, _) = super::generate_prom_config(&args).unwrap();
+        let alerting = prometheus_config.alerting.unwrap();
+        assert_eq!(
+            alerting.alertmanagers[0].static_configs[0].targets,
+            vec!["localhost:9193".to_string()]
+        );
+    }
 }
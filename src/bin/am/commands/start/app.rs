@@ -0,0 +1,182 @@
+use super::CLIENT;
+use crate::error::AmError;
+use crate::shutdown;
+use crate::status::{self, ProcessState, STATUS};
+use crate::targets::{Target, TARGETS};
+use anyhow::{Context, Result};
+use autometrics_am::config::AppConfig;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::select;
+use tracing::{info, warn};
+use url::Url;
+
+/// How long am waits for the metrics endpoint of the application to respond,
+/// for example while it is still being compiled.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often the metrics endpoint is checked while the application starts.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The application of the user, started with `am start -- <command>` or the
+/// `[app]` section of the config file.
+#[derive(Debug, Clone)]
+pub(crate) struct App {
+    /// The program and its arguments.
+    command: Vec<String>,
+    endpoint: Option<Url>,
+    job_name: String,
+}
+
+impl App {
+    /// The arguments after `--` take precedence over the command in the config
+    /// file, which is run by the shell. Returns `None` if neither is set.
+    pub(super) fn new(
+        command: Vec<String>,
+        endpoint: Option<Url>,
+        config: Option<AppConfig>,
+    ) -> Option<Self> {
+        let config = config.unwrap_or_default();
+
+        let command = if command.is_empty() {
+            shell_command(config.command?)
+        } else {
+            command
+        };
+
+        Some(App {
+            command,
+            endpoint: endpoint.or(config.endpoint),
+            job_name: config.job_name.unwrap_or_else(|| "app".to_string()),
+        })
+    }
+
+    pub(super) fn endpoint(&self) -> Option<&Url> {
+        self.endpoint.as_ref()
+    }
+
+    pub(super) fn command(&self) -> &[String] {
+        &self.command
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell_command(command: String) -> Vec<String> {
+    vec!["sh".to_string(), "-c".to_string(), command]
+}
+
+#[cfg(target_os = "windows")]
+fn shell_command(command: String) -> Vec<String> {
+    vec!["cmd".to_string(), "/C".to_string(), command]
+}
+
+/// Start the application. Once its metrics endpoint responds, it is
+/// registered as a scrape target until the application exits. This will block
+/// until the application stops.
+pub(super) async fn start_app(app: &App) -> Result<()> {
+    let (program, args) = app
+        .command
+        .split_first()
+        .context("The command of the application is empty")?;
+
+    info!("Starting {}", app.command.join(" "));
+    STATUS.set_state("app", ProcessState::Starting);
+
+    // The output isn't captured, so it shows up like it would when the
+    // application is run on its own.
+    let child = shutdown::command(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Unable to start {program}"))?;
+
+    let wait = status::wait(child, "app");
+    tokio::pin!(wait);
+
+    let status = match &app.endpoint {
+        Some(endpoint) => {
+            let status = select! {
+                status = &mut wait => status?,
+                up = wait_for_endpoint(endpoint) => {
+                    if up {
+                        info!("{endpoint} is up, scraping it as job {}", app.job_name);
+                        TARGETS.register(Target {
+                            job_name: app.job_name.clone(),
+                            url: endpoint.clone(),
+                            scrape_interval: None,
+                            honor_labels: false,
+                        });
+                    } else {
+                        warn!(
+                            "{endpoint} did not respond within {}, not scraping it",
+                            humantime::format_duration(STARTUP_TIMEOUT)
+                        );
+                    }
+
+                    wait.await?
+                }
+            };
+
+            TARGETS.unregister(&app.job_name);
+            status
+        }
+        None => wait.await?,
+    };
+
+    if !status.success() && !shutdown::is_requested() {
+        return Err(AmError::ChildCrashed {
+            process: "The application".to_string(),
+            status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Wait until `endpoint` responds successfully. Returns `false` if it didn't
+/// within the [`STARTUP_TIMEOUT`].
+pub(super) async fn wait_for_endpoint(endpoint: &Url) -> bool {
+    let poll = async {
+        loop {
+            let response = CLIENT
+                .get(endpoint.as_str())
+                .timeout(POLL_INTERVAL * 2)
+                .send()
+                .await;
+
+            if matches!(response, Ok(response) if response.status().is_success()) {
+                return;
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    };
+
+    tokio::time::timeout(STARTUP_TIMEOUT, poll).await.is_ok()
+}
+
+#[test]
+fn test_app_command() {
+    let config = AppConfig {
+        command: Some("cargo run".to_string()),
+        endpoint: None,
+        job_name: None,
+    };
+
+    let app = App::new(vec![], None, Some(config.clone())).unwrap();
+    assert_eq!(app.command.last().unwrap(), "cargo run");
+    assert_eq!(app.job_name, "app");
+
+    let endpoint = Url::parse("http://localhost:3000/metrics").unwrap();
+    let app = App::new(
+        vec!["npm".to_string(), "start".to_string()],
+        Some(endpoint.clone()),
+        Some(config),
+    )
+    .unwrap();
+    assert_eq!(app.command, vec!["npm", "start"]);
+    assert_eq!(app.endpoint, Some(endpoint));
+
+    assert!(App::new(vec![], None, None).is_none());
+}
@@ -0,0 +1,240 @@
+use super::{binary_path, determine_os_and_arch};
+use crate::dir::AutoCleanupDir;
+use crate::downloader::{install_release_archive, ARCHIVE_EXTENSION, SHA256SUMS_FILE};
+use crate::error::AmError;
+use crate::logs;
+use crate::shutdown;
+use crate::status;
+use anyhow::{bail, Context, Result};
+use autometrics_am::blackbox;
+use autometrics_am::prometheus::{self, RelabelConfig, ScrapeConfig, StaticScrapeConfig};
+use indicatif::MultiProgress;
+use rand::distributions::{Alphanumeric, DistString};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::process::Stdio;
+use std::str::FromStr;
+use tracing::{debug, error, info};
+use url::Url;
+
+/// The address on which the blackbox_exporter listens.
+pub(super) const LISTEN_ADDRESS: &str = "localhost:9115";
+
+/// A service that is probed by the blackbox_exporter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Probe {
+    /// An http(s) URL that should respond with a 2xx status code.
+    Http(Url),
+    /// A `host:port` that should accept TCP connections, written as
+    /// `tcp://host:port`.
+    Tcp(String),
+}
+
+impl FromStr for Probe {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        if let Some(address) = input.strip_prefix("tcp://") {
+            let address = address.trim_end_matches('/');
+            match address.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                    return Ok(Probe::Tcp(address.to_string()))
+                }
+                _ => bail!("Invalid probe {input}, expected `tcp://host:port`"),
+            }
+        }
+
+        // Like the endpoints, the scheme is optional. Services that are
+        // probed are usually public websites, so https is assumed.
+        let url = if input.contains("://") {
+            Url::parse(input)
+        } else {
+            Url::parse(&format!("https://{input}"))
+        }
+        .with_context(|| format!("Unable to parse probe {input}"))?;
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            bail!("unsupported protocol {} for probe {input}", url.scheme());
+        }
+
+        Ok(Probe::Http(url))
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Probe::Http(url) => write!(f, "{url}"),
+            Probe::Tcp(address) => write!(f, "tcp://{address}"),
+        }
+    }
+}
+
+/// The scrape configs that let Prometheus probe `probes` through the
+/// blackbox_exporter, one job per module.
+///
+/// The exporter is scraped on its `/probe` endpoint with the probed service as
+/// the `target` parameter, which is also kept as the `instance` label.
+pub(super) fn scrape_configs(probes: &[Probe]) -> Vec<ScrapeConfig> {
+    let (http, tcp): (Vec<_>, Vec<_>) = probes
+        .iter()
+        .partition(|probe| matches!(probe, Probe::Http(_)));
+
+    let jobs = [
+        ("am_blackbox_http", blackbox::HTTP_MODULE, http),
+        ("am_blackbox_tcp", blackbox::TCP_MODULE, tcp),
+    ];
+
+    jobs.into_iter()
+        .filter(|(_, _, probes)| !probes.is_empty())
+        .map(|(job_name, module, probes)| ScrapeConfig {
+            job_name: job_name.to_string(),
+            static_configs: vec![StaticScrapeConfig {
+                targets: probes
+                    .iter()
+                    .map(|probe| match probe {
+                        Probe::Http(url) => url.to_string(),
+                        Probe::Tcp(address) => address.clone(),
+                    })
+                    .collect(),
+                labels: BTreeMap::new(),
+            }],
+            metrics_path: Some("/probe".to_string()),
+            params: [("module".to_string(), vec![module.to_string()])].into(),
+            scheme: Some(prometheus::Scheme::Http),
+            relabel_configs: vec![
+                RelabelConfig {
+                    source_labels: vec!["__address__".to_string()],
+                    target_label: Some("__param_target".to_string()),
+                    ..Default::default()
+                },
+                RelabelConfig {
+                    source_labels: vec!["__param_target".to_string()],
+                    target_label: Some("instance".to_string()),
+                    ..Default::default()
+                },
+                RelabelConfig {
+                    target_label: Some("__address__".to_string()),
+                    replacement: Some(LISTEN_ADDRESS.to_string()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Install the specified version of the blackbox_exporter into
+/// `blackbox_path`.
+///
+/// This function will first create a temporary file to download the exporter
+/// archive into. Then it will verify the downloaded archive against the
+/// downloaded checksum. Finally it will unpack the archive into
+/// `blackbox_path`.
+pub(super) async fn install_blackbox(
+    blackbox_path: &Path,
+    blackbox_version: &str,
+    multi_progress: MultiProgress,
+) -> Result<()> {
+    let (os, arch) = determine_os_and_arch()?;
+
+    let base = format!("blackbox_exporter-{blackbox_version}.{os}-{arch}");
+    let package = format!("{base}.{ARCHIVE_EXTENSION}");
+    let prefix = format!("{base}/");
+
+    install_release_archive(
+        "prometheus",
+        "blackbox_exporter",
+        blackbox_version,
+        &package,
+        SHA256SUMS_FILE,
+        &prefix,
+        blackbox_path,
+        &multi_progress,
+    )
+    .await
+}
+
+/// Start a blackbox_exporter process. This will block until the exporter
+/// stops.
+pub(super) async fn start_blackbox(blackbox_path: &Path) -> Result<()> {
+    // First write the config file to a temporary location
+    let runtime_dir = AutoCleanupDir::new(
+        &format!(
+            "am-blackbox-{}",
+            Alphanumeric.sample_string(&mut rand::thread_rng(), 6)
+        ),
+        true,
+    )?;
+
+    let config_file_path = runtime_dir.join("blackbox.yml");
+    let config_file = File::create(&config_file_path)?;
+
+    debug!(
+        path = ?config_file_path,
+        "Created temporary file for blackbox_exporter config serialization"
+    );
+
+    serde_yaml::to_writer(&config_file, &blackbox::Config::default())?;
+
+    info!("Starting blackbox_exporter");
+    let child = shutdown::command(binary_path(blackbox_path, "blackbox_exporter"))
+        .args(blackbox_command_args(&config_file_path))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Unable to start blackbox_exporter")?;
+
+    let status = status::wait(child, "blackbox").await?;
+
+    if !status.success() && !shutdown::is_requested() {
+        let output = logs::dump("blackbox");
+        if !output.is_empty() {
+            error!("blackbox_exporter output:\n{output}");
+        }
+
+        return Err(AmError::ChildCrashed {
+            process: "blackbox_exporter".to_string(),
+            status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+pub(super) fn blackbox_command_args(config_file_path: &Path) -> Vec<String> {
+    vec![
+        format!("--config.file={}", config_file_path.display()),
+        format!("--web.listen-address={LISTEN_ADDRESS}"),
+    ]
+}
+
+#[test]
+fn test_probe_scrape_configs() {
+    let probes: Vec<Probe> = ["https://example.com", "example.org/health", "tcp://db:5432"]
+        .iter()
+        .map(|probe| probe.parse().unwrap())
+        .collect();
+    assert_eq!(probes[1].to_string(), "https://example.org/health");
+
+    let scrape_configs = scrape_configs(&probes);
+    assert_eq!(scrape_configs.len(), 2);
+    assert_eq!(scrape_configs[0].job_name, "am_blackbox_http");
+    assert_eq!(
+        scrape_configs[0].static_configs[0].targets,
+        vec!["https://example.com/", "https://example.org/health"]
+    );
+    assert_eq!(scrape_configs[1].job_name, "am_blackbox_tcp");
+    assert_eq!(scrape_configs[1].static_configs[0].targets, vec!["db:5432"]);
+    assert_eq!(
+        scrape_configs[1].params["module"],
+        vec![blackbox::TCP_MODULE.to_string()]
+    );
+
+    assert!("tcp://db".parse::<Probe>().is_err());
+    assert!("ftp://example.com".parse::<Probe>().is_err());
+}
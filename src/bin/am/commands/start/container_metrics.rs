@@ -0,0 +1,92 @@
+use crate::error::AmError;
+use crate::logs;
+use crate::shutdown;
+use crate::status::{self, ProcessState, STATUS};
+use anyhow::{Context, Result};
+use rand::distributions::{Alphanumeric, DistString};
+use std::process::Stdio;
+use tracing::{error, info};
+
+/// The address on which the Docker daemon exposes its metrics, once
+/// `"metrics-addr": "127.0.0.1:9323"` is set in its `daemon.json`.
+pub(super) const DOCKER_METRICS_ADDRESS: &str = "localhost:9323";
+
+/// The port on localhost on which the cAdvisor container is published.
+pub(super) const CADVISOR_PORT: u16 = 9338;
+
+const CADVISOR_IMAGE: &str = "gcr.io/cadvisor/cadvisor:v0.47.2";
+
+/// The read-only mounts that cAdvisor needs to inspect the containers, as
+/// documented in its README.
+const CADVISOR_VOLUMES: [&str; 5] = [
+    "/:/rootfs:ro",
+    "/var/run:/var/run:ro",
+    "/sys:/sys:ro",
+    "/var/lib/docker/:/var/lib/docker:ro",
+    "/dev/disk/:/dev/disk:ro",
+];
+
+/// Run cAdvisor as a container, published on [`CADVISOR_PORT`]. This will
+/// block until the container stops.
+pub(super) async fn start_cadvisor() -> Result<()> {
+    let name = format!(
+        "am-cadvisor-{}",
+        Alphanumeric
+            .sample_string(&mut rand::thread_rng(), 6)
+            .to_lowercase()
+    );
+
+    let mut command = shutdown::command("docker");
+    command
+        .args(cadvisor_command_args(&name))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    info!("Starting cAdvisor");
+    STATUS.set_state("cadvisor", ProcessState::Starting);
+
+    // `docker run` forwards the signal that stops am to the container, after
+    // which the container is removed.
+    let child = command
+        .spawn()
+        .context("Unable to start cAdvisor, make sure Docker is installed and running")?;
+
+    let status = status::wait(child, "cadvisor").await?;
+
+    if !status.success() && !shutdown::is_requested() {
+        let output = logs::dump("cadvisor");
+        if !output.is_empty() {
+            error!("cAdvisor output:\n{output}");
+        }
+
+        return Err(AmError::ChildCrashed {
+            process: "cAdvisor".to_string(),
+            status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// The arguments of `docker run` for the cAdvisor container called `name`.
+pub(super) fn cadvisor_command_args(name: &str) -> Vec<String> {
+    let mut command_args: Vec<String> = ["run", "--rm", "--name", name, "--privileged"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    for volume in CADVISOR_VOLUMES {
+        command_args.extend(["--volume".to_string(), volume.to_string()]);
+    }
+    command_args.extend([
+        "--device".to_string(),
+        "/dev/kmsg".to_string(),
+        format!("--publish=127.0.0.1:{CADVISOR_PORT}:8080"),
+        CADVISOR_IMAGE.to_string(),
+        // Only the Docker containers are interesting, not every cgroup of the
+        // host.
+        "--docker_only=true".to_string(),
+    ]);
+    command_args
+}
@@ -0,0 +1,89 @@
+use autometrics_am::prometheus::{DockerSdConfig, RelabelAction, RelabelConfig, ScrapeConfig};
+use itertools::Itertools;
+use std::time::Duration;
+
+/// The label that a container can set to `true` to be scraped.
+const SCRAPE_LABEL: &str = "__meta_docker_container_label_autometrics_dev_scrape";
+
+/// The label that optionally overrides the metrics path of a container.
+const PATH_LABEL: &str = "__meta_docker_container_label_autometrics_dev_path";
+
+/// The label that optionally overrides the metrics port of a container.
+const PORT_LABEL: &str = "__meta_docker_container_label_autometrics_dev_port";
+
+/// Settings for discovering scrape targets through the Docker daemon.
+///
+/// A container is scraped if it has the `autometrics.dev/scrape=true` label,
+/// or if it exposes one of the `metrics_ports`. Prometheus will query the
+/// daemon every `refresh_interval`, so containers that are started or stopped
+/// will be picked up automatically.
+#[derive(Debug, Clone)]
+pub struct DockerDiscovery {
+    pub host: String,
+    pub metrics_ports: Vec<u16>,
+    pub refresh_interval: Duration,
+}
+
+impl From<DockerDiscovery> for ScrapeConfig {
+    fn from(discovery: DockerDiscovery) -> Self {
+        // Prometheus creates a target for every port of a container, so we
+        // either keep the labeled containers, or the targets of which the
+        // private port is one of the metrics ports.
+        let keep_regex = if discovery.metrics_ports.is_empty() {
+            "true;.*".to_string()
+        } else {
+            format!(
+                "true;.*|[^;]*;({})",
+                discovery.metrics_ports.iter().join("|")
+            )
+        };
+
+        let relabel_configs = vec![
+            RelabelConfig {
+                source_labels: vec![
+                    SCRAPE_LABEL.to_string(),
+                    "__meta_docker_port_private".to_string(),
+                ],
+                separator: Some(";".to_string()),
+                regex: Some(keep_regex),
+                action: Some(RelabelAction::Keep),
+                ..Default::default()
+            },
+            RelabelConfig {
+                source_labels: vec![PATH_LABEL.to_string()],
+                regex: Some("(.+)".to_string()),
+                target_label: Some("__metrics_path__".to_string()),
+                action: Some(RelabelAction::Replace),
+                ..Default::default()
+            },
+            RelabelConfig {
+                source_labels: vec![
+                    "__meta_docker_network_ip".to_string(),
+                    PORT_LABEL.to_string(),
+                ],
+                separator: Some(";".to_string()),
+                regex: Some(r"(.+);(\d+)".to_string()),
+                replacement: Some("$1:$2".to_string()),
+                target_label: Some("__address__".to_string()),
+                action: Some(RelabelAction::Replace),
+            },
+            RelabelConfig {
+                source_labels: vec!["__meta_docker_container_name".to_string()],
+                regex: Some("/?(.*)".to_string()),
+                target_label: Some("container".to_string()),
+                action: Some(RelabelAction::Replace),
+                ..Default::default()
+            },
+        ];
+
+        ScrapeConfig {
+            job_name: "am_docker".to_string(),
+            docker_sd_configs: vec![DockerSdConfig {
+                host: discovery.host,
+                refresh_interval: discovery.refresh_interval,
+            }],
+            relabel_configs,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{bail, Result};
+use semver_rs::Version;
+use tracing::warn;
+
+/// A feature of Prometheus that is enabled with `--enable-feature`.
+struct Feature {
+    name: &'static str,
+    /// The Prometheus version that added the feature.
+    since: &'static str,
+}
+
+const FEATURES: [Feature; 13] = [
+    Feature {
+        name: "exemplar-storage",
+        since: "2.26.0",
+    },
+    Feature {
+        name: "expand-external-labels",
+        since: "2.27.0",
+    },
+    Feature {
+        name: "memory-snapshot-on-shutdown",
+        since: "2.30.0",
+    },
+    Feature {
+        name: "new-service-discovery-manager",
+        since: "2.30.0",
+    },
+    Feature {
+        name: "extra-scrape-metrics",
+        since: "2.31.0",
+    },
+    Feature {
+        name: "promql-per-step-stats",
+        since: "2.35.0",
+    },
+    Feature {
+        name: "native-histograms",
+        since: "2.40.0",
+    },
+    Feature {
+        name: "no-default-scrape-port",
+        since: "2.43.0",
+    },
+    Feature {
+        name: "auto-gomaxprocs",
+        since: "2.44.0",
+    },
+    Feature {
+        name: "promql-experimental-functions",
+        since: "2.44.0",
+    },
+    Feature {
+        name: "otlp-write-receiver",
+        since: "2.47.0",
+    },
+    Feature {
+        name: "created-timestamp-zero-ingestion",
+        since: "2.50.0",
+    },
+    Feature {
+        name: "auto-gomemlimit",
+        since: "2.50.0",
+    },
+];
+
+/// Check that Prometheus `version` has all of the `features`. Prometheus only
+/// logs the features that it doesn't know about, so this refuses the ones
+/// that were added in a later version. Features that am doesn't know about
+/// are passed on as they are.
+pub(super) fn check(features: &[String], version: &str) -> Result<()> {
+    let Ok(parsed) = Version::new(version.trim_start_matches('v')).parse() else {
+        return Ok(());
+    };
+
+    for name in features {
+        match FEATURES.iter().find(|feature| feature.name == name) {
+            Some(feature) => {
+                let since = Version::new(feature.since).parse().expect("valid version");
+                if parsed < since {
+                    bail!(
+                        "The Prometheus feature `{name}` requires Prometheus {} or newer, but {version} is used. Select a newer version with --prometheus-version",
+                        feature.since
+                    );
+                }
+            }
+            None => warn!("Unknown Prometheus feature `{name}`, Prometheus {version} ignores it if it doesn't support it"),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_check() {
+    let features = vec![
+        "exemplar-storage".to_string(),
+        "native-histograms".to_string(),
+    ];
+    assert!(check(&features, "v2.45.0").is_ok());
+
+    let err = check(&features, "v2.39.0").unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("The Prometheus feature `native-histograms` requires Prometheus 2.40.0"));
+
+    assert!(check(&["some-future-feature".to_string()], "v2.45.0").is_ok());
+}
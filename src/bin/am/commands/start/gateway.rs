@@ -0,0 +1,112 @@
+use super::{binary_path, determine_os_and_arch};
+use crate::dir::AutoCleanupDir;
+use crate::downloader::{install_release_archive, ARCHIVE_EXTENSION, SHA256SUMS_FILE};
+use crate::error::AmError;
+use crate::logs;
+use crate::shutdown;
+use crate::status;
+use anyhow::{Context, Result};
+use indicatif::MultiProgress;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::sync::watch::Receiver;
+use tracing::{error, info};
+
+pub(crate) const DEFAULT_VERSION: &str = "v0.1.0";
+
+pub(super) const DEFAULT_PORT: u16 = 9092;
+
+/// The name of the program in the archive and of the directory in it.
+const PROGRAM: &str = "autometrics-gateway";
+
+/// Install the specified version of the autometrics gateway into
+/// `gateway_path`.
+///
+/// This function will first create a temporary file to download the gateway
+/// archive into. Then it will verify the downloaded archive against the
+/// downloaded checksum. Finally it will unpack the archive into
+/// `gateway_path`.
+pub(super) async fn install_gateway(
+    gateway_path: &Path,
+    gateway_version: &str,
+    multi_progress: MultiProgress,
+) -> Result<()> {
+    let (os, arch) = determine_os_and_arch()?;
+
+    let base = format!("{PROGRAM}-{gateway_version}.{os}-{arch}");
+    let package = format!("{base}.{ARCHIVE_EXTENSION}");
+    let prefix = format!("{base}/");
+
+    install_release_archive(
+        "autometrics-dev",
+        PROGRAM,
+        gateway_version,
+        &package,
+        SHA256SUMS_FILE,
+        &prefix,
+        gateway_path,
+        &multi_progress,
+    )
+    .await
+}
+
+/// Start a gateway process. This will block until the gateway process stops.
+pub(super) async fn start_gateway(
+    gateway_path: &Path,
+    listen_address: &str,
+    ephemeral: bool,
+    web_scheme: &str,
+    mut rx: Receiver<Option<SocketAddr>>,
+) -> Result<()> {
+    let work_dir = AutoCleanupDir::new("gateway", ephemeral)?;
+
+    let external_url = rx.wait_for(Option::is_some).await.map_or_else(
+        |_| "localhost:6789".to_string(),
+        |address| address.unwrap().to_string(),
+    );
+
+    info!("Starting autometrics gateway");
+    let child = shutdown::command(binary_path(gateway_path, PROGRAM))
+        .args(gateway_command_args(
+            listen_address,
+            web_scheme,
+            &external_url,
+        ))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .current_dir(&work_dir)
+        .spawn()
+        .context("Unable to start the autometrics gateway")?;
+
+    let status = status::wait(child, "gateway").await?;
+
+    if !status.success() && !shutdown::is_requested() {
+        let output = logs::dump("gateway");
+        if !output.is_empty() {
+            error!("Autometrics gateway output:\n{output}");
+        }
+
+        return Err(AmError::ChildCrashed {
+            process: "The autometrics gateway".to_string(),
+            status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// The arguments of the gateway, which serves its API and the metrics that
+/// were pushed to it under `/gateway`, like the web server of am proxies it.
+pub(super) fn gateway_command_args(
+    listen_address: &str,
+    web_scheme: &str,
+    external_url: &str,
+) -> Vec<String> {
+    vec![
+        format!("--web.listen-address={listen_address}"),
+        format!("--web.external-url={web_scheme}://{external_url}/gateway"),
+    ]
+}
@@ -0,0 +1,184 @@
+use super::statsd::{format_labels, sanitize_name};
+use anyhow::{Context, Result};
+use autometrics_am::config::GraphiteMapping;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+
+/// The metrics received by the Graphite listener, until Prometheus scrapes
+/// them.
+pub(crate) static GRAPHITE: Lazy<Store> = Lazy::new(Store::default);
+
+/// The port on which Graphite clients send their metrics by default.
+pub(crate) const DEFAULT_PORT: u16 = 2003;
+
+/// The path on the web server of am on which the metrics are exposed.
+pub(crate) const METRICS_PATH: &str = "/api/graphite/metrics";
+
+/// Graphite metrics are only sent when they change, so they are kept around
+/// for a while before they are considered gone, like the graphite_exporter
+/// does.
+const EXPIRY: Duration = Duration::from_secs(300);
+
+/// The last value of every series of a metric, by their labels, and when it
+/// was received.
+type Series = BTreeMap<Vec<(String, String)>, (f64, Instant)>;
+
+#[derive(Debug, Default)]
+pub(crate) struct Store {
+    mappings: Mutex<Vec<GraphiteMapping>>,
+    series: Mutex<BTreeMap<String, Series>>,
+}
+
+impl Store {
+    fn record(&self, line: &str) -> Option<()> {
+        let mut fields = line.split_whitespace();
+        let path = fields.next()?;
+        let value: f64 = fields.next()?.parse().ok()?;
+        // The timestamp is ignored, Prometheus uses the time of the scrape.
+
+        let (name, labels) = map_path(path, &self.mappings.lock().unwrap());
+        self.series
+            .lock()
+            .unwrap()
+            .entry(name)
+            .or_default()
+            .insert(labels, (value, Instant::now()));
+
+        Some(())
+    }
+
+    /// The metrics as gauges in the Prometheus text format.
+    pub(crate) fn render(&self) -> String {
+        let mut output = String::new();
+        let mut series = self.series.lock().unwrap();
+
+        series.retain(|_, values| {
+            values.retain(|_, (_, updated)| updated.elapsed() < EXPIRY);
+            !values.is_empty()
+        });
+
+        for (name, values) in series.iter() {
+            let _ = writeln!(output, "# TYPE {name} gauge");
+            for (labels, (value, _)) in values {
+                let _ = writeln!(output, "{name}{} {value}", format_labels(labels, None));
+            }
+        }
+
+        output
+    }
+}
+
+/// Turn a Graphite path into a metric name and labels with the first of the
+/// `mappings` that matches it. Paths without a mapping become a metric
+/// without labels, with the dots replaced by underscores.
+fn map_path(path: &str, mappings: &[GraphiteMapping]) -> (String, Vec<(String, String)>) {
+    let components: Vec<&str> = path.split('.').collect();
+
+    for mapping in mappings {
+        let pattern: Vec<&str> = mapping.pattern.split('.').collect();
+        if pattern.len() != components.len() {
+            continue;
+        }
+
+        let mut captures = vec![];
+        let matches = pattern.iter().zip(&components).all(|(pattern, component)| {
+            if *pattern == "*" {
+                captures.push(*component);
+                true
+            } else {
+                pattern == component
+            }
+        });
+
+        if matches {
+            let expand = |template: &str| {
+                // Replace the highest numbers first, so `$1` doesn't match the
+                // start of `$10`.
+                captures.iter().enumerate().rev().fold(
+                    template.to_string(),
+                    |template, (index, capture)| {
+                        template.replace(&format!("${}", index + 1), capture)
+                    },
+                )
+            };
+
+            let labels = mapping
+                .labels
+                .iter()
+                .map(|(key, value)| (sanitize_name(key), expand(value)))
+                .collect();
+            return (sanitize_name(&expand(&mapping.name)), labels);
+        }
+    }
+
+    (sanitize_name(path), vec![])
+}
+
+/// Listen for Graphite plaintext metrics (`<path> <value> <timestamp>`) on TCP
+/// `port` of localhost, which are mapped with `mappings` and kept in
+/// [`GRAPHITE`]. This never returns.
+pub(super) async fn listen(port: u16, mappings: Vec<GraphiteMapping>) -> Result<()> {
+    *GRAPHITE.mappings.lock().unwrap() = mappings;
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .with_context(|| format!("Unable to listen for Graphite metrics on port {port}"))?;
+    info!("Listening for Graphite metrics on tcp://127.0.0.1:{port}");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stream).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if GRAPHITE.record(&line).is_none() {
+                    debug!("Ignoring invalid Graphite metric {line:?}");
+                }
+            }
+        });
+    }
+}
+
+#[test]
+fn test_map_path() {
+    let mappings = vec![GraphiteMapping {
+        pattern: "servers.*.cpu.*".to_string(),
+        name: "server_cpu".to_string(),
+        labels: BTreeMap::from([
+            ("host".to_string(), "$1".to_string()),
+            ("mode".to_string(), "$2".to_string()),
+        ]),
+    }];
+
+    assert_eq!(
+        map_path("servers.web-1.cpu.user", &mappings),
+        (
+            "server_cpu".to_string(),
+            vec![
+                ("host".to_string(), "web-1".to_string()),
+                ("mode".to_string(), "user".to_string())
+            ]
+        )
+    );
+    assert_eq!(
+        map_path("servers.web-1.memory.used", &mappings),
+        ("servers_web_1_memory_used".to_string(), vec![])
+    );
+
+    let store = Store::default();
+    *store.mappings.lock().unwrap() = mappings;
+    assert!(store
+        .record("servers.web-1.cpu.user 0.25 1700000000")
+        .is_some());
+    assert!(store.record("servers.web-1.cpu.user").is_none());
+    assert_eq!(
+        store.render(),
+        "# TYPE server_cpu gauge\nserver_cpu{host=\"web-1\",mode=\"user\"} 0.25\n"
+    );
+}
@@ -0,0 +1,126 @@
+use super::{binary_path, determine_os_and_arch};
+use crate::downloader::SHA256SUMS_FILE;
+use crate::error::AmError;
+use crate::logs;
+use crate::shutdown;
+use crate::status;
+use crate::versions::{self, Program};
+use anyhow::{Context, Result};
+use indicatif::MultiProgress;
+use std::path::Path;
+use std::process::Stdio;
+use tracing::{error, info};
+
+/// The exporter that exposes the CPU, memory, disk and network metrics of the
+/// machine am runs on.
+#[cfg(not(target_os = "windows"))]
+pub(crate) const EXPORTER: Program = versions::NODE_EXPORTER;
+#[cfg(target_os = "windows")]
+pub(crate) const EXPORTER: Program = versions::WINDOWS_EXPORTER;
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) const DEFAULT_VERSION: &str = "v1.6.1";
+#[cfg(target_os = "windows")]
+pub(crate) const DEFAULT_VERSION: &str = "v0.24.0";
+
+/// The address on which the exporter listens.
+pub(super) const LISTEN_ADDRESS: &str = "localhost:9100";
+
+/// Install the specified version of node_exporter into `exporter_path`.
+#[cfg(not(target_os = "windows"))]
+pub(super) async fn install_exporter(
+    exporter_path: &Path,
+    exporter_version: &str,
+    multi_progress: MultiProgress,
+) -> Result<()> {
+    use crate::downloader::{install_release_archive, ARCHIVE_EXTENSION};
+
+    let (os, arch) = determine_os_and_arch()?;
+
+    let base = format!("node_exporter-{exporter_version}.{os}-{arch}");
+    let package = format!("{base}.{ARCHIVE_EXTENSION}");
+    let prefix = format!("{base}/");
+
+    install_release_archive(
+        EXPORTER.org,
+        EXPORTER.repo,
+        exporter_version,
+        &package,
+        SHA256SUMS_FILE,
+        &prefix,
+        exporter_path,
+        &multi_progress,
+    )
+    .await
+}
+
+/// Install the specified version of windows_exporter into `exporter_path`.
+///
+/// windows_exporter is released as a plain executable instead of an archive,
+/// so it is downloaded into `exporter_path` directly.
+#[cfg(target_os = "windows")]
+pub(super) async fn install_exporter(
+    exporter_path: &Path,
+    exporter_version: &str,
+    multi_progress: MultiProgress,
+) -> Result<()> {
+    use crate::downloader::download_release;
+
+    let (_, arch) = determine_os_and_arch()?;
+
+    let package = format!("windows_exporter-{exporter_version}-{arch}.exe");
+
+    // The executable is only moved into place once it is verified, so a
+    // failed install doesn't leave a broken install behind.
+    let exporter_file = download_release(
+        EXPORTER.org,
+        EXPORTER.repo,
+        exporter_version,
+        &package,
+        SHA256SUMS_FILE,
+        &multi_progress,
+    )
+    .await?;
+
+    std::fs::create_dir_all(exporter_path)?;
+    std::fs::copy(
+        exporter_file.path(),
+        binary_path(exporter_path, EXPORTER.directory),
+    )
+    .context("Unable to install windows_exporter")?;
+
+    Ok(())
+}
+
+/// Start the exporter. This will block until the exporter stops.
+pub(super) async fn start_exporter(exporter_path: &Path) -> Result<()> {
+    info!("Starting {}", EXPORTER.name);
+    let child = shutdown::command(binary_path(exporter_path, EXPORTER.directory))
+        .args(exporter_command_args())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Unable to start {}", EXPORTER.name))?;
+
+    let status = status::wait(child, "host-metrics").await?;
+
+    if !status.success() && !shutdown::is_requested() {
+        let output = logs::dump("host-metrics");
+        if !output.is_empty() {
+            error!("{} output:\n{output}", EXPORTER.name);
+        }
+
+        return Err(AmError::ChildCrashed {
+            process: EXPORTER.name.to_string(),
+            status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+pub(super) fn exporter_command_args() -> Vec<String> {
+    vec![format!("--web.listen-address={LISTEN_ADDRESS}")]
+}
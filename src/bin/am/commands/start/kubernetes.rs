@@ -0,0 +1,120 @@
+use autometrics_am::prometheus::{
+    KubernetesNamespaces, KubernetesRole, KubernetesSdConfig, KubernetesSelector, RelabelAction,
+    RelabelConfig, ScrapeConfig,
+};
+use std::path::PathBuf;
+
+/// The annotation that a pod needs to have (set to `true`) to be scraped.
+const SCRAPE_ANNOTATION: &str = "__meta_kubernetes_pod_annotation_autometrics_dev_scrape";
+
+/// The annotation that optionally overrides the metrics path of a pod.
+const PATH_ANNOTATION: &str = "__meta_kubernetes_pod_annotation_autometrics_dev_path";
+
+/// The annotation that optionally overrides the metrics port of a pod.
+const PORT_ANNOTATION: &str = "__meta_kubernetes_pod_annotation_autometrics_dev_port";
+
+/// Settings for discovering scrape targets through the Kubernetes API.
+///
+/// Pods are only scraped if they have the `autometrics.dev/scrape: "true"`
+/// annotation. The `autometrics.dev/path` and `autometrics.dev/port`
+/// annotations can be used to override the path and port that will be scraped.
+#[derive(Debug, Clone)]
+pub struct KubernetesDiscovery {
+    pub namespaces: Vec<String>,
+    pub label_selector: Option<String>,
+    pub kubeconfig: Option<PathBuf>,
+}
+
+impl From<KubernetesDiscovery> for ScrapeConfig {
+    fn from(discovery: KubernetesDiscovery) -> Self {
+        let namespaces = if discovery.namespaces.is_empty() {
+            None
+        } else {
+            Some(KubernetesNamespaces {
+                names: discovery.namespaces,
+            })
+        };
+
+        let selectors = discovery
+            .label_selector
+            .map(|label| KubernetesSelector {
+                role: KubernetesRole::Pod,
+                label: Some(label),
+            })
+            .into_iter()
+            .collect();
+
+        let relabel_configs = vec![
+            RelabelConfig {
+                source_labels: vec![SCRAPE_ANNOTATION.to_string()],
+                regex: Some("true".to_string()),
+                action: Some(RelabelAction::Keep),
+                ..Default::default()
+            },
+            RelabelConfig {
+                source_labels: vec![PATH_ANNOTATION.to_string()],
+                regex: Some("(.+)".to_string()),
+                target_label: Some("__metrics_path__".to_string()),
+                action: Some(RelabelAction::Replace),
+                ..Default::default()
+            },
+            RelabelConfig {
+                source_labels: vec!["__address__".to_string(), PORT_ANNOTATION.to_string()],
+                regex: Some(r"([^:]+)(?::\d+)?;(\d+)".to_string()),
+                replacement: Some("$1:$2".to_string()),
+                target_label: Some("__address__".to_string()),
+                action: Some(RelabelAction::Replace),
+                ..Default::default()
+            },
+            RelabelConfig {
+                source_labels: vec!["__meta_kubernetes_namespace".to_string()],
+                target_label: Some("namespace".to_string()),
+                action: Some(RelabelAction::Replace),
+                ..Default::default()
+            },
+            RelabelConfig {
+                source_labels: vec!["__meta_kubernetes_pod_name".to_string()],
+                target_label: Some("pod".to_string()),
+                action: Some(RelabelAction::Replace),
+                ..Default::default()
+            },
+        ];
+
+        ScrapeConfig {
+            job_name: "am_kubernetes".to_string(),
+            kubernetes_sd_configs: vec![KubernetesSdConfig {
+                role: KubernetesRole::Pod,
+                kubeconfig_file: discovery
+                    .kubeconfig
+                    .map(|path| path.to_string_lossy().into_owned()),
+                namespaces,
+                selectors,
+            }],
+            relabel_configs,
+            ..Default::default()
+        }
+    }
+}
+
+/// Resolve the kubeconfig file that Prometheus should use to connect to the
+/// cluster. This follows the same rules as `kubectl`: an explicitly configured
+/// file, the first entry of `KUBECONFIG` and finally `~/.kube/config`.
+///
+/// Returns `None` if none of these exist, in which case Prometheus will try to
+/// use the in-cluster configuration.
+pub fn resolve_kubeconfig(explicit: Option<PathBuf>) -> Option<PathBuf> {
+    if explicit.is_some() {
+        return explicit;
+    }
+
+    if let Some(path) = std::env::var_os("KUBECONFIG")
+        .as_ref()
+        .and_then(|paths| std::env::split_paths(paths).next())
+    {
+        return Some(path);
+    }
+
+    directories::BaseDirs::new()
+        .map(|dirs| dirs.home_dir().join(".kube").join("config"))
+        .filter(|path| path.exists())
+}
@@ -0,0 +1,126 @@
+use super::{binary_path, determine_os_and_arch, run_with_config};
+use crate::dir::AutoCleanupDir;
+use crate::downloader::install_release_archive;
+use anyhow::{Context, Result};
+use autometrics_am::loki;
+use indicatif::MultiProgress;
+use std::env;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+pub(crate) const DEFAULT_VERSION: &str = "v2.9.3";
+
+pub(super) const DEFAULT_PORT: u16 = 3100;
+
+/// The job of the log lines that Promtail reads from the log files.
+const LOG_FILES_JOB: &str = "am_logs";
+
+/// Install the specified version of Loki, or of Promtail which is released
+/// alongside it, into `path`.
+///
+/// This function will first create a temporary file to download the zip
+/// archive into. Then it will verify the downloaded archive against the
+/// downloaded checksum. Finally it will unpack the archive into `path`.
+pub(super) async fn install(
+    program: &str,
+    path: &Path,
+    version: &str,
+    multi_progress: MultiProgress,
+) -> Result<()> {
+    let (os, arch) = determine_os_and_arch()?;
+
+    // The archive contains a single binary with the same name.
+    let binary = format!("{program}-{os}-{arch}{}", env::consts::EXE_SUFFIX);
+    let package = format!("{binary}.zip");
+
+    install_release_archive(
+        "grafana",
+        "loki",
+        version,
+        &package,
+        "SHA256SUMS",
+        "",
+        path,
+        &multi_progress,
+    )
+    .await?;
+
+    // Give the binary a name that doesn't depend on the platform.
+    std::fs::rename(path.join(&binary), binary_path(path, program))
+        .with_context(|| format!("Unable to find {binary} in the {program} archive"))
+}
+
+/// Start a Loki process that listens on `listen_address` and `port`. This will
+/// block until the Loki process stops.
+pub(super) async fn start_loki(
+    loki_path: &Path,
+    listen_address: &str,
+    port: u16,
+    ephemeral: bool,
+) -> Result<()> {
+    let work_dir = AutoCleanupDir::new("loki", ephemeral)?;
+
+    let config = loki_config(listen_address, port, &work_dir);
+
+    info!("Starting Loki");
+    info!("Loki push endpoint: http://localhost:{port}/loki/api/v1/push");
+
+    run_with_config(
+        loki_path,
+        "loki",
+        "loki",
+        "Loki",
+        &config,
+        command_args,
+        &work_dir,
+    )
+    .await
+}
+
+/// The config of a Loki that serves its API on `listen_address` and `port`,
+/// and stores the logs in `work_dir`.
+pub(super) fn loki_config(listen_address: &str, port: u16, work_dir: &Path) -> loki::Config {
+    // The gRPC server isn't used by am, so it listens on a random port instead
+    // of a fixed one that could clash with Tempo or another instance of am.
+    loki::Config::new(listen_address, port, 0, &work_dir.to_string_lossy())
+}
+
+/// Start a Promtail process which sends the lines of the `log_files` to the
+/// Loki on `loki_port`. This will block until the Promtail process stops.
+pub(super) async fn start_promtail(
+    promtail_path: &Path,
+    log_files: &[PathBuf],
+    loki_port: u16,
+    ephemeral: bool,
+) -> Result<()> {
+    // Promtail remembers how far it has read the files in its working
+    // directory, so it doesn't send the same lines twice after a restart.
+    let work_dir = AutoCleanupDir::new("promtail", ephemeral)?;
+
+    let log_files: Vec<_> = log_files
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    let config = loki::PromtailConfig::new(LOG_FILES_JOB, &log_files, loki_port);
+
+    info!("Starting Promtail");
+    for path in &log_files {
+        debug!("Sending the lines of {path} to Loki");
+    }
+
+    run_with_config(
+        promtail_path,
+        "promtail",
+        "promtail",
+        "Promtail",
+        &config,
+        command_args,
+        &work_dir,
+    )
+    .await
+}
+
+/// The arguments of both Loki and Promtail.
+pub(super) fn command_args(config_file_path: &Path) -> Vec<String> {
+    vec![format!("-config.file={}", config_file_path.display())]
+}
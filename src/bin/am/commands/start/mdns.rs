@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::net::SocketAddr;
+use tracing::{debug, info, warn};
+
+/// The mDNS service type under which the web server of am is announced.
+pub(crate) const SERVICE_TYPE: &str = "_autometrics._tcp.local.";
+
+/// Unregisters the announced services once it is dropped, so they disappear
+/// from the network as soon as am exits.
+struct Announcement {
+    daemon: ServiceDaemon,
+    fullnames: Vec<String>,
+}
+
+impl Drop for Announcement {
+    fn drop(&mut self) {
+        for fullname in &self.fullnames {
+            if let Err(err) = self.daemon.unregister(fullname) {
+                debug!(?err, "Unable to unregister mDNS service {fullname}");
+            }
+        }
+
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Announce the web server on every listen address that can be reached from
+/// other machines, until the returned future is dropped.
+pub(crate) async fn announce(listen_addresses: Vec<SocketAddr>, scheme: &str) -> Result<()> {
+    let listen_addresses: Vec<_> = listen_addresses
+        .into_iter()
+        .filter(|listen_address| !listen_address.ip().is_loopback())
+        .collect();
+
+    if listen_addresses.is_empty() {
+        warn!("Not announcing am through mDNS, as it only listens on localhost. Use --listen-address 0.0.0.0:6789 to make it reachable from other machines");
+        return Ok(());
+    }
+
+    let hostname = gethostname::gethostname().to_string_lossy().into_owned();
+    let hostname = hostname.trim_end_matches('.').trim_end_matches(".local");
+    let properties = [
+        ("version", env!("CARGO_PKG_VERSION")),
+        ("scheme", scheme),
+        ("path", "/explorer/"),
+    ];
+
+    let mut announcement = Announcement {
+        daemon: ServiceDaemon::new().context("Unable to start the mDNS responder")?,
+        fullnames: vec![],
+    };
+
+    for listen_address in listen_addresses {
+        let instance_name = format!("am on {hostname}:{}", listen_address.port());
+        let host_name = format!("{hostname}.local.");
+
+        // For the unspecified address (`0.0.0.0`) the addresses of all the
+        // network interfaces are announced, and kept up to date.
+        let service = if listen_address.ip().is_unspecified() {
+            ServiceInfo::new(
+                SERVICE_TYPE,
+                &instance_name,
+                &host_name,
+                (),
+                listen_address.port(),
+                &properties[..],
+            )
+            .map(ServiceInfo::enable_addr_auto)
+        } else {
+            ServiceInfo::new(
+                SERVICE_TYPE,
+                &instance_name,
+                &host_name,
+                listen_address.ip(),
+                listen_address.port(),
+                &properties[..],
+            )
+        }
+        .with_context(|| format!("Invalid mDNS service for {listen_address}"))?;
+
+        let fullname = service.get_fullname().to_string();
+        announcement
+            .daemon
+            .register(service)
+            .with_context(|| format!("Unable to announce {fullname} through mDNS"))?;
+
+        info!("Announcing {listen_address} through mDNS as {fullname}");
+        announcement.fullnames.push(fullname);
+    }
+
+    // The services stay announced for as long as the announcement is alive.
+    std::future::pending::<()>().await;
+
+    Ok(())
+}
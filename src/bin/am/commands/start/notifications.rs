@@ -0,0 +1,241 @@
+use super::CLIENT;
+use crate::status::{TargetReport, STATUS};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+
+/// How often the health of the scrape targets is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize)]
+struct TargetsResponse {
+    data: TargetsData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TargetsData {
+    active_targets: Vec<ActiveTarget>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ActiveTarget {
+    pub labels: HashMap<String, String>,
+    pub scrape_url: String,
+    pub health: String,
+    #[serde(default)]
+    pub last_error: String,
+}
+
+/// Show a desktop notification. This doesn't wait for the notification to be
+/// shown, and failures are only logged, since notifications are best-effort.
+///
+/// This uses the tools that come with the OS, so no extra libraries are needed:
+/// `notify-send` on Linux, `osascript` on macOS and PowerShell on Windows.
+pub(super) fn send(title: &str, body: &str) {
+    #[cfg(target_os = "linux")]
+    let mut command = {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name=am", title, body]);
+        command
+    };
+
+    // The title and body are passed as arguments to the script, so they don't
+    // need to be escaped.
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("osascript");
+        command.args([
+            "-e",
+            "on run argv",
+            "-e",
+            "display notification (item 2 of argv) with title (item 1 of argv)",
+            "-e",
+            "end run",
+            title,
+            body,
+        ]);
+        command
+    };
+
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        const SCRIPT: &str = "\
+[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null
+$template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+$text = $template.GetElementsByTagName('text')
+$text.Item(0).AppendChild($template.CreateTextNode($env:AM_NOTIFICATION_TITLE)) > $null
+$text.Item(1).AppendChild($template.CreateTextNode($env:AM_NOTIFICATION_BODY)) > $null
+[Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('am').Show([Windows.UI.Notifications.ToastNotification]::new($template))";
+
+        let mut command = Command::new("powershell.exe");
+        command
+            .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+            .env("AM_NOTIFICATION_TITLE", title)
+            .env("AM_NOTIFICATION_BODY", body);
+        command
+    };
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        debug!(
+            title,
+            body, "Desktop notifications are not supported on this OS"
+        );
+        return;
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+    {
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        tokio::spawn(async move {
+            match command.output().await {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => debug!(
+                    stderr = %String::from_utf8_lossy(&output.stderr),
+                    "Unable to show desktop notification"
+                ),
+                Err(err) => debug!(?err, "Unable to show desktop notification"),
+            }
+        });
+    }
+}
+
+/// A scrape target that went down or came back up.
+#[derive(Debug, PartialEq, Eq)]
+struct Change {
+    up: bool,
+    title: String,
+    body: String,
+}
+
+/// Periodically check the health of the scrape targets of the Prometheus
+/// listening on `prometheus_port`, which is reported by the status API. A
+/// warning is logged whenever a target goes down, and with `notify` a desktop
+/// notification is sent as well. This never returns.
+pub(super) async fn watch_targets(prometheus_port: u16, notify: bool) -> anyhow::Result<()> {
+    let mut health = HashMap::new();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        // Prometheus might still be starting or restarting, which is already
+        // reported by the supervisor.
+        let targets = match fetch_targets(prometheus_port).await {
+            Ok(targets) => targets,
+            Err(err) => {
+                debug!(?err, "Unable to fetch the scrape targets from Prometheus");
+                continue;
+            }
+        };
+
+        STATUS.set_targets(
+            targets
+                .iter()
+                .map(|target| TargetReport {
+                    job: target.labels.get("job").cloned().unwrap_or_default(),
+                    scrape_url: target.scrape_url.clone(),
+                    health: target.health.clone(),
+                    last_error: (!target.last_error.is_empty()).then(|| target.last_error.clone()),
+                })
+                .collect(),
+        );
+
+        for change in changes(&mut health, targets) {
+            if change.up {
+                info!("{}: {}", change.title, change.body);
+            } else {
+                warn!("{}: {}", change.title, change.body);
+            }
+
+            if notify {
+                send(&change.title, &change.body);
+            }
+        }
+    }
+}
+
+/// The scrape targets of the Prometheus that listens on `prometheus_port`.
+pub(crate) async fn fetch_targets(prometheus_port: u16) -> anyhow::Result<Vec<ActiveTarget>> {
+    let response: TargetsResponse = CLIENT
+        .get(format!(
+            "http://localhost:{prometheus_port}/api/v1/targets?state=active"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(response.data.active_targets)
+}
+
+/// Update the known `health` of the targets and return a change for every
+/// target that went down or came back up. Targets that are seen for the first
+/// time don't result in a change.
+fn changes(health: &mut HashMap<String, String>, targets: Vec<ActiveTarget>) -> Vec<Change> {
+    let mut notifications = vec![];
+
+    for target in targets {
+        let job = target.labels.get("job").map_or("unknown", String::as_str);
+        let previous = health.insert(target.scrape_url.clone(), target.health.clone());
+
+        match (previous.as_deref(), target.health.as_str()) {
+            (Some("up"), "down") => notifications.push(Change {
+                up: false,
+                title: format!("{job} is down"),
+                body: format!(
+                    "Unable to scrape {}: {}",
+                    target.scrape_url, target.last_error
+                ),
+            }),
+            (Some("down"), "up") => notifications.push(Change {
+                up: true,
+                title: format!("{job} is back up"),
+                body: format!("{} can be scraped again", target.scrape_url),
+            }),
+            _ => {}
+        }
+    }
+
+    notifications
+}
+
+#[test]
+fn test_changes() {
+    let target = |health: &str| ActiveTarget {
+        labels: HashMap::from([("job".to_string(), "api".to_string())]),
+        scrape_url: "http://localhost:3000/metrics".to_string(),
+        health: health.to_string(),
+        last_error: "connection refused".to_string(),
+    };
+    let mut health = HashMap::new();
+
+    assert!(changes(&mut health, vec![target("up")]).is_empty());
+    assert!(changes(&mut health, vec![target("up")]).is_empty());
+    assert_eq!(
+        changes(&mut health, vec![target("down")]),
+        vec![Change {
+            up: false,
+            title: "api is down".to_string(),
+            body: "Unable to scrape http://localhost:3000/metrics: connection refused".to_string()
+        }]
+    );
+    assert_eq!(
+        changes(&mut health, vec![target("up")]),
+        vec![Change {
+            up: true,
+            title: "api is back up".to_string(),
+            body: "http://localhost:3000/metrics can be scraped again".to_string()
+        }]
+    );
+}
@@ -0,0 +1,83 @@
+use super::{determine_os_and_arch, run_with_config};
+use crate::dir::AutoCleanupDir;
+use crate::downloader::install_release_archive;
+use anyhow::Result;
+use autometrics_am::otel_collector;
+use indicatif::MultiProgress;
+use std::path::Path;
+use tracing::info;
+
+/// The address on which the collector receives OTLP over gRPC.
+pub(super) const OTLP_GRPC_ENDPOINT: &str = "localhost:4317";
+
+/// The address on which the collector receives OTLP over HTTP.
+pub(super) const OTLP_HTTP_ENDPOINT: &str = "localhost:4318";
+
+/// The address on which the collector exposes the received metrics for
+/// Prometheus to scrape.
+pub(super) const PROMETHEUS_ENDPOINT: &str = "localhost:8889";
+
+/// Install the specified version of the OpenTelemetry Collector (contrib
+/// distribution) into `otel_collector_path`.
+///
+/// This function will first create a temporary file to download the collector
+/// archive into. Then it will verify the downloaded archive against the
+/// downloaded checksum. Finally it will unpack the archive into
+/// `otel_collector_path`.
+pub(super) async fn install_otel_collector(
+    otel_collector_path: &Path,
+    otel_collector_version: &str,
+    multi_progress: MultiProgress,
+) -> Result<()> {
+    let (os, arch) = determine_os_and_arch()?;
+
+    let package = format!("otelcol-contrib_{otel_collector_version}_{os}_{arch}.tar.gz");
+
+    // The collector archive does not contain a top-level directory.
+    install_release_archive(
+        "open-telemetry",
+        "opentelemetry-collector-releases",
+        otel_collector_version,
+        &package,
+        "opentelemetry-collector-releases_otelcol-contrib_checksums.txt",
+        "",
+        otel_collector_path,
+        &multi_progress,
+    )
+    .await
+}
+
+/// Start a OpenTelemetry Collector process. This will block until the
+/// collector process stops. The received traces are forwarded to the OTLP
+/// receiver on `traces_endpoint`, if any.
+pub(super) async fn start_otel_collector(
+    otel_collector_path: &Path,
+    ephemeral: bool,
+    traces_endpoint: Option<&str>,
+) -> Result<()> {
+    let mut config =
+        otel_collector::Config::new(OTLP_GRPC_ENDPOINT, OTLP_HTTP_ENDPOINT, PROMETHEUS_ENDPOINT);
+    if let Some(traces_endpoint) = traces_endpoint {
+        config = config.with_traces_exporter(traces_endpoint);
+    }
+
+    let work_dir = AutoCleanupDir::new("otel-collector", ephemeral)?;
+
+    info!("Starting OpenTelemetry Collector");
+    info!("OTLP endpoints: grpc://{OTLP_GRPC_ENDPOINT}, http://{OTLP_HTTP_ENDPOINT}");
+
+    run_with_config(
+        otel_collector_path,
+        "otelcol-contrib",
+        "otel-collector",
+        "OpenTelemetry Collector",
+        &config,
+        otel_collector_command_args,
+        &work_dir,
+    )
+    .await
+}
+
+pub(super) fn otel_collector_command_args(config_file_path: &Path) -> Vec<String> {
+    vec![format!("--config={}", config_file_path.display())]
+}
@@ -0,0 +1,197 @@
+use super::app::wait_for_endpoint;
+use crate::error::AmError;
+use crate::shutdown;
+use crate::status::{self, ProcessState, STATUS};
+use crate::targets::{Target, TARGETS};
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::path::Path;
+use std::process::Stdio;
+use std::str::FromStr;
+use tokio::select;
+use tracing::{info, warn};
+use url::Url;
+
+/// A port of a Kubernetes resource that is forwarded to localhost with
+/// `kubectl port-forward`, so that it can be scraped like a local endpoint.
+///
+/// It is written as `[namespace/]kind/name:port[/path]`, like `pod/api:9464`
+/// or `staging/svc/api:http/internal/metrics`. The path defaults to
+/// `/metrics`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PortForward {
+    namespace: Option<String>,
+    /// The resource as kubectl expects it, like `pod/api` or `svc/api`.
+    resource: String,
+    /// The port number or the name of the port.
+    port: String,
+    path: String,
+}
+
+impl PortForward {
+    /// The name of the resource without its kind, which is used as the job
+    /// name.
+    pub(super) fn name(&self) -> &str {
+        self.resource
+            .split_once('/')
+            .map_or(&self.resource, |(_, name)| name)
+    }
+
+    /// The name under which the kubectl process shows up in `am status`.
+    pub(super) fn process_name(&self) -> String {
+        format!("port-forward-{}", self.name())
+    }
+}
+
+impl FromStr for PortForward {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let Some((resource, rest)) = input.split_once(':') else {
+            bail!("Invalid port forward {input}, expected `[namespace/]kind/name:port`");
+        };
+
+        let (port, path) = match rest.split_once('/') {
+            Some((port, path)) => (port, format!("/{path}")),
+            None => (rest, "/metrics".to_string()),
+        };
+
+        let (namespace, resource) = match resource.split('/').collect::<Vec<_>>()[..] {
+            [kind, name] => (None, format!("{kind}/{name}")),
+            [namespace, kind, name] => (Some(namespace.to_string()), format!("{kind}/{name}")),
+            _ => bail!("Invalid port forward {input}, expected `[namespace/]kind/name:port`"),
+        };
+
+        if port.is_empty() || resource.starts_with('/') || resource.ends_with('/') {
+            bail!("Invalid port forward {input}, expected `[namespace/]kind/name:port`");
+        }
+
+        Ok(PortForward {
+            namespace,
+            resource,
+            port: port.to_string(),
+            path,
+        })
+    }
+}
+
+impl fmt::Display for PortForward {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(namespace) = &self.namespace {
+            write!(f, "{namespace}/")?;
+        }
+        write!(f, "{}:{}", self.resource, self.port)
+    }
+}
+
+/// Run `kubectl port-forward` for `forward` on `local_port`. Once the
+/// forwarded endpoint responds, it is registered as a scrape target until
+/// kubectl exits. This will block until kubectl stops.
+pub(super) async fn start_port_forward(
+    forward: &PortForward,
+    local_port: u16,
+    kubeconfig: Option<&Path>,
+) -> Result<()> {
+    let process = forward.process_name();
+
+    let mut command = shutdown::command("kubectl");
+    command
+        .args(port_forward_command_args(forward, local_port, kubeconfig))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    info!("Forwarding localhost:{local_port} to {forward}");
+    STATUS.set_state(&process, ProcessState::Starting);
+
+    let child = command
+        .spawn()
+        .context("Unable to start kubectl, make sure it is installed and in your PATH")?;
+
+    let wait = status::wait(child, &process);
+    tokio::pin!(wait);
+
+    let endpoint = Url::parse(&format!("http://localhost:{local_port}{}", forward.path))?;
+    let job_name = forward.name().to_string();
+
+    let status = select! {
+        status = &mut wait => status?,
+        up = wait_for_endpoint(&endpoint) => {
+            if up {
+                info!("{endpoint} is up, scraping {forward} as job {job_name}");
+                TARGETS.register(Target {
+                    job_name: job_name.clone(),
+                    url: endpoint.clone(),
+                    scrape_interval: None,
+                    honor_labels: false,
+                });
+            } else {
+                warn!("{endpoint} of {forward} did not respond, not scraping it");
+            }
+
+            wait.await?
+        }
+    };
+
+    TARGETS.unregister(&job_name);
+
+    if !status.success() && !shutdown::is_requested() {
+        return Err(AmError::ChildCrashed {
+            process: format!("kubectl port-forward {forward}"),
+            status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// The arguments of `kubectl` to forward `local_port` to `forward`.
+pub(super) fn port_forward_command_args(
+    forward: &PortForward,
+    local_port: u16,
+    kubeconfig: Option<&Path>,
+) -> Vec<String> {
+    let mut command_args = vec![
+        "port-forward".to_string(),
+        "--address".to_string(),
+        "127.0.0.1".to_string(),
+    ];
+    if let Some(kubeconfig) = kubeconfig {
+        command_args.extend(["--kubeconfig".to_string(), kubeconfig.display().to_string()]);
+    }
+    if let Some(namespace) = &forward.namespace {
+        command_args.extend(["--namespace".to_string(), namespace.clone()]);
+    }
+    command_args.extend([
+        forward.resource.clone(),
+        format!("{local_port}:{}", forward.port),
+    ]);
+    command_args
+}
+
+#[test]
+fn test_parse_port_forward() {
+    let forward: PortForward = "pod/api:9464".parse().unwrap();
+    assert_eq!(
+        forward,
+        PortForward {
+            namespace: None,
+            resource: "pod/api".to_string(),
+            port: "9464".to_string(),
+            path: "/metrics".to_string(),
+        }
+    );
+    assert_eq!(forward.name(), "api");
+
+    let forward: PortForward = "staging/svc/api:http/internal/metrics".parse().unwrap();
+    assert_eq!(forward.namespace.as_deref(), Some("staging"));
+    assert_eq!(forward.resource, "svc/api");
+    assert_eq!(forward.port, "http");
+    assert_eq!(forward.path, "/internal/metrics");
+    assert_eq!(forward.to_string(), "staging/svc/api:http");
+
+    assert!("api:9464".parse::<PortForward>().is_err());
+    assert!("pod/api".parse::<PortForward>().is_err());
+    assert!("pod/api:".parse::<PortForward>().is_err());
+}
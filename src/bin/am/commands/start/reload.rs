@@ -0,0 +1,293 @@
+use super::{
+    add_internal_endpoints, generate_prom_config, render_prom_config, write_file_sd_targets,
+    write_prom_config, write_slo_rules, Arguments, FILE_SD_TARGETS_FILE,
+};
+use super::{CliArguments, CLIENT};
+use crate::targets::TARGETS;
+use anyhow::{anyhow, Context, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// Watch the `config_file` (if any) and the targets registered through the
+/// API for changes. Whenever either changes, the Prometheus config will be
+/// regenerated and Prometheus will be asked to reload it.
+///
+/// Only settings that affect the Prometheus config (such as the endpoints and
+/// scrape intervals) will be applied, all other changes require a restart.
+/// This includes the ports, so the ones from `args` are kept.
+pub(super) async fn watch_config(
+    config_file: Option<PathBuf>,
+    cli_args: CliArguments,
+    mut args: Arguments,
+    prometheus_config_path: PathBuf,
+) -> Result<()> {
+    // Only a single pending event is needed to trigger a reload, so any events
+    // that come in while one is pending can safely be dropped.
+    let (tx, mut rx) = mpsc::channel(1);
+
+    let config_file =
+        match config_file {
+            Some(config_file) => Some(config_file.canonicalize().with_context(|| {
+                format!("Unable to resolve config file {}", config_file.display())
+            })?),
+            None => None,
+        };
+
+    // The watcher stops once it is dropped, so it needs to be kept around.
+    let _watcher = match &config_file {
+        Some(config_file) => Some(watch_file(config_file, tx)?),
+        None => None,
+    };
+
+    let mut targets = TARGETS.subscribe();
+
+    loop {
+        let source = select! {
+            Some(()) = rx.recv() => {
+                // Editors tend to emit multiple events when saving a file, so
+                // wait a bit until the dust settles.
+                tokio::time::sleep(Duration::from_millis(250)).await;
+                while rx.try_recv().is_ok() {}
+
+                // Events are only sent if there is a config file to watch.
+                let config_file = config_file.as_deref().unwrap();
+                match load_args(config_file, &cli_args, &args).await {
+                    Ok(new_args) => {
+                        args = new_args;
+                        TARGETS.set_configured_jobs(args.job_names());
+                    }
+                    Err(err) => {
+                        warn!("Unable to reload config: {err:?}");
+                        continue;
+                    }
+                }
+
+                config_file.display().to_string()
+            }
+            Ok(()) = targets.changed() => "the registered targets".to_string(),
+            else => break,
+        };
+
+        if let Err(err) = reload(&args, &prometheus_config_path, &source).await {
+            warn!("Unable to reload config: {err:?}");
+        }
+    }
+
+    Ok(())
+}
+
+fn watch_file(config_file: &Path, tx: mpsc::Sender<()>) -> Result<RecommendedWatcher> {
+    let file_name = config_file
+        .file_name()
+        .ok_or_else(|| anyhow!("config file path does not contain a file name"))?
+        .to_owned();
+    let config_dir = config_file
+        .parent()
+        .ok_or_else(|| anyhow!("config file does not have a parent directory"))?;
+
+    let mut watcher =
+        notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+            Ok(event) if event.kind.is_create() || event.kind.is_modify() => {
+                if event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name() == Some(file_name.as_os_str()))
+                {
+                    let _ = tx.try_send(());
+                }
+            }
+            Ok(_) => {}
+            Err(err) => warn!(?err, "Error while watching config file"),
+        })?;
+
+    // Watch the directory instead of the file, since a lot of editors replace
+    // the file instead of writing to it.
+    watcher
+        .watch(config_dir, RecursiveMode::NonRecursive)
+        .context("Unable to watch config file")?;
+
+    debug!(path = ?config_file, "Watching config file for changes");
+    Ok(watcher)
+}
+
+/// Build the arguments from the changed config file, keeping the ports and
+/// listen addresses of `current`. These might have been moved out of the way
+/// of other instances, and the internal endpoints need to point at the ones
+/// that are actually in use. The endpoints that were discovered or entered at
+/// startup are kept as well, unless the config now scrapes them itself.
+async fn load_args(
+    config_file: &Path,
+    cli_args: &CliArguments,
+    current: &Arguments,
+) -> Result<Arguments> {
+    let config = crate::load_config(Some(config_file.to_path_buf()))
+        .await?
+        .with_profile(cli_args.profile.as_deref())?;
+
+    let mut args = Arguments::new(cli_args.clone(), config)?;
+    args.ports = current.ports;
+    args.listen_address = current.listen_address;
+    args.extra_listen_addresses = current.extra_listen_addresses.clone();
+    args.gateway_port = current.gateway_port;
+    for endpoint in &current.startup_endpoints {
+        if args.metrics_endpoints.iter().any(|configured| {
+            configured.url == endpoint.url || configured.job_name == endpoint.job_name
+        }) {
+            debug!(
+                url = %endpoint.url,
+                "Endpoint from startup is replaced by the config"
+            );
+            continue;
+        }
+        args.add_startup_endpoint(endpoint.clone());
+    }
+    add_internal_endpoints(&mut args);
+    Ok(args)
+}
+
+async fn reload(args: &Arguments, prometheus_config_path: &Path, source: &str) -> Result<()> {
+    let (prometheus_config, file_sd_targets) = generate_prom_config(args)?;
+    let rules_changed = write_slo_rules(args)?;
+
+    // Prometheus watches this file itself, so it doesn't need to be reloaded
+    // for these targets.
+    let targets_changed = args.file_sd
+        && write_file_sd_targets(
+            &prometheus_config_path.with_file_name(FILE_SD_TARGETS_FILE),
+            &file_sd_targets,
+        )?;
+
+    let current = tokio::fs::read_to_string(prometheus_config_path)
+        .await
+        .unwrap_or_default();
+    let rendered = render_prom_config(args, &prometheus_config)?;
+    if current == rendered && !rules_changed {
+        if targets_changed {
+            info!("Updated the scrape targets after a change to {source}");
+        } else {
+            debug!("{source} changed, but the Prometheus config is unchanged");
+        }
+        return Ok(());
+    }
+
+    write_prom_config(prometheus_config_path, &rendered)?;
+
+    CLIENT
+        .post(format!(
+            "http://localhost:{}/-/reload",
+            args.ports.prometheus
+        ))
+        .send()
+        .await?
+        .error_for_status()
+        .context("Prometheus was unable to reload its config")?;
+
+    info!("Reloaded Prometheus config after a change to {source}");
+    Ok(())
+}
+
+/// Load `config` like a reload from `current` does, and once more from the
+/// result, as the arguments of every reload are based on the previous one.
+#[cfg(test)]
+async fn load_twice(config: &str, cli_args: &CliArguments, current: &Arguments) -> [Arguments; 2] {
+    let dir = tempfile::tempdir().unwrap();
+    let config_file = dir.path().join("am.toml");
+    std::fs::write(&config_file, config).unwrap();
+
+    let first = load_args(&config_file, cli_args, current).await.unwrap();
+    let second = load_args(&config_file, cli_args, &first).await.unwrap();
+    [first, second]
+}
+
+#[tokio::test]
+async fn test_reload_keeps_job_names() {
+    use clap::Parser;
+
+    let endpoints = r#"
+        [[endpoint]]
+        url = "http://localhost:3030/metrics"
+
+        [[endpoint]]
+        url = "http://localhost:3031/metrics"
+        "#;
+    let cli_args = CliArguments::try_parse_from(["start"]).unwrap();
+    let current = Arguments::new(cli_args.clone(), Default::default()).unwrap();
+    let job_names = |args: &Arguments| -> Vec<String> {
+        args.metrics_endpoints
+            .iter()
+            .map(|endpoint| endpoint.job_name.clone())
+            .collect()
+    };
+
+    let [first, second] = load_twice(endpoints, &cli_args, &current).await;
+
+    assert_eq!(job_names(&first), job_names(&second));
+    assert!(job_names(&second).starts_with(&["am_0".to_string(), "am_1".to_string()]));
+}
+
+#[tokio::test]
+async fn test_reload_keeps_listen_address() {
+    use clap::Parser;
+
+    let cli_args = CliArguments::try_parse_from(["start"]).unwrap();
+    let mut current = Arguments::new(cli_args.clone(), Default::default()).unwrap();
+    // Moved out of the way of another instance.
+    current.listen_address.set_port(6790);
+
+    for args in load_twice("", &cli_args, &current).await {
+        assert_eq!(args.listen_address.port(), 6790);
+        let am_endpoint = args
+            .metrics_endpoints
+            .iter()
+            .find(|endpoint| endpoint.job_name == "am")
+            .unwrap();
+        assert_eq!(am_endpoint.url.port(), Some(6790));
+    }
+}
+
+#[tokio::test]
+async fn test_reload_keeps_startup_endpoints() {
+    use super::Endpoint;
+    use clap::Parser;
+    use url::Url;
+
+    let cli_args = CliArguments::try_parse_from(["start"]).unwrap();
+    let mut current = Arguments::new(cli_args.clone(), Default::default()).unwrap();
+    let url = Url::parse("http://localhost:3000/metrics").unwrap();
+    current.add_startup_endpoint(Endpoint::new(
+        url,
+        "discovered_3000".to_string(),
+        false,
+        None,
+    ));
+
+    for args in load_twice("", &cli_args, &current).await {
+        assert_eq!(
+            args.metrics_endpoints
+                .iter()
+                .filter(|endpoint| endpoint.job_name == "discovered_3000")
+                .count(),
+            1
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_reload_keeps_extra_endpoints() {
+    use clap::Parser;
+
+    let mut cli_args = CliArguments::try_parse_from(["start"]).unwrap();
+    cli_args.add_endpoint("http://localhost:8000/metrics".parse().unwrap(), "am_demo");
+    let current = Arguments::new(cli_args.clone(), Default::default()).unwrap();
+
+    for args in load_twice("", &cli_args, &current).await {
+        assert!(args
+            .metrics_endpoints
+            .iter()
+            .any(|endpoint| endpoint.job_name == "am_demo"));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::metrics::{Histogram, BUCKETS};
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use tokio::net::UdpSocket;
+use tracing::{debug, info};
+
+/// The metrics received by the StatsD listener, aggregated until Prometheus
+/// scrapes them.
+pub(crate) static STATSD: Lazy<Aggregator> = Lazy::new(Aggregator::default);
+
+/// The port on which StatsD clients send their metrics by default.
+pub(crate) const DEFAULT_PORT: u16 = 8125;
+
+/// The path on the web server of am on which the aggregated metrics are
+/// exposed.
+pub(crate) const METRICS_PATH: &str = "/api/statsd/metrics";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// A single metric of a StatsD packet, like `requests:1|c|@0.5|#path:/api`.
+#[derive(Debug, PartialEq)]
+struct Sample {
+    name: String,
+    labels: Vec<(String, String)>,
+    kind: Kind,
+    value: f64,
+    /// Whether a gauge value is added to the current value (`+1` or `-1`)
+    /// instead of replacing it.
+    relative: bool,
+}
+
+#[derive(Debug)]
+enum Value {
+    Counter(f64),
+    Gauge(f64),
+    Histogram(Histogram),
+}
+
+#[derive(Debug)]
+struct Family {
+    kind: Kind,
+    series: BTreeMap<Vec<(String, String)>, Value>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Aggregator {
+    families: Mutex<BTreeMap<String, Family>>,
+}
+
+impl Aggregator {
+    fn record(&self, sample: Sample) {
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(sample.name.clone()).or_insert(Family {
+            kind: sample.kind,
+            series: BTreeMap::new(),
+        });
+
+        // Prometheus doesn't accept a metric with several types.
+        if family.kind != sample.kind {
+            debug!(
+                name = sample.name,
+                "Ignoring StatsD metric that was sent with another type before"
+            );
+            return;
+        }
+
+        let value = family
+            .series
+            .entry(sample.labels)
+            .or_insert_with(|| match sample.kind {
+                Kind::Counter => Value::Counter(0.0),
+                Kind::Gauge => Value::Gauge(0.0),
+                Kind::Histogram => Value::Histogram(Histogram::default()),
+            });
+
+        match value {
+            Value::Counter(total) => *total += sample.value,
+            Value::Gauge(current) if sample.relative => *current += sample.value,
+            Value::Gauge(current) => *current = sample.value,
+            Value::Histogram(histogram) => histogram.observe(sample.value),
+        }
+    }
+
+    /// The aggregated metrics in the Prometheus text format.
+    pub(crate) fn render(&self) -> String {
+        let mut output = String::new();
+
+        for (name, family) in self.families.lock().unwrap().iter() {
+            let kind = match family.kind {
+                Kind::Counter => "counter",
+                Kind::Gauge => "gauge",
+                Kind::Histogram => "histogram",
+            };
+            let _ = writeln!(output, "# TYPE {name} {kind}");
+
+            for (labels, value) in &family.series {
+                match value {
+                    Value::Counter(value) | Value::Gauge(value) => {
+                        let _ = writeln!(output, "{name}{} {value}", format_labels(labels, None));
+                    }
+                    Value::Histogram(histogram) => {
+                        for (bucket, le) in histogram.buckets.iter().zip(BUCKETS) {
+                            let le = le.to_string();
+                            let labels = format_labels(labels, Some(&le));
+                            let _ = writeln!(output, "{name}_bucket{labels} {bucket}");
+                        }
+                        let _ = writeln!(
+                            output,
+                            "{name}_bucket{} {}",
+                            format_labels(labels, Some("+Inf")),
+                            histogram.count
+                        );
+                        let labels = format_labels(labels, None);
+                        let _ = writeln!(output, "{name}_sum{labels} {}", histogram.sum);
+                        let _ = writeln!(output, "{name}_count{labels} {}", histogram.count);
+                    }
+                }
+            }
+        }
+
+        output
+    }
+}
+
+pub(super) fn format_labels(labels: &[(String, String)], le: Option<&str>) -> String {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', r"\\")
+                .replace('"', r#"\""#)
+                .replace('\n', r"\n");
+            format!("{key}=\"{value}\"")
+        })
+        .collect();
+
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+/// Replace the characters that aren't allowed in Prometheus metric and label
+/// names, like the dots that StatsD names are usually separated with.
+pub(super) fn sanitize_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+
+    sanitized
+}
+
+/// The label name for the tag `key`, unless it isn't a valid Prometheus label
+/// name (`[a-zA-Z_][a-zA-Z0-9_]*`) after sanitizing it or is reserved: `le` is
+/// used for the buckets of histograms, and names starting with `__` are used by
+/// Prometheus itself.
+fn label_name(key: &str) -> Option<String> {
+    let name = sanitize_name(key);
+
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid || name == "le" || name.starts_with("__") {
+        return None;
+    }
+
+    Some(name)
+}
+
+/// Parse a line of a StatsD packet: `<name>:<value>|<type>`, optionally
+/// followed by a sample rate (`|@0.1`) and DogStatsD tags (`|#key:value`).
+fn parse_line(line: &str) -> Result<Option<Sample>> {
+    let (name, rest) = line.split_once(':').context("missing value")?;
+    let name = sanitize_name(name);
+    if name.is_empty() {
+        bail!("missing name");
+    }
+    let mut fields = rest.split('|');
+    let value = fields.next().context("missing value")?;
+    let kind = fields.next().context("missing type")?;
+
+    let (kind, scale) = match kind {
+        "c" => (Kind::Counter, 1.0),
+        "g" => (Kind::Gauge, 1.0),
+        // Timers are in milliseconds, while Prometheus uses seconds.
+        "ms" => (Kind::Histogram, 0.001),
+        "h" | "d" => (Kind::Histogram, 1.0),
+        // Sets count unique values, which doesn't map onto Prometheus.
+        "s" => return Ok(None),
+        kind => bail!("unknown type {kind}"),
+    };
+
+    let mut rate = 1.0;
+    let mut labels = BTreeMap::new();
+    for field in fields {
+        if let Some(sample_rate) = field.strip_prefix('@') {
+            rate = sample_rate.parse().context("invalid sample rate")?;
+        } else if let Some(tags) = field.strip_prefix('#') {
+            for tag in tags.split(',').filter(|tag| !tag.is_empty()) {
+                let (key, value) = tag.split_once(':').unwrap_or((tag, ""));
+                match label_name(key) {
+                    Some(key) => {
+                        labels.insert(key, value.to_string());
+                    }
+                    None => debug!("Ignoring tag {key:?} of {name}, it isn't a valid label name"),
+                }
+            }
+        }
+    }
+
+    let mut sample_value: f64 = value.parse().context("invalid value")?;
+    if kind == Kind::Counter && rate > 0.0 {
+        sample_value /= rate;
+    }
+
+    Ok(Some(Sample {
+        name,
+        labels: labels.into_iter().collect(),
+        kind,
+        value: sample_value * scale,
+        relative: kind == Kind::Gauge && (value.starts_with('+') || value.starts_with('-')),
+    }))
+}
+
+/// Listen for StatsD metrics on UDP `port` of localhost, which are aggregated
+/// in [`STATSD`]. This never returns.
+pub(super) async fn listen(port: u16) -> Result<()> {
+    let socket = UdpSocket::bind(("127.0.0.1", port))
+        .await
+        .with_context(|| format!("Unable to listen for StatsD metrics on UDP port {port}"))?;
+    info!("Listening for StatsD metrics on udp://127.0.0.1:{port}");
+
+    let mut buffer = vec![0; 65535];
+    loop {
+        let (length, _) = socket.recv_from(&mut buffer).await?;
+        let packet = String::from_utf8_lossy(&buffer[..length]);
+
+        for line in packet.lines().filter(|line| !line.trim().is_empty()) {
+            match parse_line(line.trim()) {
+                Ok(Some(sample)) => STATSD.record(sample),
+                Ok(None) => {}
+                Err(err) => debug!("Ignoring invalid StatsD metric {line:?}: {err}"),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_statsd_aggregation() {
+    let aggregator = Aggregator::default();
+    let packet = "api.requests:1|c|#path:/users\n\
+                  api.requests:1|c|@0.5|#path:/users\n\
+                  queue.size:10|g\n\
+                  queue.size:-3|g\n\
+                  api.latency:320|ms\n\
+                  users.unique:42|s";
+
+    for line in packet.lines() {
+        if let Some(sample) = parse_line(line).unwrap() {
+            aggregator.record(sample);
+        }
+    }
+
+    let output = aggregator.render();
+    assert!(output.contains("# TYPE api_requests counter\napi_requests{path=\"/users\"} 3\n"));
+    assert!(output.contains("# TYPE queue_size gauge\nqueue_size 7\n"));
+    assert!(output.contains("api_latency_bucket{le=\"0.25\"} 0\n"));
+    assert!(output.contains("api_latency_bucket{le=\"0.5\"} 1\n"));
+    assert!(output.contains("api_latency_count 1\n"));
+    assert!(!output.contains("users_unique"));
+
+    assert!(parse_line("api.requests").is_err());
+    assert!(parse_line("api.requests:1|x").is_err());
+    assert!(parse_line(":1|c").is_err());
+}
+
+#[test]
+fn test_statsd_tags() {
+    let sample = parse_line("api.latency:320|ms|#service.name:api,le:1,__name__:x,:empty,1st:a")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        sample.labels,
+        vec![
+            ("_1st".to_string(), "a".to_string()),
+            ("service_name".to_string(), "api".to_string()),
+        ]
+    );
+
+    assert_eq!(label_name("env"), Some("env".to_string()));
+    assert_eq!(label_name("_env"), Some("_env".to_string()));
+    assert_eq!(label_name(""), None);
+    assert_eq!(label_name("le"), None);
+    assert_eq!(label_name("__address__"), None);
+    assert_eq!(label_name("..meta"), None);
+}
@@ -0,0 +1,108 @@
+use super::notifications;
+use crate::shutdown;
+use crate::status::STATUS;
+use anyhow::Result;
+use autometrics_am::config::RestartPolicy;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// The delay before the first restart, this gets doubled for every consecutive
+/// restart.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two restarts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// If a process ran for at least this long, it is considered to have been
+/// healthy and the restart count will be reset.
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Supervisor {
+    pub policy: RestartPolicy,
+    pub max_restarts: u32,
+    /// Send a desktop notification when a process exits unexpectedly.
+    pub notify: bool,
+}
+
+impl Supervisor {
+    /// Run `start` and restart it according to the restart policy once it
+    /// returns. This will only return once the policy does not allow another
+    /// restart.
+    pub(super) async fn supervise<F, Fut>(&self, process: &str, mut start: F) -> Result<()>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut restarts = 0;
+
+        loop {
+            let started_at = Instant::now();
+            let result = start().await;
+
+            // The process was stopped on purpose.
+            if shutdown::is_requested() {
+                return Ok(());
+            }
+
+            if self.notify {
+                if let Err(err) = &result {
+                    notifications::send(&format!("{process} exited"), &format!("{err:#}"));
+                }
+            }
+
+            let should_restart = match (self.policy, &result) {
+                (RestartPolicy::Never, _) => false,
+                (RestartPolicy::OnFailure, Ok(_)) => false,
+                (RestartPolicy::OnFailure, Err(_)) | (RestartPolicy::Always, _) => true,
+            };
+
+            if !should_restart {
+                return result;
+            }
+
+            if started_at.elapsed() >= HEALTHY_RUN {
+                restarts = 0;
+            }
+
+            if restarts >= self.max_restarts {
+                warn!(
+                    "{process} exited {} times in a row, not restarting it again",
+                    restarts + 1
+                );
+                return result;
+            }
+
+            let delay = backoff(restarts);
+            match &result {
+                Ok(_) => info!("{process} exited, restarting in {delay:?}"),
+                Err(err) => {
+                    warn!("{process} exited with an error, restarting in {delay:?}: {err:?}")
+                }
+            }
+
+            tokio::time::sleep(delay).await;
+
+            restarts += 1;
+            STATUS.record_restart(process);
+        }
+    }
+}
+
+/// The delay before restarting a process that has already been restarted
+/// `restarts` times in a row.
+fn backoff(restarts: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(restarts))
+        .min(MAX_BACKOFF)
+}
+
+#[test]
+fn test_backoff_is_exponential_and_capped() {
+    assert_eq!(backoff(0), Duration::from_secs(1));
+    assert_eq!(backoff(1), Duration::from_secs(2));
+    assert_eq!(backoff(4), Duration::from_secs(16));
+    assert_eq!(backoff(5), MAX_BACKOFF);
+    assert_eq!(backoff(100), MAX_BACKOFF);
+}
@@ -0,0 +1,114 @@
+use super::{determine_os_and_arch, run_with_config};
+use crate::dir::AutoCleanupDir;
+use crate::downloader::install_release_archive;
+use anyhow::Result;
+use autometrics_am::tempo;
+use indicatif::MultiProgress;
+use std::path::Path;
+use tracing::info;
+
+pub(crate) const DEFAULT_VERSION: &str = "v2.3.1";
+
+pub(super) const DEFAULT_PORT: u16 = 3200;
+
+/// The path under which the API of Tempo is served, and proxied by the web
+/// server of am.
+const API_PREFIX: &str = "/tempo";
+
+/// The addresses on which Tempo receives OTLP when the OpenTelemetry Collector
+/// is running. The collector receives the traces on the usual ports and
+/// forwards them to Tempo.
+pub(super) const FORWARDED_OTLP_GRPC_ENDPOINT: &str = "localhost:14317";
+const FORWARDED_OTLP_HTTP_ENDPOINT: &str = "localhost:14318";
+
+/// Install the specified version of Tempo into `tempo_path`.
+///
+/// This function will first create a temporary file to download the Tempo
+/// archive into. Then it will verify the downloaded archive against the
+/// downloaded checksum. Finally it will unpack the archive into `tempo_path`.
+pub(super) async fn install_tempo(
+    tempo_path: &Path,
+    tempo_version: &str,
+    multi_progress: MultiProgress,
+) -> Result<()> {
+    let (os, arch) = determine_os_and_arch()?;
+
+    let package = format!("tempo_{tempo_version}_{os}_{arch}.tar.gz");
+
+    // The Tempo archive does not contain a top-level directory.
+    install_release_archive(
+        "grafana",
+        "tempo",
+        tempo_version,
+        &package,
+        "SHA256SUMS",
+        "",
+        tempo_path,
+        &multi_progress,
+    )
+    .await
+}
+
+/// Start a Tempo process that listens on `listen_address` and `port`. This
+/// will block until the Tempo process stops.
+pub(super) async fn start_tempo(
+    tempo_path: &Path,
+    listen_address: &str,
+    port: u16,
+    behind_otel_collector: bool,
+    ephemeral: bool,
+) -> Result<()> {
+    let work_dir = AutoCleanupDir::new("tempo", ephemeral)?;
+
+    let config = tempo_config(listen_address, port, behind_otel_collector);
+
+    info!("Starting Tempo");
+    if !behind_otel_collector {
+        let (grpc_endpoint, http_endpoint) = otlp_endpoints(false);
+        info!("OTLP trace endpoints: grpc://{grpc_endpoint}, http://{http_endpoint}");
+    }
+
+    run_with_config(
+        tempo_path,
+        "tempo",
+        "tempo",
+        "Tempo",
+        &config,
+        tempo_command_args,
+        &work_dir,
+    )
+    .await
+}
+
+/// The config of a Tempo that serves its API on `listen_address` and `port`.
+pub(super) fn tempo_config(
+    listen_address: &str,
+    port: u16,
+    behind_otel_collector: bool,
+) -> tempo::Config {
+    let (grpc_endpoint, http_endpoint) = otlp_endpoints(behind_otel_collector);
+    tempo::Config::new(
+        API_PREFIX,
+        listen_address,
+        port,
+        grpc_endpoint,
+        http_endpoint,
+    )
+}
+
+/// The addresses on which Tempo receives OTLP over gRPC and HTTP. Without the
+/// OpenTelemetry Collector, applications send their traces to Tempo directly.
+fn otlp_endpoints(behind_otel_collector: bool) -> (&'static str, &'static str) {
+    if behind_otel_collector {
+        (FORWARDED_OTLP_GRPC_ENDPOINT, FORWARDED_OTLP_HTTP_ENDPOINT)
+    } else {
+        (
+            super::otel_collector::OTLP_GRPC_ENDPOINT,
+            super::otel_collector::OTLP_HTTP_ENDPOINT,
+        )
+    }
+}
+
+pub(super) fn tempo_command_args(config_file_path: &Path) -> Vec<String> {
+    vec![format!("-config.file={}", config_file_path.display())]
+}
@@ -0,0 +1,134 @@
+use crate::commands::start::notifications::fetch_targets;
+use crate::commands::start::CLIENT;
+use crate::daemon;
+use crate::instances::{self, Instance};
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+#[derive(Parser, Clone)]
+pub struct Arguments {}
+
+/// The subset of the `/api/status` response that is shown to the user.
+#[derive(Deserialize)]
+struct StatusResponse {
+    version: String,
+    uptime_seconds: u64,
+    processes: BTreeMap<String, ProcessResponse>,
+}
+
+#[derive(Deserialize)]
+struct ProcessResponse {
+    state: String,
+    version: Option<String>,
+    restarts: u32,
+}
+
+pub async fn handle_command(_args: Arguments) -> Result<()> {
+    let running = instances::running()?;
+    if running.is_empty() {
+        info!("am is not running");
+        return Ok(());
+    }
+
+    let daemon = daemon::read_state()?;
+
+    for (index, instance) in running.iter().enumerate() {
+        if index > 0 {
+            info!("");
+        }
+
+        match &daemon {
+            Some(state) if state.pid == instance.pid => {
+                info!("am is running in the background (pid {})", instance.pid);
+                info!("Log file: {}", state.log_file.display());
+            }
+            _ => info!("am is running (pid {})", instance.pid),
+        }
+        info!("Explorer endpoint: http://{}", instance.listen_address);
+
+        show_status(instance).await;
+    }
+
+    Ok(())
+}
+
+async fn show_status(instance: &Instance) {
+    match fetch_status(instance).await {
+        Ok(status) => {
+            info!(
+                "Version {}, up for {}",
+                status.version,
+                humantime::format_duration(std::time::Duration::from_secs(status.uptime_seconds))
+            );
+
+            for (name, process) in status.processes {
+                let version = process.version.unwrap_or_else(|| "unknown".to_string());
+                if process.restarts > 0 {
+                    info!(
+                        "{name} ({version}): {} (restarted {} times)",
+                        process.state, process.restarts
+                    );
+                } else {
+                    info!("{name} ({version}): {}", process.state);
+                }
+            }
+        }
+        Err(err) => {
+            // The instance might still be starting, in which case the start
+            // time from the registry is all there is to show.
+            let started = SystemTime::now()
+                .duration_since(instance.started_at)
+                .unwrap_or_default();
+            warn!(
+                ?err,
+                "Unable to retrieve the status of the running processes (started {} ago)",
+                humantime::format_duration(std::time::Duration::from_secs(started.as_secs()))
+            );
+        }
+    }
+
+    let mut targets = match fetch_targets(instance.prometheus_port).await {
+        Ok(targets) => targets,
+        Err(err) => {
+            warn!(
+                ?err,
+                "Unable to retrieve the scrape targets from Prometheus"
+            );
+            return;
+        }
+    };
+
+    if targets.is_empty() {
+        info!("No scrape targets");
+        return;
+    }
+
+    info!("Scrape targets:");
+    targets.sort_by(|a, b| a.labels.get("job").cmp(&b.labels.get("job")));
+    for target in targets {
+        let job = target.labels.get("job").map_or("unknown", String::as_str);
+        if target.last_error.is_empty() {
+            info!("  {job} ({}): {}", target.scrape_url, target.health);
+        } else {
+            info!(
+                "  {job} ({}): {} ({})",
+                target.scrape_url, target.health, target.last_error
+            );
+        }
+    }
+}
+
+async fn fetch_status(instance: &Instance) -> Result<StatusResponse> {
+    CLIENT
+        .get(format!("http://{}/api/status", instance.listen_address))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .context("Unable to parse status response")
+}
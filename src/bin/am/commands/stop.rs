@@ -0,0 +1,20 @@
+use crate::daemon;
+use anyhow::Result;
+use clap::Parser;
+use tracing::info;
+
+#[derive(Parser, Clone)]
+pub struct Arguments {}
+
+pub async fn handle_command(_args: Arguments) -> Result<()> {
+    let Some(state) = daemon::read_state()? else {
+        info!("am is not running in the background");
+        return Ok(());
+    };
+
+    info!("Stopping am (pid {})", state.pid);
+    daemon::stop(&state).await?;
+    info!("am stopped");
+
+    Ok(())
+}
@@ -1,8 +1,13 @@
 use anyhow::Result;
+use autometrics_am::config::AmConfig;
 use clap::{Parser, Subcommand};
 use indicatif::MultiProgress;
 
+pub mod download_explorer;
+pub mod list;
 pub mod prune;
+pub mod report;
+pub mod snapshot;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -13,12 +18,32 @@ pub struct Arguments {
 
 #[derive(Subcommand)]
 pub enum SubCommands {
+    /// Download the assets of the explorer, so `am start --explorer-offline`
+    /// can serve them without internet access.
+    DownloadExplorer(download_explorer::Arguments),
+
+    /// List all locally downloaded binaries.
+    List(list::Arguments),
+
     /// Delete all locally downloaded binaries.
     Prune(prune::Arguments),
+
+    /// Collect the sanitized config, the generated Prometheus config, the logs
+    /// and the versions of am and its processes into a `tar.gz` archive that
+    /// can be attached to a bug report.
+    Report(report::Arguments),
+
+    /// Create a snapshot of the data of the Prometheus from `am start` and
+    /// store it as an archive in the data directory of am.
+    Snapshot(snapshot::Arguments),
 }
 
-pub async fn handle_command(args: Arguments, mp: MultiProgress) -> Result<()> {
+pub async fn handle_command(args: Arguments, config: AmConfig, mp: MultiProgress) -> Result<()> {
     match args.command {
-        SubCommands::Prune(args) => prune::handle_command(args, mp).await,
+        SubCommands::DownloadExplorer(args) => download_explorer::handle_command(args).await,
+        SubCommands::List(args) => list::handle_command(args).await,
+        SubCommands::Prune(args) => prune::handle_command(args, config, mp).await,
+        SubCommands::Report(args) => report::handle_command(args, config).await,
+        SubCommands::Snapshot(args) => snapshot::handle_command(args).await,
     }
 }
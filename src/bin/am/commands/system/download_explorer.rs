@@ -0,0 +1,77 @@
+use crate::commands::start::CLIENT;
+use crate::server::explorer::{self, CDN_URL, OFFLINE_DIR};
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use directories::ProjectDirs;
+use std::collections::HashSet;
+use std::fs;
+use tracing::{debug, info};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct Arguments {}
+
+pub async fn handle_command(_args: Arguments) -> Result<()> {
+    let project_dirs =
+        ProjectDirs::from("", "autometrics", "am").context("Unable to determine home directory")?;
+    let explorer_dir = project_dirs.data_local_dir().join(OFFLINE_DIR);
+
+    // The assets are downloaded to a new directory, so the assets of the
+    // explorer versions that am no longer uses don't pile up, and a failed
+    // download leaves the previous assets in place.
+    let download_dir = explorer_dir.with_extension("download");
+    if download_dir.exists() {
+        fs::remove_dir_all(&download_dir)?;
+    }
+
+    // The assets that the pages refer to are required, the ones that are found
+    // in the scripts and styles might just look like a path.
+    let mut queue: Vec<(String, bool)> = explorer::pages()
+        .flat_map(|(_, contents)| explorer::asset_references(&String::from_utf8_lossy(contents)))
+        .map(|path| (path, true))
+        .collect();
+    let mut seen = HashSet::new();
+    let mut downloaded = 0;
+
+    while let Some((path, required)) = queue.pop() {
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+
+        let url = format!("{CDN_URL}{path}");
+        let response = match CLIENT.get(&url).send().await?.error_for_status() {
+            Ok(response) => response,
+            Err(err) if required => bail!("Unable to download {url}: {err}"),
+            Err(err) => {
+                debug!(?err, "Skipping {url}");
+                continue;
+            }
+        };
+        let contents = response.bytes().await?;
+
+        let destination = download_dir.join(&path);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&destination, &contents)
+            .with_context(|| format!("Unable to write {}", destination.display()))?;
+        debug!("Downloaded {url}");
+        downloaded += 1;
+
+        if path.ends_with(".js") || path.ends_with(".css") {
+            let references = explorer::asset_references(&String::from_utf8_lossy(&contents));
+            queue.extend(references.into_iter().map(|path| (path, false)));
+        }
+    }
+
+    if explorer_dir.exists() {
+        fs::remove_dir_all(&explorer_dir)?;
+    }
+    fs::rename(&download_dir, &explorer_dir)?;
+
+    info!(
+        "Downloaded {downloaded} explorer assets to {}, use `am start --explorer-offline` to serve them",
+        explorer_dir.display()
+    );
+    Ok(())
+}
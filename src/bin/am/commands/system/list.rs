@@ -0,0 +1,68 @@
+use crate::installs;
+use anyhow::{Context, Result};
+use clap::Parser;
+use directories::ProjectDirs;
+use tracing::info;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct Arguments {}
+
+pub async fn handle_command(_args: Arguments) -> Result<()> {
+    let project_dirs =
+        ProjectDirs::from("", "autometrics", "am").context("Unable to determine home directory")?;
+    let local_data = project_dirs.data_local_dir().to_owned();
+
+    let installs = installs::list(&local_data)?;
+
+    if installs.is_empty() {
+        info!("No programs have been downloaded yet");
+        return Ok(());
+    }
+
+    for install in &installs {
+        let last_used = install
+            .last_used
+            .map(|last_used| humantime::format_rfc3339_seconds(last_used).to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        info!(
+            "{:<16} {:<10} {:>10}   last used: {last_used}",
+            install.program,
+            install.version,
+            format_size(install.size)
+        );
+    }
+
+    info!(
+        "Total: {} in {}",
+        format_size(installs.iter().map(|install| install.size).sum()),
+        local_data.display()
+    );
+
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[test]
+fn test_format_size() {
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(1536), "1.5 KiB");
+    assert_eq!(format_size(90 * 1024 * 1024), "90.0 MiB");
+}
@@ -1,9 +1,21 @@
+use crate::commands::grafana::DEFAULT_GRAFANA_VERSION;
+use crate::commands::start::{gateway, host_metrics, loki, tempo};
+use crate::commands::start::{
+    DEFAULT_ALERTMANAGER_VERSION, DEFAULT_BLACKBOX_VERSION, DEFAULT_OTEL_COLLECTOR_VERSION,
+    DEFAULT_PROMETHEUS_VERSION, DEFAULT_PUSHGATEWAY_VERSION,
+};
+use crate::commands::system::snapshot::SNAPSHOTS_DIR;
+use crate::installs;
 use crate::interactive;
+use crate::versions::{self, VersionSpec};
 use anyhow::{bail, Context, Result};
+use autometrics_am::config::AmConfig;
 use clap::Parser;
 use directories::ProjectDirs;
 use indicatif::MultiProgress;
-use std::io;
+use std::collections::HashSet;
+use std::path::Path;
+use std::{fs, io};
 use tracing::{debug, info};
 
 #[derive(Parser)]
@@ -12,32 +24,204 @@ pub struct Arguments {
     /// Force the cleanup without asking for confirmation.
     #[clap(short, long, default_value = "false")]
     force: bool,
+
+    /// Only delete the versions that are not used by the current config
+    /// (including its profiles) or the defaults of am.
+    #[clap(long)]
+    unused: bool,
 }
 
-pub async fn handle_command(args: Arguments, _: MultiProgress) -> Result<()> {
+pub async fn handle_command(args: Arguments, config: AmConfig, _: MultiProgress) -> Result<()> {
+    // Get local directory
+    let project_dirs =
+        ProjectDirs::from("", "autometrics", "am").context("Unable to determine home directory")?;
+    let local_data = project_dirs.data_local_dir().to_owned();
+
+    if args.unused {
+        return prune_unused(&local_data, &config, args.force);
+    }
+
     // If the users hasn't specified the `force` argument, then ask the user if
     // they want to continue.
     if !args.force && !interactive::confirm("Prune all am program files?")? {
         bail!("Pruning cancelled");
     }
 
-    // Get local directory
-    let project_dirs =
-        ProjectDirs::from("", "autometrics", "am").context("Unable to determine home directory")?;
-    let local_data = project_dirs.data_local_dir().to_owned();
-
     debug!("Deleting all content from {:?}", local_data);
 
-    // For now just greedily delete everything in the local data directory for am
-    if let Err(err) = remove_dir_all::remove_dir_contents(&local_data) {
-        // If the root directory does not exist, we can ignore the error (NOTE:
-        // I don't know if it is possible to get this error in any other
-        // situations)
-        if err.kind() != io::ErrorKind::NotFound {
-            return Err(err.into());
+    // For now just greedily delete everything in the local data directory for
+    // am, except for the snapshots since those are not program files.
+    let entries = match fs::read_dir(&local_data) {
+        Ok(entries) => entries,
+        // If the root directory does not exist, there is nothing to delete.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            info!("Pruning complete");
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.ends_with(SNAPSHOTS_DIR) {
+            continue;
+        }
+
+        if path.is_dir() {
+            remove_dir_all::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
         }
     }
 
     info!("Pruning complete");
     Ok(())
 }
+
+fn prune_unused(local_data: &Path, config: &AmConfig, force: bool) -> Result<()> {
+    let referenced = referenced_installs(config, local_data)?;
+    let unused: Vec<_> = installs::list(local_data)?
+        .into_iter()
+        .filter(|install| !referenced.contains(&install.directory_name()))
+        .collect();
+
+    if unused.is_empty() {
+        info!("No unused versions found");
+        return Ok(());
+    }
+
+    for install in &unused {
+        info!("Unused: {} {}", install.program, install.version);
+    }
+
+    if !force && !interactive::confirm("Delete these versions?")? {
+        bail!("Pruning cancelled");
+    }
+
+    for install in &unused {
+        debug!("Deleting {:?}", install.path);
+        fs::remove_dir_all(&install.path)
+            .with_context(|| format!("Unable to delete {}", install.path.display()))?;
+    }
+
+    info!("Pruning complete, deleted {} version(s)", unused.len());
+    Ok(())
+}
+
+/// The directory names of the installs in `local_data` that are used by
+/// `config`. Versions that are not exact, like `latest` or `^2.48`, reference
+/// the highest installed version that satisfies them, which is the one am uses
+/// when it is offline.
+fn referenced_installs(config: &AmConfig, local_data: &Path) -> Result<HashSet<String>> {
+    let configs = std::iter::once(config).chain(
+        config
+            .profiles
+            .iter()
+            .flat_map(|profiles| profiles.values()),
+    );
+
+    let mut referenced = HashSet::from([format!(
+        "grafana-{}",
+        DEFAULT_GRAFANA_VERSION.trim_start_matches('v')
+    )]);
+
+    for config in configs {
+        let versions = [
+            (
+                &versions::PROMETHEUS,
+                &config.prometheus_version,
+                DEFAULT_PROMETHEUS_VERSION,
+            ),
+            (
+                &versions::PUSHGATEWAY,
+                &config.pushgateway_version,
+                DEFAULT_PUSHGATEWAY_VERSION,
+            ),
+            (
+                &versions::GATEWAY,
+                &config.gateway_version,
+                gateway::DEFAULT_VERSION,
+            ),
+            (
+                &versions::ALERTMANAGER,
+                &config.alertmanager_version,
+                DEFAULT_ALERTMANAGER_VERSION,
+            ),
+            (
+                &versions::OTEL_COLLECTOR,
+                &config.otel_collector_version,
+                DEFAULT_OTEL_COLLECTOR_VERSION,
+            ),
+            (
+                &versions::TEMPO,
+                &config.tempo_version,
+                tempo::DEFAULT_VERSION,
+            ),
+            (&versions::LOKI, &config.loki_version, loki::DEFAULT_VERSION),
+            (
+                &versions::PROMTAIL,
+                &config.loki_version,
+                loki::DEFAULT_VERSION,
+            ),
+            (
+                &versions::BLACKBOX_EXPORTER,
+                &config.blackbox_version,
+                DEFAULT_BLACKBOX_VERSION,
+            ),
+            (
+                &host_metrics::EXPORTER,
+                &config.host_metrics_version,
+                host_metrics::DEFAULT_VERSION,
+            ),
+        ];
+
+        for (program, version, default) in versions {
+            let version = version.as_deref().unwrap_or(default);
+            let version = match VersionSpec::parse(version)
+                .with_context(|| format!("Invalid {} version in the config", program.name))?
+            {
+                VersionSpec::Exact(version) => version.trim_start_matches('v').to_string(),
+                spec => match versions::resolve_installed(program, &spec, local_data)? {
+                    Some(version) => version.to_string(),
+                    None => continue,
+                },
+            };
+            referenced.insert(format!("{}-{version}", program.directory));
+        }
+    }
+
+    Ok(referenced)
+}
+
+#[test]
+fn test_referenced_installs() {
+    let local_data = tempfile::tempdir().unwrap();
+    for directory in [
+        "prometheus-2.47.0",
+        "prometheus-2.48.0",
+        "prometheus-2.48.1",
+    ] {
+        fs::create_dir(local_data.path().join(directory)).unwrap();
+    }
+
+    let referenced = |prometheus_version: &str| {
+        let config = AmConfig {
+            prometheus_version: Some(prometheus_version.to_string()),
+            ..Default::default()
+        };
+        referenced_installs(&config, local_data.path()).unwrap()
+    };
+
+    assert!(referenced("v2.47.0").contains("prometheus-2.47.0"));
+
+    let latest = referenced("latest");
+    assert!(latest.contains("prometheus-2.48.1"));
+    assert!(!latest.contains("prometheus-2.48.0"));
+    assert!(!latest.contains("prometheus-2.47.0"));
+
+    assert!(referenced("~2.48.0").contains("prometheus-2.48.1"));
+    assert!(referenced("<2.48").contains("prometheus-2.47.0"));
+    assert!(!referenced("^3")
+        .iter()
+        .any(|directory| directory.starts_with("prometheus-")));
+}
@@ -0,0 +1,190 @@
+use crate::commands::start::CLIENT;
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use directories::ProjectDirs;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+use url::Url;
+
+/// The directory in the local data directory of am in which the snapshots are
+/// stored.
+pub(crate) const SNAPSHOTS_DIR: &str = "snapshots";
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct Arguments {
+    /// The Prometheus to snapshot. It needs to run on this machine, since the
+    /// snapshot is read from its data directory.
+    ///
+    /// This defaults to the Prometheus proxy of a running `am start`.
+    #[clap(
+        long,
+        env,
+        default_value = "http://127.0.0.1:6789/prometheus",
+        alias = "prometheus-address"
+    )]
+    prometheus_url: Url,
+
+    /// The amount of snapshots to keep. Older snapshots are deleted.
+    #[clap(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    keep: u64,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    status: String,
+    data: Option<T>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RuntimeInfo {
+    #[serde(rename = "CWD")]
+    cwd: PathBuf,
+}
+
+pub async fn handle_command(args: Arguments) -> Result<()> {
+    let project_dirs =
+        ProjectDirs::from("", "autometrics", "am").context("Unable to determine home directory")?;
+    let snapshots_dir = project_dirs.data_local_dir().join(SNAPSHOTS_DIR);
+    fs::create_dir_all(&snapshots_dir)
+        .with_context(|| format!("Unable to create directory {}", snapshots_dir.display()))?;
+
+    let snapshot: Snapshot = api_call(&args.prometheus_url, "admin/tsdb/snapshot", true)
+        .await
+        .context("Unable to create a snapshot, start am with --enable-admin-api to enable the admin API of Prometheus")?;
+
+    // The snapshot is created in the `snapshots` directory of the TSDB path,
+    // which is relative to the working directory of Prometheus by default.
+    let flags: HashMap<String, String> =
+        api_call(&args.prometheus_url, "status/flags", false).await?;
+    let runtime_info: RuntimeInfo =
+        api_call(&args.prometheus_url, "status/runtimeinfo", false).await?;
+    let tsdb_path = runtime_info.cwd.join(
+        flags
+            .get("storage.tsdb.path")
+            .map_or("data", String::as_str),
+    );
+    let snapshot_path = tsdb_path.join("snapshots").join(&snapshot.name);
+
+    if !snapshot_path.is_dir() {
+        bail!(
+            "Snapshot {} not found in {}, is Prometheus running on another machine?",
+            snapshot.name,
+            tsdb_path.display()
+        );
+    }
+
+    let archive = snapshots_dir.join(format!("prometheus-{}.tar.gz", snapshot.name));
+    write_archive(&snapshot_path, &archive)?;
+
+    // The archive is all we need, so the snapshot shouldn't take up space in
+    // the data directory of Prometheus.
+    fs::remove_dir_all(&snapshot_path)
+        .with_context(|| format!("Unable to delete {}", snapshot_path.display()))?;
+
+    info!("Saved snapshot to {}", archive.display());
+    info!(
+        "Extract it into an empty directory and pass that to `am start --data-dir` to restore it"
+    );
+
+    for old in expired_snapshots(&snapshots_dir, args.keep as usize)? {
+        debug!("Deleting {:?}", old);
+        fs::remove_file(&old).with_context(|| format!("Unable to delete {}", old.display()))?;
+    }
+
+    Ok(())
+}
+
+async fn api_call<T: DeserializeOwned>(prometheus_url: &Url, path: &str, post: bool) -> Result<T> {
+    // Make sure the path of the Prometheus URL is kept.
+    let mut url = prometheus_url.clone();
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    let url = url.join("api/v1/")?.join(path)?;
+
+    let request = if post {
+        CLIENT.post(url)
+    } else {
+        CLIENT.get(url)
+    };
+
+    let response: ApiResponse<T> = request
+        .send()
+        .await
+        .with_context(|| format!("Unable to reach Prometheus at {prometheus_url}"))?
+        .json()
+        .await
+        .context("Unexpected response from Prometheus")?;
+
+    match (response.status.as_str(), response.data) {
+        ("success", Some(data)) => Ok(data),
+        _ => bail!(
+            "Prometheus returned an error: {}",
+            response.error.as_deref().unwrap_or("unknown error")
+        ),
+    }
+}
+
+fn write_archive(snapshot_path: &Path, archive: &Path) -> Result<()> {
+    let file =
+        File::create(archive).with_context(|| format!("Unable to create {}", archive.display()))?;
+
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    builder.append_dir_all(".", snapshot_path)?;
+    builder.into_inner()?.finish()?;
+
+    Ok(())
+}
+
+/// The snapshots in `snapshots_dir`, except for the `keep` most recent ones.
+/// Snapshot names start with their creation time, so sorting them by name
+/// sorts them by age.
+fn expired_snapshots(snapshots_dir: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+    let mut snapshots: Vec<PathBuf> = fs::read_dir(snapshots_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("prometheus-") && name.ends_with(".tar.gz"))
+        })
+        .collect();
+
+    snapshots.sort();
+    let expired = snapshots.len().saturating_sub(keep);
+    snapshots.truncate(expired);
+
+    Ok(snapshots)
+}
+
+#[test]
+fn test_expired_snapshots() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in [
+        "prometheus-20231010T100000Z-1a.tar.gz",
+        "prometheus-20231012T100000Z-3c.tar.gz",
+        "prometheus-20231011T100000Z-2b.tar.gz",
+        "notes.txt",
+    ] {
+        fs::write(dir.path().join(name), "").unwrap();
+    }
+
+    assert_eq!(
+        expired_snapshots(dir.path(), 2).unwrap(),
+        vec![dir.path().join("prometheus-20231010T100000Z-1a.tar.gz")]
+    );
+    assert!(expired_snapshots(dir.path(), 5).unwrap().is_empty());
+}
@@ -1,38 +1,115 @@
 use crate::commands::start::CLIENT;
-use crate::downloader::download_github_release;
+use crate::downloader::{download_github_release, verify_signature};
+use crate::error::AmError;
 use anyhow::{anyhow, bail, Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use directories::ProjectDirs;
 use indicatif::MultiProgress;
-use itertools::Itertools;
 use octocrab::models::repos::{Asset, Release};
 use self_replace::self_replace;
 use semver_rs::Version;
-use std::fs::{File, OpenOptions};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::Path;
 use std::time::{Duration, SystemTime};
 use std::{env, fs};
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, info, trace, warn};
 
 const AUTOMETRICS_GITHUB_ORG: &str = "autometrics-dev";
 const AUTOMETRICS_AM_REPO: &str = "am";
 
+/// The tag of the release that is rebuilt from the main branch every night.
+const NIGHTLY_TAG: &str = "nightly";
+
+/// The exit code of `am update --check` when a newer version is available.
+/// It is distinct from the codes of the [`AmError`]s and of clap, which uses
+/// `2` for invalid arguments.
+const UPDATE_AVAILABLE_EXIT_CODE: i32 = 10;
+
 #[derive(Parser)]
 pub struct Arguments {
     /// Whenever to ignore Homebrew checks and forcefully update
     #[clap(long, short)]
     force: bool,
+
+    /// The release channel to update from.
+    #[clap(long, env = "AM_UPDATE_CHANNEL", value_enum, default_value_t)]
+    channel: Channel,
+
+    /// Only check whether a newer version is available, without installing it.
+    ///
+    /// Exits with status 2 if a newer version is available and with 0 if am is
+    /// up to date, so it can be used in scripts.
+    #[clap(long)]
+    check: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Channel {
+    /// The latest stable release.
+    #[default]
+    Stable,
+
+    /// The latest release, including betas and release candidates.
+    Beta,
+
+    /// The nightly build of the main branch.
+    Nightly,
 }
 
 pub(crate) async fn handle_command(args: Arguments, mp: MultiProgress) -> Result<()> {
-    let release = latest_release().await?;
+    let release = latest_release(args.channel).await?;
+    let new_tag = release.tag_name.clone();
+
+    // Only the binary for the current platform and its checksum are needed,
+    // not the other assets of the release.
+    let asset_needed = asset_needed()?;
+    let (binary_asset, sha256_asset) = find_assets(&release.assets, asset_needed)
+        .with_context(|| format!("Could not find {asset_needed} in release {new_tag}"))?;
+
+    let checksum_line = CLIENT
+        .get(sha256_asset.browser_download_url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let remote_checksum = checksum_line
+        .split_once(' ')
+        .map(|(checksum, _)| checksum)
+        .unwrap_or(&checksum_line)
+        .trim()
+        .to_string();
 
-    if !update_needed(&release)? {
+    let executable = env::current_exe()?;
+
+    // Nightly builds all have the same tag, so the checksum tells whether
+    // the nightly build has changed since it was installed.
+    let needs_update = match args.channel {
+        Channel::Nightly => file_checksum(&executable)? != remote_checksum,
+        Channel::Stable | Channel::Beta => is_newer(&release.tag_name)?,
+    };
+
+    if args.check {
+        if !needs_update {
+            println!("am v{} is up to date", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+
+        println!(
+            "A new version of am is available: {new_tag} (current version: v{})",
+            env!("CARGO_PKG_VERSION")
+        );
+        std::process::exit(UPDATE_AVAILABLE_EXIT_CODE);
+    }
+
+    if !needs_update {
         info!("Already on the latest version");
         return Ok(());
     }
 
-    let new_tag = release.tag_name;
-
     if is_homebrew() && !args.force {
         info!("A new version of `am` is available: {new_tag}");
         info!("You can update by running `brew upgrade am` (or use `am update --force`)");
@@ -41,25 +118,6 @@ pub(crate) async fn handle_command(args: Arguments, mp: MultiProgress) -> Result
 
     info!("Updating to {new_tag}");
 
-    let asset_needed = asset_needed()?;
-
-    let assets: Option<(&Asset, &Asset)> = release
-        .assets
-        .iter()
-        .filter(|a| a.name.starts_with(asset_needed))
-        .sorted_by(|a, b| a.name.cmp(&b.name))
-        .collect_tuple();
-
-    if assets.is_none() {
-        error!("Could not find release for your target platform.");
-        return Ok(());
-    }
-
-    // .unwrap is safe because we checked above if its none
-    // because of .sorted_by above (which sorts by name), the .sha256 file will be the second one *guaranteed*
-    let (binary_asset, sha256_asset) = assets.unwrap();
-
-    let executable = env::current_exe()?;
     let temp_exe = executable
         .parent()
         .ok_or_else(|| anyhow!("Parent directory not found"))?
@@ -77,18 +135,6 @@ pub(crate) async fn handle_command(args: Arguments, mp: MultiProgress) -> Result
     )
     .await?;
 
-    let checksum_line = CLIENT
-        .get(sha256_asset.browser_download_url.clone())
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    let remote_checksum = checksum_line
-        .split_once(' ')
-        .map(|(checksum, _)| checksum)
-        .unwrap_or(&checksum_line);
-
     if calculated_checksum != remote_checksum {
         debug!(
             %remote_checksum,
@@ -98,7 +144,23 @@ pub(crate) async fn handle_command(args: Arguments, mp: MultiProgress) -> Result
         fs::remove_file(&temp_exe).context("Failed to delete file that failed checksum match")?;
         drop(temp_exe);
 
-        bail!("Calculated sha256 hash does not match the remote sha256 hash");
+        return Err(AmError::ChecksumMismatch {
+            package: binary_asset.name.clone(),
+        }
+        .into());
+    }
+
+    if let Err(err) = verify_signature(
+        &temp_exe,
+        AUTOMETRICS_GITHUB_ORG,
+        AUTOMETRICS_AM_REPO,
+        new_tag.strip_prefix('v').unwrap_or(&new_tag),
+        &binary_asset.name,
+    )
+    .await
+    {
+        fs::remove_file(&temp_exe).context("Failed to delete file that failed signature check")?;
+        return Err(err);
     }
 
     self_replace(&temp_exe).context("failed to replace self")?;
@@ -108,94 +170,148 @@ pub(crate) async fn handle_command(args: Arguments, mp: MultiProgress) -> Result
     Ok(())
 }
 
+/// Find the binary `asset_needed` and its checksum file in `assets`. Other
+/// assets that start with the same name, like its signature, are ignored.
+fn find_assets<'a>(assets: &'a [Asset], asset_needed: &str) -> Option<(&'a Asset, &'a Asset)> {
+    let binary = assets.iter().find(|asset| asset.name == asset_needed)?;
+    let sha256 = assets
+        .iter()
+        .find(|asset| asset.name == format!("{asset_needed}.sha256"))?;
+    Some((binary, sha256))
+}
+
+/// The sha256sum hex-digest of the file at `path`.
+fn file_checksum(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// How long the latest version that was found by the background check is
+/// cached, before GitHub is asked again.
+const UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Check in the background whether a newer version of am is available, and
+/// hint to update if so. The latest version is cached in the data directory,
+/// so GitHub is only asked once a day.
 pub(crate) async fn update_check() {
     let Some(project_dirs) = ProjectDirs::from("", "autometrics", "am") else {
         warn!("failed to run update checker: home directory does not exist");
         return;
     };
 
-    let config_dir = project_dirs.config_dir();
-
-    if let Err(err) = fs::create_dir_all(config_dir) {
-        error!(?err, "failed to create config directory");
-        return;
-    }
-
-    let check_file = config_dir.join("version_check");
-
-    let should_check = match fs::metadata(&check_file) {
-        Ok(metadata) => {
-            if let Ok(date) = metadata.modified() {
-                date < (SystemTime::now() - Duration::from_secs(60 * 60 * 24))
-            } else {
-                false
+    let check_file = project_dirs.data_local_dir().join("latest_version");
+
+    let cached_tag = fs::metadata(&check_file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .filter(|modified| {
+            SystemTime::now()
+                .duration_since(*modified)
+                .is_ok_and(|age| age < UPDATE_CHECK_INTERVAL)
+        })
+        .and_then(|_| fs::read_to_string(&check_file).ok())
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty());
+
+    let tag = match cached_tag {
+        Some(tag) => tag,
+        None => {
+            let Ok(release) = latest_release(Channel::Stable).await else {
+                return;
+            };
+
+            // Failing to cache the version only means that GitHub gets asked
+            // again next time.
+            let result = check_file
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&check_file, &release.tag_name));
+            if let Err(err) = result {
+                trace!(?err, "failed to write the `latest_version` file");
             }
-        }
-        Err(err) => {
-            // This will most likely be caused by the file not existing, so we
-            // will just trace it and go ahead with the version check.
-            trace!(%err, "checking the update file check resulted in a error");
-            true
+
+            release.tag_name
         }
     };
 
-    // We've checked the version recently, so just return early indicating that
-    // no update should be done.
-    if !should_check {
+    if !matches!(is_newer(&tag), Ok(true)) {
         return;
     }
 
-    let Ok(release) = latest_release().await else {
-        return;
-    };
-    let Ok(needs_update) = update_needed(&release) else {
-        return;
+    let command = if is_homebrew() {
+        "brew upgrade am"
+    } else {
+        "am update"
     };
-
-    if let Err(err) = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&check_file)
-    {
-        trace!(?err, "failed to create `version_check` file");
-    }
-
-    if !needs_update {
-        return;
-    }
-
-    info!("New update is available: {}", release.tag_name);
+    info!(
+        "am {tag} is available (you have v{}), run `{command}` to update",
+        env!("CARGO_PKG_VERSION")
+    );
 }
 
-fn update_needed(release: &Release) -> Result<bool> {
+/// Whether `tag` is a newer version than the running version of am.
+fn is_newer(tag: &str) -> Result<bool> {
     let current_tag = Version::new(env!("CARGO_PKG_VERSION")).parse()?;
-    let new_tag = Version::new(
-        release
-            .tag_name
-            .strip_prefix('v')
-            .unwrap_or(&release.tag_name),
-    )
-    .parse()?;
+    let new_tag = Version::new(tag.strip_prefix('v').unwrap_or(tag)).parse()?;
 
     Ok(new_tag > current_tag)
 }
 
-async fn latest_release() -> Result<Release> {
-    octocrab::instance()
+async fn latest_release(channel: Channel) -> Result<Release> {
+    let releases = octocrab::instance();
+    let releases = releases
         .repos(AUTOMETRICS_GITHUB_ORG, AUTOMETRICS_AM_REPO)
-        .releases()
-        .get_latest()
-        .await
-        .context("failed to check latest release from GitHub")
+        .releases();
+
+    match channel {
+        Channel::Stable => releases.get_latest().await,
+        Channel::Nightly => releases.get_by_tag(NIGHTLY_TAG).await,
+        Channel::Beta => {
+            let page = releases
+                .list()
+                .per_page(100u8)
+                .send()
+                .await
+                .context("failed to list releases from GitHub")?;
+
+            let releases: Vec<_> = page
+                .items
+                .into_iter()
+                .filter(|release| !release.draft)
+                .collect();
+            let newest = newest_tag(releases.iter().map(|release| release.tag_name.as_str()))
+                .map(ToString::to_string);
+
+            return releases
+                .into_iter()
+                .find(|release| Some(&release.tag_name) == newest.as_ref())
+                .ok_or_else(|| anyhow!("no releases found on GitHub"));
+        }
+    }
+    .with_context(|| format!("failed to check latest {channel:?} release from GitHub"))
+}
+
+/// The tag with the highest version, including pre-releases. Tags that aren't
+/// a version, like the nightly tag, are skipped.
+fn newest_tag<'a>(tags: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    tags.filter_map(|tag| {
+        let version = Version::new(tag.strip_prefix('v').unwrap_or(tag))
+            .parse()
+            .ok()?;
+        (!version.is_empty() && !version.is_any()).then_some((version, tag))
+    })
+    .max_by(|(a, _), (b, _)| a.cmp(b))
+    .map(|(_, tag)| tag)
 }
 
 fn asset_needed() -> Result<&'static str> {
     Ok(match env!("TARGET") {
         "x86_64-unknown-linux-gnu" => "am-linux-x86_64",
         "aarch64-unknown-linux-gnu" => "am-linux-aarch64",
-        "x86_64-apple-darwin" => "am-macos-aarch64",
-        "aarch64-apple-darwin" => "am-macos-x86_64",
+        "x86_64-apple-darwin" => "am-macos-x86_64",
+        "aarch64-apple-darwin" => "am-macos-aarch64",
         target => bail!("unsupported target: {target}"),
     })
 }
@@ -215,3 +331,16 @@ fn is_homebrew() -> bool {
     #[cfg(all(not(target_os = "linux"), not(target_os = "macos")))]
     return false;
 }
+
+#[test]
+fn test_newest_tag() {
+    let tags = [
+        "v0.5.1",
+        "nightly",
+        "v0.6.0-beta.1",
+        "v0.6.0-beta.2",
+        "v0.5.0",
+    ];
+    assert_eq!(newest_tag(tags.into_iter()), Some("v0.6.0-beta.2"));
+    assert_eq!(newest_tag(["nightly"].into_iter()), None);
+}
@@ -0,0 +1,276 @@
+use super::start::{preview_prom_config, Endpoint, DEFAULT_PROMETHEUS_VERSION};
+use crate::error::AmError;
+use crate::versions::{self, VersionSpec};
+use anyhow::{bail, Context, Result};
+use autometrics_am::config::{endpoints_from_first_input, AmConfig};
+use autometrics_am::parser::prometheus_arg_parser;
+use autometrics_am::{rules, slo};
+use clap::Parser;
+use directories::ProjectDirs;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use tempfile::NamedTempFile;
+use tracing::{debug, error, info};
+
+#[derive(Parser, Clone)]
+pub struct Arguments {
+    /// Validate the config with the settings of this profile applied.
+    #[clap(long, env = "AM_PROFILE")]
+    profile: Option<String>,
+
+    /// Print the Prometheus config that `am start` would generate.
+    #[clap(long)]
+    print: bool,
+}
+
+/// A problem with the config file, optionally with the line of the config file
+/// that it relates to.
+struct Problem {
+    message: String,
+    line: Option<String>,
+}
+
+pub fn handle_command(args: Arguments, config_file: Option<PathBuf>) -> Result<()> {
+    let path = config_file.unwrap_or_else(|| PathBuf::from("./am.toml"));
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read config file {}", path.display()))?;
+
+    // The error of the toml parser already points to the offending line.
+    let config: AmConfig = match toml::from_str(&contents) {
+        Ok(config) => config,
+        Err(err) => {
+            return Err(anyhow::Error::new(AmError::InvalidConfig).context(format!(
+                "{} is not a valid config file:\n{err}",
+                path.display()
+            )))
+        }
+    };
+    let config = config.with_profile(args.profile.as_deref())?;
+
+    let mut problems = vec![];
+    let mut report = |message: String, needle: Option<&str>| {
+        problems.push(Problem {
+            message,
+            line: needle.and_then(|needle| locate(&contents, needle)),
+        })
+    };
+
+    for endpoint in endpoints_from_first_input(vec![], config.endpoints.clone()) {
+        let needle = endpoint
+            .url
+            .host_str()
+            .map(|host| match endpoint.url.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host.to_string(),
+            });
+
+        if let Some(ca_cert) = &endpoint.ca_cert {
+            if !ca_cert.is_file() {
+                report(
+                    format!("CA certificate {} does not exist", ca_cert.display()),
+                    Some("ca-cert"),
+                );
+            }
+        }
+
+        let url = endpoint.url.clone();
+        if let Err(err) = Endpoint::try_from(endpoint) {
+            report(
+                format!("endpoint {url} is invalid: {err}"),
+                needle.as_deref(),
+            );
+        }
+    }
+
+    if let Err(err) = slo::load(config.slos.clone(), config.slo_file.as_deref()) {
+        report(format!("{err:#}"), None);
+    }
+
+    for path in config.rule_files.iter().flatten() {
+        let needle = path.to_string_lossy();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                report(
+                    format!("Unable to read rule file {}: {err}", path.display()),
+                    Some(&needle),
+                );
+                continue;
+            }
+        };
+
+        match rules::check(&contents) {
+            Ok(rules_report) => {
+                for problem in rules_report.problems {
+                    report(format!("rule file {}: {problem}", path.display()), None);
+                }
+            }
+            Err(err) => report(
+                format!("rule file {} is invalid: {err}", path.display()),
+                Some(&needle),
+            ),
+        }
+    }
+
+    if let Some(kubeconfig) = config
+        .kubernetes
+        .as_ref()
+        .and_then(|kubernetes| kubernetes.kubeconfig.as_ref())
+    {
+        if !kubeconfig.is_file() {
+            report(
+                format!("kubeconfig {} does not exist", kubeconfig.display()),
+                Some("kubeconfig"),
+            );
+        }
+    }
+
+    if let Some(data_dir) = &config.prometheus_data_dir {
+        if data_dir.exists() && !data_dir.is_dir() {
+            report(
+                format!(
+                    "Prometheus data dir {} is not a directory",
+                    data_dir.display()
+                ),
+                Some("prometheus-data-dir"),
+            );
+        }
+    }
+
+    for arg in config.prometheus_args.iter().flatten() {
+        if let Err(err) = prometheus_arg_parser(arg) {
+            report(format!("{err:#}"), Some(arg));
+        }
+    }
+
+    let prometheus_version = config
+        .prometheus_version
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PROMETHEUS_VERSION.to_string());
+    let prometheus_version = match VersionSpec::parse(&prometheus_version) {
+        Ok(version) => Some(version),
+        Err(err) => {
+            report(format!("{err:#}"), Some("prometheus-version"));
+            None
+        }
+    };
+
+    match preview_prom_config(config) {
+        Ok((prometheus_config, rendered)) => {
+            for problem in prometheus_config.validate() {
+                report(format!("generated Prometheus config: {problem}"), None);
+            }
+
+            if args.print {
+                print!("{rendered}");
+            }
+
+            if let Some(prometheus_version) = &prometheus_version {
+                if let Err(err) = promtool_check(&rendered, prometheus_version) {
+                    report(format!("{err:#}"), None);
+                }
+            }
+        }
+        Err(err) => report(
+            format!("Unable to generate the Prometheus config: {err:#}"),
+            None,
+        ),
+    }
+
+    if problems.is_empty() {
+        info!("{} is valid", path.display());
+        return Ok(());
+    }
+
+    for problem in &problems {
+        error!("{}", problem.message);
+        if let Some(line) = &problem.line {
+            error!("{line}");
+        }
+    }
+
+    Err(anyhow::Error::new(AmError::InvalidConfig).context(format!(
+        "Found {} problem(s) in {}",
+        problems.len(),
+        path.display()
+    )))
+}
+
+/// Find the first line of the config file that contains `needle`, formatted
+/// with its line number.
+fn locate(contents: &str, needle: &str) -> Option<String> {
+    contents
+        .lines()
+        .enumerate()
+        .find(|(_, line)| line.contains(needle))
+        .map(|(line_number, line)| format!("  {} | {}", line_number + 1, line.trim_end()))
+}
+
+/// Run `promtool check config` on the rendered config, if `am start` has
+/// downloaded the Prometheus version that will be used. Versions that aren't
+/// exact are resolved with the installed versions only.
+fn promtool_check(rendered: &str, prometheus_version: &VersionSpec) -> Result<()> {
+    let Some(project_dirs) = ProjectDirs::from("", "autometrics", "am") else {
+        return Ok(());
+    };
+
+    #[cfg(not(target_os = "windows"))]
+    let program = "promtool";
+    #[cfg(target_os = "windows")]
+    let program = "promtool.exe";
+
+    let local_data = project_dirs.data_local_dir();
+    let prometheus_version = match prometheus_version {
+        VersionSpec::Exact(version) => version.trim_start_matches('v').to_string(),
+        spec => match versions::resolve_installed(&versions::PROMETHEUS, spec, local_data)? {
+            Some(version) => version.to_string(),
+            None => {
+                debug!("No installed Prometheus version matches, skipping promtool check");
+                return Ok(());
+            }
+        },
+    };
+
+    let promtool = local_data
+        .join(format!("prometheus-{prometheus_version}"))
+        .join(program);
+
+    if !promtool.exists() {
+        debug!(path = ?promtool, "promtool not found, skipping promtool check");
+        return Ok(());
+    }
+
+    let mut file = NamedTempFile::new()?;
+    file.write_all(rendered.as_bytes())?;
+
+    // The rule files are only written by `am start`, so only the syntax of the
+    // config is checked.
+    let output = Command::new(promtool)
+        .args(["check", "config", "--syntax-only"])
+        .arg(file.path())
+        .output()
+        .context("Unable to run promtool")?;
+
+    if !output.status.success() {
+        bail!(
+            "promtool rejected the generated Prometheus config:\n{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_locate() {
+    let contents = "pushgateway-enabled = true\n\n[[endpoint]]\nurl = \"localhost:3000\"\n";
+
+    assert_eq!(
+        locate(contents, "localhost:3000").as_deref(),
+        Some("  4 | url = \"localhost:3000\"")
+    );
+    assert_eq!(locate(contents, "kubeconfig"), None);
+}
@@ -0,0 +1,176 @@
+use anyhow::{bail, Context, Result};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+const PID_FILE: &str = "am.pid";
+const STATE_FILE: &str = "am-state.json";
+const LOG_FILE: &str = "am.log";
+
+/// Information about an `am start` instance running in the background.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DaemonState {
+    pub pid: u32,
+    pub listen_address: SocketAddr,
+    pub log_file: PathBuf,
+    #[serde(with = "humantime_serde")]
+    pub started_at: SystemTime,
+}
+
+pub(crate) fn data_dir() -> Result<PathBuf> {
+    let project_dirs =
+        ProjectDirs::from("", "autometrics", "am").context("Unable to determine home directory")?;
+    let data_dir = project_dirs.data_local_dir().to_owned();
+
+    fs::create_dir_all(&data_dir)
+        .with_context(|| format!("Unable to create data directory: {:?}", data_dir))?;
+
+    Ok(data_dir)
+}
+
+/// The log file of the instance that runs in the background. It is kept
+/// after the instance stops, so it can be inspected if it crashed.
+pub(crate) fn log_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join(LOG_FILE))
+}
+
+/// Start the current command again in the background, without the `--detach`
+/// flag. Its output will be written to a log file in the data directory.
+pub(crate) fn detach(listen_address: SocketAddr) -> Result<DaemonState> {
+    if let Some(state) = read_state()? {
+        bail!(
+            "am is already running in the background (pid {}), use `am stop` to stop it",
+            state.pid
+        );
+    }
+
+    let data_dir = data_dir()?;
+    let log_file = data_dir.join(LOG_FILE);
+    let log = File::create(&log_file)
+        .with_context(|| format!("Unable to create log file: {:?}", log_file))?;
+
+    let args = std::env::args_os().skip(1).filter(|arg| arg != "--detach");
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(args)
+        // The background instance shouldn't check for updates on every start.
+        .env("AM_NO_UPDATE", "1")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+
+    // Put the child in its own process group, so it doesn't receive the
+    // signals (ie. Ctrl+C) meant for the shell that started it.
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    let child = command
+        .spawn()
+        .context("Unable to start am in the background")?;
+
+    let state = DaemonState {
+        pid: child.id(),
+        listen_address,
+        log_file,
+        started_at: SystemTime::now(),
+    };
+
+    fs::write(data_dir.join(PID_FILE), state.pid.to_string())?;
+    fs::write(
+        data_dir.join(STATE_FILE),
+        serde_json::to_string_pretty(&state)?,
+    )?;
+
+    Ok(state)
+}
+
+/// Read the state of the background instance. This returns `None` if there is
+/// no instance running, cleaning up any stale state files.
+pub(crate) fn read_state() -> Result<Option<DaemonState>> {
+    let data_dir = data_dir()?;
+
+    let state = match fs::read_to_string(data_dir.join(STATE_FILE)) {
+        Ok(state) => state,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).context("Unable to read state file"),
+    };
+
+    let state: DaemonState = serde_json::from_str(&state).context("Unable to parse state file")?;
+
+    if !is_running(state.pid) {
+        debug!(
+            pid = state.pid,
+            "Removing state of stopped background instance"
+        );
+        remove_state()?;
+        return Ok(None);
+    }
+
+    Ok(Some(state))
+}
+
+/// Stop the background instance and wait for it to exit.
+pub(crate) async fn stop(state: &DaemonState) -> Result<()> {
+    // am stops gracefully (cleaning up its child processes) on SIGINT.
+    #[cfg(unix)]
+    let status = Command::new("kill")
+        .args(["-INT", &state.pid.to_string()])
+        .status()?;
+    #[cfg(windows)]
+    let status = Command::new("taskkill")
+        .args(["/PID", &state.pid.to_string(), "/T", "/F"])
+        .status()?;
+
+    if !status.success() {
+        bail!("Unable to stop am (pid {})", state.pid);
+    }
+
+    for _ in 0..100 {
+        if !is_running(state.pid) {
+            return remove_state();
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    bail!("am (pid {}) did not stop within 10 seconds", state.pid)
+}
+
+fn remove_state() -> Result<()> {
+    let data_dir = data_dir()?;
+
+    for file in [PID_FILE, STATE_FILE] {
+        match fs::remove_file(data_dir.join(file)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn is_running(pid: u32) -> bool {
+    #[cfg(unix)]
+    return Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+
+    #[cfg(windows)]
+    return Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/NH"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+        .unwrap_or(false);
+}
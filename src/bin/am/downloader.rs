@@ -1,14 +1,50 @@
 use crate::commands::start::CLIENT;
-use anyhow::{anyhow, bail, Result};
+use crate::error::AmError;
+use crate::metrics::METRICS;
+use anyhow::{anyhow, bail, Context, Result};
+use directories::ProjectDirs;
 use flate2::read::GzDecoder;
 use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
+use once_cell::sync::OnceCell;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
 use sha2::{Digest, Sha256};
 use std::fmt;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use tracing::{debug, error};
+use tempfile::NamedTempFile;
+use tokio::process::Command;
+use tracing::{debug, error, warn};
+use url::Url;
+
+/// How often a download is attempted before giving up. The delay between the
+/// attempts doubles every time, starting at one second.
+const DOWNLOAD_ATTEMPTS: u32 = 4;
+
+/// The base URL of a mirror of the GitHub releases. If set, release assets are
+/// downloaded from `{mirror}/{org}/{repo}/releases/download/v{version}/{file}`
+/// instead of github.com.
+pub(crate) static DOWNLOAD_MIRROR: OnceCell<Url> = OnceCell::new();
+
+/// Whether downloads need to have a valid signature. If not set, signatures are
+/// only verified when the release is signed and cosign is installed.
+pub(crate) static REQUIRE_SIGNATURE: OnceCell<bool> = OnceCell::new();
+
+/// The OIDC issuer of the certificates of releases that are signed keyless
+/// with cosign in GitHub Actions.
+const GITHUB_ACTIONS_ISSUER: &str = "https://token.actions.githubusercontent.com";
+
+/// The URL of `file` of a GitHub release, taking the mirror into account.
+fn github_release_url(org: &str, repo: &str, version: &str, file: &str) -> String {
+    let base = DOWNLOAD_MIRROR
+        .get()
+        .map(|mirror| mirror.as_str().trim_end_matches('/').to_string())
+        .unwrap_or_else(|| "https://github.com".to_string());
+
+    format!("{base}/{org}/{repo}/releases/download/v{version}/{file}")
+}
 
 /// downloads `package` into `destination`, returning the sha256sum hex-digest of the downloaded file
 pub async fn download_github_release(
@@ -19,21 +55,43 @@ pub async fn download_github_release(
     package: &str,
     multi_progress: &MultiProgress,
 ) -> Result<String> {
-    let mut hasher = Sha256::new();
-    let mut response = CLIENT
-        .get(format!(
-            "https://github.com/{org}/{repo}/releases/download/v{version}/{package}"
-        ))
-        .send()
-        .await?
-        .error_for_status()?;
+    download_file(
+        destination,
+        &github_release_url(org, repo, version, package),
+        &format!("Downloading {package} from github.com/{org}/{repo}"),
+        multi_progress,
+    )
+    .await
+}
 
-    let total_size = response
-        .content_length()
-        .ok_or_else(|| anyhow!("didn't receive content length"))?;
-    let mut downloaded = 0;
+/// downloads `url` into `destination`, returning the sha256sum hex-digest of the downloaded file
+///
+/// The download is first written to a partial file in the cache directory, so
+/// an interrupted download is resumed the next time instead of starting over.
+/// Transient network failures are retried with backoff.
+pub async fn download_file(
+    destination: &File,
+    url: &str,
+    message: &str,
+    multi_progress: &MultiProgress,
+) -> Result<String> {
+    let partial_path = partial_download_path(url);
+    let mut partial = match &partial_path {
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?
+        }
+        None => tempfile::tempfile()?,
+    };
 
-    let pb = multi_progress.add(ProgressBar::new(total_size));
+    let pb = multi_progress.add(ProgressBar::new(0));
 
     // https://github.com/console-rs/indicatif/blob/HEAD/examples/download.rs#L12
     pb.set_style(
@@ -42,45 +100,154 @@ pub async fn download_github_release(
             .progress_chars("=> ")
     );
 
-    pb.set_message(format!(
-        "Downloading {package} from github.com/{org}/{repo}"
-    ));
+    pb.set_message(message.to_string());
+
+    let mut attempt = 1;
+    loop {
+        match download_attempt(&mut partial, url, &pb).await {
+            Ok(()) => {
+                METRICS.record_download(url, true);
+                break;
+            }
+            Err(err) if attempt < DOWNLOAD_ATTEMPTS && is_transient(&err) => {
+                let delay = Duration::from_secs(1 << (attempt - 1));
+                warn!("Downloading {url} failed: {err}, retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(err) => {
+                METRICS.record_download(url, false);
+                pb.finish_and_clear();
+                multi_progress.remove(&pb);
+                return Err(err.context(AmError::Download {
+                    url: url.to_string(),
+                }));
+            }
+        }
+    }
+
+    pb.finish_and_clear();
+    multi_progress.remove(&pb);
+
+    // Copying the download is blocking, so make sure other downloads can
+    // continue in the meantime.
+    let checksum = tokio::task::block_in_place(|| -> Result<String> {
+        let mut hasher = Sha256::new();
+        let mut writer = BufWriter::new(destination);
+        let mut buffer = vec![0; 64 * 1024];
+
+        partial.seek(SeekFrom::Start(0))?;
+        loop {
+            let read = partial.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            writer.write_all(&buffer[..read])?;
+        }
+        writer.flush()?;
+
+        Ok(hex::encode(hasher.finalize()))
+    })?;
 
-    let mut buffer = BufWriter::new(destination);
+    if let Some(path) = partial_path {
+        if let Err(err) = fs::remove_file(&path) {
+            warn!(?err, ?path, "Unable to remove partial download");
+        }
+    }
 
+    Ok(checksum)
+}
+
+/// Download `url` into `partial`, continuing after the bytes that are already
+/// in there if the server supports range requests.
+async fn download_attempt(partial: &mut File, url: &str, pb: &ProgressBar) -> Result<()> {
+    let mut offset = partial.seek(SeekFrom::End(0))?;
+
+    let mut request = CLIENT.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+    let mut response = request.send().await?;
+
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => debug!(offset, "Resuming download of {url}"),
+        status => {
+            // The partial download is either complete already or the server
+            // doesn't support range requests, so start over.
+            if status == StatusCode::RANGE_NOT_SATISFIABLE {
+                response = CLIENT.get(url).send().await?;
+            }
+            response = response.error_for_status()?;
+
+            offset = 0;
+            partial.set_len(0)?;
+            partial.seek(SeekFrom::Start(0))?;
+        }
+    }
+
+    let total_size = offset
+        + response
+            .content_length()
+            .ok_or_else(|| anyhow!("didn't receive content length"))?;
+
+    pb.set_length(total_size);
+    pb.set_position(offset);
+
+    let mut buffer = BufWriter::new(&mut *partial);
     while let Some(ref chunk) = response.chunk().await? {
         buffer.write_all(chunk)?;
-        hasher.update(chunk);
+        pb.inc(chunk.len() as u64);
+    }
+    buffer.flush()?;
 
-        let new_size = (downloaded + chunk.len() as u64).min(total_size);
-        downloaded = new_size;
+    Ok(())
+}
 
-        pb.set_position(downloaded);
+/// Whether a download failed because of a problem that might go away when
+/// trying again, like a dropped connection or an overloaded server.
+fn is_transient(err: &anyhow::Error) -> bool {
+    let Some(err) = err.downcast_ref::<reqwest::Error>() else {
+        return false;
+    };
+
+    match err.status() {
+        Some(status) => {
+            status.is_server_error()
+                || status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS
+        }
+        None => err.is_timeout() || err.is_connect() || err.is_body() || err.is_request(),
     }
+}
 
-    pb.finish_and_clear();
-    multi_progress.remove(&pb);
+/// The file in the cache directory in which `url` is downloaded, before it is
+/// complete.
+fn partial_download_path(url: &str) -> Option<PathBuf> {
+    let project_dirs = ProjectDirs::from("", "autometrics", "am")?;
+    let file_name = url.rsplit('/').next().unwrap_or_default();
 
-    let checksum = hex::encode(hasher.finalize());
-    Ok(checksum)
+    Some(project_dirs.cache_dir().join("downloads").join(format!(
+        "{}-{file_name}.part",
+        &hex::encode(Sha256::digest(url.as_bytes()))[..16]
+    )))
 }
 
-pub async fn verify_checksum(
+/// The file with the checksums of the releases that are built with the
+/// release tooling of Prometheus (`promu`).
+pub const SHA256SUMS_FILE: &str = "sha256sums.txt";
+
+/// Verify `sha256sum` against the checksum of `package` that is listed in the
+/// `checksums_file` of the GitHub release.
+pub async fn verify_checksum_from_file(
     sha256sum: &str,
     org: &str,
     repo: &str,
     version: &str,
+    checksums_file: &str,
     package: &str,
 ) -> Result<()> {
-    let checksums = CLIENT
-        .get(format!(
-            "https://github.com/{org}/{repo}/releases/download/v{version}/sha256sums.txt"
-        ))
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
+    let checksums = fetch_checksums(org, repo, version, checksums_file).await?;
 
     // Go through all the lines in the checksum file and look for the one that
     // we need for our current service/version/os/arch.
@@ -98,12 +265,190 @@ pub async fn verify_checksum(
             calculated_checksum = ?sha256sum,
             "Calculated checksum for downloaded archive did not match expected checksum",
         );
-        bail!("checksum did not match");
+        return Err(AmError::ChecksumMismatch {
+            package: package.to_string(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// The outcome of checking the signature of a download, if it is not invalid.
+enum SignatureCheck {
+    Verified,
+    Unavailable(String),
+}
+
+/// Verify the cosign signature of `package` of a GitHub release, which was
+/// downloaded to `archive`. The signature and certificate are expected next to
+/// the package as `{package}.sig` and `{package}.pem`, like the releases of the
+/// OpenTelemetry Collector.
+///
+/// An invalid signature is always an error, a missing signature or cosign
+/// installation only if `REQUIRE_SIGNATURE` is set.
+pub async fn verify_signature(
+    archive: &Path,
+    org: &str,
+    repo: &str,
+    version: &str,
+    package: &str,
+) -> Result<()> {
+    match check_signature(archive, org, repo, version, package).await? {
+        SignatureCheck::Verified => {
+            debug!("Verified the signature of {package}");
+            Ok(())
+        }
+        SignatureCheck::Unavailable(reason) => allow_unsigned(package, &reason),
+    }
+}
+
+/// Check whether `package` can be used without verifying its signature, which
+/// is not possible because of `reason`.
+pub fn allow_unsigned(package: &str, reason: &str) -> Result<()> {
+    check_unsigned(
+        REQUIRE_SIGNATURE.get().copied().unwrap_or(false),
+        package,
+        reason,
+    )
+}
+
+/// Refuse the unsigned `package` if `require_signature` is set.
+fn check_unsigned(require_signature: bool, package: &str, reason: &str) -> Result<()> {
+    if require_signature {
+        bail!("Unable to verify the signature of {package}: {reason}");
     }
 
+    debug!("Not verifying the signature of {package}: {reason}");
     Ok(())
 }
 
+async fn check_signature(
+    archive: &Path,
+    org: &str,
+    repo: &str,
+    version: &str,
+    package: &str,
+) -> Result<SignatureCheck> {
+    if Command::new("cosign")
+        .arg("version")
+        .output()
+        .await
+        .is_err()
+    {
+        return Ok(SignatureCheck::Unavailable(
+            "cosign is not installed".to_string(),
+        ));
+    }
+
+    let mut files = vec![];
+    for extension in ["sig", "pem"] {
+        let response = CLIENT
+            .get(github_release_url(
+                org,
+                repo,
+                version,
+                &format!("{package}.{extension}"),
+            ))
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(SignatureCheck::Unavailable(
+                "the release is not signed".to_string(),
+            ));
+        }
+
+        let mut file = NamedTempFile::new()?;
+        file.write_all(&response.error_for_status()?.bytes().await?)?;
+        files.push(file);
+    }
+
+    let output = Command::new("cosign")
+        .arg("verify-blob")
+        .arg("--signature")
+        .arg(files[0].path())
+        .arg("--certificate")
+        .arg(files[1].path())
+        .arg("--certificate-identity-regexp")
+        .arg(format!("^https://github.com/{org}/{repo}/"))
+        .arg("--certificate-oidc-issuer")
+        .arg(GITHUB_ACTIONS_ISSUER)
+        .arg(archive)
+        .output()
+        .await
+        .context("Unable to run cosign")?;
+
+    if !output.status.success() {
+        bail!(
+            "signature of {package} is invalid: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(SignatureCheck::Verified)
+}
+
+/// Retrieve the `checksums_file` of a GitHub release. Release assets never
+/// change, so the file is cached locally to prevent downloading it again for
+/// every install.
+async fn fetch_checksums(
+    org: &str,
+    repo: &str,
+    version: &str,
+    checksums_file: &str,
+) -> Result<String> {
+    let cache_path = ProjectDirs::from("", "autometrics", "am").map(|project_dirs| {
+        project_dirs
+            .cache_dir()
+            .join("checksums")
+            .join(org)
+            .join(repo)
+            .join(format!("v{version}"))
+            .join(checksums_file)
+    });
+
+    if let Some(checksums) = cache_path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+    {
+        debug!(?cache_path, "Using cached checksums");
+        return Ok(checksums);
+    }
+
+    let checksums = CLIENT
+        .get(github_release_url(org, repo, version, checksums_file))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    // Failing to cache the checksums is not fatal, it only means they will be
+    // downloaded again next time.
+    if let Some(path) = cache_path {
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, &checksums));
+        if let Err(err) = result {
+            warn!(?err, ?path, "Unable to cache checksums");
+        }
+    }
+
+    Ok(checksums)
+}
+
+/// The extension of the archives in which Prometheus, Pushgateway, etc. are
+/// released for the current platform.
+#[cfg(not(target_os = "windows"))]
+pub const ARCHIVE_EXTENSION: &str = "tar.gz";
+#[cfg(target_os = "windows")]
+pub const ARCHIVE_EXTENSION: &str = "zip";
+
+/// Unpack the `archive` (either a `.tar.gz` or a `.zip` file) into
+/// `destination_path`, stripping `prefix` from all the paths in the archive.
+/// The archive should be positioned at its start.
 pub async fn unpack(
     archive: &File,
     package: &str,
@@ -111,14 +456,135 @@ pub async fn unpack(
     prefix: &str,
     multi_progress: &MultiProgress,
 ) -> Result<()> {
-    let tar_file = GzDecoder::new(archive);
-    let mut ar = tar::Archive::new(tar_file);
-
     let pb = multi_progress.add(ProgressBar::new_spinner());
     pb.set_style(ProgressStyle::default_spinner());
     pb.enable_steady_tick(Duration::from_millis(120));
     pb.set_message(format!("Unpacking {package}..."));
 
+    // Unpacking is blocking, so make sure other downloads can continue in the
+    // meantime.
+    tokio::task::block_in_place(|| {
+        if is_zip(archive)? {
+            unpack_zip(archive, destination_path, prefix)
+        } else {
+            unpack_tar_gz(archive, destination_path, prefix)
+        }
+    })?;
+
+    pb.finish_and_clear();
+    multi_progress.remove(&pb);
+    Ok(())
+}
+
+/// Download `package` of the `version` release of `org/repo` into a temporary
+/// file, and verify it against the `checksums_file` of the release and its
+/// signature. The returned file is positioned at its start.
+pub async fn download_release(
+    org: &str,
+    repo: &str,
+    version: &str,
+    package: &str,
+    checksums_file: &str,
+    multi_progress: &MultiProgress,
+) -> Result<NamedTempFile> {
+    let mut file = NamedTempFile::new()?;
+
+    let calculated_checksum =
+        download_github_release(file.as_file(), org, repo, version, package, multi_progress)
+            .await?;
+
+    verify_checksum_from_file(
+        &calculated_checksum,
+        org,
+        repo,
+        version,
+        checksums_file,
+        package,
+    )
+    .await?;
+
+    verify_signature(file.path(), org, repo, version, package).await?;
+
+    file.as_file_mut().seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Install the archive `package` of the `version` release of `org/repo` into
+/// `path`, once it is verified by [`download_release`]. The `prefix` is
+/// stripped from all the paths in the archive.
+#[allow(clippy::too_many_arguments)]
+pub async fn install_release_archive(
+    org: &str,
+    repo: &str,
+    version: &str,
+    package: &str,
+    checksums_file: &str,
+    prefix: &str,
+    path: &Path,
+    multi_progress: &MultiProgress,
+) -> Result<()> {
+    let archive =
+        download_release(org, repo, version, package, checksums_file, multi_progress).await?;
+
+    unpack(archive.as_file(), package, path, prefix, multi_progress).await
+}
+
+/// Whether `archive` starts with the signature of a zip file.
+fn is_zip(mut archive: &File) -> Result<bool> {
+    let mut signature = [0; 4];
+    let is_zip = match archive.read_exact(&mut signature) {
+        Ok(_) => signature == *b"PK\x03\x04",
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(err) => return Err(err.into()),
+    };
+
+    archive.seek(SeekFrom::Start(0))?;
+    Ok(is_zip)
+}
+
+fn unpack_zip(archive: &File, destination_path: &Path, prefix: &str) -> Result<()> {
+    let mut ar = zip::ZipArchive::new(archive)?;
+
+    for i in 0..ar.len() {
+        let mut entry = ar.by_index(i)?;
+
+        // `enclosed_name` rejects paths that would escape the destination.
+        let Some(path) = entry.enclosed_name().map(Path::to_path_buf) else {
+            bail!("archive contains an invalid path: {}", entry.name());
+        };
+
+        debug!("Unpacking {}", path.display());
+
+        // Remove the prefix and join it with the base directory.
+        let path = path.strip_prefix(prefix)?.to_owned();
+        let path = destination_path.join(path);
+
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = File::create(&path)?;
+        io::copy(&mut entry, &mut file)?;
+
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn unpack_tar_gz(archive: &File, destination_path: &Path, prefix: &str) -> Result<()> {
+    let tar_file = GzDecoder::new(archive);
+    let mut ar = tar::Archive::new(tar_file);
+
     for entry in ar.entries()? {
         let mut entry = entry?;
         let path = entry.path()?;
@@ -132,7 +598,156 @@ pub async fn unpack(
         entry.unpack(&path)?;
     }
 
-    pb.finish_and_clear();
-    multi_progress.remove(&pb);
     Ok(())
 }
+
+#[test]
+fn test_github_release_url() {
+    assert_eq!(
+        github_release_url("prometheus", "prometheus", "2.45.0", "sha256sums.txt"),
+        "https://github.com/prometheus/prometheus/releases/download/v2.45.0/sha256sums.txt"
+    );
+}
+
+#[test]
+fn test_check_unsigned() {
+    assert!(check_unsigned(false, "grafana.tar.gz", "not signed").is_ok());
+
+    assert_eq!(
+        check_unsigned(true, "grafana.tar.gz", "not signed")
+            .unwrap_err()
+            .to_string(),
+        "Unable to verify the signature of grafana.tar.gz: not signed"
+    );
+}
+
+/// Serve `BODY` on a free local port, answering range requests like GitHub
+/// does unless the path is `/no-ranges`. `/status/{code}` responds with
+/// `code`. Returns the base URL.
+#[cfg(test)]
+async fn serve_download() -> String {
+    use axum::extract::Path;
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::Router;
+    use http::HeaderMap;
+
+    let app = Router::new()
+        .route(
+            "/file",
+            get(|headers: HeaderMap| async move {
+                let offset = headers
+                    .get(RANGE)
+                    .and_then(|range| range.to_str().ok())
+                    .and_then(|range| range.strip_prefix("bytes="))
+                    .and_then(|range| range.strip_suffix('-'))
+                    .and_then(|offset| offset.parse::<usize>().ok());
+
+                match offset {
+                    None => (StatusCode::OK, BODY.to_vec()).into_response(),
+                    Some(offset) if offset >= BODY.len() => {
+                        StatusCode::RANGE_NOT_SATISFIABLE.into_response()
+                    }
+                    Some(offset) => {
+                        (StatusCode::PARTIAL_CONTENT, BODY[offset..].to_vec()).into_response()
+                    }
+                }
+            }),
+        )
+        .route("/no-ranges", get(|| async { BODY.to_vec() }))
+        .route(
+            "/status/:code",
+            get(|Path(code): Path<u16>| async move { StatusCode::from_u16(code).unwrap() }),
+        );
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service()),
+    );
+
+    base_url
+}
+
+#[cfg(test)]
+const BODY: &[u8] = b"the contents of a release archive";
+
+#[tokio::test]
+async fn test_download_attempt_resumes() {
+    let base_url = serve_download().await;
+
+    let mut partial = tempfile::tempfile().unwrap();
+    partial.write_all(&BODY[..10]).unwrap();
+    download_attempt(
+        &mut partial,
+        &format!("{base_url}/file"),
+        &ProgressBar::hidden(),
+    )
+    .await
+    .unwrap();
+
+    let mut contents = vec![];
+    partial.seek(SeekFrom::Start(0)).unwrap();
+    partial.read_to_end(&mut contents).unwrap();
+    assert_eq!(contents, BODY);
+}
+
+#[tokio::test]
+async fn test_download_attempt_starts_over() {
+    let base_url = serve_download().await;
+
+    // A complete partial download gets a `416 Range Not Satisfiable`, and a
+    // server without range requests sends everything again.
+    for (path, written) in [("/file", BODY.len()), ("/no-ranges", 10)] {
+        let mut partial = tempfile::tempfile().unwrap();
+        partial.write_all(&BODY[..written]).unwrap();
+        download_attempt(
+            &mut partial,
+            &format!("{base_url}{path}"),
+            &ProgressBar::hidden(),
+        )
+        .await
+        .unwrap();
+
+        let mut contents = vec![];
+        partial.seek(SeekFrom::Start(0)).unwrap();
+        partial.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, BODY, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn test_is_transient() {
+    let base_url = serve_download().await;
+
+    let status_error = |code: u16| {
+        let url = format!("{base_url}/status/{code}");
+        async move {
+            let response = CLIENT.get(url).send().await.unwrap();
+            anyhow::Error::from(response.error_for_status().unwrap_err())
+        }
+    };
+
+    assert!(is_transient(&status_error(503).await));
+    assert!(is_transient(&status_error(429).await));
+    assert!(is_transient(&status_error(408).await));
+    assert!(!is_transient(&status_error(404).await));
+    assert!(!is_transient(&status_error(403).await));
+
+    // Nothing listens on the port anymore once the listener is dropped.
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let err = CLIENT
+        .get(format!("http://127.0.0.1:{port}/file"))
+        .send()
+        .await
+        .unwrap_err();
+    assert!(is_transient(&err.into()));
+
+    assert!(!is_transient(&anyhow!("didn't receive content length")));
+}
@@ -0,0 +1,69 @@
+use std::process::ExitStatus;
+use thiserror::Error;
+
+/// The exit code for failures that don't have a code of their own. Invalid
+/// arguments exit with `2`, which is handled by clap.
+pub(crate) const GENERIC_EXIT_CODE: i32 = 1;
+
+/// The failures that am exits with a distinct code for, so scripts can react
+/// to them. These are returned as (part of) an [`anyhow::Error`], so they can
+/// be given more context like any other error.
+#[derive(Debug, Error)]
+pub(crate) enum AmError {
+    #[error("Unable to download {url}")]
+    Download { url: String },
+
+    #[error("The checksum of {package} did not match")]
+    ChecksumMismatch { package: String },
+
+    #[error("Port {port} is already in use")]
+    PortInUse { port: u16 },
+
+    #[error("{process} exited with status {status}")]
+    ChildCrashed { process: String, status: ExitStatus },
+
+    #[error("The config file is invalid")]
+    InvalidConfig,
+}
+
+impl AmError {
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            AmError::Download { .. } => 3,
+            AmError::ChecksumMismatch { .. } => 4,
+            AmError::PortInUse { .. } => 5,
+            AmError::ChildCrashed { .. } => 6,
+            AmError::InvalidConfig => 7,
+        }
+    }
+}
+
+/// The code that am exits with because of `err`. If multiple [`AmError`]s are
+/// part of `err`, the outermost one is used.
+pub(crate) fn exit_code(err: &anyhow::Error) -> i32 {
+    err.downcast_ref::<AmError>()
+        .map_or(GENERIC_EXIT_CODE, AmError::exit_code)
+}
+
+#[test]
+fn test_exit_code() {
+    use anyhow::Context;
+
+    let err = anyhow::anyhow!("connection refused");
+    assert_eq!(exit_code(&err), GENERIC_EXIT_CODE);
+
+    let err = anyhow::Error::new(AmError::PortInUse { port: 6789 })
+        .context("Unable to start the web server");
+    assert_eq!(exit_code(&err), 5);
+
+    let err = err.context(AmError::Download {
+        url: "https://github.com".to_string(),
+    });
+    assert_eq!(exit_code(&err), 3);
+
+    let err = Err::<(), _>(anyhow::anyhow!("timeout"))
+        .context(AmError::InvalidConfig)
+        .context("Unable to load config")
+        .unwrap_err();
+    assert_eq!(exit_code(&err), 7);
+}
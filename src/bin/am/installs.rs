@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::debug;
+
+/// The file that gets touched every time an installed program is used.
+const LAST_USED_FILE: &str = ".am-last-used";
+
+/// The programs that am downloads into its local data directory. Each version
+/// is installed in its own `{program}-{version}` directory.
+const PROGRAMS: [&str; 12] = [
+    "prometheus",
+    "pushgateway",
+    "autometrics-gateway",
+    "alertmanager",
+    "otelcol-contrib",
+    "tempo",
+    "loki",
+    "promtail",
+    "blackbox_exporter",
+    "node_exporter",
+    "windows_exporter",
+    "grafana",
+];
+
+/// A version of a program that has been downloaded by am.
+#[derive(Debug)]
+pub(crate) struct Install {
+    pub program: &'static str,
+    pub version: String,
+    pub path: PathBuf,
+    /// The size of the install in bytes.
+    pub size: u64,
+    /// When am used this install for the last time. This is `None` for installs
+    /// that haven't been used since this was being tracked.
+    pub last_used: Option<SystemTime>,
+}
+
+impl Install {
+    /// The name of the directory of the install (ie. `prometheus-2.45.0`).
+    pub(crate) fn directory_name(&self) -> String {
+        format!("{}-{}", self.program, self.version)
+    }
+}
+
+/// List all installs in `local_data`, sorted by program and version.
+pub(crate) fn list(local_data: &Path) -> Result<Vec<Install>> {
+    let entries = match fs::read_dir(local_data) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Unable to read directory {}", local_data.display()))
+        }
+    };
+
+    let mut installs = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let file_name = entry.file_name();
+        let Some(name) = file_name.to_str() else {
+            continue;
+        };
+
+        let Some((program, version)) = PROGRAMS.iter().find_map(|program| {
+            name.strip_prefix(program)
+                .and_then(|rest| rest.strip_prefix('-'))
+                .map(|version| (*program, version))
+        }) else {
+            continue;
+        };
+
+        let path = entry.path();
+        installs.push(Install {
+            program,
+            version: version.to_string(),
+            size: dir_size(&path)?,
+            last_used: fs::metadata(path.join(LAST_USED_FILE))
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+            path,
+        });
+    }
+
+    installs.sort_by(|a, b| (a.program, &a.version).cmp(&(b.program, &b.version)));
+    Ok(installs)
+}
+
+/// Record that the install in `path` is being used.
+pub(crate) fn mark_used(path: &Path) {
+    if let Err(err) = fs::write(path.join(LAST_USED_FILE), "") {
+        debug!(?err, ?path, "Unable to record usage of install");
+    }
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+
+    Ok(size)
+}
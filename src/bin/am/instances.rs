@@ -0,0 +1,130 @@
+use crate::daemon::{data_dir, is_running};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+const INSTANCES_FILE: &str = "instances.json";
+const LOCK_FILE: &str = "instances.lock";
+
+/// An `am start` instance and the addresses it listens on. Every instance
+/// registers itself in the data directory, so that concurrent runs don't try
+/// to use the same ports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Instance {
+    pub pid: u32,
+    pub listen_address: SocketAddr,
+    pub prometheus_port: u16,
+    pub pushgateway_port: Option<u16>,
+    pub alertmanager_port: Option<u16>,
+    #[serde(with = "humantime_serde")]
+    pub started_at: SystemTime,
+}
+
+impl Instance {
+    pub(crate) fn uses_port(&self, port: u16) -> bool {
+        self.listen_address.port() == port
+            || self.prometheus_port == port
+            || self.pushgateway_port == Some(port)
+            || self.alertmanager_port == Some(port)
+    }
+}
+
+/// Removes the instance from the registry once it is dropped.
+pub(crate) struct Registration {
+    pid: u32,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let result = update(|instances| instances.retain(|instance| instance.pid != self.pid));
+
+        if let Err(err) = result {
+            warn!(?err, "Unable to remove am from the instance registry");
+        }
+    }
+}
+
+/// The other `am start` instances that are currently running.
+pub(crate) fn running() -> Result<Vec<Instance>> {
+    let data_dir = data_dir()?;
+    let mut instances = read(&data_dir)?;
+    instances.retain(|instance| instance.pid != std::process::id() && is_running(instance.pid));
+
+    Ok(instances)
+}
+
+/// Add `instance` to the registry, until the returned [`Registration`] is
+/// dropped.
+pub(crate) fn register(instance: Instance) -> Result<Registration> {
+    let pid = instance.pid;
+    update(|instances| {
+        instances.retain(|existing| existing.pid != pid);
+        instances.push(instance);
+    })?;
+
+    Ok(Registration { pid })
+}
+
+/// Apply `change` to the registry while holding its lock. Instances that are
+/// no longer running, ie. because they were killed, are removed as well.
+fn update(change: impl FnOnce(&mut Vec<Instance>)) -> Result<()> {
+    let data_dir = data_dir()?;
+    let _lock = Lock::acquire(&data_dir)?;
+
+    let mut instances = read(&data_dir)?;
+    instances.retain(|instance| is_running(instance.pid));
+    change(&mut instances);
+
+    fs::write(
+        data_dir.join(INSTANCES_FILE),
+        serde_json::to_string_pretty(&instances)?,
+    )
+    .context("Unable to write the instance registry")
+}
+
+fn read(data_dir: &Path) -> Result<Vec<Instance>> {
+    match fs::read_to_string(data_dir.join(INSTANCES_FILE)) {
+        Ok(instances) => {
+            serde_json::from_str(&instances).context("Unable to parse the instance registry")
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err).context("Unable to read the instance registry"),
+    }
+}
+
+/// A lock file that makes sure only one instance updates the registry at a
+/// time.
+struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    fn acquire(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join(LOCK_FILE);
+
+        for _ in 0..50 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Lock { path }),
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(err) => return Err(err).context("Unable to lock the instance registry"),
+            }
+        }
+
+        // The lock is only held for a moment, so it was most likely left
+        // behind by an am that was killed while holding it.
+        debug!("Taking over stale lock of the instance registry");
+        Ok(Lock { path })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -1,5 +1,5 @@
 use dialoguer::theme::SimpleTheme;
-use dialoguer::{Confirm, Input};
+use dialoguer::{Confirm, Input, MultiSelect};
 use indicatif::MultiProgress;
 use std::io::{stderr, IoSlice, Result, Write};
 use tracing_subscriber::fmt::MakeWriter;
@@ -31,6 +31,16 @@ pub fn confirm_optional(prompt: impl Into<String>) -> Result<Option<bool>> {
         .interact_opt()
 }
 
+/// Let the user pick any number of `items`, which are all selected by default.
+/// Returns the indices of the selected items.
+pub fn select_all_by_default(prompt: impl Into<String>, items: &[String]) -> Result<Vec<usize>> {
+    MultiSelect::with_theme(&SimpleTheme)
+        .with_prompt(prompt)
+        .items(items)
+        .defaults(&vec![true; items.len()])
+        .interact()
+}
+
 /// A Writer that will output to stderr. It will also suspend any progress bars,
 /// so that the output of the progress bar is not mangled.
 ///
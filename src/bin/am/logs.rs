@@ -0,0 +1,157 @@
+use crate::shutdown;
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Child;
+use tokio::sync::broadcast;
+use tracing::trace;
+
+/// The maximum amount of lines that will be kept in memory per process.
+const MAX_LINES: usize = 1000;
+
+/// The output of all the processes that am has started, keyed by the name of
+/// the process (ie. `prometheus`).
+pub(crate) static PROCESS_LOGS: Lazy<ProcessLogs> = Lazy::new(ProcessLogs::default);
+
+#[derive(Default)]
+pub(crate) struct ProcessLogs {
+    processes: Mutex<HashMap<String, Arc<LogBuffer>>>,
+}
+
+impl ProcessLogs {
+    /// Retrieve the log buffer for `process`, creating it if it doesn't exist.
+    pub(crate) fn get_or_create(&self, process: &str) -> Arc<LogBuffer> {
+        self.processes
+            .lock()
+            .unwrap()
+            .entry(process.to_string())
+            .or_insert_with(|| Arc::new(LogBuffer::new()))
+            .clone()
+    }
+
+    pub(crate) fn get(&self, process: &str) -> Option<Arc<LogBuffer>> {
+        self.processes.lock().unwrap().get(process).cloned()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LogLine {
+    pub stream: LogStream,
+    pub line: String,
+}
+
+/// A ring buffer containing the last [`MAX_LINES`] lines of output of a
+/// process. New lines are also broadcast to any subscribers.
+pub(crate) struct LogBuffer {
+    lines: Mutex<VecDeque<LogLine>>,
+    tx: broadcast::Sender<LogLine>,
+}
+
+impl LogBuffer {
+    fn new() -> Self {
+        let (tx, _) = broadcast::channel(MAX_LINES);
+        Self {
+            lines: Mutex::new(VecDeque::with_capacity(MAX_LINES)),
+            tx,
+        }
+    }
+
+    fn push(&self, line: LogLine) {
+        {
+            let mut lines = self.lines.lock().unwrap();
+            if lines.len() == MAX_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line.clone());
+        }
+
+        // An error only means that there are no subscribers at the moment.
+        let _ = self.tx.send(line);
+    }
+
+    /// All the lines that are currently in the buffer.
+    pub(crate) fn lines(&self) -> Vec<LogLine> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Subscribe to all lines that will be written after this call.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<LogLine> {
+        self.tx.subscribe()
+    }
+}
+
+/// Wait for `child` to exit, while capturing its stdout and stderr into the
+/// log buffer of `process`. The child should have been spawned with piped
+/// stdout and stderr.
+pub(crate) async fn wait_with_logs(mut child: Child, process: &str) -> Result<ExitStatus> {
+    let buffer = PROCESS_LOGS.get_or_create(process);
+
+    let stdout = child
+        .stdout
+        .take()
+        .map(|stdout| tokio::spawn(capture(stdout, LogStream::Stdout, buffer.clone())));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|stderr| tokio::spawn(capture(stderr, LogStream::Stderr, buffer.clone())));
+
+    let status = shutdown::wait(&mut child, process).await?;
+
+    // Make sure that all output has been read before returning.
+    for task in [stdout, stderr].into_iter().flatten() {
+        let _ = task.await;
+    }
+
+    Ok(status)
+}
+
+/// Returns the captured output of `process` as a single string.
+pub(crate) fn dump(process: &str) -> String {
+    PROCESS_LOGS
+        .get(process)
+        .map(|buffer| {
+            buffer
+                .lines()
+                .into_iter()
+                .map(|line| line.line)
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default()
+}
+
+async fn capture(reader: impl AsyncRead + Unpin, stream: LogStream, buffer: Arc<LogBuffer>) {
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        trace!(?stream, %line, "Captured process output");
+        buffer.push(LogLine { stream, line });
+    }
+}
+
+#[test]
+fn test_log_buffer_is_bounded() {
+    let buffer = LogBuffer::new();
+
+    for i in 0..MAX_LINES + 10 {
+        buffer.push(LogLine {
+            stream: LogStream::Stdout,
+            line: i.to_string(),
+        });
+    }
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), MAX_LINES);
+    assert_eq!(lines.first().unwrap().line, "10");
+}
@@ -2,23 +2,41 @@ use crate::commands::update;
 use anyhow::{bail, Context, Result};
 use autometrics_am::config::AmConfig;
 use clap::Parser;
-use commands::{handle_command, Application};
+use commands::{handle_command, Application, LogFormat, SubCommands};
+use directories::ProjectDirs;
+use error::AmError;
+use indicatif::ProgressDrawTarget;
 use interactive::IndicatifWriter;
+use network::NetworkSettings;
 use std::path::PathBuf;
 use std::time::Duration;
 use tokio::time::timeout;
 use tracing::level_filters::LevelFilter;
-use tracing::{debug, error, warn};
-use tracing_subscriber::fmt::format;
+use tracing::{debug, error, warn, Event, Subscriber};
+use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::FmtContext;
 use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{EnvFilter, Layer, Registry};
 
 mod commands;
+mod daemon;
 mod dir;
 mod downloader;
+mod error;
+mod installs;
+mod instances;
 mod interactive;
+mod logs;
+mod metrics;
+mod network;
 mod server;
+mod shutdown;
+mod status;
+mod targets;
+mod unix_bridge;
+mod versions;
 
 #[tokio::main]
 async fn main() {
@@ -31,18 +49,53 @@ async fn main() {
         std::process::exit(1);
     }
 
-    let task = if std::env::var_os("AM_NO_UPDATE").is_none() {
-        tokio::task::spawn(update::update_check())
-    } else {
-        tokio::task::spawn(async { /* intentionally left empty */ })
-    };
+    // Progress bars would end up between the JSON objects.
+    if app.log_format == LogFormat::Json {
+        multi_progress.set_draw_target(ProgressDrawTarget::hidden());
+    }
 
     let config = match load_config(app.config_file.clone()).await {
         Ok(config) => config,
+        // `am validate` reports the problems with the config file itself.
+        Err(_) if matches!(app.command, SubCommands::Validate(_)) => AmConfig::default(),
         Err(err) => {
             error!("Unable to load config: {:?}", err);
+            std::process::exit(AmError::InvalidConfig.exit_code());
+        }
+    };
+
+    if let Some(mirror) = app
+        .download_mirror
+        .clone()
+        .or_else(|| config.download_mirror.clone())
+    {
+        let _ = downloader::DOWNLOAD_MIRROR.set(mirror);
+    }
+
+    let _ = downloader::REQUIRE_SIGNATURE
+        .set(app.require_signature || config.require_signature.unwrap_or(false));
+
+    // This needs to happen before the first request is made.
+    match NetworkSettings::new(config.network.as_ref(), app.ca_bundle.as_deref()) {
+        Ok(settings) => network::configure(settings),
+        Err(err) => {
+            error!("Invalid network settings: {:?}", err);
             std::process::exit(1);
         }
+    }
+
+    let offline = matches!(&app.command, SubCommands::Start(args) if args.offline);
+
+    // `am update` does its own check.
+    let check_update = std::env::var_os("AM_NO_UPDATE").is_none()
+        && !offline
+        && !matches!(app.command, SubCommands::Update(_))
+        && config.update_check != Some(false);
+
+    let task = if check_update {
+        tokio::task::spawn(update::update_check())
+    } else {
+        tokio::task::spawn(async { /* intentionally left empty */ })
     };
 
     let result = handle_command(app, config, multi_progress).await;
@@ -55,7 +108,7 @@ async fn main() {
         Ok(_) => debug!("Command completed successfully"),
         Err(err) => {
             error!("Command failed: {:?}", err);
-            std::process::exit(1);
+            std::process::exit(error::exit_code(&err));
         }
     }
 }
@@ -69,37 +122,51 @@ async fn main() {
 /// For example: for local development it is convenient to set the environment
 /// variable to `RUST_LOG=am=trace,info`. This will display all log messages
 /// within the `am` module, but will only show info for other modules.
+///
+/// The `--log-format` determines how the messages are written, `--verbose`
+/// only changes the format if it is `pretty`.
 fn init_logging(app: &Application, writer: IndicatifWriter) -> Result<()> {
-    let (filter_layer, log_layer) = if app.verbose {
-        let filter_layer = EnvFilter::try_from_default_env()
-            .unwrap_or_else(|_| EnvFilter::try_new("am=debug,info").unwrap());
-
-        let log_layer = tracing_subscriber::fmt::layer().with_writer(writer).boxed();
-
-        (filter_layer, log_layer)
+    let filter_layer = if app.verbose {
+        EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::try_new("am=debug,info").unwrap())
     } else {
-        let filter_layer = EnvFilter::default().add_directive(LevelFilter::INFO.into());
+        EnvFilter::default().add_directive(LevelFilter::INFO.into())
+    };
 
-        // Create a custom field formatter, which only outputs the `message`
-        // field, all other fields are ignored.
-        let field_formatter = format::debug_fn(|writer, field, value| {
-            if field.name() == "message" {
-                write!(writer, "{value:?}")
-            } else {
-                Ok(())
-            }
-        });
-
-        let log_layer = tracing_subscriber::fmt::layer()
-            .fmt_fields(field_formatter)
-            .without_time()
-            .with_level(false)
-            .with_span_events(format::FmtSpan::NONE)
-            .with_target(false)
+    let log_layer = match app.log_format {
+        // The component is recorded on the span that the message was logged
+        // in, which is included as the `span` field.
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
             .with_writer(writer)
-            .boxed();
+            .boxed(),
+        LogFormat::Compact => tracing_subscriber::fmt::layer()
+            .compact()
+            .with_writer(writer)
+            .boxed(),
+        LogFormat::Pretty if app.verbose => {
+            tracing_subscriber::fmt::layer().with_writer(writer).boxed()
+        }
+        LogFormat::Pretty => {
+            // Create a custom field formatter, which only outputs the `message`
+            // field, all other fields are ignored.
+            let field_formatter = format::debug_fn(|writer, field, value| {
+                if field.name() == "message" {
+                    write!(writer, "{value:?}")
+                } else {
+                    Ok(())
+                }
+            });
 
-        (filter_layer, log_layer)
+            tracing_subscriber::fmt::layer()
+                .fmt_fields(field_formatter)
+                .event_format(MessageOnly)
+                .with_writer(writer)
+                .boxed()
+        }
     };
 
     Registry::default()
@@ -111,10 +178,60 @@ fn init_logging(app: &Application, writer: IndicatifWriter) -> Result<()> {
     Ok(())
 }
 
+/// Formats an event as just its fields, without the time, level, target or
+/// the spans it was logged in.
+struct MessageOnly;
+
+impl<S, N> FormatEvent<S, N> for MessageOnly
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
 /// Try to load the config from the specified path. If the file doesn't exist it
 /// will return a AmConfig with all its defaults set. If it is invalid toml file
 /// it will return an error.
+/// Load the config of the project, layered on top of the config of the user in
+/// `~/.config/am/config.toml` (or the equivalent on other platforms). The
+/// command line arguments and environment variables are applied on top of this
+/// by each command.
 async fn load_config(config_file: Option<PathBuf>) -> Result<AmConfig> {
+    let project_config = load_project_config(config_file).await?;
+
+    let Some(path) = user_config_path() else {
+        return Ok(project_config);
+    };
+
+    match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => {
+            debug!(?path, "Found user config file, parsing");
+            let user_config: AmConfig = toml::from_str(&contents)
+                .with_context(|| format!("{} contains invalid toml contents", path.display()))?;
+            Ok(project_config.merge(user_config))
+        }
+        Err(err) => {
+            debug!(?err, ?path, "No user config file found");
+            Ok(project_config)
+        }
+    }
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "autometrics", "am")
+        .map(|project_dirs| project_dirs.config_dir().join("config.toml"))
+}
+
+async fn load_project_config(config_file: Option<PathBuf>) -> Result<AmConfig> {
     let (path, is_default) = match config_file {
         Some(path) => (path, false),
         None => (PathBuf::from("./am.toml"), true),
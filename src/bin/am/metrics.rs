@@ -0,0 +1,197 @@
+use crate::status::STATUS;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The metrics of am itself, which are exposed on its web server and scraped
+/// by the managed Prometheus like any other endpoint.
+pub(crate) static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+/// The buckets of the histograms that am renders, in seconds. These are the
+/// default buckets of the Prometheus client libraries.
+pub(crate) const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Default)]
+pub(crate) struct Histogram {
+    pub(crate) buckets: [u64; BUCKETS.len()],
+    pub(crate) count: u64,
+    pub(crate) sum: f64,
+}
+
+impl Histogram {
+    pub(crate) fn observe(&mut self, value: f64) {
+        for (bucket, le) in self.buckets.iter_mut().zip(BUCKETS) {
+            if value <= le {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    /// Downloads by file and whether they succeeded.
+    downloads: Mutex<BTreeMap<(String, bool), u64>>,
+    /// Requests to the web server by route, method and status code.
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    /// The duration of the requests to the web server by route.
+    request_durations: Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl Metrics {
+    /// Record a download of `url`, after all its attempts.
+    pub(crate) fn record_download(&self, url: &str, success: bool) {
+        let file = url.rsplit('/').next().unwrap_or(url).to_string();
+        *self
+            .downloads
+            .lock()
+            .unwrap()
+            .entry((file, success))
+            .or_default() += 1;
+    }
+
+    /// Record a request to `route` of the web server, which is the path it
+    /// was matched with (like `/prometheus/*path`) to keep the number of
+    /// series down.
+    pub(crate) fn record_request(
+        &self,
+        route: &str,
+        method: &str,
+        status: u16,
+        duration: Duration,
+    ) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((route.to_string(), method.to_string(), status))
+            .or_default() += 1;
+        self.request_durations
+            .lock()
+            .unwrap()
+            .entry(route.to_string())
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    /// All metrics in the Prometheus text format, including the state of the
+    /// managed processes from [`STATUS`].
+    pub(crate) fn render(&self) -> String {
+        let mut output = String::new();
+
+        let _ = writeln!(
+            output,
+            "# HELP am_build_info The version of am.\n# TYPE am_build_info gauge\nam_build_info{{version=\"{}\"}} 1",
+            env!("CARGO_PKG_VERSION")
+        );
+
+        let processes = STATUS.processes();
+        let _ = writeln!(
+            output,
+            "# HELP am_process_restarts_total The number of times a managed process was restarted.\n# TYPE am_process_restarts_total counter"
+        );
+        for (process, _, restarts) in &processes {
+            let _ = writeln!(
+                output,
+                "am_process_restarts_total{{process=\"{process}\"}} {restarts}"
+            );
+        }
+        let _ = writeln!(
+            output,
+            "# HELP am_process_running Whether a managed process is running.\n# TYPE am_process_running gauge"
+        );
+        for (process, running, _) in &processes {
+            let _ = writeln!(
+                output,
+                "am_process_running{{process=\"{process}\"}} {}",
+                u8::from(*running)
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "# HELP am_downloads_total The number of downloads of the managed programs.\n# TYPE am_downloads_total counter"
+        );
+        for ((file, success), count) in self.downloads.lock().unwrap().iter() {
+            let result = if *success { "success" } else { "error" };
+            let _ = writeln!(
+                output,
+                "am_downloads_total{{file=\"{}\",result=\"{result}\"}} {count}",
+                escape(file)
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "# HELP am_http_requests_total The number of requests to the web server of am.\n# TYPE am_http_requests_total counter"
+        );
+        for ((route, method, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                output,
+                "am_http_requests_total{{route=\"{}\",method=\"{method}\",status=\"{status}\"}} {count}",
+                escape(route)
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "# HELP am_http_request_duration_seconds How long the web server of am took to respond.\n# TYPE am_http_request_duration_seconds histogram"
+        );
+        for (route, histogram) in self.request_durations.lock().unwrap().iter() {
+            let route = escape(route);
+            for (bucket, le) in histogram.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    output,
+                    "am_http_request_duration_seconds_bucket{{route=\"{route}\",le=\"{le}\"}} {bucket}"
+                );
+            }
+            let _ = writeln!(
+                output,
+                "am_http_request_duration_seconds_bucket{{route=\"{route}\",le=\"+Inf\"}} {}\n\
+                 am_http_request_duration_seconds_sum{{route=\"{route}\"}} {}\n\
+                 am_http_request_duration_seconds_count{{route=\"{route}\"}} {}",
+                histogram.count, histogram.sum, histogram.count
+            );
+        }
+
+        output
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[test]
+fn test_render_metrics() {
+    let metrics = Metrics::default();
+    metrics.record_download(
+        "https://github.com/prometheus/prometheus/releases/download/v2.45.0/prometheus-2.45.0.linux-amd64.tar.gz",
+        true,
+    );
+    metrics.record_request("/prometheus/*path", "GET", 200, Duration::from_millis(30));
+    metrics.record_request("/prometheus/*path", "GET", 200, Duration::from_millis(300));
+
+    let output = metrics.render();
+    assert!(output.contains(
+        "am_downloads_total{file=\"prometheus-2.45.0.linux-amd64.tar.gz\",result=\"success\"} 1\n"
+    ));
+    assert!(output.contains(
+        "am_http_requests_total{route=\"/prometheus/*path\",method=\"GET\",status=\"200\"} 2\n"
+    ));
+    assert!(output.contains(
+        "am_http_request_duration_seconds_bucket{route=\"/prometheus/*path\",le=\"0.05\"} 1\n"
+    ));
+    assert!(
+        output.contains("am_http_request_duration_seconds_count{route=\"/prometheus/*path\"} 2\n")
+    );
+}
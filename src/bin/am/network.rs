@@ -0,0 +1,200 @@
+use anyhow::{bail, Context, Result};
+use autometrics_am::config::NetworkConfig;
+use once_cell::sync::OnceCell;
+use reqwest::{Certificate, ClientBuilder, Proxy};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use url::{Host, Url};
+
+/// The settings of every reqwest client that am creates. These are set once
+/// the config file is loaded, which needs to happen before any requests are
+/// made.
+static SETTINGS: OnceCell<NetworkSettings> = OnceCell::new();
+
+#[derive(Debug, Default)]
+pub(crate) struct NetworkSettings {
+    http_proxy: Option<Url>,
+    https_proxy: Option<Url>,
+    no_proxy: Vec<String>,
+    ca_certs: Vec<Certificate>,
+}
+
+impl NetworkSettings {
+    /// The settings of the config file, falling back to the usual environment
+    /// variables for the proxy. A `ca_bundle` passed on the command line takes
+    /// precedence over the one in the config file.
+    pub(crate) fn new(config: Option<&NetworkConfig>, ca_bundle: Option<&Path>) -> Result<Self> {
+        let config = config.cloned().unwrap_or_default();
+
+        let (http_proxy, https_proxy) = match config.proxy {
+            Some(proxy) => (Some(proxy.clone()), Some(proxy)),
+            None => (
+                proxy_from_env(&["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"])?,
+                proxy_from_env(&["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"])?,
+            ),
+        };
+
+        let no_proxy = match config.no_proxy {
+            Some(no_proxy) => no_proxy,
+            None => env_var(&["NO_PROXY", "no_proxy"])
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(|host| host.trim().to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+
+        let ca_certs = match ca_bundle.or(config.ca_bundle.as_deref()) {
+            Some(path) => read_ca_bundle(path)?,
+            None => vec![],
+        };
+
+        Ok(Self {
+            http_proxy,
+            https_proxy,
+            no_proxy,
+            ca_certs,
+        })
+    }
+
+    /// The proxy to use for a request to `url`, if any. Requests to this
+    /// machine never go through the proxy, since am talks to Prometheus and
+    /// the instrumented applications on localhost.
+    fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let proxy = match url.scheme() {
+            "https" => self.https_proxy.as_ref(),
+            _ => self.http_proxy.as_ref(),
+        }?;
+
+        let is_loopback = match url.host()? {
+            Host::Domain(domain) => domain.eq_ignore_ascii_case("localhost"),
+            Host::Ipv4(ip) => ip.is_loopback(),
+            Host::Ipv6(ip) => ip.is_loopback(),
+        };
+        if is_loopback {
+            return None;
+        }
+
+        let host = url.host_str()?.trim_matches(|c| c == '[' || c == ']');
+        let bypass = self.no_proxy.iter().any(|entry| {
+            let entry = entry.trim_start_matches('.');
+            entry == "*"
+                || host.eq_ignore_ascii_case(entry)
+                || host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", entry.to_ascii_lowercase()))
+        });
+
+        (!bypass).then(|| proxy.clone())
+    }
+}
+
+/// Use `settings` for all clients that are created from now on.
+pub(crate) fn configure(settings: NetworkSettings) {
+    let _ = SETTINGS.set(settings);
+}
+
+/// A client builder with the user agent, timeouts, proxy and CA certificates
+/// that all requests of am should use.
+pub(crate) fn client_builder() -> ClientBuilder {
+    let settings = SETTINGS.get_or_init(NetworkSettings::default);
+
+    // The proxy is selected per request, reqwest only looks at the
+    // environment variables and doesn't know about the config file.
+    let proxy = Proxy::custom(|url| SETTINGS.get().and_then(|settings| settings.proxy_for(url)));
+
+    let mut builder = reqwest::Client::builder()
+        .user_agent(concat!("am/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(Duration::from_secs(5))
+        .no_proxy()
+        .proxy(proxy);
+
+    for cert in &settings.ca_certs {
+        builder = builder.add_root_certificate(cert.clone());
+    }
+
+    builder
+}
+
+fn env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+fn proxy_from_env(names: &[&str]) -> Result<Option<Url>> {
+    let Some(value) = env_var(names) else {
+        return Ok(None);
+    };
+
+    // Proxies are commonly configured without a scheme, ie. `proxy:3128`.
+    let value = value.trim();
+    let url = if value.contains("://") {
+        Url::parse(value)
+    } else {
+        Url::parse(&format!("http://{value}"))
+    };
+
+    url.map(Some)
+        .with_context(|| format!("Invalid proxy {value} in {}", names[0]))
+}
+
+/// Read all certificates of the PEM file at `path`.
+fn read_ca_bundle(path: &Path) -> Result<Vec<Certificate>> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Unable to read CA certificate {}", path.display()))?;
+
+    let certs = contents
+        .split(BEGIN)
+        .skip(1)
+        .map(|cert| Certificate::from_pem(format!("{BEGIN}{cert}").as_bytes()))
+        .collect::<reqwest::Result<Vec<_>>>()
+        .with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+
+    if certs.is_empty() {
+        bail!("{} does not contain any certificates", path.display());
+    }
+
+    Ok(certs)
+}
+
+#[test]
+fn test_proxy_for() {
+    let settings = NetworkSettings {
+        http_proxy: Some(Url::parse("http://proxy:3128").unwrap()),
+        https_proxy: Some(Url::parse("http://secure-proxy:3128").unwrap()),
+        no_proxy: vec!["corp.internal".to_string(), ".example.org".to_string()],
+        ca_certs: vec![],
+    };
+    let proxy_for = |url: &str| {
+        settings
+            .proxy_for(&Url::parse(url).unwrap())
+            .map(|proxy| proxy.to_string())
+    };
+
+    assert_eq!(
+        proxy_for("https://github.com/prometheus"),
+        Some("http://secure-proxy:3128/".to_string())
+    );
+    assert_eq!(
+        proxy_for("http://api.example.com/metrics"),
+        Some("http://proxy:3128/".to_string())
+    );
+    assert_eq!(proxy_for("http://localhost:9090/api/v1/query"), None);
+    assert_eq!(proxy_for("http://127.0.0.1:3000/metrics"), None);
+    assert_eq!(proxy_for("http://[::1]:3000/metrics"), None);
+    assert_eq!(proxy_for("https://mirror.corp.internal/releases"), None);
+    assert_eq!(proxy_for("https://CORP.internal/releases"), None);
+    assert_eq!(proxy_for("https://example.org/"), None);
+    assert_eq!(
+        proxy_for("https://notcorp.internal/"),
+        Some("http://secure-proxy:3128/".to_string())
+    );
+}
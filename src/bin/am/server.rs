@@ -1,29 +1,82 @@
+use self::auth::AccessControl;
+use self::prometheus::Upstream;
+use self::tls::TlsConfig;
+use crate::commands::start::graphite::{self, GRAPHITE};
+use crate::commands::start::statsd::{self, STATSD};
+use crate::error::AmError;
+use crate::status::STATUS;
 use anyhow::{Context, Result};
 use axum::body::Body;
+use axum::extract::Query;
+use axum::middleware;
 use axum::response::Redirect;
-use axum::routing::{any, get};
-use axum::{Router, Server};
-use std::net::SocketAddr;
+use axum::routing::{any, delete, get, post};
+use axum::{Json, Router, Server};
+use std::io;
+use std::net::{SocketAddr, TcpListener};
 use std::sync::Arc;
 use tokio::sync::watch::Sender;
 use tracing::{debug, info};
-use url::Url;
 
-mod explorer;
-mod functions;
-mod prometheus;
+pub(crate) mod access_log;
+mod alertmanager;
+pub(crate) mod auth;
+pub(crate) mod explorer;
+pub(crate) mod functions;
+mod gateway;
+mod influx;
+mod logs;
+mod loki;
+pub(crate) mod metrics;
+pub(crate) mod otlp;
+pub(crate) mod prometheus;
+pub(crate) mod promql_compat;
 mod pushgateway;
+pub(crate) mod query_cache;
+mod status;
+mod targets;
+mod tempo;
+pub(crate) mod tls;
 mod util;
 
+/// Bind the listeners of the web server. The OS picks the port for addresses
+/// with port `0`, which can be read back with [`TcpListener::local_addr`].
+pub(crate) fn bind_listeners(listen_addresses: &[SocketAddr]) -> Result<Vec<TcpListener>> {
+    listen_addresses
+        .iter()
+        .map(|listen_address| {
+            let listener = TcpListener::bind(listen_address)
+                .map_err(|err| match err.kind() {
+                    io::ErrorKind::AddrInUse => {
+                        anyhow::Error::new(err).context(AmError::PortInUse {
+                            port: listen_address.port(),
+                        })
+                    }
+                    _ => anyhow::Error::new(err),
+                })
+                .with_context(|| format!("failed to bind to {}", listen_address))?;
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn start_web_server(
-    listen_address: &SocketAddr,
-    enable_prometheus: bool,
-    enable_pushgateway: bool,
-    prometheus_proxy_url: Option<Url>,
+    listeners: Vec<TcpListener>,
+    prometheus_port: Option<u16>,
+    pushgateway_port: Option<u16>,
+    gateway_port: Option<u16>,
+    tempo_port: Option<u16>,
+    loki_port: Option<u16>,
+    alertmanager_port: Option<u16>,
+    prometheus_upstream: Option<Upstream>,
+    access_control: AccessControl,
+    tls: Option<TlsConfig>,
     tx: Sender<Option<SocketAddr>>,
 ) -> Result<()> {
-    let is_proxying_prometheus = prometheus_proxy_url.is_some();
-    let should_enable_prometheus = enable_prometheus && !is_proxying_prometheus;
+    let is_proxying_prometheus = prometheus_upstream.is_some();
+    let prometheus_port = prometheus_port.filter(|_| !is_proxying_prometheus);
     let mut app = Router::new()
         // Any calls to the root should be redirected to the explorer which is most likely what the user wants to use.
         .route("/", get(|| async { Redirect::temporary("/explorer/") }))
@@ -40,22 +93,53 @@ pub(crate) async fn start_web_server(
         )
         .route("/explorer/", get(explorer::handler))
         .route("/explorer/*path", get(explorer::handler))
-        .route("/api/functions", get(functions::all_functions));
+        .route("/api/functions", get(functions::all_functions))
+        .route("/api/logs/:process", get(logs::handler))
+        .route("/api/logs/:process/tail", get(logs::tail_handler))
+        .route("/api/health", get(status::health_handler))
+        .route(metrics::METRICS_PATH, get(metrics::handler))
+        .route("/api/status", get(status::handler))
+        .route(
+            "/api/prometheus-config",
+            get(status::prometheus_config_handler),
+        )
+        .route(statsd::METRICS_PATH, get(|| async { STATSD.render() }))
+        .route(graphite::METRICS_PATH, get(|| async { GRAPHITE.render() }));
 
     // Proxy `/prometheus` to the upstream (local) prometheus instance
-    if should_enable_prometheus {
+    if let Some(port) = prometheus_port {
+        let handler = move |req| prometheus::handler(req, port);
         app = app
-            .route("/prometheus/*path", any(prometheus::handler))
-            .route("/prometheus", any(prometheus::handler));
+            .route("/prometheus/*path", any(handler))
+            .route("/prometheus", any(handler))
+            .route(
+                "/api/targets",
+                get(targets::list_handler).post(targets::register_handler),
+            )
+            .route(
+                "/api/targets/:job_name",
+                delete(targets::unregister_handler),
+            )
+            .route(
+                "/api/otlp/v1/metrics",
+                post(move |headers, body| otlp::metrics_handler(headers, body, port)),
+            )
+            .route(
+                "/api/influx/write",
+                post(move |Query(query), headers, body| {
+                    influx::write_handler(query, headers, body, port)
+                }),
+            );
     }
 
     // NOTE - this will override local prometheus routes if specified
     if is_proxying_prometheus {
-        let prometheus_upstream_base = Arc::new(prometheus_proxy_url.clone().unwrap());
+        let upstream = Arc::new(prometheus_upstream.clone().unwrap());
+        let promql_compat = upstream.promql_compat.clone();
 
         // Define a handler that will proxy to an external Prometheus instance
         let handler = move |mut req: http::Request<Body>| {
-            let upstream_base = prometheus_upstream_base.clone();
+            let upstream = upstream.clone();
             // 1. Get the path and query from the request, since we need to strip out `/prometheus`
             let path_and_query = req
                 .uri()
@@ -78,46 +162,156 @@ pub(crate) async fn start_web_server(
                 // 4. Replace the request's URI with the modified URI.
                 *req.uri_mut() = new_uri;
             }
-            async move { prometheus::handler_with_url(req, &upstream_base).await }
+            async move { prometheus::handler_with_upstream(req, &upstream).await }
         };
 
         app = app
             .route("/prometheus/*path", any(handler.clone()))
-            .route("/prometheus", any(handler));
+            .route("/prometheus", any(handler))
+            .route(
+                "/api/proxy/capabilities",
+                get(move || async move { Json(promql_compat.capabilities()) }),
+            );
+    }
+
+    if let Some(port) = pushgateway_port {
+        let handler = move |req| pushgateway::handler(req, port);
+        app = app
+            .route(
+                "/metrics",
+                any(move |req| pushgateway::metrics_proxy_handler(req, port)),
+            )
+            .route("/pushgateway/*path", any(handler))
+            .route("/pushgateway", any(handler));
+    } else {
+        app = app.route("/metrics", get(metrics::handler));
+    }
+
+    if let Some(port) = gateway_port {
+        let handler = move |req| gateway::handler(req, port);
+        app = app
+            .route("/gateway/*path", any(handler))
+            .route("/gateway", any(handler));
+    }
+
+    if let Some(port) = tempo_port {
+        let handler = move |req| tempo::handler(req, port);
+        app = app
+            .route("/tempo/*path", any(handler))
+            .route("/tempo", any(handler));
+    }
+
+    if let Some(port) = loki_port {
+        let handler = move |req| loki::handler(req, port);
+        app = app
+            .route("/loki/*path", any(handler))
+            .route("/loki", any(handler));
     }
 
-    if enable_pushgateway {
+    if let Some(port) = alertmanager_port {
+        let handler = move |req| alertmanager::handler(req, port);
         app = app
-            .route("/metrics", any(pushgateway::metrics_proxy_handler))
-            .route("/pushgateway/*path", any(pushgateway::handler))
-            .route("/pushgateway", any(pushgateway::handler));
+            .route("/alertmanager/*path", any(handler))
+            .route("/alertmanager", any(handler));
     }
 
-    let server = Server::try_bind(listen_address)
-        .with_context(|| format!("failed to bind to {}", listen_address))?
-        .serve(app.into_make_service());
+    // This only sees the requests that matched a route, so the metrics are
+    // labeled with the route instead of every path that was requested.
+    app = app.route_layer(middleware::from_fn(access_log::middleware));
 
-    tx.send_replace(Some(server.local_addr()));
+    let cors_layer = access_control.cors_layer()?;
 
-    debug!("Web server listening on {}", server.local_addr());
+    if access_control.is_enabled() {
+        app = app.layer(middleware::from_fn_with_state(
+            Arc::new(access_control),
+            auth::middleware,
+        ));
+    }
+
+    // This wraps the authentication, as the preflight requests of browsers
+    // never include credentials.
+    if let Some(cors_layer) = cors_layer {
+        app = app.layer(cors_layer);
+    }
+
+    // The certificate is loaded before serving, so a missing or invalid one
+    // is reported before any connection gets accepted.
+    let rustls_config = match &tls {
+        Some(tls) => Some(tls.rustls_config().await?),
+        None => None,
+    };
 
-    info!("Explorer endpoint: http://{}", server.local_addr());
+    // The OS picks the port for addresses with port `0`, so only the bound
+    // addresses tell where am can be reached.
+    let local_addrs = listeners
+        .iter()
+        .map(TcpListener::local_addr)
+        .collect::<std::io::Result<Vec<_>>>()?;
 
-    if should_enable_prometheus {
-        info!("Prometheus endpoint: http://127.0.0.1:9090/prometheus");
+    // The first address is the one that the managed processes use to reach
+    // the web server.
+    tx.send_replace(local_addrs.first().copied());
+    STATUS.set_listen_addresses(local_addrs.clone());
+
+    let scheme = if rustls_config.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    for local_addr in &local_addrs {
+        debug!("Web server listening on {}", local_addr);
+        info!("Explorer endpoint: {scheme}://{}", local_addr);
+    }
+
+    if let Some(port) = prometheus_port {
+        info!("Prometheus endpoint: http://127.0.0.1:{port}/prometheus");
     }
 
     if is_proxying_prometheus {
-        info!("Proxying to prometheus: {}", prometheus_proxy_url.unwrap());
+        info!(
+            "Proxying to prometheus: {}",
+            prometheus_upstream.unwrap().url
+        );
+    }
+
+    if let Some(port) = pushgateway_port {
+        info!("Pushgateway endpoint: http://127.0.0.1:{port}/pushgateway");
+    }
+
+    if let Some(port) = gateway_port {
+        info!("Autometrics gateway endpoint: http://127.0.0.1:{port}/gateway");
+    }
+
+    if let Some(port) = tempo_port {
+        info!("Tempo endpoint: http://127.0.0.1:{port}/tempo");
+    }
+
+    if let Some(port) = loki_port {
+        info!("Loki endpoint: http://127.0.0.1:{port}/loki");
     }
 
-    if enable_pushgateway {
-        info!("Pushgateway endpoint: http://127.0.0.1:9091/pushgateway");
+    if let Some(port) = alertmanager_port {
+        info!("Alertmanager endpoint: http://127.0.0.1:{port}/alertmanager");
     }
 
     // TODO: Add support for graceful shutdown
     // server.with_graceful_shutdown(shutdown_signal()).await?;
-    server.await?;
+    let servers = listeners.into_iter().map(|listener| {
+        let make_service = app.clone().into_make_service();
+        let rustls_config = rustls_config.clone();
+        async move {
+            match rustls_config {
+                Some(rustls_config) => {
+                    axum_server::from_tcp_rustls(listener, rustls_config)
+                        .serve(make_service)
+                        .await?
+                }
+                None => Server::from_tcp(listener)?.serve(make_service).await?,
+            }
+            anyhow::Ok(())
+        }
+    });
+    futures_util::future::try_join_all(servers).await?;
 
     Ok(())
 }
@@ -0,0 +1,71 @@
+use crate::metrics::METRICS;
+use anyhow::{Context, Result};
+use axum::extract::MatchedPath;
+use axum::middleware::Next;
+use axum::response::Response;
+use http::Request;
+use once_cell::sync::OnceCell;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+use tracing::{debug, warn};
+
+/// The file to which a line is appended for every request to the web server,
+/// if enabled with [`open`].
+static ACCESS_LOG: OnceCell<Mutex<LineWriter<File>>> = OnceCell::new();
+
+/// Log every request to the web server to `path`, which is appended to if it
+/// already exists.
+pub(crate) fn open(path: &Path) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Unable to open access log {}", path.display()))?;
+
+    let _ = ACCESS_LOG.set(Mutex::new(LineWriter::new(file)));
+    Ok(())
+}
+
+/// Record the method, path, status and latency of every request that matched
+/// a route, including the ones that are proxied to Prometheus and the other
+/// programs. These end up in the metrics of am, the debug logs and the access
+/// log if there is one.
+pub(crate) async fn middleware<B>(req: Request<B>, next: Next<B>) -> Response {
+    // The route (like `/prometheus/*path`) keeps the number of series in the
+    // metrics down, the logs get the actual path.
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unknown", MatchedPath::as_str)
+        .to_string();
+    let method = req.method().clone();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or_else(|| req.uri().path().to_string(), ToString::to_string);
+
+    let started_at = Instant::now();
+    let response = next.run(req).await;
+    let latency = started_at.elapsed();
+    let status = response.status().as_u16();
+
+    METRICS.record_request(&route, method.as_str(), status, latency);
+    debug!(%method, path, status, ?latency, "Handled request");
+
+    if let Some(access_log) = ACCESS_LOG.get() {
+        let result = writeln!(
+            access_log.lock().unwrap(),
+            "{} {method} {path} {status} {:.3}ms",
+            humantime::format_rfc3339_millis(SystemTime::now()),
+            latency.as_secs_f64() * 1000.0
+        );
+        if let Err(err) = result {
+            warn!(?err, "Unable to write to the access log");
+        }
+    }
+
+    response
+}
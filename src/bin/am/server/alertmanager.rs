@@ -0,0 +1,9 @@
+use crate::server::util::proxy_handler;
+use axum::body::Body;
+use axum::response::IntoResponse;
+use url::Url;
+
+pub(crate) async fn handler(req: http::Request<Body>, port: u16) -> impl IntoResponse {
+    let upstream_base = Url::parse(&format!("http://localhost:{port}")).unwrap();
+    proxy_handler(req, upstream_base).await
+}
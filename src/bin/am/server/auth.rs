@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use autometrics_am::config::{BasicAuth, WebAuth};
+use axum::extract::State;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use http::{HeaderMap, HeaderValue, Request, StatusCode};
+use std::sync::Arc;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// The credentials that incoming requests need to present. Either one of them
+/// is accepted if both are set.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AccessControl {
+    pub bearer_token: Option<String>,
+    pub basic_auth: Option<BasicAuth>,
+    /// The origins from which browsers may call the web server, as parsed by
+    /// `cors_origin_parser`. `*` allows any origin.
+    pub cors_allow_origins: Vec<String>,
+}
+
+impl AccessControl {
+    /// The access control that requires the `--web-auth` credentials, if any.
+    pub(crate) fn with_web_auth(web_auth: Option<&WebAuth>) -> Self {
+        match web_auth {
+            Some(WebAuth::BearerToken(token)) => AccessControl {
+                bearer_token: Some(token.clone()),
+                ..AccessControl::default()
+            },
+            Some(WebAuth::Basic(basic_auth)) => AccessControl {
+                basic_auth: Some(basic_auth.clone()),
+                ..AccessControl::default()
+            },
+            None => AccessControl::default(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.bearer_token.is_some() || self.basic_auth.is_some()
+    }
+
+    /// The CORS layer for the allowed origins, if there are any. Browsers
+    /// block cross-origin requests without it.
+    pub(crate) fn cors_layer(&self) -> Result<Option<CorsLayer>> {
+        if self.cors_allow_origins.is_empty() {
+            return Ok(None);
+        }
+
+        let allow_origin = if self.cors_allow_origins.iter().any(|origin| origin == "*") {
+            AllowOrigin::any()
+        } else {
+            let origins = self
+                .cors_allow_origins
+                .iter()
+                .map(|origin| HeaderValue::from_str(origin))
+                .collect::<Result<Vec<_>, _>>()
+                .context("Invalid CORS origin")?;
+            AllowOrigin::list(origins)
+        };
+
+        Ok(Some(
+            CorsLayer::new()
+                .allow_origin(allow_origin)
+                .allow_methods(Any)
+                .allow_headers(Any),
+        ))
+    }
+
+    fn allows(&self, headers: &HeaderMap) -> bool {
+        let Some((scheme, credentials)) = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+        else {
+            return false;
+        };
+        let credentials = credentials.trim().as_bytes();
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            if let Some(token) = &self.bearer_token {
+                return constant_time_eq(token.as_bytes(), credentials);
+            }
+        }
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            if let Some(basic_auth) = &self.basic_auth {
+                let expected =
+                    STANDARD.encode(format!("{}:{}", basic_auth.username, basic_auth.password));
+                return constant_time_eq(expected.as_bytes(), credentials);
+            }
+        }
+
+        false
+    }
+}
+
+/// Rejects requests that don't present the credentials of the access control
+/// with a `401 Unauthorized`. The health check is always allowed, so it can
+/// be used by load balancers.
+pub(crate) async fn middleware<B>(
+    State(access_control): State<Arc<AccessControl>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if req.uri().path() == "/api/health" {
+        return next.run(req).await;
+    }
+
+    if !access_control.allows(req.headers()) {
+        let challenge = if access_control.basic_auth.is_some() {
+            "Basic realm=\"am\""
+        } else {
+            "Bearer"
+        };
+        return (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, challenge)]).into_response();
+    }
+
+    // The credentials are meant for am, they should not be forwarded to any
+    // of the upstreams.
+    req.headers_mut().remove(AUTHORIZATION);
+    next.run(req).await
+}
+
+/// Build the `Authorization` header that am sends to an upstream.
+pub(crate) fn authorization_header(
+    bearer_token: Option<&str>,
+    basic_auth: Option<&BasicAuth>,
+) -> Result<Option<HeaderValue>> {
+    let value = match (bearer_token, basic_auth) {
+        (Some(token), _) => format!("Bearer {token}"),
+        (None, Some(basic_auth)) => format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", basic_auth.username, basic_auth.password))
+        ),
+        (None, None) => return Ok(None),
+    };
+
+    let mut value = HeaderValue::try_from(value).context("Invalid upstream credentials")?;
+    value.set_sensitive(true);
+    Ok(Some(value))
+}
+
+/// Compare the credentials without returning early, so the time the
+/// comparison takes doesn't tell how much of them was correct.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[test]
+fn test_access_control() {
+    let access_control = AccessControl {
+        bearer_token: Some("secret".to_string()),
+        basic_auth: Some(BasicAuth {
+            username: "admin".to_string(),
+            password: "hunter2".to_string(),
+        }),
+        ..AccessControl::default()
+    };
+    let headers = |authorization: &str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    };
+
+    assert!(access_control.allows(&headers("Bearer secret")));
+    assert!(access_control.allows(&headers("bearer secret")));
+    assert!(access_control.allows(&headers("Basic YWRtaW46aHVudGVyMg==")));
+    assert!(!access_control.allows(&headers("Bearer secre")));
+    assert!(!access_control.allows(&headers("Basic YWRtaW46aHVudGVyMw==")));
+    assert!(!access_control.allows(&headers("secret")));
+    assert!(!access_control.allows(&HeaderMap::new()));
+
+    let bearer_only = AccessControl {
+        bearer_token: Some("secret".to_string()),
+        basic_auth: None,
+        ..AccessControl::default()
+    };
+    assert!(!bearer_only.allows(&headers("Basic YWRtaW46aHVudGVyMg==")));
+}
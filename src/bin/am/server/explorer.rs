@@ -1,25 +1,196 @@
+use anyhow::{bail, Result};
 use axum::body;
 use axum::extract::Path;
 use axum::response::{IntoResponse, Response};
+use http::header::CONTENT_TYPE;
 use http::StatusCode;
 use include_dir::{include_dir, Dir};
+use once_cell::sync::OnceCell;
+use std::path::PathBuf;
 use tracing::{error, trace, warn};
+use url::Url;
 
 static STATIC_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/files/explorer");
 
+/// The CDN from which the explorer loads its scripts, styles and images.
+pub(crate) const CDN_URL: &str = "https://explorer.autometrics.dev/";
+
+/// The directory in the data directory of am to which
+/// `am system download-explorer` downloads the assets from the CDN.
+pub(crate) const OFFLINE_DIR: &str = "explorer";
+
+/// Where the explorer is served from, if not from the pages that are part of
+/// am with the assets on the CDN.
+static SOURCE: OnceCell<Source> = OnceCell::new();
+
+#[derive(Debug)]
+enum Source {
+    /// The assets that were downloaded to this directory.
+    Offline(PathBuf),
+
+    /// The assets on another URL, like a specific version of the explorer or
+    /// a development server.
+    Url(Url),
+
+    /// A locally built explorer, including its pages.
+    Dir(PathBuf),
+}
+
+/// Serve the assets that were downloaded to `dir` instead of loading them
+/// from the CDN.
+pub(crate) fn serve_offline(dir: PathBuf) -> Result<()> {
+    if !dir.join("static").is_dir() {
+        bail!(
+            "The explorer assets have not been downloaded to {}, run `am system download-explorer` first",
+            dir.display()
+        );
+    }
+
+    let _ = SOURCE.set(Source::Offline(dir));
+    Ok(())
+}
+
+/// Serve the explorer from `input`, which is either the URL on which its
+/// assets are hosted or a directory with a build of the explorer.
+pub(crate) fn serve_from(input: &str) -> Result<()> {
+    let source = match Url::parse(input) {
+        Ok(mut url) if matches!(url.scheme(), "http" | "https") => {
+            // The asset paths are appended to the URL.
+            if !url.path().ends_with('/') {
+                url.set_path(&format!("{}/", url.path()));
+            }
+            Source::Url(url)
+        }
+        _ => {
+            let dir = PathBuf::from(input);
+            if !dir.join("index.html").is_file() {
+                bail!(
+                    "The explorer URL should be a http(s) URL or a directory with an index.html, but {input} is neither"
+                );
+            }
+            Source::Dir(dir)
+        }
+    };
+
+    let _ = SOURCE.set(source);
+    Ok(())
+}
+
+/// The pages of the explorer that are part of am itself, with their contents.
+pub(crate) fn pages() -> impl Iterator<Item = (&'static str, &'static [u8])> {
+    STATIC_DIR.files().filter_map(|file| {
+        let path = file.path().to_str()?;
+        Some((path, file.contents()))
+    })
+}
+
+/// The paths of the assets on the CDN that `contents` refers to, either with
+/// the full URL or relative to the CDN, like the chunks that the scripts load.
+pub(crate) fn asset_references(contents: &str) -> Vec<String> {
+    let mut references = vec![];
+
+    for (index, _) in contents.match_indices(CDN_URL) {
+        let path = asset_path(&contents[index + CDN_URL.len()..]);
+        if !path.is_empty() {
+            references.push(path.to_string());
+        }
+    }
+
+    for (index, _) in contents.match_indices("static/") {
+        // Skip the references that are part of a full URL, which are
+        // handled above.
+        if contents[..index].ends_with(CDN_URL) {
+            continue;
+        }
+
+        let path = asset_path(&contents[index..]);
+        if path.contains('.') {
+            references.push(path.to_string());
+        }
+    }
+
+    references.sort();
+    references.dedup();
+    references
+}
+
+/// The path at the start of `input`, up to the quote, parenthesis or
+/// whitespace that ends it.
+fn asset_path(input: &str) -> &str {
+    let end = input
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "-_./".contains(c)))
+        .unwrap_or(input.len());
+    input[..end].trim_end_matches('.')
+}
+
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json" | "map") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Point the references to the CDN at `base_url` instead.
+fn rewrite_cdn_url(path: &str, contents: Vec<u8>, base_url: &str) -> Vec<u8> {
+    if !content_type(path).starts_with("text/") {
+        return contents;
+    }
+
+    match String::from_utf8(contents) {
+        Ok(contents) => contents.replace(CDN_URL, base_url).into_bytes(),
+        Err(err) => err.into_bytes(),
+    }
+}
+
+async fn read_asset(dir: &std::path::Path, path: &str) -> Option<Vec<u8>> {
+    // Only paths within the directory are served.
+    if path
+        .split('/')
+        .any(|segment| segment == ".." || segment.is_empty())
+    {
+        return None;
+    }
+
+    tokio::fs::read(dir.join(path)).await.ok()
+}
+
 pub(crate) async fn handler(optional_path: Option<Path<String>>) -> impl IntoResponse {
     let path = optional_path.map_or_else(|| "index.html".to_string(), |path| path.0);
 
     trace!(?path, "Serving static file");
 
-    match STATIC_DIR.get_file(&path) {
+    let page = STATIC_DIR
+        .get_file(&path)
+        .map(|file| file.contents().to_vec());
+    let contents = match SOURCE.get() {
+        None => page,
+        Some(Source::Offline(dir)) => match page {
+            Some(page) => Some(page),
+            None => read_asset(dir, &path).await,
+        }
+        .map(|contents| rewrite_cdn_url(&path, contents, "/explorer/")),
+        Some(Source::Url(url)) => page.map(|page| rewrite_cdn_url(&path, page, url.as_str())),
+        Some(Source::Dir(dir)) => read_asset(dir, &path).await,
+    };
+
+    match contents {
         None => {
             warn!(?path, "Request file was not found in the explorer assets");
             StatusCode::NOT_FOUND.into_response()
         }
-        Some(file) => Response::builder()
+        Some(contents) => Response::builder()
             .status(StatusCode::OK)
-            .body(body::boxed(body::Full::from(file.contents())))
+            .header(CONTENT_TYPE, content_type(&path))
+            .body(body::boxed(body::Full::from(contents)))
             .map(|res| res.into_response())
             .unwrap_or_else(|err| {
                 error!("Failed to build response: {}", err);
@@ -27,3 +198,19 @@ pub(crate) async fn handler(optional_path: Option<Path<String>>) -> impl IntoRes
             }),
     }
 }
+
+#[test]
+fn test_asset_references() {
+    let html = r#"<link href="https://explorer.autometrics.dev/favicon.raw.19b993d4.svg" />
+        <script src="https://explorer.autometrics.dev/static/js/index.24432f8e.js"></script>"#;
+    assert_eq!(
+        asset_references(html),
+        vec!["favicon.raw.19b993d4.svg", "static/js/index.24432f8e.js"]
+    );
+
+    let script = r#"n.p+"static/js/async/123.8a1b2c3d.js";url(static/font/inter.woff2)"#;
+    assert_eq!(
+        asset_references(script),
+        vec!["static/font/inter.woff2", "static/js/async/123.8a1b2c3d.js"]
+    );
+}
@@ -1,61 +1,85 @@
+use am_list::{FunctionInfo, Language};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use http::StatusCode;
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
-pub(crate) async fn all_functions() -> Result<impl IntoResponse, AllFunctionError> {
-    let functions = am_list::list_all_project_functions(
-        std::env::current_dir()
-            .map_err(|_| AllFunctionError::DirNotFound)?
-            .as_path(),
-    )
-    .map_err(|err| AllFunctionError::AmListError(format!("{err:?}")))?;
+/// The directory in which the functions are listed, set with `am start
+/// --project-root`. The working directory is used when it isn't set.
+pub(crate) static PROJECT_ROOT: OnceCell<PathBuf> = OnceCell::new();
 
-    let mut output = vec![];
+/// A function of the codebase, as shown in the function picker of the explorer.
+#[derive(Debug, Serialize)]
+pub(crate) struct Function {
+    #[serde(flatten)]
+    info: FunctionInfo,
+    name: String,
+    module: String,
+    /// The file in which the function is defined, or instrumented if the
+    /// definition is unknown.
+    file: Option<PathBuf>,
+    instrumented: bool,
+    language: Language,
+    /// The root of the project that the function is part of.
+    path: PathBuf,
+}
 
-    for (path, (language, language_functions)) in functions {
-        for func in language_functions {
-            let mut value =
-                serde_json::to_value(&func).map_err(|_| AllFunctionError::SerdeError)?;
+pub(crate) async fn all_functions() -> Result<impl IntoResponse, AllFunctionError> {
+    let root = match PROJECT_ROOT.get() {
+        Some(root) => root.clone(),
+        None => std::env::current_dir().map_err(|_| AllFunctionError::DirNotFound)?,
+    };
 
-            // this is in a separate block so the mutable reference gets dropped before we try to move the value in the last line
-            {
-                let obj = value.as_object_mut().ok_or(AllFunctionError::NonObject)?;
+    // Parsing a large codebase takes a while, so it shouldn't block the other
+    // requests.
+    let functions = tokio::task::spawn_blocking(move || list_functions(&root))
+        .await
+        .map_err(|err| AllFunctionError::AmListError(err.to_string()))??;
 
-                obj.insert(
-                    "language".to_string(),
-                    serde_json::to_value(language).map_err(|_| AllFunctionError::SerdeError)?,
-                );
+    Ok(Json(functions))
+}
 
-                obj.insert(
-                    "path".to_string(),
-                    serde_json::to_value(path.to_string_lossy())
-                        .map_err(|_| AllFunctionError::SerdeError)?,
-                );
-            }
+fn list_functions(root: &Path) -> Result<Vec<Function>, AllFunctionError> {
+    let projects = am_list::list_all_project_functions(root)
+        .map_err(|err| AllFunctionError::AmListError(format!("{err:?}")))?;
 
-            output.push(value);
-        }
-    }
+    let functions = projects
+        .into_iter()
+        .flat_map(|(path, (language, functions))| {
+            functions.into_iter().map(move |info| {
+                let file = info
+                    .definition
+                    .as_ref()
+                    .or(info.instrumentation.as_ref())
+                    .map(|location| path.join(&location.file));
+
+                Function {
+                    name: info.id.function.clone(),
+                    module: info.id.module.clone(),
+                    file,
+                    instrumented: info.instrumentation.is_some(),
+                    language,
+                    path: path.clone(),
+                    info,
+                }
+            })
+        })
+        .collect();
 
-    Ok(Json(output))
+    Ok(functions)
 }
 
 #[derive(Deserialize, Serialize, Debug, Error)]
 #[serde(tag = "error", content = "details", rename_all = "snake_case")]
 pub(crate) enum AllFunctionError {
-    #[error("`FunctionInfo` needs to serialize to a `Value::Object`")]
-    NonObject,
-
     #[error("unable to determinate current working directory")]
     DirNotFound,
 
     #[error("{0}")]
     AmListError(String),
-
-    #[error("serde error")]
-    SerdeError,
 }
 
 impl IntoResponse for AllFunctionError {
@@ -63,3 +87,41 @@ impl IntoResponse for AllFunctionError {
         (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
     }
 }
+
+#[test]
+fn test_list_functions() {
+    // The default name of a temporary directory starts with a dot, which
+    // would make it a hidden directory that isn't searched.
+    let dir = tempfile::Builder::new().prefix("api").tempdir().unwrap();
+    std::fs::write(dir.path().join("go.mod"), "module example.com/api\n").unwrap();
+    std::fs::write(
+        dir.path().join("main.go"),
+        "package main\n\n//autometrics:inst\nfunc handler() {}\n\nfunc helper() {}\n",
+    )
+    .unwrap();
+
+    let mut functions = list_functions(dir.path()).unwrap();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let root = dir.path().canonicalize().unwrap();
+    let summary: Vec<_> = functions
+        .iter()
+        .map(|function| {
+            (
+                function.name.as_str(),
+                function.module.as_str(),
+                function.file.clone(),
+                function.instrumented,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("handler", "main", Some(root.join("main.go")), true),
+            ("helper", "main", Some(root.join("main.go")), false),
+        ]
+    );
+    assert_eq!(functions[0].language, Language::Go);
+    assert_eq!(functions[0].path, root);
+}
@@ -0,0 +1,11 @@
+use crate::server::util::proxy_handler;
+use axum::body::Body;
+use axum::response::IntoResponse;
+use url::Url;
+
+/// Proxy to the autometrics gateway, which serves everything under `/gateway`
+/// itself.
+pub(crate) async fn handler(req: http::Request<Body>, port: u16) -> impl IntoResponse {
+    let upstream_base = Url::parse(&format!("http://localhost:{port}")).unwrap();
+    proxy_handler(req, upstream_base).await
+}
@@ -0,0 +1,264 @@
+use super::otlp::proto::{Label, Sample, TimeSeries};
+use super::otlp::{decode_body, remote_write, sanitize_name};
+use anyhow::{bail, Context, Result};
+use axum::body::Bytes;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::{HeaderMap, StatusCode};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// The `job` label of the samples, unless they have a `job` tag themselves.
+const DEFAULT_JOB: &str = "influx";
+
+/// Accepts InfluxDB line protocol, as sent to the `/write` endpoint of
+/// InfluxDB 1.x, and writes it into the managed Prometheus through its
+/// remote-write receiver.
+pub(crate) async fn write_handler(
+    query: HashMap<String, String>,
+    headers: HeaderMap,
+    body: Bytes,
+    port: u16,
+) -> Response {
+    let body = match decode_body(&headers, &body) {
+        Ok(body) => body,
+        Err(err) => return err.into_response(),
+    };
+
+    let precision = query.get("precision").map_or("ns", String::as_str);
+    let timeseries = match std::str::from_utf8(&body)
+        .context("the body is not valid UTF-8")
+        .and_then(|body| parse_lines(body, precision))
+    {
+        Ok(timeseries) => timeseries,
+        Err(err) => {
+            // InfluxDB clients show the `error` field of the response.
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{err:#}") })),
+            )
+                .into_response();
+        }
+    };
+
+    debug!(
+        series = timeseries.len(),
+        "Translated InfluxDB line protocol"
+    );
+
+    if !timeseries.is_empty() {
+        if let Err(response) = remote_write(port, timeseries).await {
+            return response;
+        }
+    }
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Parse the lines of line protocol into a series per numeric field.
+/// `precision` is the unit of the timestamps, which is nanoseconds unless the
+/// client specifies otherwise.
+fn parse_lines(input: &str, precision: &str) -> Result<Vec<TimeSeries>> {
+    let nanos_per_unit: i64 = match precision {
+        "n" | "ns" => 1,
+        "u" | "us" => 1_000,
+        "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        "m" => 60 * 1_000_000_000,
+        "h" => 60 * 60 * 1_000_000_000,
+        precision => bail!("unsupported precision {precision}"),
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+
+    let mut timeseries = vec![];
+    for (index, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let points =
+            parse_line(line).with_context(|| format!("invalid line {}: {line}", index + 1))?;
+        for (labels, value, timestamp) in points {
+            let timestamp = timestamp.map_or(now, |timestamp| {
+                (timestamp as i128 * nanos_per_unit as i128 / 1_000_000) as i64
+            });
+            timeseries.push(TimeSeries {
+                labels: labels
+                    .into_iter()
+                    .map(|(name, value)| Label { name, value })
+                    .collect(),
+                samples: vec![Sample { value, timestamp }],
+            });
+        }
+    }
+
+    Ok(timeseries)
+}
+
+type Point = (BTreeMap<String, String>, f64, Option<i64>);
+
+/// Parse a single line: `<measurement>[,<tag>=<value>...] <field>=<value>[,...]
+/// [timestamp]`. Every numeric or boolean field becomes a metric named
+/// `<measurement>_<field>`, or just `<measurement>` for a field called
+/// `value`. String fields have no Prometheus equivalent and are skipped.
+fn parse_line(line: &str) -> Result<Vec<Point>> {
+    let series = split_unescaped(line, ' ', false)[0];
+    let rest: Vec<&str> = split_unescaped(line[series.len()..].trim_start(), ' ', true)
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    let (fields, timestamp) = match rest.as_slice() {
+        [fields] => (*fields, None),
+        [fields, timestamp] => (
+            *fields,
+            Some(timestamp.parse().context("invalid timestamp")?),
+        ),
+        [] => bail!("missing fields"),
+        _ => bail!("unexpected data after the timestamp"),
+    };
+
+    let mut tags = split_unescaped(series, ',', false).into_iter();
+    let measurement = unescape(tags.next().unwrap_or_default());
+    if measurement.is_empty() {
+        bail!("missing measurement");
+    }
+
+    let mut labels = BTreeMap::new();
+    for tag in tags {
+        let (key, value) = split_pair(tag).context("tags should be in the `key=value` format")?;
+        labels.insert(sanitize_name(&unescape(key), false), unescape(value));
+    }
+    labels
+        .entry("job".to_string())
+        .or_insert_with(|| DEFAULT_JOB.to_string());
+
+    let mut points = vec![];
+    for field in split_unescaped(fields, ',', true) {
+        let (key, value) =
+            split_pair(field).context("fields should be in the `key=value` format")?;
+        let Some(value) = parse_field_value(value)? else {
+            continue;
+        };
+
+        let key = unescape(key);
+        let name = if key == "value" {
+            measurement.clone()
+        } else {
+            format!("{measurement}_{key}")
+        };
+
+        let mut labels = labels.clone();
+        labels.insert("__name__".to_string(), sanitize_name(&name, true));
+        points.push((labels, value, timestamp));
+    }
+
+    Ok(points)
+}
+
+/// Parse a field value, which is a float, an integer with an `i` or `u`
+/// suffix, a boolean or a quoted string. Strings result in `None`.
+fn parse_field_value(value: &str) -> Result<Option<f64>> {
+    if value.starts_with('"') {
+        return Ok(None);
+    }
+
+    let parsed = match value {
+        "t" | "T" | "true" | "True" | "TRUE" => 1.0,
+        "f" | "F" | "false" | "False" | "FALSE" => 0.0,
+        value => value
+            .strip_suffix(['i', 'u'])
+            .unwrap_or(value)
+            .parse()
+            .with_context(|| format!("invalid field value {value}"))?,
+    };
+
+    Ok(Some(parsed))
+}
+
+/// Split `input` on every `separator` that isn't escaped with a backslash or,
+/// with `quotes`, inside a quoted string field. The parts keep their escapes.
+fn split_unescaped(input: &str, separator: char, quotes: bool) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut chars = input.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' if quotes => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(&input[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&input[start..]);
+    parts
+}
+
+fn split_pair(input: &str) -> Option<(&str, &str)> {
+    let key = split_unescaped(input, '=', false)[0];
+    let value = input.get(key.len() + 1..)?;
+    (!key.is_empty()).then_some((key, value))
+}
+
+fn unescape(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => output.extend(chars.next()),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+#[test]
+fn test_parse_lines() {
+    let input = "cpu,host=web\\ 1,region=eu usage_user=0.25,usage_system=1i,up=true 1700000000\n\
+                 \n\
+                 temperature,room=kitchen value=21.5,note=\"warm, humid\"\n";
+
+    let timeseries = parse_lines(input, "s").unwrap();
+    assert_eq!(timeseries.len(), 4);
+
+    let labels = |series: &TimeSeries| -> Vec<(String, String)> {
+        series
+            .labels
+            .iter()
+            .map(|label| (label.name.clone(), label.value.clone()))
+            .collect()
+    };
+    assert_eq!(
+        labels(&timeseries[0]),
+        vec![
+            ("__name__".to_string(), "cpu_usage_user".to_string()),
+            ("host".to_string(), "web 1".to_string()),
+            ("job".to_string(), "influx".to_string()),
+            ("region".to_string(), "eu".to_string()),
+        ]
+    );
+    assert_eq!(timeseries[0].samples[0].value, 0.25);
+    assert_eq!(timeseries[0].samples[0].timestamp, 1700000000000);
+    assert_eq!(timeseries[1].samples[0].value, 1.0);
+    assert_eq!(timeseries[2].samples[0].value, 1.0);
+    assert_eq!(
+        labels(&timeseries[3])[0],
+        ("__name__".to_string(), "temperature".to_string())
+    );
+    assert_eq!(timeseries[3].samples[0].value, 21.5);
+
+    assert!(parse_lines("cpu", "ns").is_err());
+    assert!(parse_lines("cpu usage=high", "ns").is_err());
+    assert!(parse_lines("cpu usage=1 1700000000", "d").is_err());
+}
@@ -0,0 +1,53 @@
+use crate::logs::PROCESS_LOGS;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::Path;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+/// Returns the captured output of the process as JSON.
+pub(crate) async fn handler(Path(process): Path<String>) -> Response {
+    match PROCESS_LOGS.get(&process) {
+        Some(buffer) => Json(buffer.lines()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Upgrades the connection to a websocket, which will receive every new line
+/// of output of the process as a JSON encoded message.
+pub(crate) async fn tail_handler(Path(process): Path<String>, ws: WebSocketUpgrade) -> Response {
+    match PROCESS_LOGS.get(&process) {
+        Some(_) => ws.on_upgrade(move |socket| tail(socket, process)),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn tail(mut socket: WebSocket, process: String) {
+    let Some(buffer) = PROCESS_LOGS.get(&process) else {
+        return;
+    };
+
+    let mut rx = buffer.subscribe();
+
+    loop {
+        let line = match rx.recv().await {
+            Ok(line) => line,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(%process, skipped, "Websocket client is not keeping up with the logs");
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        let Ok(message) = serde_json::to_string(&line) else {
+            continue;
+        };
+
+        if socket.send(Message::Text(message)).await.is_err() {
+            debug!(%process, "Websocket client disconnected");
+            break;
+        }
+    }
+}
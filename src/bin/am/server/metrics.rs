@@ -0,0 +1,15 @@
+use crate::metrics::METRICS;
+use axum::response::{IntoResponse, Response};
+use http::header::CONTENT_TYPE;
+
+/// The path on the web server of am on which its own metrics are exposed.
+/// They are also served on `/metrics`, unless that is used by the Pushgateway.
+pub(crate) const METRICS_PATH: &str = "/api/metrics";
+
+pub(crate) async fn handler() -> Response {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(),
+    )
+        .into_response()
+}
@@ -0,0 +1,566 @@
+use self::proto::metric::Data;
+use self::proto::number_data_point::Value;
+use self::proto::*;
+use crate::commands::start::CLIENT;
+use axum::body::Bytes;
+use axum::response::{IntoResponse, Response};
+use flate2::read::GzDecoder;
+use http::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use http::{HeaderMap, StatusCode};
+use prost::Message;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use tracing::{debug, error, warn};
+
+pub(crate) mod proto;
+
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// Data points with this flag set don't have a value, which Prometheus
+/// represents as a staleness marker.
+const FLAG_NO_RECORDED_VALUE: u32 = 1;
+const STALE_NAN: u64 = 0x7ff0000000000002;
+
+/// Upper bound for a decompressed request body, so that a small gzip body
+/// can't expand into an arbitrary amount of memory.
+const MAX_DECODED_BODY: u64 = 32 * 1024 * 1024;
+
+/// Accepts OTLP/HTTP metrics in the protobuf encoding and writes them into
+/// the managed Prometheus through its remote-write receiver.
+pub(crate) async fn metrics_handler(headers: HeaderMap, body: Bytes, port: u16) -> Response {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with(PROTOBUF_CONTENT_TYPE) {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "only the application/x-protobuf encoding of OTLP is supported",
+        )
+            .into_response();
+    }
+
+    let body = match decode_body(&headers, &body) {
+        Ok(body) => body,
+        Err(err) => return err.into_response(),
+    };
+
+    let request = match ExportMetricsServiceRequest::decode(body.as_slice()) {
+        Ok(request) => request,
+        Err(err) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("invalid OTLP request: {err}"),
+            )
+                .into_response()
+        }
+    };
+
+    let translation = translate(request);
+    debug!(
+        series = translation.timeseries.len(),
+        rejected = translation.rejected,
+        "Translated OTLP metrics"
+    );
+
+    if !translation.timeseries.is_empty() {
+        if let Err(response) = remote_write(port, translation.timeseries).await {
+            return response;
+        }
+    }
+
+    let partial_success = (translation.rejected > 0).then(|| {
+        let error_message = translation
+            .errors
+            .into_iter()
+            .collect::<Vec<_>>()
+            .join("; ");
+        warn!(
+            rejected = translation.rejected,
+            "Rejected OTLP data points: {error_message}"
+        );
+
+        ExportMetricsPartialSuccess {
+            rejected_data_points: translation.rejected,
+            error_message,
+        }
+    });
+
+    (
+        [(CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)],
+        ExportMetricsServiceResponse { partial_success }.encode_to_vec(),
+    )
+        .into_response()
+}
+
+/// Decompress `body` according to its `Content-Encoding`, of which only gzip
+/// is supported.
+pub(super) fn decode_body(
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Vec<u8>, (StatusCode, String)> {
+    match headers.get(CONTENT_ENCODING).map(|value| value.as_bytes()) {
+        None | Some(b"identity") => Ok(body.to_vec()),
+        Some(b"gzip") => {
+            let mut decoded = vec![];
+            GzDecoder::new(body)
+                .take(MAX_DECODED_BODY + 1)
+                .read_to_end(&mut decoded)
+                .map_err(|err| (StatusCode::BAD_REQUEST, format!("invalid gzip body: {err}")))?;
+            if decoded.len() as u64 > MAX_DECODED_BODY {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("the decompressed body exceeds {MAX_DECODED_BODY} bytes"),
+                ));
+            }
+            Ok(decoded)
+        }
+        Some(_) => Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "only gzip is supported as content encoding".to_string(),
+        )),
+    }
+}
+
+pub(super) async fn remote_write(port: u16, timeseries: Vec<TimeSeries>) -> Result<(), Response> {
+    let body = snap::raw::Encoder::new()
+        .compress_vec(&WriteRequest { timeseries }.encode_to_vec())
+        .map_err(|err| {
+            error!(?err, "Unable to compress remote-write request");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+
+    let res = CLIENT
+        .post(format!("http://localhost:{port}/prometheus/api/v1/write"))
+        .header(CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)
+        .header(CONTENT_ENCODING, "snappy")
+        .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+        .body(body)
+        .send()
+        .await;
+
+    match res {
+        Ok(res) if res.status().is_success() => Ok(()),
+        Ok(res) => {
+            // OTLP exporters retry on 503 but drop the data on 400, which
+            // matches what Prometheus means with a client or server error.
+            let status = if res.status().is_client_error() {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            let message = res.text().await.unwrap_or_default();
+            debug!(%status, "Prometheus rejected remote-write request: {message}");
+
+            Err((
+                status,
+                format!("Prometheus rejected the samples: {}", message.trim()),
+            )
+                .into_response())
+        }
+        Err(err) => {
+            error!(?err, "Unable to send remote-write request to Prometheus");
+            Err(StatusCode::SERVICE_UNAVAILABLE.into_response())
+        }
+    }
+}
+
+#[derive(Default)]
+struct Translation {
+    timeseries: Vec<TimeSeries>,
+    rejected: i64,
+    errors: BTreeSet<String>,
+}
+
+/// Translate OTLP metrics to Prometheus series, following the OpenTelemetry
+/// compatibility spec: `service.name` becomes the `job` label, monotonic sums
+/// become counters with a `_total` suffix and histograms and summaries are
+/// split into their `_bucket`, `_sum` and `_count` series. Delta temporality
+/// and exponential histograms can't be represented and are rejected.
+fn translate(request: ExportMetricsServiceRequest) -> Translation {
+    let mut translation = Translation::default();
+
+    for resource_metrics in request.resource_metrics {
+        let resource_labels = resource_labels(resource_metrics.resource.as_ref());
+
+        for metric in resource_metrics
+            .scope_metrics
+            .into_iter()
+            .flat_map(|scope_metrics| scope_metrics.metrics)
+        {
+            translation.add_metric(metric, &resource_labels);
+        }
+    }
+
+    translation
+}
+
+impl Translation {
+    fn add_metric(&mut self, metric: Metric, resource_labels: &BTreeMap<String, String>) {
+        let name = sanitize_name(&metric.name, true);
+
+        match metric.data {
+            None => {}
+            Some(Data::Gauge(gauge)) => {
+                for point in gauge.data_points {
+                    self.add_number(&name, resource_labels, point);
+                }
+            }
+            Some(Data::Sum(sum)) => {
+                if sum.aggregation_temporality() == AggregationTemporality::Delta {
+                    self.reject(sum.data_points.len(), &metric.name, "delta temporality");
+                    return;
+                }
+
+                let name = if sum.is_monotonic && !name.ends_with("_total") {
+                    format!("{name}_total")
+                } else {
+                    name
+                };
+
+                for point in sum.data_points {
+                    self.add_number(&name, resource_labels, point);
+                }
+            }
+            Some(Data::Histogram(histogram)) => {
+                if histogram.aggregation_temporality() == AggregationTemporality::Delta {
+                    self.reject(
+                        histogram.data_points.len(),
+                        &metric.name,
+                        "delta temporality",
+                    );
+                    return;
+                }
+
+                for point in histogram.data_points {
+                    let labels = labels(resource_labels, &point.attributes);
+                    let timestamp = timestamp(point.time_unix_nano);
+                    let value = |value: f64| sample_value(value, point.flags);
+
+                    let mut cumulative = 0;
+                    for (index, count) in point.bucket_counts.iter().enumerate() {
+                        cumulative += count;
+                        let le = match point.explicit_bounds.get(index) {
+                            Some(bound) => bound.to_string(),
+                            None => "+Inf".to_string(),
+                        };
+
+                        let mut labels = labels.clone();
+                        labels.insert("le".to_string(), le);
+                        self.push(
+                            format!("{name}_bucket"),
+                            labels,
+                            value(cumulative as f64),
+                            timestamp,
+                        );
+                    }
+
+                    if let Some(sum) = point.sum {
+                        self.push(format!("{name}_sum"), labels.clone(), value(sum), timestamp);
+                    }
+                    self.push(
+                        format!("{name}_count"),
+                        labels,
+                        value(point.count as f64),
+                        timestamp,
+                    );
+                }
+            }
+            Some(Data::Summary(summary)) => {
+                for point in summary.data_points {
+                    let labels = labels(resource_labels, &point.attributes);
+                    let timestamp = timestamp(point.time_unix_nano);
+                    let value = |value: f64| sample_value(value, point.flags);
+
+                    for quantile in &point.quantile_values {
+                        let mut labels = labels.clone();
+                        labels.insert("quantile".to_string(), quantile.quantile.to_string());
+                        self.push(name.clone(), labels, value(quantile.value), timestamp);
+                    }
+
+                    self.push(
+                        format!("{name}_sum"),
+                        labels.clone(),
+                        value(point.sum),
+                        timestamp,
+                    );
+                    self.push(
+                        format!("{name}_count"),
+                        labels,
+                        value(point.count as f64),
+                        timestamp,
+                    );
+                }
+            }
+            Some(Data::ExponentialHistogram(histogram)) => {
+                self.reject(
+                    histogram.data_points.len(),
+                    &metric.name,
+                    "exponential histograms",
+                );
+            }
+        }
+    }
+
+    fn add_number(
+        &mut self,
+        name: &str,
+        resource_labels: &BTreeMap<String, String>,
+        point: NumberDataPoint,
+    ) {
+        let value = match point.value {
+            Some(Value::AsDouble(value)) => value,
+            Some(Value::AsInt(value)) => value as f64,
+            None => 0.0,
+        };
+
+        self.push(
+            name.to_string(),
+            labels(resource_labels, &point.attributes),
+            sample_value(value, point.flags),
+            timestamp(point.time_unix_nano),
+        );
+    }
+
+    fn push(
+        &mut self,
+        name: String,
+        mut labels: BTreeMap<String, String>,
+        value: f64,
+        timestamp: i64,
+    ) {
+        labels.insert("__name__".to_string(), name);
+
+        self.timeseries.push(TimeSeries {
+            labels: labels
+                .into_iter()
+                .map(|(name, value)| Label { name, value })
+                .collect(),
+            samples: vec![Sample { value, timestamp }],
+        });
+    }
+
+    fn reject(&mut self, data_points: usize, metric: &str, reason: &str) {
+        self.rejected += data_points as i64;
+        self.errors
+            .insert(format!("{metric}: {reason} is not supported"));
+    }
+}
+
+/// The `job` and `instance` labels, derived from the resource attributes.
+fn resource_labels(resource: Option<&Resource>) -> BTreeMap<String, String> {
+    let attribute = |key: &str| {
+        resource?
+            .attributes
+            .iter()
+            .find(|attribute| attribute.key == key)
+            .and_then(|attribute| attribute_value(attribute.value.as_ref()?))
+    };
+
+    let mut labels = BTreeMap::new();
+    if let Some(service_name) = attribute("service.name") {
+        let job = match attribute("service.namespace") {
+            Some(namespace) => format!("{namespace}/{service_name}"),
+            None => service_name,
+        };
+        labels.insert("job".to_string(), job);
+    }
+    if let Some(instance) = attribute("service.instance.id") {
+        labels.insert("instance".to_string(), instance);
+    }
+
+    labels
+}
+
+/// The labels of a data point. The `job` and `instance` labels of the resource
+/// take precedence over attributes with the same name.
+fn labels(
+    resource_labels: &BTreeMap<String, String>,
+    attributes: &[KeyValue],
+) -> BTreeMap<String, String> {
+    let mut labels: BTreeMap<String, String> = attributes
+        .iter()
+        .filter_map(|attribute| {
+            let value = attribute_value(attribute.value.as_ref()?)?;
+            Some((sanitize_name(&attribute.key, false), value))
+        })
+        .collect();
+
+    labels.extend(resource_labels.clone());
+    labels
+}
+
+fn attribute_value(value: &AnyValue) -> Option<String> {
+    match value.value.as_ref()? {
+        any_value::Value::String(value) => Some(value.clone()),
+        any_value::Value::Bool(value) => Some(value.to_string()),
+        any_value::Value::Int(value) => Some(value.to_string()),
+        any_value::Value::Double(value) => Some(value.to_string()),
+    }
+}
+
+/// Replace the characters that are not allowed in metric names (which also
+/// allow `:`) or label names with underscores.
+pub(super) fn sanitize_name(name: &str, is_metric: bool) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || (is_metric && c == ':') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    match sanitized.chars().next() {
+        Some(c) if c.is_ascii_digit() && is_metric => format!("_{sanitized}"),
+        Some(c) if c.is_ascii_digit() => format!("key_{sanitized}"),
+        _ => sanitized,
+    }
+}
+
+fn sample_value(value: f64, flags: u32) -> f64 {
+    if flags & FLAG_NO_RECORDED_VALUE != 0 {
+        f64::from_bits(STALE_NAN)
+    } else {
+        value
+    }
+}
+
+fn timestamp(time_unix_nano: u64) -> i64 {
+    (time_unix_nano / 1_000_000) as i64
+}
+
+#[test]
+fn test_translate() {
+    let attribute = |key: &str, value: &str| KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::String(value.to_string())),
+        }),
+    };
+    let metric = |name: &str, data: Data| Metric {
+        name: name.to_string(),
+        data: Some(data),
+    };
+
+    let request = ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Some(Resource {
+                attributes: vec![attribute("service.name", "api")],
+            }),
+            scope_metrics: vec![ScopeMetrics {
+                metrics: vec![
+                    metric(
+                        "function.calls",
+                        Data::Sum(Sum {
+                            data_points: vec![NumberDataPoint {
+                                attributes: vec![attribute("function", "handler")],
+                                time_unix_nano: 1_700_000_000_000_000_000,
+                                value: Some(Value::AsInt(3)),
+                                flags: 0,
+                            }],
+                            aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                            is_monotonic: true,
+                        }),
+                    ),
+                    metric(
+                        "function.calls.duration",
+                        Data::Histogram(Histogram {
+                            data_points: vec![HistogramDataPoint {
+                                attributes: vec![],
+                                time_unix_nano: 1_700_000_000_000_000_000,
+                                count: 3,
+                                sum: Some(0.4),
+                                bucket_counts: vec![1, 2],
+                                explicit_bounds: vec![0.1],
+                                flags: 0,
+                            }],
+                            aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                        }),
+                    ),
+                    metric(
+                        "requests",
+                        Data::Sum(Sum {
+                            data_points: vec![NumberDataPoint::default()],
+                            aggregation_temporality: AggregationTemporality::Delta as i32,
+                            is_monotonic: true,
+                        }),
+                    ),
+                ],
+            }],
+        }],
+    };
+
+    let translation = translate(request);
+
+    let series: Vec<(String, f64)> = translation
+        .timeseries
+        .iter()
+        .map(|series| {
+            let labels: Vec<String> = series
+                .labels
+                .iter()
+                .map(|label| format!("{}={}", label.name, label.value))
+                .collect();
+            (labels.join(","), series.samples[0].value)
+        })
+        .collect();
+
+    assert_eq!(
+        series,
+        vec![
+            (
+                "__name__=function_calls_total,function=handler,job=api".to_string(),
+                3.0
+            ),
+            (
+                "__name__=function_calls_duration_bucket,job=api,le=0.1".to_string(),
+                1.0
+            ),
+            (
+                "__name__=function_calls_duration_bucket,job=api,le=+Inf".to_string(),
+                3.0
+            ),
+            (
+                "__name__=function_calls_duration_sum,job=api".to_string(),
+                0.4
+            ),
+            (
+                "__name__=function_calls_duration_count,job=api".to_string(),
+                3.0
+            ),
+        ]
+    );
+    assert_eq!(
+        translation.timeseries[0].samples[0].timestamp,
+        1_700_000_000_000
+    );
+    assert_eq!(translation.rejected, 1);
+    assert_eq!(
+        translation.errors.into_iter().collect::<Vec<_>>(),
+        vec!["requests: delta temporality is not supported"]
+    );
+}
+
+#[test]
+fn test_decode_body_limit() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let gzip = |len: u64| {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&vec![0; len as usize]).unwrap();
+        encoder.finish().unwrap()
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_ENCODING, "gzip".parse().unwrap());
+
+    let decoded = decode_body(&headers, &gzip(MAX_DECODED_BODY)).unwrap();
+    assert_eq!(decoded.len() as u64, MAX_DECODED_BODY);
+
+    let (status, _) = decode_body(&headers, &gzip(MAX_DECODED_BODY + 1)).unwrap_err();
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
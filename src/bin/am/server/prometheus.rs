@@ -1,16 +1,179 @@
+use crate::commands::start::CLIENT;
+use crate::server::promql_compat::PromqlCompat;
+use crate::server::query_cache::QueryCache;
 use crate::server::util::proxy_handler;
+use anyhow::{Context, Result};
+use autometrics_am::config::UpstreamFlavor;
 use axum::body::Body;
-use axum::response::IntoResponse;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::header::AUTHORIZATION;
+use http::uri::PathAndQuery;
+use http::{HeaderMap, HeaderValue, Uri};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
 use url::Url;
 
-pub(crate) async fn handler(req: http::Request<Body>) -> impl IntoResponse {
-    let upstream_base = url::Url::parse("http://localhost:9090").unwrap();
+/// An external Prometheus that the `/prometheus` routes are proxied to.
+#[derive(Debug, Clone)]
+pub(crate) struct Upstream {
+    /// The URL under which the Prometheus API is served, like
+    /// `http://mimir:8080/prometheus/`.
+    pub url: Url,
+    /// The kind of backend, which determines the quirks that are handled.
+    pub flavor: UpstreamFlavor,
+    /// The `Authorization` header that is sent with every request.
+    pub authorization: Option<HeaderValue>,
+    /// Extra headers that are sent with every request, like the tenant of a
+    /// multi-tenant backend.
+    pub headers: HeaderMap,
+    /// Translates the queries that use functions which the upstream doesn't
+    /// support.
+    pub promql_compat: PromqlCompat,
+    /// Caches the responses to queries, if enabled.
+    pub query_cache: Option<Arc<QueryCache>>,
+}
+
+pub(crate) async fn handler(req: http::Request<Body>, port: u16) -> impl IntoResponse {
+    let upstream_base = Url::parse(&format!("http://localhost:{port}")).unwrap();
     proxy_handler(req, upstream_base).await
 }
 
-pub(crate) async fn handler_with_url(
-    req: http::Request<Body>,
-    upstream_base: &Url,
-) -> impl IntoResponse {
-    proxy_handler(req, upstream_base.clone()).await
+pub(crate) async fn handler_with_upstream(
+    mut req: http::Request<Body>,
+    upstream: &Upstream,
+) -> Response {
+    if let Some(authorization) = &upstream.authorization {
+        req.headers_mut()
+            .insert(AUTHORIZATION, authorization.clone());
+    }
+
+    for (name, value) in &upstream.headers {
+        req.headers_mut().insert(name, value.clone());
+    }
+
+    let req = match upstream.promql_compat.rewrite_request(req).await {
+        Ok(req) => req,
+        Err(status) => return status.into_response(),
+    };
+
+    if let Some(response) = unsupported_endpoint(upstream.flavor, req.uri().path()) {
+        return response;
+    }
+
+    match &upstream.query_cache {
+        Some(query_cache) if QueryCache::is_cacheable(&req) => {
+            query_cache.handle(req, |req| forward(req, upstream)).await
+        }
+        _ => forward(req, upstream).await,
+    }
+}
+
+/// Send the request to the upstream, below the path of its URL.
+async fn forward(mut req: http::Request<Body>, upstream: &Upstream) -> Response {
+    let prefix = upstream.url.path().trim_end_matches('/');
+    if !prefix.is_empty() {
+        let path_and_query = match req.uri().query() {
+            Some(query) => format!("{prefix}{}?{query}", req.uri().path()),
+            None => format!("{prefix}{}", req.uri().path()),
+        };
+
+        let mut uri_parts = req.uri().clone().into_parts();
+        uri_parts.path_and_query = PathAndQuery::try_from(path_and_query).ok();
+        if let Ok(uri) = Uri::from_parts(uri_parts) {
+            *req.uri_mut() = uri;
+        }
+    }
+
+    proxy_handler(req, upstream.url.clone())
+        .await
+        .into_response()
+}
+
+/// The response to the endpoints that the explorer uses, but which `flavor`
+/// doesn't implement. An empty result works better than an error.
+fn unsupported_endpoint(flavor: UpstreamFlavor, path: &str) -> Option<Response> {
+    match (flavor, path) {
+        (UpstreamFlavor::Mimir | UpstreamFlavor::VictoriaMetrics, "/api/v1/targets") => Some(
+            Json(json!({
+                "status": "success",
+                "data": { "activeTargets": [], "droppedTargets": [] }
+            }))
+            .into_response(),
+        ),
+        _ => None,
+    }
+}
+
+/// Point the URL of the upstream at the path under which its flavor serves the
+/// Prometheus API, unless the URL includes a path already. VictoriaMetrics
+/// cluster has the tenant in the path, instead of in a header.
+pub(crate) fn set_api_path(upstream: &mut Upstream) {
+    if upstream.url.path() != "/" {
+        return;
+    }
+
+    let tenant_id = upstream
+        .headers
+        .get("x-scope-orgid")
+        .and_then(|tenant_id| tenant_id.to_str().ok());
+    let path = match upstream.flavor {
+        UpstreamFlavor::Prometheus | UpstreamFlavor::Thanos => return,
+        UpstreamFlavor::Mimir => "/prometheus/".to_string(),
+        UpstreamFlavor::VictoriaMetrics => {
+            format!("/select/{}/prometheus/", tenant_id.unwrap_or("0"))
+        }
+    };
+    upstream.url.set_path(&path);
+}
+
+/// The kind of backend that `url` and its `build_info` point at. Thanos can't
+/// be told apart from Prometheus, but has the same API.
+pub(crate) fn detect_flavor(url: &Url, build_info: Option<&BuildInfo>) -> UpstreamFlavor {
+    if url.path().starts_with("/select/") {
+        return UpstreamFlavor::VictoriaMetrics;
+    }
+
+    match build_info.and_then(|build_info| build_info.application.as_deref()) {
+        Some(application) if application.contains("Mimir") => UpstreamFlavor::Mimir,
+        _ => UpstreamFlavor::Prometheus,
+    }
+}
+
+#[derive(Deserialize)]
+struct BuildInfoResponse {
+    data: BuildInfo,
+}
+
+/// What the upstream reports about itself.
+#[derive(Debug, Deserialize)]
+pub(crate) struct BuildInfo {
+    /// The version of Prometheus, or of the backend that is compatible with
+    /// it.
+    pub version: String,
+    /// The name of the backend, which Mimir reports.
+    #[serde(default)]
+    pub application: Option<String>,
+}
+
+/// Request the build info of the upstream. Backends other than Prometheus
+/// itself don't always implement this endpoint.
+pub(crate) async fn build_info(upstream: &Upstream) -> Result<BuildInfo> {
+    let url = upstream.url.join("api/v1/status/buildinfo")?;
+    let mut request = CLIENT.get(url.clone()).headers(upstream.headers.clone());
+    if let Some(authorization) = &upstream.authorization {
+        request = request.header(AUTHORIZATION, authorization.clone());
+    }
+
+    let response: BuildInfoResponse = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Unable to request the build info from {url}"))?
+        .json()
+        .await
+        .context("Unable to parse the build info of the upstream")?;
+
+    Ok(response.data)
 }
@@ -0,0 +1,431 @@
+use crate::server::util::collect;
+use anyhow::{bail, Result};
+use axum::body::Body;
+use http::header::{HeaderValue, CONTENT_LENGTH};
+use http::uri::PathAndQuery;
+use http::{Method, Request, StatusCode, Uri};
+use semver_rs::Version;
+use serde::Serialize;
+use tracing::debug;
+use url::form_urlencoded;
+
+/// The Prometheus API endpoints that evaluate a PromQL query.
+const QUERY_PATHS: [&str; 2] = ["/api/v1/query", "/api/v1/query_range"];
+
+/// A PromQL function that was added in a later Prometheus version, with the
+/// query that replaces it for the versions before that.
+struct Function {
+    name: &'static str,
+    /// The Prometheus version that added the function.
+    since: &'static str,
+    /// Whether the replacement gives the same results, or only an
+    /// approximation.
+    exact: bool,
+    /// The replacement for a call with these (already rewritten) arguments,
+    /// or `None` if the arguments are not what the function expects.
+    translate: fn(&[String]) -> Option<String>,
+}
+
+const FUNCTIONS: [Function; 6] = [
+    Function {
+        name: "clamp",
+        since: "2.26.0",
+        exact: true,
+        translate: translate_clamp,
+    },
+    Function {
+        name: "sgn",
+        since: "2.26.0",
+        exact: true,
+        translate: translate_sgn,
+    },
+    Function {
+        name: "last_over_time",
+        since: "2.26.0",
+        exact: false,
+        translate: translate_last_over_time,
+    },
+    Function {
+        name: "present_over_time",
+        since: "2.29.0",
+        exact: true,
+        translate: translate_present_over_time,
+    },
+    Function {
+        name: "sort_by_label",
+        since: "2.49.0",
+        exact: false,
+        translate: translate_sort_by_label,
+    },
+    Function {
+        name: "sort_by_label_desc",
+        since: "2.49.0",
+        exact: false,
+        translate: translate_sort_by_label,
+    },
+];
+
+fn translate_clamp(args: &[String]) -> Option<String> {
+    match args {
+        [vector, min, max] => Some(format!("clamp_max(clamp_min({vector}, {min}), {max})")),
+        _ => None,
+    }
+}
+
+fn translate_sgn(args: &[String]) -> Option<String> {
+    match args {
+        [vector] => Some(format!("((({vector}) > bool 0) - (({vector}) < bool 0))")),
+        _ => None,
+    }
+}
+
+/// The last value of a counter is also its highest one, as long as it didn't
+/// reset within the range.
+fn translate_last_over_time(args: &[String]) -> Option<String> {
+    match args {
+        [range] => Some(format!("max_over_time({range})")),
+        _ => None,
+    }
+}
+
+fn translate_present_over_time(args: &[String]) -> Option<String> {
+    match args {
+        [range] => Some(format!("(count_over_time({range}) ^ 0)")),
+        _ => None,
+    }
+}
+
+/// The series are returned in an arbitrary order instead.
+fn translate_sort_by_label(args: &[String]) -> Option<String> {
+    match args {
+        [vector, _, ..] => Some(vector.clone()),
+        _ => None,
+    }
+}
+
+/// Translates the PromQL queries that are sent to an upstream Prometheus that
+/// is too old for some of the functions that the explorer uses.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PromqlCompat {
+    /// The version of the upstream, if it is known. Without it all functions
+    /// are assumed to be supported.
+    version: Option<String>,
+    /// The names of the functions that the upstream doesn't support.
+    unsupported: Vec<&'static str>,
+}
+
+/// Which PromQL functions the upstream supports, as reported by the API of
+/// am.
+#[derive(Debug, Serialize)]
+pub(crate) struct Capabilities {
+    upstream_version: Option<String>,
+    functions: Vec<FunctionCapability>,
+}
+
+#[derive(Debug, Serialize)]
+struct FunctionCapability {
+    name: &'static str,
+    since: &'static str,
+    support: Support,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Support {
+    /// The upstream supports the function itself.
+    Native,
+    /// Queries with the function are rewritten to give the same results.
+    Rewritten,
+    /// Queries with the function are rewritten to an approximation.
+    Degraded,
+}
+
+impl PromqlCompat {
+    /// The translations for an upstream with this Prometheus version, like
+    /// `2.24.0`.
+    pub(crate) fn new(version: &str) -> Result<Self> {
+        let version = version.trim().trim_start_matches('v');
+        let parsed = match Version::new(version).parse() {
+            Ok(parsed) if !parsed.is_empty() && !parsed.is_any() => parsed,
+            _ => bail!("invalid Prometheus version: {version} (expected a version like 2.45.0)"),
+        };
+
+        let unsupported = FUNCTIONS
+            .iter()
+            .filter(|function| {
+                let since = Version::new(function.since).parse().expect("valid version");
+                parsed < since
+            })
+            .map(|function| function.name)
+            .collect();
+
+        Ok(Self {
+            version: Some(version.to_string()),
+            unsupported,
+        })
+    }
+
+    pub(crate) fn capabilities(&self) -> Capabilities {
+        let functions = FUNCTIONS
+            .iter()
+            .map(|function| FunctionCapability {
+                name: function.name,
+                since: function.since,
+                support: if !self.unsupported.contains(&function.name) {
+                    Support::Native
+                } else if function.exact {
+                    Support::Rewritten
+                } else {
+                    Support::Degraded
+                },
+            })
+            .collect();
+
+        Capabilities {
+            upstream_version: self.version.clone(),
+            functions,
+        }
+    }
+
+    /// Rewrite the calls to the functions that the upstream doesn't support.
+    pub(crate) fn rewrite_query(&self, query: &str) -> String {
+        let functions: Vec<_> = FUNCTIONS
+            .iter()
+            .filter(|function| self.unsupported.contains(&function.name))
+            .collect();
+
+        if functions.is_empty() {
+            return query.to_string();
+        }
+
+        let rewritten = rewrite(query, &functions);
+        if rewritten != query {
+            debug!(query, rewritten, "Rewrote query for the upstream");
+        }
+        rewritten
+    }
+
+    /// Rewrite the `query` parameter of a request to the query endpoints,
+    /// which is part of the URL or of the form in the body of a POST request.
+    ///
+    /// If the body can't be read, the status to respond with is returned.
+    pub(crate) async fn rewrite_request(
+        &self,
+        req: Request<Body>,
+    ) -> Result<Request<Body>, StatusCode> {
+        if self.unsupported.is_empty() || !QUERY_PATHS.contains(&req.uri().path()) {
+            return Ok(req);
+        }
+
+        let (mut parts, body) = req.into_parts();
+
+        if let Some(query) = parts.uri.query().and_then(|query| self.rewrite_form(query)) {
+            if let Ok(path_and_query) =
+                PathAndQuery::try_from(format!("{}?{query}", parts.uri.path()))
+            {
+                let mut uri_parts = parts.uri.clone().into_parts();
+                uri_parts.path_and_query = Some(path_and_query);
+                if let Ok(uri) = Uri::from_parts(uri_parts) {
+                    parts.uri = uri;
+                }
+            }
+        }
+
+        if parts.method != Method::POST {
+            return Ok(Request::from_parts(parts, body));
+        }
+
+        let body = match collect(body).await {
+            Ok(body) => body,
+            Err(err) => {
+                debug!(?err, "Unable to read the request body");
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        };
+
+        let form = std::str::from_utf8(&body)
+            .ok()
+            .and_then(|form| self.rewrite_form(form));
+        let body = match form {
+            Some(form) => {
+                parts
+                    .headers
+                    .insert(CONTENT_LENGTH, HeaderValue::from(form.len()));
+                Body::from(form)
+            }
+            None => Body::from(body),
+        };
+
+        Ok(Request::from_parts(parts, body))
+    }
+
+    /// The form with its `query` rewritten, or `None` if that didn't change
+    /// anything.
+    fn rewrite_form(&self, form: &str) -> Option<String> {
+        let mut changed = false;
+        let pairs: Vec<(String, String)> = form_urlencoded::parse(form.as_bytes())
+            .map(|(key, value)| {
+                if key != "query" {
+                    return (key.into_owned(), value.into_owned());
+                }
+
+                let query = self.rewrite_query(&value);
+                changed |= query != value;
+                (key.into_owned(), query)
+            })
+            .collect();
+
+        changed.then(|| {
+            form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(pairs)
+                .finish()
+        })
+    }
+}
+
+/// Replace the calls to `functions` in `query` by their translation, without
+/// touching strings and comments.
+fn rewrite(query: &str, functions: &[&Function]) -> String {
+    let bytes = query.as_bytes();
+    let mut rewritten = String::with_capacity(query.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if matches!(bytes[i], b'"' | b'\'' | b'`' | b'#') {
+            i = skip_literal(bytes, i);
+            continue;
+        }
+
+        if !is_identifier_start(bytes[i]) || (i > 0 && is_identifier(bytes[i - 1])) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < bytes.len() && is_identifier(bytes[i]) {
+            i += 1;
+        }
+
+        let Some(function) = functions
+            .iter()
+            .find(|function| function.name == &query[start..i])
+        else {
+            continue;
+        };
+
+        let mut open = i;
+        while open < bytes.len() && bytes[open].is_ascii_whitespace() {
+            open += 1;
+        }
+        if bytes.get(open) != Some(&b'(') {
+            continue;
+        }
+
+        // Calls that can't be translated are left as they are, the upstream
+        // reports the error then.
+        let Some((args, end)) = call_arguments(query, open) else {
+            continue;
+        };
+        let args: Vec<String> = args
+            .into_iter()
+            .map(|arg| rewrite(arg.trim(), functions))
+            .collect();
+        let Some(translation) = (function.translate)(&args) else {
+            continue;
+        };
+
+        rewritten.push_str(&query[copied..start]);
+        rewritten.push_str(&translation);
+        copied = end;
+        i = end;
+    }
+
+    rewritten.push_str(&query[copied..]);
+    rewritten
+}
+
+/// The arguments of the call of which the opening parenthesis is at `open`,
+/// and the index right after the closing parenthesis.
+fn call_arguments(query: &str, open: usize) -> Option<(Vec<&str>, usize)> {
+    let bytes = query.as_bytes();
+    let mut args = vec![];
+    let mut depth = 0;
+    let mut arg_start = open + 1;
+    let mut i = open + 1;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' | b'`' | b'#' => {
+                i = skip_literal(bytes, i);
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' if depth == 0 => {
+                let arg = &query[arg_start..i];
+                if !arg.trim().is_empty() || !args.is_empty() {
+                    args.push(arg);
+                }
+                return Some((args, i + 1));
+            }
+            b')' | b']' | b'}' => depth -= 1,
+            b',' if depth == 0 => {
+                args.push(&query[arg_start..i]);
+                arg_start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// The index right after the string or comment that starts at `start`.
+fn skip_literal(bytes: &[u8], start: usize) -> usize {
+    let delimiter = bytes[start];
+    let end = if delimiter == b'#' { b'\n' } else { delimiter };
+    let escapes = matches!(delimiter, b'"' | b'\'');
+
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if escapes => i += 2,
+            c if c == end => return i + 1,
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+fn is_identifier_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_' || c == b':'
+}
+
+fn is_identifier(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b':'
+}
+
+#[test]
+fn test_rewrite_query() {
+    let compat = PromqlCompat::new("2.24.0").unwrap();
+
+    assert_eq!(
+        compat.rewrite_query("clamp(rate(x[5m]), 0, 1) * sgn(y)"),
+        "clamp_max(clamp_min(rate(x[5m]), 0), 1) * (((y) > bool 0) - ((y) < bool 0))"
+    );
+    assert_eq!(
+        compat.rewrite_query(r#"sum by (clamp) (present_over_time(up{job="a,b"}[1h]))"#),
+        r#"sum by (clamp) ((count_over_time(up{job="a,b"}[1h]) ^ 0))"#
+    );
+    assert_eq!(
+        compat.rewrite_query(r#"last_over_time(build_info{version="clamp(1)"}[1h])"#),
+        r#"max_over_time(build_info{version="clamp(1)"}[1h])"#
+    );
+
+    // Newer versions support some of the functions themselves.
+    let compat = PromqlCompat::new("v2.45.0").unwrap();
+    assert_eq!(compat.rewrite_query("clamp(x, 0, 1)"), "clamp(x, 0, 1)");
+    assert_eq!(compat.rewrite_query("sort_by_label(up, \"job\")"), "up");
+}
@@ -0,0 +1,181 @@
+use crate::server::util::collect;
+use axum::body::{Body, Bytes};
+use axum::response::{IntoResponse, Response};
+use http::header::ACCEPT_ENCODING;
+use http::{HeaderMap, Method, Request, StatusCode};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, trace};
+
+/// Responses larger than this are not cached, so a few huge range queries
+/// can't take up all the memory.
+const MAX_RESPONSE_SIZE: usize = 4 * 1024 * 1024;
+
+/// The Prometheus API endpoints whose responses only depend on the request,
+/// so they can be cached.
+const CACHEABLE_PATHS: [&str; 2] = ["/api/v1/query", "/api/v1/query_range"];
+
+/// An in-memory cache for the responses of the query endpoints of an upstream
+/// Prometheus. Dashboards in the explorer send the same queries every time
+/// they refresh, which is slow with a remote Prometheus.
+#[derive(Debug)]
+pub(crate) struct QueryCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    stored_at: Instant,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl QueryCache {
+    pub(crate) fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the response to `req` can be served from the cache.
+    pub(crate) fn is_cacheable(req: &Request<Body>) -> bool {
+        matches!(*req.method(), Method::GET | Method::POST)
+            && CACHEABLE_PATHS.contains(&req.uri().path())
+    }
+
+    /// Serve `req` from the cache, or with `upstream` if it is not cached yet
+    /// or has expired. Successful responses of the upstream are cached.
+    pub(crate) async fn handle<F, Fut>(&self, req: Request<Body>, upstream: F) -> Response
+    where
+        F: FnOnce(Request<Body>) -> Fut,
+        Fut: std::future::Future<Output = Response>,
+    {
+        let (parts, body) = req.into_parts();
+
+        // Queries can be sent as a form in a POST request as well, in which
+        // case the form is part of the key.
+        let body = match collect(body).await {
+            Ok(body) => body,
+            Err(err) => {
+                debug!(?err, "Unable to read the request body");
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+        };
+        let key = format!(
+            "{} {}?{} {} {}",
+            parts.method,
+            parts.uri.path(),
+            parts.uri.query().unwrap_or_default(),
+            parts
+                .headers
+                .get(ACCEPT_ENCODING)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default(),
+            String::from_utf8_lossy(&body)
+        );
+
+        if let Some(entry) = self.get(&key) {
+            trace!(key, "Serving the response from the query cache");
+            return entry.into_response();
+        }
+
+        let response = upstream(Request::from_parts(parts, Body::from(body))).await;
+        if response.status() != StatusCode::OK {
+            return response;
+        }
+
+        let (parts, body) = response.into_parts();
+        let body = match collect(body).await {
+            Ok(body) => body,
+            Err(err) => {
+                debug!(?err, "Unable to read the response of the upstream");
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+        };
+
+        let entry = Entry {
+            stored_at: Instant::now(),
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        };
+        if entry.body.len() <= MAX_RESPONSE_SIZE {
+            self.insert(key, entry.clone());
+        }
+
+        entry.into_response()
+    }
+
+    fn get(&self, key: &str) -> Option<Entry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|entry| entry.stored_at.elapsed() < self.ttl)
+            .cloned()
+    }
+
+    fn insert(&self, key: String, entry: Entry) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.max_entries {
+            entries.retain(|_, entry| entry.stored_at.elapsed() < self.ttl);
+        }
+
+        // Make room by evicting the oldest entry, which is the first one to
+        // expire anyway.
+        if entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stored_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        if self.max_entries > 0 {
+            entries.insert(key, entry);
+        }
+    }
+}
+
+impl IntoResponse for Entry {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, self.body).into_response();
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
+
+#[tokio::test]
+async fn test_query_cache() {
+    let cache = QueryCache::new(Duration::from_secs(60), 1);
+    let request = |query: &str| {
+        Request::get(format!("/api/v1/query?query={query}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let upstream = |body: &'static str| {
+        move |_: Request<Body>| async move { (StatusCode::OK, body).into_response() }
+    };
+
+    let response = cache.handle(request("up"), upstream("first")).await;
+    assert_eq!(collect(response.into_body()).await.unwrap(), "first");
+
+    // The response of the first request is served from the cache.
+    let response = cache.handle(request("up"), upstream("second")).await;
+    assert_eq!(collect(response.into_body()).await.unwrap(), "first");
+
+    // A new query evicts the oldest one, since the cache only has one entry.
+    let response = cache.handle(request("down"), upstream("third")).await;
+    assert_eq!(collect(response.into_body()).await.unwrap(), "third");
+    let response = cache.handle(request("up"), upstream("fourth")).await;
+    assert_eq!(collect(response.into_body()).await.unwrap(), "fourth");
+}
@@ -0,0 +1,37 @@
+use crate::status::STATUS;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::{header, StatusCode};
+use serde_json::json;
+
+/// Returns `200 OK` if none of the managed processes have crashed, otherwise
+/// `503 Service Unavailable`.
+pub(crate) async fn health_handler() -> Response {
+    if STATUS.is_healthy() {
+        (StatusCode::OK, Json(json!({ "status": "ok" }))).into_response()
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "unhealthy" })),
+        )
+            .into_response()
+    }
+}
+
+/// Returns the state of am and all the processes that it manages.
+pub(crate) async fn handler() -> Response {
+    Json(STATUS.report()).into_response()
+}
+
+/// Returns the Prometheus config (`prometheus.yml`) that am generated, or
+/// `404 Not Found` if it hasn't been written yet.
+pub(crate) async fn prometheus_config_handler() -> Response {
+    let Some(path) = STATUS.prometheus_config_file() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => ([(header::CONTENT_TYPE, "application/yaml")], contents).into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -0,0 +1,78 @@
+use crate::targets::{Target, TARGETS};
+use crate::unix_bridge;
+use autometrics_am::parser::endpoint_parser;
+use axum::extract::Path;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+#[derive(Deserialize)]
+pub(crate) struct RegisterRequest {
+    job_name: String,
+    /// Parsed the same way as the endpoints passed to `am start`, so
+    /// `localhost:3000` is accepted as well.
+    url: String,
+    #[serde(default, with = "humantime_serde::option")]
+    scrape_interval: Option<Duration>,
+    #[serde(default)]
+    honor_labels: bool,
+}
+
+/// Returns all targets that have been registered through the API.
+pub(crate) async fn list_handler() -> Response {
+    Json(TARGETS.list()).into_response()
+}
+
+/// Registers a scrape target, replacing the target with the same job name and
+/// url. Returns `201 Created` for new targets, `200 OK` for replaced ones and
+/// `409 Conflict` if the job name is taken by a configured job or by a target
+/// with another url.
+pub(crate) async fn register_handler(Json(request): Json<RegisterRequest>) -> Response {
+    if request.job_name.is_empty() || request.job_name.starts_with("am_") {
+        return bad_request("job_name cannot be empty or start with `am_`".to_string());
+    }
+
+    let url = match endpoint_parser(&request.url)
+        .and_then(|url| unix_bridge::local_url(&url, "/metrics"))
+    {
+        Ok(url) => url,
+        Err(err) => return bad_request(format!("invalid url: {err}")),
+    };
+
+    let target = Target {
+        job_name: request.job_name,
+        url,
+        scrape_interval: request.scrape_interval,
+        honor_labels: request.honor_labels,
+    };
+
+    let status = match TARGETS.register_unique(target.clone()) {
+        Ok(true) => StatusCode::OK,
+        Ok(false) => StatusCode::CREATED,
+        Err(err) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({ "error": err.to_string() })),
+            )
+                .into_response()
+        }
+    };
+
+    (status, Json(target)).into_response()
+}
+
+/// Unregisters the scrape target with the given job name.
+pub(crate) async fn unregister_handler(Path(job_name): Path<String>) -> Response {
+    if TARGETS.unregister(&job_name) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+fn bad_request(message: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+}
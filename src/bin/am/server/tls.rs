@@ -0,0 +1,107 @@
+use crate::daemon;
+use anyhow::{bail, Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// The directory in the data directory of am in which the generated
+/// certificate is stored, so browsers only need to trust it once.
+const LOCAL_CERTIFICATE_DIR: &str = "tls";
+
+/// The certificate with which the web server of am serves HTTPS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TlsConfig {
+    /// A self-signed certificate for `localhost`, which is generated on first
+    /// use.
+    Local,
+
+    /// A certificate and its private key, both PEM encoded.
+    Files { cert: PathBuf, key: PathBuf },
+}
+
+impl TlsConfig {
+    /// The TLS config for the `--tls`, `--tls-cert` and `--tls-key` flags, if
+    /// HTTPS is enabled. Passing a certificate enables it as well.
+    pub(crate) fn new(
+        enabled: bool,
+        cert: Option<PathBuf>,
+        key: Option<PathBuf>,
+    ) -> Result<Option<Self>> {
+        match (cert, key) {
+            (Some(cert), Some(key)) => Ok(Some(TlsConfig::Files { cert, key })),
+            (None, None) => Ok(enabled.then_some(TlsConfig::Local)),
+            _ => bail!("`tls-cert` and `tls-key` need to be set together"),
+        }
+    }
+
+    pub(crate) async fn rustls_config(&self) -> Result<RustlsConfig> {
+        let (cert, key) = match self {
+            TlsConfig::Local => local_certificate()?,
+            TlsConfig::Files { cert, key } => (cert.clone(), key.clone()),
+        };
+
+        RustlsConfig::from_pem_file(&cert, &key)
+            .await
+            .with_context(|| {
+                format!(
+                    "Unable to load TLS certificate {} with key {}",
+                    cert.display(),
+                    key.display()
+                )
+            })
+    }
+}
+
+/// Returns the paths of the self-signed certificate for `localhost` and its
+/// key, generating them if they don't exist yet.
+fn local_certificate() -> Result<(PathBuf, PathBuf)> {
+    let dir = daemon::data_dir()?.join(LOCAL_CERTIFICATE_DIR);
+    let cert = dir.join("cert.pem");
+    let key = dir.join("key.pem");
+
+    if cert.exists() && key.exists() {
+        return Ok((cert, key));
+    }
+
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Unable to create directory {}", dir.display()))?;
+    generate_certificate(&cert, &key)?;
+
+    info!(
+        "Generated a self-signed certificate for localhost in {}, add it to the trusted certificates of your browser to avoid warnings",
+        cert.display()
+    );
+
+    Ok((cert, key))
+}
+
+fn generate_certificate(cert: &Path, key: &Path) -> Result<()> {
+    let subject_alt_names = ["localhost", "127.0.0.1", "::1"].map(String::from);
+    let certificate = rcgen::generate_simple_self_signed(subject_alt_names)
+        .context("Unable to generate a TLS certificate")?;
+
+    fs::write(cert, certificate.serialize_pem()?)
+        .with_context(|| format!("Unable to write {}", cert.display()))?;
+    fs::write(key, certificate.serialize_private_key_pem())
+        .with_context(|| format!("Unable to write {}", key.display()))?;
+
+    Ok(())
+}
+
+#[test]
+fn test_tls_config() {
+    assert_eq!(TlsConfig::new(false, None, None).unwrap(), None);
+    assert_eq!(
+        TlsConfig::new(true, None, None).unwrap(),
+        Some(TlsConfig::Local)
+    );
+    assert_eq!(
+        TlsConfig::new(false, Some("cert.pem".into()), Some("key.pem".into())).unwrap(),
+        Some(TlsConfig::Files {
+            cert: "cert.pem".into(),
+            key: "key.pem".into()
+        })
+    );
+    assert!(TlsConfig::new(true, Some("cert.pem".into()), None).is_err());
+}
@@ -1,6 +1,6 @@
 use crate::commands::start::CLIENT;
 use axum::body;
-use axum::body::Body;
+use axum::body::{Body, Bytes, HttpBody};
 use axum::response::{IntoResponse, Response};
 use http::{StatusCode, Uri};
 use tracing::{debug, error, trace};
@@ -66,3 +66,17 @@ pub(crate) fn convert_response(req: reqwest::Response) -> Response {
         }
     }
 }
+
+/// Read the whole `body` into memory.
+pub(crate) async fn collect<B>(body: B) -> Result<Bytes, B::Error>
+where
+    B: HttpBody<Data = Bytes>,
+{
+    let mut body = Box::pin(body);
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+
+    Ok(Bytes::from(bytes))
+}
@@ -0,0 +1,141 @@
+use anyhow::Result;
+use futures_util::future::join_all;
+use once_cell::sync::Lazy;
+use std::ffi::OsStr;
+use std::future::Future;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::select;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+/// How long a process gets to exit after it has been asked to terminate,
+/// before it is killed.
+const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Set to `true` once am is shutting down. From then on the processes that am
+/// started are terminated instead of restarted.
+static SHUTDOWN: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+/// The amount of processes that are currently waited on with [`wait`].
+static RUNNING: Lazy<watch::Sender<usize>> = Lazy::new(|| watch::channel(0).0);
+
+/// Wait until am receives a SIGINT (ie. Ctrl+C) or a SIGTERM (ie. `docker
+/// stop`), and return the name of the signal.
+pub(crate) async fn signal() -> &'static str {
+    #[cfg(unix)]
+    let sigterm = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(err) => {
+                warn!(?err, "Unable to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let sigterm = std::future::pending::<()>();
+
+    select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = sigterm => "SIGTERM",
+    }
+}
+
+/// Start shutting down, which terminates all processes that are waited on
+/// with [`wait`].
+pub(crate) fn request() {
+    SHUTDOWN.send_replace(true);
+}
+
+/// Shut down and wait for the processes to exit. `tasks` are the futures that
+/// wait on the processes, they are only driven until the processes have exited
+/// since they might be busy with something else (ie. downloading).
+pub(crate) async fn stop<F: Future>(tasks: impl IntoIterator<Item = F>) {
+    request();
+
+    let mut running = RUNNING.subscribe();
+    select! {
+        _ = join_all(tasks) => {}
+        _ = running.wait_for(|running| *running == 0) => {}
+        _ = tokio::time::sleep(GRACE_PERIOD + Duration::from_secs(5)) => {
+            warn!("Not all processes exited in time");
+        }
+    }
+}
+
+pub(crate) fn is_requested() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+/// A command for a process that is stopped by am when it shuts down. It runs
+/// in its own process group, so a Ctrl+C in the terminal only reaches am,
+/// which then decides when to terminate it.
+pub(crate) fn command(program: impl AsRef<OsStr>) -> Command {
+    let mut command = Command::new(program);
+
+    #[cfg(unix)]
+    command.process_group(0);
+
+    command
+}
+
+/// Wait for `child` to exit. If am shuts down in the meantime, the child gets
+/// terminated and this waits for it to exit.
+pub(crate) async fn wait(child: &mut Child, process: &str) -> Result<ExitStatus> {
+    let _running = Running::new();
+    let mut shutdown = SHUTDOWN.subscribe();
+
+    select! {
+        status = child.wait() => return Ok(status?),
+        _ = shutdown.wait_for(|shutdown| *shutdown) => {}
+    }
+
+    terminate(child, process).await
+}
+
+/// Counts a process as running in [`RUNNING`] until it is dropped.
+struct Running;
+
+impl Running {
+    fn new() -> Self {
+        RUNNING.send_modify(|running| *running += 1);
+        Running
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.send_modify(|running| *running -= 1);
+    }
+}
+
+async fn terminate(child: &mut Child, process: &str) -> Result<ExitStatus> {
+    debug!("Terminating {process}");
+
+    // The process was started in its own process group (see `command`), so
+    // this also reaches the processes that it started itself.
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        Command::new("kill")
+            .args(["-TERM", "--", &format!("-{pid}")])
+            .status()
+            .await?;
+    }
+    #[cfg(not(unix))]
+    child.start_kill()?;
+
+    match tokio::time::timeout(GRACE_PERIOD, child.wait()).await {
+        Ok(status) => Ok(status?),
+        Err(_) => {
+            warn!("{process} did not exit within {GRACE_PERIOD:?}, killing it");
+            child.kill().await?;
+            Ok(child.wait().await?)
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use crate::logs;
+use crate::shutdown;
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::process::Child;
+
+/// The state of am and all the processes that it manages.
+pub(crate) static STATUS: Lazy<Status> = Lazy::new(Status::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ProcessState {
+    Downloading,
+    Starting,
+    Running,
+    Stopped,
+    Crashed,
+}
+
+#[derive(Debug, Clone)]
+struct Process {
+    state: ProcessState,
+    version: Option<String>,
+    since: Instant,
+    restarts: u32,
+}
+
+pub(crate) struct Status {
+    started_at: Instant,
+    listen_addresses: Mutex<Vec<SocketAddr>>,
+    config_file: Mutex<Option<PathBuf>>,
+    prometheus_config_file: Mutex<Option<PathBuf>>,
+    processes: Mutex<BTreeMap<String, Process>>,
+    targets: Mutex<Vec<TargetReport>>,
+}
+
+impl Status {
+    fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            listen_addresses: Mutex::new(vec![]),
+            config_file: Mutex::new(None),
+            prometheus_config_file: Mutex::new(None),
+            processes: Mutex::new(BTreeMap::new()),
+            targets: Mutex::new(vec![]),
+        }
+    }
+
+    /// Register `process` with the `version` that will be used. The process
+    /// will be in the [`ProcessState::Starting`] state until told otherwise.
+    pub(crate) fn register(&self, process: &str, version: &str) {
+        self.processes.lock().unwrap().insert(
+            process.to_string(),
+            Process {
+                state: ProcessState::Starting,
+                version: Some(version.to_string()),
+                since: Instant::now(),
+                restarts: 0,
+            },
+        );
+    }
+
+    pub(crate) fn set_state(&self, process: &str, state: ProcessState) {
+        let mut processes = self.processes.lock().unwrap();
+        let entry = processes
+            .entry(process.to_string())
+            .or_insert_with(|| Process {
+                state,
+                version: None,
+                since: Instant::now(),
+                restarts: 0,
+            });
+
+        if entry.state != state {
+            entry.state = state;
+            entry.since = Instant::now();
+        }
+    }
+
+    /// Mark `process` as restarting.
+    pub(crate) fn record_restart(&self, process: &str) {
+        self.set_state(process, ProcessState::Starting);
+
+        if let Some(process) = self.processes.lock().unwrap().get_mut(process) {
+            process.restarts += 1;
+        }
+    }
+
+    /// Set the addresses that the web server is bound to.
+    pub(crate) fn set_listen_addresses(&self, listen_addresses: Vec<SocketAddr>) {
+        *self.listen_addresses.lock().unwrap() = listen_addresses;
+    }
+
+    pub(crate) fn set_config_files(
+        &self,
+        config_file: Option<PathBuf>,
+        prometheus_config_file: PathBuf,
+    ) {
+        *self.config_file.lock().unwrap() = config_file;
+        *self.prometheus_config_file.lock().unwrap() = Some(prometheus_config_file);
+    }
+
+    /// The path of the Prometheus config that am generated, if it has been
+    /// started.
+    pub(crate) fn prometheus_config_file(&self) -> Option<PathBuf> {
+        self.prometheus_config_file.lock().unwrap().clone()
+    }
+
+    /// Replace the scrape targets with the ones that Prometheus last reported.
+    pub(crate) fn set_targets(&self, targets: Vec<TargetReport>) {
+        *self.targets.lock().unwrap() = targets;
+    }
+
+    /// Whether none of the processes have crashed.
+    pub(crate) fn is_healthy(&self) -> bool {
+        self.processes
+            .lock()
+            .unwrap()
+            .values()
+            .all(|process| process.state != ProcessState::Crashed)
+    }
+
+    /// The name of every process, whether it is running and how often it was
+    /// restarted.
+    pub(crate) fn processes(&self) -> Vec<(String, bool, u32)> {
+        self.processes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, process)| {
+                let running = process.state == ProcessState::Running;
+                (name.clone(), running, process.restarts)
+            })
+            .collect()
+    }
+
+    pub(crate) fn report(&self) -> StatusReport {
+        let processes = self
+            .processes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, process)| {
+                let report = ProcessReport {
+                    state: process.state,
+                    version: process.version.clone(),
+                    state_duration_seconds: process.since.elapsed().as_secs(),
+                    restarts: process.restarts,
+                };
+                (name.clone(), report)
+            })
+            .collect();
+
+        StatusReport {
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_seconds: self.started_at.elapsed().as_secs(),
+            listen_addresses: self.listen_addresses.lock().unwrap().clone(),
+            config_file: self.config_file.lock().unwrap().clone(),
+            prometheus_config_file: self.prometheus_config_file.lock().unwrap().clone(),
+            processes,
+            targets: self.targets.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct StatusReport {
+    version: &'static str,
+    uptime_seconds: u64,
+    listen_addresses: Vec<SocketAddr>,
+    config_file: Option<PathBuf>,
+    prometheus_config_file: Option<PathBuf>,
+    processes: BTreeMap<String, ProcessReport>,
+    targets: Vec<TargetReport>,
+}
+
+/// The health of a scrape target, as reported by Prometheus.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct TargetReport {
+    pub job: String,
+    pub scrape_url: String,
+    /// `up`, `down` or `unknown` if it hasn't been scraped yet.
+    pub health: String,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ProcessReport {
+    state: ProcessState,
+    version: Option<String>,
+    /// How long the process has been in its current state.
+    state_duration_seconds: u64,
+    restarts: u32,
+}
+
+/// Wait for `child` to exit while capturing its output (see
+/// [`logs::wait_with_logs`]) and keeping its state up to date.
+pub(crate) async fn wait(child: Child, process: &str) -> Result<ExitStatus> {
+    STATUS.set_state(process, ProcessState::Running);
+
+    let status = logs::wait_with_logs(child, process).await;
+
+    let state = match &status {
+        Ok(status) if status.success() || shutdown::is_requested() => ProcessState::Stopped,
+        _ => ProcessState::Crashed,
+    };
+    STATUS.set_state(process, state);
+
+    status
+}
+
+#[test]
+fn test_crashed_process_is_unhealthy() {
+    let status = Status::new();
+
+    status.register("prometheus", "2.47.2");
+    status.set_state("prometheus", ProcessState::Running);
+    assert!(status.is_healthy());
+
+    status.set_state("prometheus", ProcessState::Crashed);
+    assert!(!status.is_healthy());
+}
@@ -0,0 +1,172 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::watch;
+use url::Url;
+
+/// The scrape targets that other tools registered through the API, on top of
+/// the endpoints from the CLI and the config file.
+pub(crate) static TARGETS: Lazy<Targets> = Lazy::new(Targets::new);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Target {
+    pub job_name: String,
+    pub url: Url,
+    #[serde(default, with = "humantime_serde::option")]
+    pub scrape_interval: Option<Duration>,
+    #[serde(default)]
+    pub honor_labels: bool,
+}
+
+pub(crate) struct Targets {
+    targets: Mutex<BTreeMap<String, Target>>,
+    /// The job names of the endpoints that am was started with.
+    configured_jobs: Mutex<BTreeSet<String>>,
+    changed: watch::Sender<()>,
+}
+
+/// Why a target that was registered through the API was refused.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub(crate) enum Conflict {
+    #[error("a job named `{0}` is already configured")]
+    ConfiguredJob(String),
+
+    #[error("a target named `{job_name}` is already registered for {url}")]
+    RegisteredTarget { job_name: String, url: Url },
+}
+
+impl Targets {
+    fn new() -> Self {
+        Self {
+            targets: Mutex::new(BTreeMap::new()),
+            configured_jobs: Mutex::new(BTreeSet::new()),
+            changed: watch::channel(()).0,
+        }
+    }
+
+    /// Register `target`, replacing the target with the same job name (if
+    /// any). Returns whether a target was replaced.
+    pub(crate) fn register(&self, target: Target) -> bool {
+        let replaced = self
+            .targets
+            .lock()
+            .unwrap()
+            .insert(target.job_name.clone(), target)
+            .is_some();
+
+        self.changed.send_replace(());
+        replaced
+    }
+
+    /// Register `target` unless its job name is taken by a configured job or
+    /// by a target with another url. Registering the same url again replaces
+    /// the target, which returns `true`.
+    pub(crate) fn register_unique(&self, target: Target) -> Result<bool, Conflict> {
+        if self
+            .configured_jobs
+            .lock()
+            .unwrap()
+            .contains(&target.job_name)
+        {
+            return Err(Conflict::ConfiguredJob(target.job_name));
+        }
+
+        let mut targets = self.targets.lock().unwrap();
+        if let Some(existing) = targets.get(&target.job_name) {
+            if existing.url != target.url {
+                return Err(Conflict::RegisteredTarget {
+                    job_name: target.job_name,
+                    url: existing.url.clone(),
+                });
+            }
+        }
+
+        let replaced = targets.insert(target.job_name.clone(), target).is_some();
+        drop(targets);
+
+        self.changed.send_replace(());
+        Ok(replaced)
+    }
+
+    /// Set the job names of the endpoints that am was started with, which
+    /// targets registered through the API can't use.
+    pub(crate) fn set_configured_jobs(&self, job_names: impl IntoIterator<Item = String>) {
+        *self.configured_jobs.lock().unwrap() = job_names.into_iter().collect();
+    }
+
+    /// Unregister the target with `job_name`. Returns whether it existed.
+    pub(crate) fn unregister(&self, job_name: &str) -> bool {
+        let removed = self.targets.lock().unwrap().remove(job_name).is_some();
+
+        if removed {
+            self.changed.send_replace(());
+        }
+        removed
+    }
+
+    pub(crate) fn list(&self) -> Vec<Target> {
+        self.targets.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Get notified whenever a target is registered or unregistered.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
+    }
+}
+
+#[test]
+fn test_register_and_unregister() {
+    let targets = Targets::new();
+    let mut rx = targets.subscribe();
+
+    let target = Target {
+        job_name: "api".to_string(),
+        url: Url::parse("http://localhost:3000/metrics").unwrap(),
+        scrape_interval: None,
+        honor_labels: false,
+    };
+
+    assert!(!targets.register(target.clone()));
+    assert!(targets.register(target));
+    assert!(rx.has_changed().unwrap());
+    rx.borrow_and_update();
+
+    assert_eq!(targets.list().len(), 1);
+    assert!(!targets.unregister("web"));
+    assert!(!rx.has_changed().unwrap());
+    assert!(targets.unregister("api"));
+    assert!(rx.has_changed().unwrap());
+    assert!(targets.list().is_empty());
+}
+
+#[test]
+fn test_register_unique() {
+    let targets = Targets::new();
+    targets.set_configured_jobs(["am".to_string(), "api".to_string()]);
+
+    let target = |job_name: &str, port: u16| Target {
+        job_name: job_name.to_string(),
+        url: Url::parse(&format!("http://localhost:{port}/metrics")).unwrap(),
+        scrape_interval: None,
+        honor_labels: false,
+    };
+
+    assert_eq!(
+        targets.register_unique(target("api", 3000)),
+        Err(Conflict::ConfiguredJob("api".to_string()))
+    );
+
+    assert_eq!(targets.register_unique(target("web", 3000)), Ok(false));
+    assert_eq!(targets.register_unique(target("web", 3000)), Ok(true));
+    assert_eq!(
+        targets.register_unique(target("web", 3001)),
+        Err(Conflict::RegisteredTarget {
+            job_name: "web".to_string(),
+            url: Url::parse("http://localhost:3000/metrics").unwrap(),
+        })
+    );
+    assert_eq!(targets.list().len(), 1);
+}
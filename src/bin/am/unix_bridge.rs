@@ -0,0 +1,127 @@
+//! Prometheus can only scrape over TCP, so endpoints on a unix domain socket
+//! (`unix:///path/to.sock`) are scraped through a bridge: a listener on a free
+//! local port that forwards every connection to the socket.
+
+use anyhow::Result;
+#[cfg(unix)]
+use once_cell::sync::Lazy;
+#[cfg(unix)]
+use std::collections::HashMap;
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::sync::Mutex;
+#[cfg(unix)]
+use tracing::{debug, warn};
+use url::Url;
+
+/// The ports of the bridges that are running, by socket. Every socket only
+/// gets a single bridge, so reloading the config keeps the same port.
+#[cfg(unix)]
+static BRIDGES: Lazy<Mutex<HashMap<PathBuf, u16>>> = Lazy::new(Default::default);
+
+/// Returns the local URL through which `url` can be reached, starting a bridge
+/// if needed. URLs that aren't a `unix://` URL are returned as is.
+///
+/// The HTTP path is taken from the `path` query parameter (ie.
+/// `unix:///run/app.sock?path=/api/metrics`), and defaults to `default_path`.
+pub(crate) fn local_url(url: &Url, default_path: &str) -> Result<Url> {
+    if url.scheme() != "unix" {
+        return Ok(url.clone());
+    }
+
+    let socket = PathBuf::from(url.path());
+    let path = url
+        .query_pairs()
+        .find(|(key, _)| key == "path")
+        .map_or_else(|| default_path.to_string(), |(_, path)| path.into_owned());
+
+    let port = bridge(socket)?;
+
+    let mut local_url = Url::parse(&format!("http://127.0.0.1:{port}"))?;
+    local_url.set_path(&path);
+    Ok(local_url)
+}
+
+#[cfg(unix)]
+fn bridge(socket: PathBuf) -> Result<u16> {
+    let mut bridges = BRIDGES.lock().unwrap();
+    if let Some(port) = bridges.get(&socket) {
+        return Ok(*port);
+    }
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let port = listener.local_addr()?.port();
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+
+    debug!(?socket, port, "Started bridge to unix socket");
+    tokio::spawn(serve(listener, socket.clone()));
+    bridges.insert(socket, port);
+
+    Ok(port)
+}
+
+#[cfg(not(unix))]
+fn bridge(_socket: PathBuf) -> Result<u16> {
+    anyhow::bail!("unix domain sockets are not supported on this platform")
+}
+
+#[cfg(unix)]
+async fn serve(listener: tokio::net::TcpListener, socket: PathBuf) {
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!(?err, "Unable to accept connection for {}", socket.display());
+                continue;
+            }
+        };
+
+        let socket = socket.clone();
+        tokio::spawn(async move {
+            // If the socket can't be reached, the connection is closed, which
+            // shows up as a failed scrape in Prometheus.
+            match tokio::net::UnixStream::connect(&socket).await {
+                Ok(mut unix_stream) => {
+                    if let Err(err) =
+                        tokio::io::copy_bidirectional(&mut stream, &mut unix_stream).await
+                    {
+                        debug!(?err, ?socket, "Connection to unix socket closed");
+                    }
+                }
+                Err(err) => warn!(?err, "Unable to connect to {}", socket.display()),
+            }
+        });
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_bridge() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("app.sock");
+    let unix_listener = tokio::net::UnixListener::bind(&socket).unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = unix_listener.accept().await.unwrap();
+        let mut request = [0; 64];
+        let read = stream.read(&mut request).await.unwrap();
+        stream.write_all(&request[..read]).await.unwrap();
+    });
+
+    let url = Url::parse(&format!("unix://{}?path=/api/metrics", socket.display())).unwrap();
+    let bridged = local_url(&url, "/metrics").unwrap();
+    assert_eq!(bridged.path(), "/api/metrics");
+
+    // The bridge is reused for the same socket.
+    assert_eq!(local_url(&url, "/").unwrap().port(), bridged.port());
+
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", bridged.port().unwrap()))
+        .await
+        .unwrap();
+    stream.write_all(b"ping").await.unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(&response, b"ping");
+}
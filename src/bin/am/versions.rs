@@ -0,0 +1,361 @@
+use crate::commands::start::CLIENT;
+use crate::installs;
+use anyhow::{bail, Context, Result};
+use directories::ProjectDirs;
+use semver_rs::{Range, Version};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+/// How long the list of releases of a repository is cached before it is
+/// fetched from GitHub again.
+const RELEASES_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The maximum number of pages of 100 releases that are fetched per
+/// repository. Older releases are only available as exact versions.
+const MAX_RELEASE_PAGES: u32 = 3;
+
+/// A program that am downloads from the releases of a GitHub repository.
+pub(crate) struct Program {
+    pub name: &'static str,
+    /// The prefix of the `{directory}-{version}` directory that the program
+    /// gets installed in.
+    pub directory: &'static str,
+    pub org: &'static str,
+    pub repo: &'static str,
+}
+
+pub(crate) const PROMETHEUS: Program = Program {
+    name: "Prometheus",
+    directory: "prometheus",
+    org: "prometheus",
+    repo: "prometheus",
+};
+
+pub(crate) const PUSHGATEWAY: Program = Program {
+    name: "Pushgateway",
+    directory: "pushgateway",
+    org: "prometheus",
+    repo: "pushgateway",
+};
+
+pub(crate) const GATEWAY: Program = Program {
+    name: "Autometrics gateway",
+    directory: "autometrics-gateway",
+    org: "autometrics-dev",
+    repo: "autometrics-gateway",
+};
+
+pub(crate) const ALERTMANAGER: Program = Program {
+    name: "Alertmanager",
+    directory: "alertmanager",
+    org: "prometheus",
+    repo: "alertmanager",
+};
+
+pub(crate) const OTEL_COLLECTOR: Program = Program {
+    name: "OpenTelemetry Collector",
+    directory: "otelcol-contrib",
+    org: "open-telemetry",
+    repo: "opentelemetry-collector-releases",
+};
+
+pub(crate) const TEMPO: Program = Program {
+    name: "Tempo",
+    directory: "tempo",
+    org: "grafana",
+    repo: "tempo",
+};
+
+pub(crate) const LOKI: Program = Program {
+    name: "Loki",
+    directory: "loki",
+    org: "grafana",
+    repo: "loki",
+};
+
+/// Promtail is released together with Loki, and always uses the same version.
+pub(crate) const PROMTAIL: Program = Program {
+    name: "Promtail",
+    directory: "promtail",
+    org: "grafana",
+    repo: "loki",
+};
+
+pub(crate) const BLACKBOX_EXPORTER: Program = Program {
+    name: "blackbox_exporter",
+    directory: "blackbox_exporter",
+    org: "prometheus",
+    repo: "blackbox_exporter",
+};
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) const NODE_EXPORTER: Program = Program {
+    name: "node_exporter",
+    directory: "node_exporter",
+    org: "prometheus",
+    repo: "node_exporter",
+};
+
+#[cfg(target_os = "windows")]
+pub(crate) const WINDOWS_EXPORTER: Program = Program {
+    name: "windows_exporter",
+    directory: "windows_exporter",
+    org: "prometheus-community",
+    repo: "windows_exporter",
+};
+
+pub(crate) const GRAFANA: Program = Program {
+    name: "Grafana",
+    directory: "grafana",
+    org: "grafana",
+    repo: "grafana",
+};
+
+/// The version of a program, as it is specified by the user.
+#[derive(Debug)]
+pub(crate) enum VersionSpec {
+    /// An exact version like `v2.45.0`, which is used as is.
+    Exact(String),
+    /// The latest stable release.
+    Latest,
+    /// The latest stable release that matches a semver range like `^2.48`.
+    Range(Range),
+}
+
+impl VersionSpec {
+    pub(crate) fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if spec.eq_ignore_ascii_case("latest") {
+            return Ok(Self::Latest);
+        }
+
+        let version = Version::new(spec.trim_start_matches('v')).parse();
+        if matches!(version, Ok(version) if !version.is_empty() && !version.is_any()) {
+            return Ok(Self::Exact(spec.to_string()));
+        }
+
+        match Range::new(spec).parse() {
+            Ok(range) if !spec.is_empty() => Ok(Self::Range(range)),
+            _ => bail!(
+                "invalid version: {spec} (expected a version like v2.45.0, a range like ^2.48 or latest)"
+            ),
+        }
+    }
+
+    /// The highest stable version in `versions` that satisfies this spec.
+    fn select<'a>(&self, versions: impl IntoIterator<Item = &'a str>) -> Option<Version> {
+        versions
+            .into_iter()
+            .filter_map(|version| Version::new(version.trim_start_matches('v')).parse().ok())
+            .filter(|version| !version.is_empty() && !version.is_any() && !version.has_prerelease())
+            .filter(|version| match self {
+                Self::Exact(_) => false,
+                Self::Latest => true,
+                Self::Range(range) => range.test(version),
+            })
+            .max()
+    }
+}
+
+/// A release of a GitHub repository, as returned by the releases API.
+#[derive(Debug, Deserialize, Serialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+}
+
+/// Resolve the version `spec` of `program` to the exact version that should be
+/// used, like `v2.48.1` for `^2.48`.
+///
+/// Versions that are not exact are resolved with the releases on GitHub, which
+/// are cached for an hour. In offline mode, or if GitHub can't be reached, the
+/// versions that are already installed in `local_data` are used instead.
+pub(crate) async fn resolve(
+    program: &Program,
+    spec: &str,
+    local_data: &Path,
+    offline: bool,
+) -> Result<String> {
+    let version_spec = VersionSpec::parse(spec)
+        .with_context(|| format!("Unable to resolve the {} version", program.name))?;
+    if let VersionSpec::Exact(version) = version_spec {
+        return Ok(version);
+    }
+
+    let version = if offline {
+        resolve_installed(program, &version_spec, local_data)?
+    } else {
+        match releases(program).await {
+            Ok(tags) => version_spec.select(tags.iter().map(String::as_str)),
+            Err(err) => {
+                warn!(
+                    ?err,
+                    "Unable to fetch the releases of {}, using the installed versions",
+                    program.name
+                );
+                resolve_installed(program, &version_spec, local_data)?
+            }
+        }
+    };
+
+    let Some(version) = version else {
+        bail!("No {} release found that matches {spec}", program.name);
+    };
+
+    info!("Resolved {} version {spec} to v{version}", program.name);
+    Ok(format!("v{version}"))
+}
+
+/// The highest version of `program` that is installed in `local_data` and
+/// satisfies `spec`.
+pub(crate) fn resolve_installed(
+    program: &Program,
+    spec: &VersionSpec,
+    local_data: &Path,
+) -> Result<Option<Version>> {
+    let installs = installs::list(local_data)?;
+    Ok(spec.select(
+        installs
+            .iter()
+            .filter(|install| install.program == program.directory)
+            .map(|install| install.version.as_str()),
+    ))
+}
+
+/// The tags of the stable releases of `program`, newest first.
+async fn releases(program: &Program) -> Result<Vec<String>> {
+    let cache_path = releases_cache_path(program);
+
+    let cached = cache_path
+        .as_ref()
+        .and_then(|path| Some((fs::metadata(path).ok()?, fs::read_to_string(path).ok()?)));
+    if let Some((metadata, cached)) = &cached {
+        let fresh = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < RELEASES_CACHE_TTL);
+        if fresh {
+            debug!(?cache_path, "Using cached releases");
+            return Ok(serde_json::from_str(cached)?);
+        }
+    }
+
+    let tags = match fetch_releases(program).await {
+        Ok(tags) => tags,
+        // An outdated list of releases is better than none at all.
+        Err(err) => match cached {
+            Some((_, cached)) => {
+                warn!(
+                    ?err,
+                    "Unable to fetch the releases of {}, using the cached releases", program.name
+                );
+                return Ok(serde_json::from_str(&cached)?);
+            }
+            None => return Err(err),
+        },
+    };
+
+    // Failing to cache the releases is not fatal, it only means they will be
+    // fetched again next time.
+    if let Some(path) = cache_path {
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, serde_json::to_string(&tags)?));
+        if let Err(err) = result {
+            warn!(?err, ?path, "Unable to cache releases");
+        }
+    }
+
+    Ok(tags)
+}
+
+fn releases_cache_path(program: &Program) -> Option<PathBuf> {
+    ProjectDirs::from("", "autometrics", "am").map(|project_dirs| {
+        project_dirs
+            .cache_dir()
+            .join("releases")
+            .join(program.org)
+            .join(format!("{}.json", program.repo))
+    })
+}
+
+async fn fetch_releases(program: &Program) -> Result<Vec<String>> {
+    let mut tags = vec![];
+
+    for page in 1..=MAX_RELEASE_PAGES {
+        let url = format!(
+            "https://api.github.com/repos/{}/{}/releases?per_page=100&page={page}",
+            program.org, program.repo
+        );
+        debug!(%url, "Fetching releases");
+
+        let mut request = CLIENT
+            .get(&url)
+            .header("Accept", "application/vnd.github+json");
+        // Unauthenticated requests are rate limited to 60 per hour.
+        if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+            request = request.bearer_auth(token);
+        }
+
+        let releases: Vec<Release> = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Unable to parse the releases of {}", program.name))?;
+
+        let last_page = releases.len() < 100;
+        tags.extend(
+            releases
+                .into_iter()
+                .filter(|release| !release.draft && !release.prerelease)
+                .map(|release| release.tag_name),
+        );
+
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(tags)
+}
+
+#[test]
+fn test_version_spec() {
+    let tags = [
+        "v2.49.0-rc.0",
+        "v2.48.1",
+        "v2.48.0",
+        "v2.45.0",
+        "v3.0.0",
+        "nightly",
+    ];
+    let select = |spec: &str| {
+        VersionSpec::parse(spec)
+            .unwrap()
+            .select(tags)
+            .map(|version| version.to_string())
+    };
+
+    assert_eq!(select("latest").as_deref(), Some("3.0.0"));
+    assert_eq!(select("^2.48").as_deref(), Some("2.48.1"));
+    assert_eq!(select("~2.45").as_deref(), Some("2.45.0"));
+    assert_eq!(select(">=2.46 <2.48.1").as_deref(), Some("2.48.0"));
+    assert_eq!(select("^4"), None);
+
+    assert!(matches!(
+        VersionSpec::parse("v2.45.0").unwrap(),
+        VersionSpec::Exact(version) if version == "v2.45.0"
+    ));
+    assert!(VersionSpec::parse("").is_err());
+    assert!(VersionSpec::parse("newest").is_err());
+}
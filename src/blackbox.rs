@@ -0,0 +1,73 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The module that probes http(s) URLs and expects a 2xx response.
+pub const HTTP_MODULE: &str = "http_2xx";
+
+/// The module that only checks whether a TCP connection can be made.
+pub const TCP_MODULE: &str = "tcp_connect";
+
+/// A blackbox_exporter configuration with the modules that am uses to probe
+/// services that don't expose any metrics themselves.
+///
+/// See: https://github.com/prometheus/blackbox_exporter/blob/master/CONFIGURATION.md
+#[derive(Debug, Serialize)]
+pub struct Config {
+    pub modules: BTreeMap<String, Module>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Module {
+    pub prober: Prober,
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpProbe>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Prober {
+    Http,
+    Tcp,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HttpProbe {
+    pub preferred_ip_protocol: String,
+    pub follow_redirects: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let timeout = Duration::from_secs(5);
+
+        Self {
+            modules: BTreeMap::from([
+                (
+                    HTTP_MODULE.to_string(),
+                    Module {
+                        prober: Prober::Http,
+                        timeout,
+                        // Most development machines don't have IPv6
+                        // connectivity, which is what the exporter prefers by
+                        // default.
+                        http: Some(HttpProbe {
+                            preferred_ip_protocol: "ip4".to_string(),
+                            follow_redirects: true,
+                        }),
+                    },
+                ),
+                (
+                    TCP_MODULE.to_string(),
+                    Module {
+                        prober: Prober::Tcp,
+                        timeout,
+                        http: None,
+                    },
+                ),
+            ]),
+        }
+    }
+}
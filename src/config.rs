@@ -1,7 +1,11 @@
-use crate::parser::endpoint_parser;
+use crate::parser::{basic_auth_parser, endpoint_parser, federate_url_parser, web_auth_parser};
+use crate::prometheus::ScrapeProtocol;
+use crate::slo::SloDefinition;
+use anyhow::{bail, Result};
 use serde::de::Error;
-use serde::{Deserialize, Deserializer, Serialize};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::time::Duration;
 use url::Url;
 
@@ -15,12 +19,573 @@ pub struct AmConfig {
     #[serde(rename = "endpoint")]
     pub endpoints: Option<Vec<Endpoint>>,
 
+    /// Prometheus servers from which selected series are pulled in through
+    /// federation.
+    pub federate: Option<Vec<FederateConfig>>,
+
     /// Startup the pushgateway.
     pub pushgateway_enabled: Option<bool>,
 
+    /// Startup the autometrics gateway.
+    pub gateway_enabled: Option<bool>,
+
+    /// Startup the alertmanager.
+    pub alertmanager_enabled: Option<bool>,
+
+    /// Listen for StatsD metrics and expose them to Prometheus.
+    pub statsd_enabled: Option<bool>,
+
+    /// The UDP port on which StatsD metrics are received.
+    pub statsd_port: Option<u16>,
+
+    /// Startup the OpenTelemetry Collector.
+    pub otel_collector_enabled: Option<bool>,
+
+    /// Startup Tempo to store traces locally.
+    pub traces_enabled: Option<bool>,
+
+    /// Startup Loki to store logs locally.
+    pub loki_enabled: Option<bool>,
+
+    /// Log files that are sent to Loki by Promtail, which can be globs.
+    /// Relative paths are resolved against the directory in which `am start`
+    /// is run.
+    pub loki_log_files: Option<Vec<PathBuf>>,
+
+    /// Startup the blackbox_exporter to probe the `probes`. Defaults to `true`
+    /// if there are any probes.
+    pub blackbox_enabled: Option<bool>,
+
+    /// Startup node_exporter (or windows_exporter on Windows) to scrape the
+    /// CPU, memory, disk and network metrics of this machine.
+    pub host_metrics: Option<bool>,
+
+    /// The URLs of services without metrics of their own that are probed by
+    /// the blackbox_exporter, like `https://example.com` or `tcp://db:5432`.
+    pub probes: Option<Vec<String>>,
+
     /// The default scrape interval for all Prometheus endpoints.
     #[serde(default, with = "humantime_serde::option")]
     pub prometheus_scrape_interval: Option<Duration>,
+
+    /// Whether Prometheus uses the timestamps that the endpoints expose, for
+    /// the endpoints that don't specify this themselves. Defaults to `true`.
+    pub honor_timestamps: Option<bool>,
+
+    /// The formats in which Prometheus asks the endpoints for their metrics,
+    /// in order of preference. Requires Prometheus 2.49 or newer.
+    pub scrape_protocols: Option<Vec<ScrapeProtocol>>,
+
+    /// Let Prometheus ingest native histograms, which are scraped in the
+    /// protobuf format.
+    pub native_histograms: Option<bool>,
+
+    /// How long to wait for the endpoints to respond before Prometheus is
+    /// started, ie. while the application is still compiling.
+    #[serde(default, with = "humantime_serde::option")]
+    pub wait_for_endpoints: Option<Duration>,
+
+    /// Look for metrics endpoints on the ports that are listening locally and
+    /// scrape them as well.
+    pub auto_discover: Option<bool>,
+
+    /// Discover scrape targets through the Kubernetes API.
+    pub kubernetes: Option<KubernetesConfig>,
+
+    /// Discover scrape targets through the Docker daemon.
+    pub docker: Option<DockerConfig>,
+
+    /// Receive metrics in the Graphite plaintext protocol.
+    pub graphite: Option<GraphiteConfig>,
+
+    /// Forward all scraped metrics to a remote Prometheus compatible backend.
+    pub remote_write: Option<RemoteWriteConfig>,
+
+    /// Labels that Prometheus adds to everything that leaves it, like the
+    /// remote-written series, ie. `{ env = "dev" }`.
+    pub external_labels: Option<BTreeMap<String, String>>,
+
+    /// When to restart Prometheus, Pushgateway, etc. after they exit.
+    pub restart_policy: Option<RestartPolicy>,
+
+    /// How many times a process will be restarted before am gives up.
+    pub max_restarts: Option<u32>,
+
+    /// Show desktop notifications when a process or scrape target goes down.
+    pub notifications: Option<bool>,
+
+    /// A file to which every request to the web server of am is logged.
+    pub access_log: Option<PathBuf>,
+
+    /// The origins from which browsers may call the web server of am, like
+    /// `http://localhost:3000`, or `*` for any origin.
+    pub cors_allow_origins: Option<Vec<String>>,
+
+    /// Credentials that are required for every request to the web server of
+    /// am, in the `token:<secret>` or `basic:<user>:<pass>` format.
+    pub web_auth: Option<WebAuth>,
+
+    /// Serve the web server of am over HTTPS, with a generated self-signed
+    /// certificate unless `tls-cert` and `tls-key` are set.
+    pub tls: Option<bool>,
+
+    /// The PEM encoded certificate with which the web server of am serves
+    /// HTTPS.
+    pub tls_cert: Option<PathBuf>,
+
+    /// The PEM encoded private key of `tls-cert`.
+    pub tls_key: Option<PathBuf>,
+
+    /// Serve the assets of the explorer that were downloaded with
+    /// `am system download-explorer`, instead of loading them from the CDN.
+    pub explorer_offline: Option<bool>,
+
+    /// Serve the explorer from this URL or directory with a build of the
+    /// explorer, instead of the version that is part of am.
+    pub explorer_url: Option<String>,
+
+    /// Announce the web server of am through mDNS, so it can be discovered on
+    /// the local network.
+    pub mdns: Option<bool>,
+
+    /// Settings of `am proxy`, see [`ProxyConfig`].
+    pub proxy: Option<ProxyConfig>,
+
+    /// The Prometheus version to use.
+    pub prometheus_version: Option<String>,
+
+    /// The pushgateway version to use.
+    pub pushgateway_version: Option<String>,
+
+    /// The autometrics gateway version to use.
+    pub gateway_version: Option<String>,
+
+    /// The alertmanager version to use.
+    pub alertmanager_version: Option<String>,
+
+    /// The OpenTelemetry Collector (contrib) version to use.
+    pub otel_collector_version: Option<String>,
+
+    /// The Tempo version to use.
+    pub tempo_version: Option<String>,
+
+    /// The Loki (and Promtail) version to use.
+    pub loki_version: Option<String>,
+
+    /// The blackbox_exporter version to use.
+    pub blackbox_version: Option<String>,
+
+    /// The node_exporter (or windows_exporter on Windows) version to use.
+    pub host_metrics_version: Option<String>,
+
+    /// Service Level Objectives for which Prometheus rules will be generated.
+    #[serde(rename = "slo")]
+    pub slos: Option<Vec<SloDefinition>>,
+
+    /// A YAML file containing additional Service Level Objectives.
+    pub slo_file: Option<PathBuf>,
+
+    /// Prometheus rule files that are loaded next to the autometrics rules.
+    pub rule_files: Option<Vec<PathBuf>>,
+
+    /// The directory in which Prometheus stores its data. Relative paths are
+    /// resolved against the directory in which `am start` is run.
+    pub prometheus_data_dir: Option<PathBuf>,
+
+    /// How long Prometheus keeps its data, ie. `15d`.
+    pub prometheus_retention_time: Option<String>,
+
+    /// The maximum amount of disk space Prometheus uses for its data, ie.
+    /// `10GB`.
+    pub prometheus_retention_size: Option<String>,
+
+    /// Extra flags passed to Prometheus, in the `KEY=VALUE` format.
+    pub prometheus_args: Option<Vec<String>>,
+
+    /// Experimental Prometheus features, like `exemplar-storage`, which are
+    /// enabled with `--enable-feature`.
+    pub prometheus_features: Option<Vec<String>>,
+
+    /// Record the autometrics metrics with fewer labels at 1m and 5m
+    /// resolutions, which speeds up queries over long running sessions.
+    pub downsampling: Option<bool>,
+
+    /// Run Prometheus in agent mode, which only forwards the scraped metrics
+    /// to the `remote-write` backend without storing them for querying.
+    pub agent: Option<bool>,
+
+    /// Enable the admin API of Prometheus, which `am system snapshot` needs.
+    pub enable_admin_api: Option<bool>,
+
+    /// A YAML file that is merged into the generated Prometheus config, ie. to
+    /// add scrape configs or `remote_read` that am doesn't support itself.
+    pub prometheus_config_extend: Option<PathBuf>,
+
+    /// The port on which Prometheus listens, `0` picks a free port.
+    pub prometheus_port: Option<u16>,
+
+    /// The port on which the pushgateway listens, `0` picks a free port.
+    pub pushgateway_port: Option<u16>,
+
+    /// The port on which alertmanager listens, `0` picks a free port.
+    pub alertmanager_port: Option<u16>,
+
+    /// The port on which the autometrics gateway listens, `0` picks a free
+    /// port.
+    pub gateway_port: Option<u16>,
+
+    /// The port on which Tempo serves its API, `0` picks a free port.
+    pub tempo_port: Option<u16>,
+
+    /// The port on which Loki serves its API, `0` picks a free port.
+    pub loki_port: Option<u16>,
+
+    /// Pass the endpoints to Prometheus through file-based service discovery,
+    /// so they can change without reloading Prometheus.
+    pub file_sd: Option<bool>,
+
+    /// The application that `am start` runs and scrapes, see [`AppConfig`].
+    pub app: Option<AppConfig>,
+
+    /// The root of the codebase in which the explorer looks for functions,
+    /// instead of the working directory of am.
+    pub project_root: Option<PathBuf>,
+
+    /// How am itself connects to the internet, for example to download
+    /// Prometheus. This can't be overridden by a profile.
+    pub network: Option<NetworkConfig>,
+
+    /// Download Prometheus, Pushgateway, etc. from this mirror of the GitHub
+    /// releases instead of github.com. See `--download-mirror`.
+    pub download_mirror: Option<Url>,
+
+    /// Refuse to use downloaded programs of which the signature can't be
+    /// verified. See `--require-signature`.
+    pub require_signature: Option<bool>,
+
+    /// Whether am checks once a day if a newer version is available, and
+    /// hints to run `am update` if so. Defaults to `true`.
+    pub update_check: Option<bool>,
+
+    /// Named profiles (ie. `[profile.staging]`), which override the settings
+    /// above when selected with `am start --profile`.
+    #[serde(rename = "profile")]
+    pub profiles: Option<BTreeMap<String, AmConfig>>,
+}
+
+impl AmConfig {
+    /// Apply the settings of `profile` on top of the top-level settings. All
+    /// settings that are specified in the profile replace the top-level ones.
+    pub fn with_profile(mut self, profile: Option<&str>) -> Result<Self> {
+        let Some(name) = profile else {
+            return Ok(self);
+        };
+
+        let mut profiles = self.profiles.take().unwrap_or_default();
+        let Some(mut profile) = profiles.remove(name) else {
+            let available = profiles.keys().cloned().collect::<Vec<_>>();
+            if available.is_empty() {
+                bail!("profile `{name}` not found, no profiles are defined in the config file");
+            }
+            bail!(
+                "profile `{name}` not found, available profiles: {}",
+                available.join(", ")
+            );
+        };
+
+        // These settings apply to am itself, not to a single environment.
+        profile.network = None;
+        profile.download_mirror = None;
+        profile.require_signature = None;
+        profile.update_check = None;
+        profile.profiles = None;
+
+        Ok(profile.merge(self))
+    }
+
+    /// Layer this config on top of `defaults`, like the project am.toml on top
+    /// of the user config. Settings that are specified in this config replace
+    /// the ones in `defaults`, profiles with the same name replace each other
+    /// as a whole.
+    pub fn merge(self, defaults: AmConfig) -> Self {
+        let profiles = match (self.profiles, defaults.profiles) {
+            (Some(profiles), Some(mut default_profiles)) => {
+                default_profiles.extend(profiles);
+                Some(default_profiles)
+            }
+            (profiles, default_profiles) => profiles.or(default_profiles),
+        };
+
+        AmConfig {
+            endpoints: self.endpoints.or(defaults.endpoints),
+            federate: self.federate.or(defaults.federate),
+            pushgateway_enabled: self.pushgateway_enabled.or(defaults.pushgateway_enabled),
+            gateway_enabled: self.gateway_enabled.or(defaults.gateway_enabled),
+            statsd_enabled: self.statsd_enabled.or(defaults.statsd_enabled),
+            statsd_port: self.statsd_port.or(defaults.statsd_port),
+            alertmanager_enabled: self.alertmanager_enabled.or(defaults.alertmanager_enabled),
+            otel_collector_enabled: self
+                .otel_collector_enabled
+                .or(defaults.otel_collector_enabled),
+            traces_enabled: self.traces_enabled.or(defaults.traces_enabled),
+            loki_enabled: self.loki_enabled.or(defaults.loki_enabled),
+            loki_log_files: self.loki_log_files.or(defaults.loki_log_files),
+            blackbox_enabled: self.blackbox_enabled.or(defaults.blackbox_enabled),
+            host_metrics: self.host_metrics.or(defaults.host_metrics),
+            probes: self.probes.or(defaults.probes),
+            prometheus_scrape_interval: self
+                .prometheus_scrape_interval
+                .or(defaults.prometheus_scrape_interval),
+            honor_timestamps: self.honor_timestamps.or(defaults.honor_timestamps),
+            scrape_protocols: self.scrape_protocols.or(defaults.scrape_protocols),
+            native_histograms: self.native_histograms.or(defaults.native_histograms),
+            wait_for_endpoints: self.wait_for_endpoints.or(defaults.wait_for_endpoints),
+            auto_discover: self.auto_discover.or(defaults.auto_discover),
+            kubernetes: self.kubernetes.or(defaults.kubernetes),
+            docker: self.docker.or(defaults.docker),
+            graphite: self.graphite.or(defaults.graphite),
+            remote_write: self.remote_write.or(defaults.remote_write),
+            external_labels: self.external_labels.or(defaults.external_labels),
+            restart_policy: self.restart_policy.or(defaults.restart_policy),
+            max_restarts: self.max_restarts.or(defaults.max_restarts),
+            notifications: self.notifications.or(defaults.notifications),
+            access_log: self.access_log.or(defaults.access_log),
+            cors_allow_origins: self.cors_allow_origins.or(defaults.cors_allow_origins),
+            web_auth: self.web_auth.or(defaults.web_auth),
+            tls: self.tls.or(defaults.tls),
+            tls_cert: self.tls_cert.or(defaults.tls_cert),
+            tls_key: self.tls_key.or(defaults.tls_key),
+            explorer_offline: self.explorer_offline.or(defaults.explorer_offline),
+            explorer_url: self.explorer_url.or(defaults.explorer_url),
+            mdns: self.mdns.or(defaults.mdns),
+            proxy: self.proxy.or(defaults.proxy),
+            prometheus_version: self.prometheus_version.or(defaults.prometheus_version),
+            pushgateway_version: self.pushgateway_version.or(defaults.pushgateway_version),
+            gateway_version: self.gateway_version.or(defaults.gateway_version),
+            alertmanager_version: self.alertmanager_version.or(defaults.alertmanager_version),
+            otel_collector_version: self
+                .otel_collector_version
+                .or(defaults.otel_collector_version),
+            tempo_version: self.tempo_version.or(defaults.tempo_version),
+            loki_version: self.loki_version.or(defaults.loki_version),
+            blackbox_version: self.blackbox_version.or(defaults.blackbox_version),
+            host_metrics_version: self.host_metrics_version.or(defaults.host_metrics_version),
+            slos: self.slos.or(defaults.slos),
+            slo_file: self.slo_file.or(defaults.slo_file),
+            rule_files: self.rule_files.or(defaults.rule_files),
+            prometheus_data_dir: self.prometheus_data_dir.or(defaults.prometheus_data_dir),
+            prometheus_retention_time: self
+                .prometheus_retention_time
+                .or(defaults.prometheus_retention_time),
+            prometheus_retention_size: self
+                .prometheus_retention_size
+                .or(defaults.prometheus_retention_size),
+            prometheus_args: self.prometheus_args.or(defaults.prometheus_args),
+            prometheus_features: self.prometheus_features.or(defaults.prometheus_features),
+            downsampling: self.downsampling.or(defaults.downsampling),
+            agent: self.agent.or(defaults.agent),
+            enable_admin_api: self.enable_admin_api.or(defaults.enable_admin_api),
+            prometheus_config_extend: self
+                .prometheus_config_extend
+                .or(defaults.prometheus_config_extend),
+            prometheus_port: self.prometheus_port.or(defaults.prometheus_port),
+            pushgateway_port: self.pushgateway_port.or(defaults.pushgateway_port),
+            alertmanager_port: self.alertmanager_port.or(defaults.alertmanager_port),
+            gateway_port: self.gateway_port.or(defaults.gateway_port),
+            tempo_port: self.tempo_port.or(defaults.tempo_port),
+            loki_port: self.loki_port.or(defaults.loki_port),
+            file_sd: self.file_sd.or(defaults.file_sd),
+            app: self.app.or(defaults.app),
+            project_root: self.project_root.or(defaults.project_root),
+            network: self.network.or(defaults.network),
+            download_mirror: self.download_mirror.or(defaults.download_mirror),
+            require_signature: self.require_signature.or(defaults.require_signature),
+            update_check: self.update_check.or(defaults.update_check),
+            profiles,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Always restart the process, even if it exited successfully.
+    Always,
+
+    /// Only restart the process if it exited with an error.
+    #[default]
+    OnFailure,
+
+    /// Never restart the process.
+    Never,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct RemoteWriteConfig {
+    /// The URL of the remote write endpoint, e.g.
+    /// `https://prometheus-prod-01-eu-west-0.grafana.net/api/prom/push`.
+    pub url: Url,
+
+    /// Send this token as a `Authorization: Bearer` header.
+    pub bearer_token: Option<String>,
+
+    /// Use these credentials (`user:pass`) for HTTP basic authentication.
+    pub basic_auth: Option<BasicAuth>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct NetworkConfig {
+    /// Send all requests to other machines through this proxy, instead of the
+    /// one in the `HTTPS_PROXY`/`HTTP_PROXY` environment variables.
+    pub proxy: Option<Url>,
+
+    /// Hosts (and their subdomains) that are reached without the proxy,
+    /// instead of the ones in the `NO_PROXY` environment variable.
+    pub no_proxy: Option<Vec<String>>,
+
+    /// A PEM file with additional CA certificates to trust, for example the
+    /// one of a proxy that intercepts TLS traffic.
+    pub ca_bundle: Option<PathBuf>,
+}
+
+/// How `am proxy` forwards the requests to the upstream Prometheus.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProxyConfig {
+    /// Headers that are sent with every request to the upstream, like a
+    /// custom authentication header of a gateway.
+    pub headers: Option<BTreeMap<String, String>>,
+
+    /// The tenant that is sent as the `X-Scope-OrgID` header, which Cortex,
+    /// Mimir and Loki use for multi-tenancy.
+    pub tenant_id: Option<String>,
+
+    /// The kind of backend of the upstream. Detected if not specified.
+    pub flavor: Option<UpstreamFlavor>,
+}
+
+/// The kinds of Prometheus compatible backends that `am proxy` forwards to,
+/// which differ in where they serve the API and which endpoints they
+/// implement.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamFlavor {
+    Prometheus,
+    #[value(name = "victoriametrics")]
+    VictoriaMetrics,
+    Mimir,
+    Thanos,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct KubernetesConfig {
+    /// Enable the Kubernetes service discovery.
+    pub enabled: Option<bool>,
+
+    /// The namespaces in which pods will be discovered. Defaults to all
+    /// namespaces.
+    pub namespaces: Option<Vec<String>>,
+
+    /// Only discover pods matching this label selector, e.g. `app=my-app`.
+    pub label_selector: Option<String>,
+
+    /// The kubeconfig file that Prometheus uses to connect to the cluster.
+    pub kubeconfig: Option<PathBuf>,
+
+    /// Ports of pods or services that are forwarded with `kubectl
+    /// port-forward` and scraped, like `pod/api:9464`.
+    pub port_forwards: Option<Vec<String>>,
+}
+
+/// The application that is being developed, which `am start` runs as a
+/// supervised process. It gets scraped once its metrics endpoint responds, and
+/// is stopped together with Prometheus.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppConfig {
+    /// The command that starts the application (ie. `cargo run`), which is run
+    /// by the shell.
+    pub command: Option<String>,
+
+    /// The metrics endpoint of the application, in the same formats as the
+    /// endpoints (ie. `:3000`).
+    #[serde(default, deserialize_with = "parse_maybe_shorthand_option")]
+    pub endpoint: Option<Url>,
+
+    /// The name of the job that scrapes the application. Defaults to `app`.
+    pub job_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct DockerConfig {
+    /// Enable the Docker container discovery.
+    pub enabled: Option<bool>,
+
+    /// The address of the Docker daemon, e.g. `unix:///var/run/docker.sock`.
+    pub host: Option<String>,
+
+    /// Containers exposing one of these ports will be scraped, even if they
+    /// don't have the `autometrics.dev/scrape` label.
+    pub metrics_ports: Option<Vec<u16>>,
+
+    /// How often the list of containers is refreshed.
+    #[serde(default, with = "humantime_serde::option")]
+    pub refresh_interval: Option<Duration>,
+
+    /// Scrape the resource usage of the containers, either from the metrics
+    /// endpoint of the Docker daemon or through cAdvisor.
+    pub container_metrics: Option<ContainerMetrics>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct GraphiteConfig {
+    /// Listen for Graphite metrics.
+    pub enabled: Option<bool>,
+
+    /// The TCP port on which the Graphite metrics are received.
+    pub port: Option<u16>,
+
+    /// The rules that turn the dotted Graphite paths into Prometheus metrics
+    /// with labels. The first rule that matches is used.
+    #[serde(default)]
+    pub mappings: Vec<GraphiteMapping>,
+}
+
+/// Maps a Graphite path like `servers.web-1.cpu.user` onto a metric, like
+/// `server_cpu{host="web-1", mode="user"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct GraphiteMapping {
+    /// The dotted path to match, in which `*` matches a single component,
+    /// like `servers.*.cpu.*`.
+    #[serde(rename = "match")]
+    pub pattern: String,
+
+    /// The name of the metric. The components matched by the wildcards can be
+    /// used as `$1`, `$2`, etc.
+    pub name: String,
+
+    /// The labels of the metric, which can use the matched components as
+    /// well.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Where the resource usage metrics of the Docker containers come from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContainerMetrics {
+    /// The metrics endpoint of the Docker daemon, which needs to be enabled
+    /// with `metrics-addr` in its `daemon.json`.
+    Docker,
+
+    /// cAdvisor, which am runs as a container.
+    Cadvisor,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -37,47 +602,238 @@ pub struct Endpoint {
 
     pub honor_labels: Option<bool>,
 
+    /// Whether Prometheus uses the timestamps that this endpoint exposes,
+    /// instead of the time of the scrape.
+    pub honor_timestamps: Option<bool>,
+
+    /// The formats in which Prometheus asks this endpoint for its metrics, in
+    /// order of preference. Requires Prometheus 2.49 or newer.
+    pub scrape_protocols: Option<Vec<ScrapeProtocol>>,
+
     /// The scrape interval for this endpoint.
     #[serde(default, with = "humantime_serde::option")]
     pub prometheus_scrape_interval: Option<Duration>,
+
+    /// How long a scrape of this endpoint may take before it is considered
+    /// failed. This can't be longer than the scrape interval.
+    #[serde(default, with = "humantime_serde::option")]
+    pub scrape_timeout: Option<Duration>,
+
+    /// Fail the scrape if the endpoint returns more samples than this, so a
+    /// misbehaving target can't flood Prometheus.
+    pub sample_limit: Option<u64>,
+
+    /// Fail the scrape if any of the samples has more labels than this.
+    pub label_limit: Option<u64>,
+
+    /// Labels that are added to all series of this endpoint, ie.
+    /// `{ team = "billing" }`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+
+    /// Send this token as a `Authorization: Bearer` header when scraping.
+    pub bearer_token: Option<String>,
+
+    /// Use these credentials (`user:pass`) for HTTP basic authentication when
+    /// scraping. Credentials can also be passed as part of the URL.
+    pub basic_auth: Option<BasicAuth>,
+
+    /// Disable validation of the server certificate.
+    pub tls_skip_verify: Option<bool>,
+
+    /// The CA certificate used to validate the server certificate.
+    pub ca_cert: Option<PathBuf>,
+
+    /// Rules that are applied to the scraped samples before they are stored,
+    /// in the order in which they are defined.
+    pub metric_relabel_configs: Option<Vec<MetricRelabelConfig>>,
+}
+
+impl Endpoint {
+    /// Create a endpoint for `url`, with all other settings left to their
+    /// defaults.
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            job_name: None,
+            honor_labels: None,
+            honor_timestamps: None,
+            scrape_protocols: None,
+            prometheus_scrape_interval: None,
+            scrape_timeout: None,
+            sample_limit: None,
+            label_limit: None,
+            labels: BTreeMap::new(),
+            bearer_token: None,
+            basic_auth: None,
+            tls_skip_verify: None,
+            ca_cert: None,
+            metric_relabel_configs: None,
+        }
+    }
 }
 
-fn parse_maybe_shorthand<'de, D: Deserializer<'de>>(input: D) -> Result<Url, D::Error> {
+/// A Prometheus server from which the series matching the `match` selectors
+/// are scraped through its `/federate` endpoint, ie. to explore production
+/// metrics locally. The labels of the series are kept as they are.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct FederateConfig {
+    /// The URL of the Prometheus server, including its route prefix if it has
+    /// one. `/federate` is appended to it.
+    #[serde(deserialize_with = "parse_federate_url")]
+    pub url: Url,
+
+    /// The job name of the federation job. Defaults to `federate_<n>`.
+    pub job_name: Option<String>,
+
+    /// The series selectors of the series that are pulled in, ie.
+    /// `{job="api"}`. At least one is required.
+    #[serde(rename = "match")]
+    pub matches: Vec<String>,
+
+    /// The scrape interval for this server.
+    #[serde(default, with = "humantime_serde::option")]
+    pub prometheus_scrape_interval: Option<Duration>,
+
+    /// Send this token as a `Authorization: Bearer` header when scraping.
+    pub bearer_token: Option<String>,
+
+    /// Use these credentials (`user:pass`) for HTTP basic authentication when
+    /// scraping.
+    pub basic_auth: Option<BasicAuth>,
+
+    /// Disable validation of the server certificate.
+    pub tls_skip_verify: Option<bool>,
+
+    /// The CA certificate used to validate the server certificate.
+    pub ca_cert: Option<PathBuf>,
+}
+
+/// A rule to filter or change the samples of an endpoint, ie.
+/// `{ action = "drop", regex = "go_.*" }`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum MetricRelabelConfig {
+    /// Only keep the samples of which the metric name matches `regex`. Other
+    /// labels can be matched by setting `source-labels`, their values are
+    /// joined with a `;`.
+    Keep {
+        #[serde(default, rename = "source-labels")]
+        source_labels: Vec<String>,
+        regex: String,
+    },
+
+    /// Drop the samples of which the metric name (or `source-labels`) matches
+    /// `regex`.
+    Drop {
+        #[serde(default, rename = "source-labels")]
+        source_labels: Vec<String>,
+        regex: String,
+    },
+
+    /// Rename the label `from` to `to`.
+    RenameLabel { from: String, to: String },
+
+    /// Remove the labels of which the name matches `regex`.
+    LabelDrop { regex: String },
+}
+
+/// Credentials for HTTP basic authentication. This is (de)serialized in the
+/// `user:pass` format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: String,
+}
+
+impl Serialize for BasicAuth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{}:{}", self.username, self.password))
+    }
+}
+
+impl<'de> Deserialize<'de> for BasicAuth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let input: String = Deserialize::deserialize(deserializer)?;
+        basic_auth_parser(&input).map_err(Error::custom)
+    }
+}
+
+/// Credentials that requests to the web server of am need to present. This is
+/// (de)serialized in the `token:<secret>` or `basic:<user>:<pass>` format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebAuth {
+    BearerToken(String),
+    Basic(BasicAuth),
+}
+
+impl Serialize for WebAuth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            WebAuth::BearerToken(token) => serializer.serialize_str(&format!("token:{token}")),
+            WebAuth::Basic(basic_auth) => serializer.serialize_str(&format!(
+                "basic:{}:{}",
+                basic_auth.username, basic_auth.password
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for WebAuth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let input: String = Deserialize::deserialize(deserializer)?;
+        web_auth_parser(&input).map_err(Error::custom)
+    }
+}
+
+fn parse_maybe_shorthand<'de, D: Deserializer<'de>>(
+    input: D,
+) -> std::result::Result<Url, D::Error> {
     let input_str: String = Deserialize::deserialize(input)?;
     endpoint_parser(&input_str).map_err(Error::custom)
 }
 
+fn parse_federate_url<'de, D: Deserializer<'de>>(input: D) -> std::result::Result<Url, D::Error> {
+    let input_str: String = Deserialize::deserialize(input)?;
+    federate_url_parser(&input_str).map_err(Error::custom)
+}
+
+fn parse_maybe_shorthand_option<'de, D: Deserializer<'de>>(
+    input: D,
+) -> std::result::Result<Option<Url>, D::Error> {
+    let input_str: Option<String> = Deserialize::deserialize(input)?;
+    input_str
+        .map(|input_str| endpoint_parser(&input_str).map_err(Error::custom))
+        .transpose()
+}
+
 /// If the user specified an endpoint using args, then use those.
 /// Otherwise, use the endpoint configured in the config file. And
 /// fallback to an empty list if neither are configured.
+///
+/// Endpoints without a job name are named after their position, so they keep
+/// the same name when the config is reloaded.
 pub fn endpoints_from_first_input(args: Vec<Url>, config: Option<Vec<Endpoint>>) -> Vec<Endpoint> {
-    static COUNTER: AtomicUsize = AtomicUsize::new(0);
-
     if !args.is_empty() {
         args.into_iter()
-            .map(|url| {
-                let num = COUNTER.fetch_add(1, Ordering::SeqCst);
-                Endpoint {
-                    url,
-                    job_name: Some(format!("am_{num}")),
-                    honor_labels: Some(false),
-                    prometheus_scrape_interval: None,
-                }
+            .enumerate()
+            .map(|(num, url)| Endpoint {
+                job_name: Some(format!("am_{num}")),
+                honor_labels: Some(false),
+                ..Endpoint::new(url)
             })
             .collect()
     } else if let Some(endpoints) = config {
         endpoints
             .into_iter()
-            .map(|endpoint| {
-                let job_name = endpoint.job_name.unwrap_or_else(|| {
-                    format!("am_{num}", num = COUNTER.fetch_add(1, Ordering::SeqCst))
-                });
+            .enumerate()
+            .map(|(num, endpoint)| {
+                let job_name = endpoint.job_name.unwrap_or_else(|| format!("am_{num}"));
 
                 Endpoint {
-                    url: endpoint.url,
                     job_name: Some(job_name),
-                    honor_labels: endpoint.honor_labels,
-                    prometheus_scrape_interval: endpoint.prometheus_scrape_interval,
+                    ..endpoint
                 }
             })
             .collect()
@@ -85,3 +841,141 @@ pub fn endpoints_from_first_input(args: Vec<Url>, config: Option<Vec<Endpoint>>)
         Vec::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_overrides_top_level_settings() {
+        let config: AmConfig = toml::from_str(
+            r#"
+            pushgateway-enabled = true
+            prometheus-version = "v2.45.0"
+
+            [[endpoint]]
+            url = ":3000"
+
+            [profile.staging]
+            prometheus-version = "v2.47.2"
+
+            [[profile.staging.endpoint]]
+            url = "https://staging.example.com"
+            "#,
+        )
+        .unwrap();
+
+        let config = config.with_profile(Some("staging")).unwrap();
+
+        assert_eq!(config.pushgateway_enabled, Some(true));
+        assert_eq!(config.prometheus_version.as_deref(), Some("v2.47.2"));
+
+        let endpoints = config.endpoints.unwrap();
+        assert_eq!(endpoints.len(), 1);
+        assert_eq!(
+            endpoints[0].url.as_str(),
+            "https://staging.example.com/metrics"
+        );
+    }
+
+    #[test]
+    fn metric_relabel_configs() {
+        let config: AmConfig = toml::from_str(
+            r#"
+            [[endpoint]]
+            url = ":3000"
+            metric-relabel-configs = [
+                { action = "drop", regex = "go_.*" },
+                { action = "keep", source-labels = ["job"], regex = "api" },
+                { action = "rename-label", from = "pod", to = "instance" },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.endpoints.unwrap()[0].metric_relabel_configs,
+            Some(vec![
+                MetricRelabelConfig::Drop {
+                    source_labels: vec![],
+                    regex: "go_.*".to_string()
+                },
+                MetricRelabelConfig::Keep {
+                    source_labels: vec!["job".to_string()],
+                    regex: "api".to_string()
+                },
+                MetricRelabelConfig::RenameLabel {
+                    from: "pod".to_string(),
+                    to: "instance".to_string()
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn federate_url() {
+        let config: AmConfig = toml::from_str(
+            r#"
+            [[federate]]
+            url = "https://prometheus.example.com"
+            match = ['{job="api"}']
+
+            [[federate]]
+            url = ":9090/prometheus/"
+            match = ['up']
+            "#,
+        )
+        .unwrap();
+
+        let federate = config.federate.unwrap();
+        assert_eq!(
+            federate[0].url.as_str(),
+            "https://prometheus.example.com/federate"
+        );
+        assert_eq!(federate[0].matches, vec![r#"{job="api"}"#]);
+        assert_eq!(
+            federate[1].url.as_str(),
+            "http://localhost:9090/prometheus/federate"
+        );
+    }
+
+    #[test]
+    fn project_config_overrides_user_config() {
+        let user: AmConfig = toml::from_str(
+            r#"
+            pushgateway-enabled = true
+            prometheus-version = "v2.45.0"
+            download-mirror = "https://mirror.example.com/github"
+
+            [profile.staging]
+            prometheus-version = "v2.47.2"
+            "#,
+        )
+        .unwrap();
+        let project: AmConfig = toml::from_str(
+            r#"
+            prometheus-version = "v2.48.0"
+
+            [[endpoint]]
+            url = ":3000"
+            "#,
+        )
+        .unwrap();
+
+        let config = project.merge(user);
+
+        assert_eq!(config.pushgateway_enabled, Some(true));
+        assert_eq!(config.prometheus_version.as_deref(), Some("v2.48.0"));
+        assert_eq!(
+            config.download_mirror.unwrap().as_str(),
+            "https://mirror.example.com/github"
+        );
+        assert_eq!(config.endpoints.unwrap().len(), 1);
+        assert!(config.profiles.unwrap().contains_key("staging"));
+    }
+
+    #[test]
+    fn unknown_profile() {
+        assert!(AmConfig::default().with_profile(Some("staging")).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! Recording rules that aggregate the autometrics metrics into series with
+//! fewer labels at a lower resolution, so queries over the hours of data of a
+//! long running session don't have to go through all the raw series.
+
+use crate::prometheus::{Rule, RuleFile, RuleGroup};
+use std::time::Duration;
+
+/// The resolutions at which the metrics are recorded, as the interval at
+/// which the rules are evaluated.
+pub const RESOLUTIONS: [(&str, Duration); 2] = [
+    ("1m", Duration::from_secs(60)),
+    ("5m", Duration::from_secs(5 * 60)),
+];
+
+/// The labels that are kept, all others (like `instance` and `job`) are
+/// aggregated away.
+const LABELS: &str = "function, module, service_name";
+
+/// The metrics that are recorded, with the extra labels that are kept for
+/// them.
+const METRICS: [(&str, &str); 3] = [
+    (
+        "function_calls_total",
+        "result, caller_function, caller_module",
+    ),
+    ("function_calls_duration_seconds_bucket", "le"),
+    ("function_calls_duration_seconds_count", ""),
+];
+
+fn rule_group(resolution: &str, interval: Duration) -> RuleGroup {
+    let rules = METRICS
+        .iter()
+        .map(|(metric, extra_labels)| {
+            let labels = if extra_labels.is_empty() {
+                LABELS.to_string()
+            } else {
+                format!("{LABELS}, {extra_labels}")
+            };
+            let level = labels.replace(", ", "_");
+
+            Rule {
+                record: Some(format!("{level}:{metric}:rate{resolution}")),
+                expr: format!("sum by ({labels}) (rate({metric}[{resolution}]))"),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    RuleGroup {
+        name: format!("autometrics-downsampling-{resolution}"),
+        interval: Some(interval),
+        rules,
+    }
+}
+
+/// Generate the recording rules for all [`RESOLUTIONS`].
+pub fn generate_rules() -> RuleFile {
+    RuleFile {
+        groups: RESOLUTIONS
+            .iter()
+            .map(|(resolution, interval)| rule_group(resolution, *interval))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_per_resolution() {
+        let rules = generate_rules();
+
+        assert_eq!(rules.groups.len(), RESOLUTIONS.len());
+        let group = &rules.groups[0];
+        assert_eq!(group.interval, Some(Duration::from_secs(60)));
+        assert_eq!(
+            group.rules[1].record.as_deref(),
+            Some("function_module_service_name_le:function_calls_duration_seconds_bucket:rate1m")
+        );
+        assert_eq!(
+            group.rules[1].expr,
+            "sum by (function, module, service_name, le) (rate(function_calls_duration_seconds_bucket[1m]))"
+        );
+    }
+}
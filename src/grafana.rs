@@ -0,0 +1,51 @@
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Grafana datasource provisioning file.
+///
+/// See: https://grafana.com/docs/grafana/latest/administration/provisioning/#data-sources
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasourcesConfig {
+    pub api_version: u8,
+    pub datasources: Vec<Datasource>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Datasource {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub uid: String,
+    pub access: String,
+    pub url: String,
+    pub is_default: bool,
+    pub editable: bool,
+}
+
+/// Grafana dashboard provider provisioning file.
+///
+/// See: https://grafana.com/docs/grafana/latest/administration/provisioning/#dashboards
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DashboardsConfig {
+    pub api_version: u8,
+    pub providers: Vec<DashboardProvider>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DashboardProvider {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub folder: String,
+    pub allow_ui_updates: bool,
+    pub options: DashboardProviderOptions,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashboardProviderOptions {
+    pub path: PathBuf,
+}
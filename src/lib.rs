@@ -1,3 +1,13 @@
+pub mod alertmanager;
+pub mod blackbox;
 pub mod config;
+pub mod downsampling;
+pub mod grafana;
+pub mod loki;
+pub mod otel_collector;
 pub mod parser;
 pub mod prometheus;
+pub mod promql;
+pub mod rules;
+pub mod slo;
+pub mod tempo;
@@ -0,0 +1,222 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A Loki configuration that runs all components in a single process and
+/// stores the logs on the local disk.
+///
+/// See: https://grafana.com/docs/loki/latest/configure/
+#[derive(Debug, Serialize)]
+pub struct Config {
+    pub auth_enabled: bool,
+    pub server: Server,
+    pub common: Common,
+    pub schema_config: SchemaConfig,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Server {
+    /// The address of the HTTP server. An empty address listens on all
+    /// interfaces.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub http_listen_address: String,
+    pub http_listen_port: u16,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub grpc_listen_address: String,
+    pub grpc_listen_port: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Common {
+    pub path_prefix: String,
+    pub storage: Storage,
+    pub replication_factor: u32,
+    pub ring: Ring,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Storage {
+    pub filesystem: Filesystem,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Filesystem {
+    pub chunks_directory: String,
+    pub rules_directory: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Ring {
+    pub instance_addr: String,
+    pub kvstore: KvStore,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KvStore {
+    pub store: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchemaConfig {
+    pub configs: Vec<PeriodConfig>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PeriodConfig {
+    pub from: String,
+    pub store: String,
+    pub object_store: String,
+    pub schema: String,
+    pub index: IndexConfig,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexConfig {
+    pub prefix: String,
+    pub period: String,
+}
+
+impl Config {
+    /// Create a config that serves the API on `listen_address` and
+    /// `http_port`, and stores the logs in `data_dir`.
+    pub fn new(listen_address: &str, http_port: u16, grpc_port: u16, data_dir: &str) -> Self {
+        Self {
+            auth_enabled: false,
+            server: Server {
+                http_listen_address: listen_address.to_string(),
+                http_listen_port: http_port,
+                grpc_listen_address: listen_address.to_string(),
+                grpc_listen_port: grpc_port,
+            },
+            common: Common {
+                path_prefix: data_dir.to_string(),
+                storage: Storage {
+                    filesystem: Filesystem {
+                        chunks_directory: format!("{data_dir}/chunks"),
+                        rules_directory: format!("{data_dir}/rules"),
+                    },
+                },
+                replication_factor: 1,
+                ring: Ring {
+                    instance_addr: "127.0.0.1".to_string(),
+                    kvstore: KvStore {
+                        store: "inmemory".to_string(),
+                    },
+                },
+            },
+            schema_config: SchemaConfig {
+                configs: vec![PeriodConfig {
+                    from: "2020-10-24".to_string(),
+                    store: "tsdb".to_string(),
+                    object_store: "filesystem".to_string(),
+                    schema: "v12".to_string(),
+                    index: IndexConfig {
+                        prefix: "index_".to_string(),
+                        period: "24h".to_string(),
+                    },
+                }],
+            },
+        }
+    }
+}
+
+/// A Promtail configuration that tails log files and pushes them to Loki.
+///
+/// See: https://grafana.com/docs/loki/latest/send-data/promtail/configuration/
+#[derive(Debug, Serialize)]
+pub struct PromtailConfig {
+    pub server: Server,
+    pub positions: Positions,
+    pub clients: Vec<Client>,
+    pub scrape_configs: Vec<PromtailScrapeConfig>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Positions {
+    /// Where Promtail remembers how far it has read the files.
+    pub filename: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Client {
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromtailScrapeConfig {
+    pub job_name: String,
+    pub static_configs: Vec<PromtailStaticConfig>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromtailStaticConfig {
+    pub targets: Vec<String>,
+    /// The `__path__` label selects the files, which can be a glob.
+    pub labels: BTreeMap<String, String>,
+}
+
+impl PromtailConfig {
+    /// Create a config that tails the `log_files` as job `job_name`, and
+    /// pushes them to the Loki on `loki_port`. Promtail's own server listens
+    /// on a random port.
+    pub fn new(job_name: &str, log_files: &[String], loki_port: u16) -> Self {
+        let static_configs = log_files
+            .iter()
+            .map(|path| PromtailStaticConfig {
+                targets: vec!["localhost".to_string()],
+                labels: BTreeMap::from([
+                    ("job".to_string(), job_name.to_string()),
+                    ("__path__".to_string(), path.clone()),
+                ]),
+            })
+            .collect();
+
+        Self {
+            server: Server {
+                http_listen_address: String::new(),
+                http_listen_port: 0,
+                grpc_listen_address: String::new(),
+                grpc_listen_port: 0,
+            },
+            positions: Positions {
+                filename: "positions.yaml".to_string(),
+            },
+            clients: vec![Client {
+                url: format!("http://localhost:{loki_port}/loki/api/v1/push"),
+            }],
+            scrape_configs: vec![PromtailScrapeConfig {
+                job_name: job_name.to_string(),
+                static_configs,
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_promtail_config() {
+        let config = PromtailConfig::new("am_logs", &["/var/log/app.log".to_string()], 3100);
+
+        assert_eq!(
+            serde_yaml::to_string(&config).unwrap(),
+            "server:
+  http_listen_port: 0
+  grpc_listen_port: 0
+positions:
+  filename: positions.yaml
+clients:
+- url: http://localhost:3100/loki/api/v1/push
+scrape_configs:
+- job_name: am_logs
+  static_configs:
+  - targets:
+    - localhost
+    labels:
+      __path__: /var/log/app.log
+      job: am_logs
+"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A OpenTelemetry Collector configuration that receives metrics through OTLP
+/// and exposes them through a Prometheus scrape endpoint.
+///
+/// See: https://opentelemetry.io/docs/collector/configuration/
+#[derive(Debug, Serialize)]
+pub struct Config {
+    pub receivers: Receivers,
+    pub processors: BTreeMap<String, Empty>,
+    pub exporters: Exporters,
+    pub service: Service,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Receivers {
+    pub otlp: OtlpReceiver,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OtlpReceiver {
+    pub protocols: OtlpProtocols,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OtlpProtocols {
+    pub grpc: Listener,
+    pub http: Listener,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Listener {
+    pub endpoint: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Exporters {
+    pub prometheus: PrometheusExporter,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otlp: Option<OtlpExporter>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrometheusExporter {
+    pub endpoint: String,
+}
+
+/// Sends the received data on to another OTLP receiver over gRPC.
+#[derive(Debug, Serialize)]
+pub struct OtlpExporter {
+    pub endpoint: String,
+    pub tls: ExporterTls,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExporterTls {
+    pub insecure: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Service {
+    pub pipelines: BTreeMap<String, Pipeline>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Pipeline {
+    pub receivers: Vec<String>,
+    pub processors: Vec<String>,
+    pub exporters: Vec<String>,
+}
+
+/// Serializes to a empty map, for components that don't need any settings.
+#[derive(Debug, Default, Serialize)]
+pub struct Empty {}
+
+impl Config {
+    /// Create a config that receives OTLP on the `grpc_endpoint` and
+    /// `http_endpoint`, and exposes the metrics on `prometheus_endpoint`.
+    pub fn new(grpc_endpoint: &str, http_endpoint: &str, prometheus_endpoint: &str) -> Self {
+        let metrics = Pipeline {
+            receivers: vec!["otlp".to_string()],
+            processors: vec!["batch".to_string()],
+            exporters: vec!["prometheus".to_string()],
+        };
+
+        Self {
+            receivers: Receivers {
+                otlp: OtlpReceiver {
+                    protocols: OtlpProtocols {
+                        grpc: Listener {
+                            endpoint: grpc_endpoint.to_string(),
+                        },
+                        http: Listener {
+                            endpoint: http_endpoint.to_string(),
+                        },
+                    },
+                },
+            },
+            processors: BTreeMap::from([("batch".to_string(), Empty::default())]),
+            exporters: Exporters {
+                prometheus: PrometheusExporter {
+                    endpoint: prometheus_endpoint.to_string(),
+                },
+                otlp: None,
+            },
+            service: Service {
+                pipelines: BTreeMap::from([("metrics".to_string(), metrics)]),
+            },
+        }
+    }
+
+    /// Forward the received traces to the OTLP receiver on `grpc_endpoint`,
+    /// like the one of Tempo.
+    pub fn with_traces_exporter(mut self, grpc_endpoint: &str) -> Self {
+        self.exporters.otlp = Some(OtlpExporter {
+            endpoint: grpc_endpoint.to_string(),
+            tls: ExporterTls { insecure: true },
+        });
+        self.service.pipelines.insert(
+            "traces".to_string(),
+            Pipeline {
+                receivers: vec!["otlp".to_string()],
+                processors: vec!["batch".to_string()],
+                exporters: vec!["otlp".to_string()],
+            },
+        );
+        self
+    }
+}
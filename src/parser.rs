@@ -1,3 +1,5 @@
+use crate::config::{BasicAuth, WebAuth};
+use crate::promql;
 use anyhow::{bail, Context, Result};
 use url::Url;
 
@@ -11,7 +13,47 @@ use url::Url;
 ///   for https.
 /// - The path should default to /metrics if the path is empty. It should not be
 ///   appended if a path is already there.
+/// - Endpoints on a unix domain socket use `unix:///path/to.sock`, optionally
+///   with the HTTP path as a query parameter: `unix:///path/to.sock?path=/metrics`.
 pub fn endpoint_parser(input: &str) -> Result<Url> {
+    if input.starts_with("unix://") {
+        let url =
+            Url::parse(input).with_context(|| format!("Unable to parse endpoint {}", input))?;
+        if url.path().is_empty() || url.path() == "/" || url.has_host() {
+            bail!("unix socket endpoints should be in the `unix:///path/to.sock` format");
+        }
+        return Ok(url);
+    }
+
+    let mut url = http_url_parser(input)?;
+
+    //  Note that this should never be Err(_) since we're always adding http://
+    // in front of the input and thus making sure it is not a "cannot-be-a-base"
+    // URL.
+    if url.path() == "" || url.path() == "/" {
+        url.set_path("/metrics");
+    }
+
+    Ok(url)
+}
+
+/// Parses the URL of a Prometheus server to federate from, in the same
+/// shorthand formats as [`endpoint_parser`]. `/federate` is appended to the
+/// path, which can be the route prefix of the server (ie.
+/// `https://example.com/prometheus`).
+pub fn federate_url_parser(input: &str) -> Result<Url> {
+    let mut url = http_url_parser(input)?;
+
+    let path = url.path().trim_end_matches('/').to_string();
+    if !path.ends_with("/federate") {
+        url.set_path(&format!("{path}/federate"));
+    }
+
+    Ok(url)
+}
+
+/// Parses an http(s) URL, where the scheme and host are optional.
+fn http_url_parser(input: &str) -> Result<Url> {
     let mut input = input.to_owned();
 
     if input.starts_with(':') {
@@ -25,15 +67,7 @@ pub fn endpoint_parser(input: &str) -> Result<Url> {
         input = format!("http://{}", input);
     }
 
-    let mut url =
-        Url::parse(&input).with_context(|| format!("Unable to parse endpoint {}", input))?;
-
-    //  Note that this should never be Err(_) since we're always adding http://
-    // in front of the input and thus making sure it is not a "cannot-be-a-base"
-    // URL.
-    if url.path() == "" || url.path() == "/" {
-        url.set_path("/metrics");
-    }
+    let url = Url::parse(&input).with_context(|| format!("Unable to parse endpoint {}", input))?;
 
     if url.scheme() != "http" && url.scheme() != "https" {
         bail!("unsupported protocol {}", url.scheme());
@@ -41,3 +75,113 @@ pub fn endpoint_parser(input: &str) -> Result<Url> {
 
     Ok(url)
 }
+
+/// Parses the input string, in the `user:pass` format, into basic auth
+/// credentials. The password is allowed to contain colons.
+pub fn basic_auth_parser(input: &str) -> Result<BasicAuth> {
+    let Some((username, password)) = input.split_once(':') else {
+        bail!("basic auth credentials should be in the `user:pass` format");
+    };
+
+    if username.is_empty() {
+        bail!("basic auth username cannot be empty");
+    }
+
+    Ok(BasicAuth {
+        username: username.to_string(),
+        password: password.to_string(),
+    })
+}
+
+/// Parses the credentials that protect the web server of am, in the
+/// `token:<secret>` format for a bearer token or the `basic:<user>:<pass>`
+/// format for HTTP basic authentication.
+pub fn web_auth_parser(input: &str) -> Result<WebAuth> {
+    match input.split_once(':') {
+        Some(("token", "")) => bail!("web auth token cannot be empty"),
+        Some(("token", token)) => Ok(WebAuth::BearerToken(token.to_string())),
+        Some(("basic", credentials)) => Ok(WebAuth::Basic(basic_auth_parser(credentials)?)),
+        _ => bail!("web auth should be in the `token:<secret>` or `basic:<user>:<pass>` format"),
+    }
+}
+
+/// Parses a HTTP header in the `Name: value` format, like
+/// `X-Scope-OrgID: team-a`, into its name and value.
+pub fn header_parser(input: &str) -> Result<(String, String)> {
+    let Some((name, value)) = input.split_once(':') else {
+        bail!("headers should be in the `Name: value` format");
+    };
+
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        bail!("invalid header name `{name}`");
+    }
+
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// Parses a label in the `KEY=VALUE` format.
+pub fn label_parser(input: &str) -> Result<(String, String)> {
+    let Some((key, value)) = input.split_once('=') else {
+        bail!("Labels should be in the `KEY=VALUE` format");
+    };
+
+    if !promql::is_valid_label_name(key) {
+        bail!("`{key}` is not a valid label name");
+    }
+
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parses an extra Prometheus flag in the `KEY=VALUE` format into the
+/// `--KEY=VALUE` argument that is passed to Prometheus. The leading dashes are
+/// optional and the value may be omitted for boolean flags.
+pub fn prometheus_arg_parser(input: &str) -> Result<String> {
+    let input = input.trim_start_matches('-');
+    let key = input.split_once('=').map_or(input, |(key, _)| key);
+
+    if key.is_empty() {
+        bail!("Prometheus flags should be in the `KEY=VALUE` format");
+    }
+
+    if key.contains(char::is_whitespace) {
+        bail!("Prometheus flag `{key}` cannot contain whitespace");
+    }
+
+    Ok(format!("--{input}"))
+}
+
+/// Parses a Prometheus feature that is enabled with `--enable-feature`, like
+/// `exemplar-storage`.
+pub fn prometheus_feature_parser(input: &str) -> Result<String> {
+    let feature = input.trim();
+
+    if feature.is_empty() {
+        bail!("Prometheus features cannot be empty");
+    }
+
+    if feature.contains(|c: char| c.is_whitespace() || c == ',' || c == '=') {
+        bail!("invalid Prometheus feature `{feature}`, pass every feature separately");
+    }
+
+    Ok(feature.to_string())
+}
+
+/// Parses an origin that browsers may call the web server of am from, like
+/// `http://localhost:3000`, into the form in which browsers send it in the
+/// `Origin` header. `*` allows any origin.
+pub fn cors_origin_parser(input: &str) -> Result<String> {
+    if input == "*" {
+        return Ok(input.to_string());
+    }
+
+    let url = Url::parse(input).with_context(|| format!("invalid origin {input}"))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        bail!("origin {input} should be an http(s) URL, like http://localhost:3000");
+    }
+    if url.path() != "/" || url.query().is_some() {
+        bail!("origin {input} cannot contain a path");
+    }
+
+    Ok(url.origin().ascii_serialization())
+}
@@ -1,5 +1,8 @@
-use serde::Serialize;
+use crate::promql;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
+use url::Url;
 
 #[derive(Debug, Serialize)]
 pub struct Config {
@@ -7,6 +10,142 @@ pub struct Config {
     pub scrape_configs: Vec<ScrapeConfig>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub rule_files: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alerting: Option<AlertingConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remote_write: Vec<RemoteWriteConfig>,
+}
+
+impl Config {
+    /// Check the config for the mistakes that Prometheus would refuse to start
+    /// with. This returns a description of every problem that is found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.global.scrape_interval.is_zero() {
+            problems.push("the global scrape interval cannot be zero".to_string());
+        }
+
+        if has_duplicates(&self.global.scrape_protocols) {
+            problems.push("the global scrape protocols contain duplicates".to_string());
+        }
+
+        for name in self.global.external_labels.keys() {
+            if let Some(problem) = label_name_problem(name) {
+                problems.push(format!("external label `{name}` {problem}"));
+            }
+        }
+
+        let mut job_names = BTreeSet::new();
+        for scrape_config in &self.scrape_configs {
+            let job = &scrape_config.job_name;
+
+            if job.is_empty() {
+                problems.push("scrape config without a job name".to_string());
+            } else if !job_names.insert(job) {
+                problems.push(format!("job `{job}` is defined more than once"));
+            }
+
+            if scrape_config.static_configs.is_empty()
+                && scrape_config.kubernetes_sd_configs.is_empty()
+                && scrape_config.docker_sd_configs.is_empty()
+                && scrape_config.file_sd_configs.is_empty()
+            {
+                problems.push(format!("job `{job}` does not have any targets"));
+            }
+
+            // Jobs that set the address through relabeling, like the blackbox
+            // probes, can use any kind of target.
+            let rewrites_address = scrape_config
+                .relabel_configs
+                .iter()
+                .any(|config| config.target_label.as_deref() == Some("__address__"));
+
+            for target in scrape_config
+                .static_configs
+                .iter()
+                .filter(|_| !rewrites_address)
+                .flat_map(|config| &config.targets)
+            {
+                if target.contains('/') || !target.contains(':') {
+                    problems.push(format!(
+                        "target `{target}` of job `{job}` should be in the `host:port` format"
+                    ));
+                }
+            }
+
+            for name in scrape_config
+                .static_configs
+                .iter()
+                .flat_map(|config| config.labels.keys())
+            {
+                if let Some(problem) = label_name_problem(name) {
+                    problems.push(format!("label `{name}` of job `{job}` {problem}"));
+                }
+            }
+
+            if scrape_config.scrape_interval == Some(Duration::ZERO) {
+                problems.push(format!("the scrape interval of job `{job}` cannot be zero"));
+            }
+
+            // Prometheus defaults the timeout to 10s, capped at the interval.
+            let scrape_interval = scrape_config
+                .scrape_interval
+                .unwrap_or(self.global.scrape_interval);
+            match scrape_config.scrape_timeout {
+                Some(Duration::ZERO) => {
+                    problems.push(format!("the scrape timeout of job `{job}` cannot be zero"))
+                }
+                Some(timeout) if timeout > scrape_interval => problems.push(format!(
+                    "the scrape timeout of job `{job}` cannot be longer than its scrape interval"
+                )),
+                _ => {}
+            }
+
+            if has_duplicates(&scrape_config.scrape_protocols) {
+                problems.push(format!(
+                    "the scrape protocols of job `{job}` contain duplicates"
+                ));
+            }
+
+            if scrape_config.basic_auth.is_some() && scrape_config.authorization.is_some() {
+                problems.push(format!(
+                    "job `{job}` cannot use basic auth and authorization at the same time"
+                ));
+            }
+        }
+
+        for remote_write in &self.remote_write {
+            match Url::parse(&remote_write.url) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+                _ => problems.push(format!(
+                    "remote write URL `{}` is not a valid http(s) URL",
+                    remote_write.url
+                )),
+            }
+        }
+
+        problems
+    }
+}
+
+fn has_duplicates(protocols: &[ScrapeProtocol]) -> bool {
+    protocols
+        .iter()
+        .enumerate()
+        .any(|(i, protocol)| protocols[..i].contains(protocol))
+}
+
+/// Labels that start with `__` are used by Prometheus itself, and are removed
+/// before the samples are stored.
+fn label_name_problem(name: &str) -> Option<&'static str> {
+    if !promql::is_valid_label_name(name) {
+        Some("is not a valid label name")
+    } else if name.starts_with("__") {
+        Some("is reserved for internal use")
+    } else {
+        None
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -14,15 +153,32 @@ pub struct GlobalConfig {
     #[serde(with = "humantime_serde")]
     pub scrape_interval: Duration,
     pub evaluation_interval: String,
+    /// Labels that are added to all series and alerts that leave Prometheus,
+    /// ie. through remote write, so they can be told apart from other sources.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub external_labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scrape_protocols: Vec<ScrapeProtocol>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct ScrapeConfig {
     pub job_name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub static_configs: Vec<StaticScrapeConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kubernetes_sd_configs: Vec<KubernetesSdConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub docker_sd_configs: Vec<DockerSdConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_sd_configs: Vec<FileSdConfig>,
     pub metrics_path: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, Vec<String>>,
     pub scheme: Option<Scheme>,
     pub honor_labels: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub honor_timestamps: Option<bool>,
 
     #[serde(
         default,
@@ -30,11 +186,173 @@ pub struct ScrapeConfig {
         skip_serializing_if = "Option::is_none"
     )]
     pub scrape_interval: Option<Duration>,
+
+    #[serde(
+        default,
+        with = "humantime_serde::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub scrape_timeout: Option<Duration>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label_limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scrape_protocols: Vec<ScrapeProtocol>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relabel_configs: Vec<RelabelConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub metric_relabel_configs: Vec<RelabelConfig>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub basic_auth: Option<BasicAuth>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization: Option<Authorization>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_config: Option<TlsConfig>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Authorization {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub credentials: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TlsConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insecure_skip_verify: Option<bool>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct StaticScrapeConfig {
     pub targets: Vec<String>,
+    /// Labels that are added to all series scraped from the targets.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// See: https://prometheus.io/docs/prometheus/latest/configuration/configuration/#kubernetes_sd_config
+#[derive(Debug, Serialize)]
+pub struct KubernetesSdConfig {
+    pub role: KubernetesRole,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kubeconfig_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespaces: Option<KubernetesNamespaces>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub selectors: Vec<KubernetesSelector>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KubernetesRole {
+    Pod,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KubernetesNamespaces {
+    pub names: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KubernetesSelector {
+    pub role: KubernetesRole,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// See: https://prometheus.io/docs/prometheus/latest/configuration/configuration/#docker_sd_config
+#[derive(Debug, Serialize)]
+pub struct DockerSdConfig {
+    pub host: String,
+    #[serde(with = "humantime_serde")]
+    pub refresh_interval: Duration,
+}
+
+/// See: https://prometheus.io/docs/prometheus/latest/configuration/configuration/#file_sd_config
+#[derive(Debug, Serialize)]
+pub struct FileSdConfig {
+    /// Relative paths are resolved against the directory of the config file.
+    pub files: Vec<String>,
+}
+
+/// A group of targets in a file that is read through [`FileSdConfig`]. Labels
+/// such as `__metrics_path__` and `__scheme__` change how the targets are
+/// scraped, instead of the settings of the scrape config.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TargetGroup {
+    pub targets: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// See: https://prometheus.io/docs/prometheus/latest/configuration/configuration/#relabel_config
+#[derive(Debug, Default, Serialize)]
+pub struct RelabelConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub source_labels: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<RelabelAction>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelabelAction {
+    Replace,
+    Keep,
+    Drop,
+    LabelMap,
+    LabelDrop,
+    LabelKeep,
+}
+
+/// The formats in which Prometheus asks the targets for their metrics, in
+/// order of preference.
+///
+/// See: https://prometheus.io/docs/prometheus/latest/configuration/configuration/#scrape_config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum ScrapeProtocol {
+    /// The protobuf format, which is needed to scrape native histograms.
+    #[serde(rename = "PrometheusProto")]
+    #[value(name = "PrometheusProto")]
+    PrometheusProto,
+
+    #[serde(rename = "OpenMetricsText1.0.0")]
+    #[value(name = "OpenMetricsText1.0.0")]
+    OpenMetricsText1_0_0,
+
+    #[serde(rename = "OpenMetricsText0.0.1")]
+    #[value(name = "OpenMetricsText0.0.1")]
+    OpenMetricsText0_0_1,
+
+    #[serde(rename = "PrometheusText0.0.4")]
+    #[value(name = "PrometheusText0.0.4")]
+    PrometheusText0_0_4,
+}
+
+impl ScrapeProtocol {
+    /// The first Prometheus version that accepts `scrape_protocols`.
+    pub const SINCE: &'static str = "2.49.0";
 }
 
 #[derive(Debug, Serialize)]
@@ -43,3 +361,195 @@ pub enum Scheme {
     Http,
     Https,
 }
+
+#[derive(Debug, Serialize)]
+pub struct AlertingConfig {
+    pub alertmanagers: Vec<AlertmanagerConfig>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AlertmanagerConfig {
+    pub static_configs: Vec<StaticScrapeConfig>,
+    pub path_prefix: Option<String>,
+    pub scheme: Option<Scheme>,
+}
+
+/// See: https://prometheus.io/docs/prometheus/latest/configuration/configuration/#remote_write
+#[derive(Debug, Serialize)]
+pub struct RemoteWriteConfig {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub basic_auth: Option<BasicAuth>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization: Option<Authorization>,
+}
+
+/// See: https://prometheus.io/docs/prometheus/latest/configuration/recording_rules/
+#[derive(Debug, Serialize)]
+pub struct RuleFile {
+    pub groups: Vec<RuleGroup>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuleGroup {
+    pub name: String,
+    /// How often the rules are evaluated, instead of the global
+    /// `evaluation_interval`.
+    #[serde(
+        default,
+        with = "humantime_serde::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub interval: Option<Duration>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Rule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alert: Option<String>,
+    pub expr: String,
+    #[serde(
+        default,
+        rename = "for",
+        with = "humantime_serde::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub for_duration: Option<Duration>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(scrape_configs: Vec<ScrapeConfig>) -> Config {
+        Config {
+            global: GlobalConfig {
+                scrape_interval: Duration::from_secs(5),
+                evaluation_interval: "30s".to_string(),
+                external_labels: BTreeMap::new(),
+                scrape_protocols: vec![],
+            },
+            scrape_configs,
+            rule_files: vec![],
+            alerting: None,
+            remote_write: vec![],
+        }
+    }
+
+    fn job(name: &str, target: &str) -> ScrapeConfig {
+        ScrapeConfig {
+            job_name: name.to_string(),
+            static_configs: vec![StaticScrapeConfig {
+                targets: vec![target.to_string()],
+                labels: BTreeMap::new(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_config() {
+        let config = config(vec![job("app", "localhost:3000")]);
+
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn duplicate_jobs_and_invalid_targets() {
+        let config = config(vec![
+            job("app", "localhost:3000"),
+            job("app", "http://localhost:3000/metrics"),
+        ]);
+
+        assert_eq!(
+            config.validate(),
+            vec![
+                "job `app` is defined more than once".to_string(),
+                "target `http://localhost:3000/metrics` of job `app` should be in the `host:port` format".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn scrape_timeout_and_limits() {
+        let config = config(vec![
+            ScrapeConfig {
+                scrape_timeout: Some(Duration::from_secs(3)),
+                sample_limit: Some(10000),
+                label_limit: Some(30),
+                ..job("app", "localhost:3000")
+            },
+            ScrapeConfig {
+                scrape_timeout: Some(Duration::from_secs(10)),
+                ..job("slow", "localhost:3001")
+            },
+        ]);
+
+        assert_eq!(
+            config.validate(),
+            vec![
+                "the scrape timeout of job `slow` cannot be longer than its scrape interval"
+                    .to_string()
+            ]
+        );
+
+        let rendered = serde_yaml::to_string(&config.scrape_configs[0]).unwrap();
+        assert!(rendered.contains("scrape_timeout: 3s\nsample_limit: 10000\nlabel_limit: 30\n"));
+    }
+
+    #[test]
+    fn external_and_static_labels() {
+        let mut config = config(vec![job("app", "localhost:3000")]);
+        config.global.external_labels = BTreeMap::from([("env".to_string(), "dev".to_string())]);
+        config.scrape_configs[0].static_configs[0].labels = BTreeMap::from([
+            ("team".to_string(), "billing".to_string()),
+            ("__name__".to_string(), "up".to_string()),
+            ("1st".to_string(), "x".to_string()),
+        ]);
+
+        assert_eq!(
+            config.validate(),
+            vec![
+                "label `1st` of job `app` is not a valid label name".to_string(),
+                "label `__name__` of job `app` is reserved for internal use".to_string(),
+            ]
+        );
+
+        let rendered = serde_yaml::to_string(&config).unwrap();
+        assert!(rendered.contains("  external_labels:\n    env: dev\n"));
+        assert!(rendered.contains("  labels:\n      1st: x\n"));
+    }
+
+    #[test]
+    fn honor_timestamps_and_scrape_protocols() {
+        let mut config = config(vec![ScrapeConfig {
+            honor_timestamps: Some(false),
+            scrape_protocols: vec![
+                ScrapeProtocol::PrometheusProto,
+                ScrapeProtocol::OpenMetricsText1_0_0,
+            ],
+            ..job("app", "localhost:3000")
+        }]);
+        assert!(config.validate().is_empty());
+
+        let rendered = serde_yaml::to_string(&config.scrape_configs[0]).unwrap();
+        assert!(rendered.contains("honor_timestamps: false\n"));
+        assert!(rendered.contains("scrape_protocols:\n- PrometheusProto\n- OpenMetricsText1.0.0\n"));
+
+        config.global.scrape_protocols = vec![
+            ScrapeProtocol::PrometheusText0_0_4,
+            ScrapeProtocol::PrometheusText0_0_4,
+        ];
+        assert_eq!(
+            config.validate(),
+            vec!["the global scrape protocols contain duplicates".to_string()]
+        );
+    }
+}
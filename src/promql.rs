@@ -0,0 +1,1211 @@
+//! A parser for PromQL expressions, which is used to check the syntax and the
+//! types of the expressions in rule files without needing Prometheus.
+//!
+//! See: https://prometheus.io/docs/prometheus/latest/querying/basics/
+
+use std::fmt;
+use thiserror::Error;
+
+/// A syntax or type error in a PromQL expression.
+#[derive(Debug, Error, PartialEq)]
+#[error("{message} (at character {position})")]
+pub struct ParseError {
+    /// The (0-based) character offset in the expression where the error was
+    /// found.
+    pub position: usize,
+    pub message: String,
+}
+
+/// The type an expression evaluates to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Scalar,
+    String,
+    InstantVector,
+    RangeVector,
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ValueType::Scalar => "scalar",
+            ValueType::String => "string",
+            ValueType::InstantVector => "instant vector",
+            ValueType::RangeVector => "range vector",
+        })
+    }
+}
+
+/// Parse `expr` and return the type it evaluates to.
+pub fn parse(expr: &str) -> Result<ValueType, ParseError> {
+    let tokens = lex(expr)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+
+    let value_type = parser.expr(0)?;
+    match parser.peek() {
+        Token::Eof => Ok(value_type),
+        token => Err(parser.error(format!("unexpected {token}"))),
+    }
+}
+
+/// A likely mistake in an expression that is valid PromQL, but probably
+/// doesn't do what was intended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// The (0-based) character offset in the expression where the mistake was
+    /// found.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at character {})", self.message, self.position)
+    }
+}
+
+/// The functions that only make sense for counters.
+const COUNTER_FUNCTIONS: [&str; 3] = ["rate", "irate", "increase"];
+
+/// The suffixes of the metric names of counters, including the ones of
+/// histograms and summaries.
+const COUNTER_SUFFIXES: [&str; 4] = ["_total", "_count", "_sum", "_bucket"];
+
+/// Parse `expr` and look for common mistakes in the way it uses counters and
+/// aggregations, like taking the rate of a gauge or aggregating away the `le`
+/// label before calculating a quantile.
+pub fn lint(expr: &str) -> Result<Vec<Lint>, ParseError> {
+    parse(expr)?;
+    let tokens = lex(expr)?;
+
+    let mut lints = vec![];
+    for (index, (token, position)) in tokens.iter().enumerate() {
+        let Token::Identifier(name) = token else {
+            continue;
+        };
+        if !matches!(tokens.get(index + 1), Some((Token::LeftParen, _))) {
+            continue;
+        }
+
+        let arguments = &tokens[index + 2..closing_paren(&tokens, index + 1)];
+        if COUNTER_FUNCTIONS.contains(&name.as_str()) {
+            lints.extend(lint_counter_function(name, arguments));
+        } else if name == "histogram_quantile" {
+            lints.extend(lint_histogram_quantile(arguments, *position));
+        }
+    }
+
+    Ok(lints)
+}
+
+fn lint_counter_function(function: &str, arguments: &[(Token, usize)]) -> Option<Lint> {
+    let [(Token::Identifier(name), position), (next, _), ..] = arguments else {
+        return None;
+    };
+
+    let message = if is_aggregation_call(name, next) {
+        format!("`{function}` is applied to the result of `{name}`, which hides counter resets; apply `{function}` before aggregating instead")
+    } else if matches!(next, Token::LeftBrace | Token::LeftBracket)
+        && !name.contains(':')
+        && !COUNTER_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+    {
+        format!(
+            "`{function}` should only be used with counters, but `{name}` doesn't look like one"
+        )
+    } else {
+        return None;
+    };
+
+    Some(Lint {
+        position: *position,
+        message,
+    })
+}
+
+fn lint_histogram_quantile(arguments: &[(Token, usize)], position: usize) -> Vec<Lint> {
+    let mut lints = vec![];
+    let mut has_buckets = false;
+
+    for (index, (token, token_position)) in arguments.iter().enumerate() {
+        let Token::Identifier(name) = token else {
+            continue;
+        };
+        let next = arguments.get(index + 1).map(|(token, _)| token);
+        has_buckets |= name.contains("_bucket");
+
+        let lint = |message: String| Lint {
+            position: *token_position,
+            message,
+        };
+
+        if (name == "by" || name == "without") && next == Some(&Token::LeftParen) {
+            let keeps_le = arguments[index + 2..]
+                .iter()
+                .take_while(|(token, _)| *token != Token::RightParen)
+                .any(|(token, _)| matches!(token, Token::Identifier(label) if label == "le"));
+
+            if name == "by" && !keeps_le {
+                lints.push(lint(
+                    "`by` needs to keep the `le` label for `histogram_quantile`".to_string(),
+                ));
+            } else if name == "without" && keeps_le {
+                lints.push(lint(
+                    "`without` can't remove the `le` label that `histogram_quantile` needs"
+                        .to_string(),
+                ));
+            }
+        } else if next == Some(&Token::LeftParen) && AGGREGATIONS.contains(&name.as_str()) {
+            // An aggregation without a grouping in front of its arguments
+            // needs one after them.
+            let end = closing_paren(arguments, index + 1);
+            let grouped = matches!(
+                arguments.get(end + 1),
+                Some((Token::Identifier(keyword), _)) if keyword == "by" || keyword == "without"
+            );
+            if !grouped {
+                lints.push(lint(format!("`{name}` removes the `le` label that `histogram_quantile` needs, use `{name} by (le)`")));
+            }
+        }
+    }
+
+    if !has_buckets {
+        lints.push(Lint {
+            position,
+            message: "`histogram_quantile` expects the `_bucket` series of a histogram".to_string(),
+        });
+    }
+
+    lints
+}
+
+/// Whether the identifier `name`, followed by `next`, starts an aggregation
+/// instead of being a metric name.
+fn is_aggregation_call(name: &str, next: &Token) -> bool {
+    AGGREGATIONS.contains(&name)
+        && match next {
+            Token::LeftParen => true,
+            Token::Identifier(keyword) => keyword == "by" || keyword == "without",
+            _ => false,
+        }
+}
+
+/// The index of the parenthesis that closes the one at `open`, in tokens of
+/// an expression that parsed successfully.
+fn closing_paren(tokens: &[(Token, usize)], open: usize) -> usize {
+    let mut depth = 0;
+    for (index, (token, _)) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::LeftParen => depth += 1,
+            Token::RightParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return index;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tokens.len()
+}
+
+const AGGREGATIONS: [&str; 15] = [
+    "sum",
+    "avg",
+    "count",
+    "min",
+    "max",
+    "group",
+    "stddev",
+    "stdvar",
+    "topk",
+    "bottomk",
+    "count_values",
+    "quantile",
+    "limitk",
+    "limit_ratio",
+    "mad",
+];
+
+use ValueType::{InstantVector as V, RangeVector as M, Scalar as S, String as Str};
+
+/// The functions, with the types of their arguments and the amount of trailing
+/// arguments that are optional.
+const FUNCTIONS: &[(&str, &[ValueType], usize, ValueType)] = &[
+    ("abs", &[V], 0, V),
+    ("absent", &[V], 0, V),
+    ("absent_over_time", &[M], 0, V),
+    ("acos", &[V], 0, V),
+    ("acosh", &[V], 0, V),
+    ("asin", &[V], 0, V),
+    ("asinh", &[V], 0, V),
+    ("atan", &[V], 0, V),
+    ("atanh", &[V], 0, V),
+    ("avg_over_time", &[M], 0, V),
+    ("ceil", &[V], 0, V),
+    ("changes", &[M], 0, V),
+    ("clamp", &[V, S, S], 0, V),
+    ("clamp_max", &[V, S], 0, V),
+    ("clamp_min", &[V, S], 0, V),
+    ("cos", &[V], 0, V),
+    ("cosh", &[V], 0, V),
+    ("count_over_time", &[M], 0, V),
+    ("day_of_month", &[V], 1, V),
+    ("day_of_week", &[V], 1, V),
+    ("day_of_year", &[V], 1, V),
+    ("days_in_month", &[V], 1, V),
+    ("deg", &[V], 0, V),
+    ("delta", &[M], 0, V),
+    ("deriv", &[M], 0, V),
+    ("exp", &[V], 0, V),
+    ("floor", &[V], 0, V),
+    ("histogram_avg", &[V], 0, V),
+    ("histogram_count", &[V], 0, V),
+    ("histogram_fraction", &[S, S, V], 0, V),
+    ("histogram_quantile", &[S, V], 0, V),
+    ("histogram_stddev", &[V], 0, V),
+    ("histogram_stdvar", &[V], 0, V),
+    ("histogram_sum", &[V], 0, V),
+    ("holt_winters", &[M, S, S], 0, V),
+    ("hour", &[V], 1, V),
+    ("idelta", &[M], 0, V),
+    ("increase", &[M], 0, V),
+    ("irate", &[M], 0, V),
+    ("label_join", &[V, Str, Str, Str], 1, V),
+    ("label_replace", &[V, Str, Str, Str, Str], 0, V),
+    ("last_over_time", &[M], 0, V),
+    ("ln", &[V], 0, V),
+    ("log10", &[V], 0, V),
+    ("log2", &[V], 0, V),
+    ("mad_over_time", &[M], 0, V),
+    ("max_over_time", &[M], 0, V),
+    ("min_over_time", &[M], 0, V),
+    ("minute", &[V], 1, V),
+    ("month", &[V], 1, V),
+    ("pi", &[], 0, S),
+    ("predict_linear", &[M, S], 0, V),
+    ("present_over_time", &[M], 0, V),
+    ("quantile_over_time", &[S, M], 0, V),
+    ("rad", &[V], 0, V),
+    ("rate", &[M], 0, V),
+    ("resets", &[M], 0, V),
+    ("round", &[V, S], 1, V),
+    ("scalar", &[V], 0, S),
+    ("sgn", &[V], 0, V),
+    ("sin", &[V], 0, V),
+    ("sinh", &[V], 0, V),
+    ("sort", &[V], 0, V),
+    ("sort_desc", &[V], 0, V),
+    ("sqrt", &[V], 0, V),
+    ("stddev_over_time", &[M], 0, V),
+    ("stdvar_over_time", &[M], 0, V),
+    ("sum_over_time", &[M], 0, V),
+    ("tan", &[V], 0, V),
+    ("tanh", &[V], 0, V),
+    ("time", &[], 0, S),
+    ("timestamp", &[V], 0, V),
+    ("vector", &[S], 0, V),
+    ("year", &[V], 1, V),
+];
+
+/// Functions that accept any amount of extra arguments of the type of their
+/// last argument.
+const VARIADIC_FUNCTIONS: [&str; 1] = ["label_join"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number,
+    Duration,
+    /// A string literal, with the contents between the quotes.
+    String(String),
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Colon,
+    At,
+    Operator(&'static str),
+    Eof,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Identifier(identifier) => write!(f, "identifier `{identifier}`"),
+            Token::Number => f.write_str("number"),
+            Token::Duration => f.write_str("duration"),
+            Token::String(_) => f.write_str("string"),
+            Token::LeftParen => f.write_str("`(`"),
+            Token::RightParen => f.write_str("`)`"),
+            Token::LeftBrace => f.write_str("`{`"),
+            Token::RightBrace => f.write_str("`}`"),
+            Token::LeftBracket => f.write_str("`[`"),
+            Token::RightBracket => f.write_str("`]`"),
+            Token::Comma => f.write_str("`,`"),
+            Token::Colon => f.write_str("`:`"),
+            Token::At => f.write_str("`@`"),
+            Token::Operator(operator) => write!(f, "`{operator}`"),
+            Token::Eof => f.write_str("end of expression"),
+        }
+    }
+}
+
+const OPERATORS: [&str; 15] = [
+    "==", "!=", "<=", ">=", "=~", "!~", "+", "-", "*", "/", "%", "^", "<", ">", "=",
+];
+
+fn lex(expr: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    let error = |position: usize, message: String| ParseError { position, message };
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+
+        let token = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '#' => {
+                // Comments run until the end of the line.
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '{' => Token::LeftBrace,
+            '}' => Token::RightBrace,
+            '[' => Token::LeftBracket,
+            ']' => Token::RightBracket,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '@' => Token::At,
+            '"' | '\'' | '`' => {
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(error(start, "unterminated string".to_string())),
+                        Some('\\') if c != '`' => i += 2,
+                        Some('\n') if c != '`' => {
+                            return Err(error(start, "unterminated string".to_string()))
+                        }
+                        Some(&quote) if quote == c => break,
+                        Some(_) => i += 1,
+                    }
+                }
+                Token::String(chars[start + 1..i].iter().collect())
+            }
+            _ if c.is_ascii_digit()
+                || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) =>
+            {
+                i = lex_number(&chars, i);
+                let number_end = i;
+
+                // A number directly followed by a unit is a duration, ie. `5m`
+                // or `1h30m`.
+                while i < chars.len() && "smhdwy".contains(chars[i]) {
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+
+                if i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    return Err(error(start, "invalid number or duration".to_string()));
+                }
+
+                tokens.push((
+                    if i == number_end {
+                        Token::Number
+                    } else {
+                        Token::Duration
+                    },
+                    start,
+                ));
+                continue;
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == ':')
+                {
+                    i += 1;
+                }
+                tokens.push((Token::Identifier(chars[start..i].iter().collect()), start));
+                continue;
+            }
+            _ => {
+                let Some(operator) = OPERATORS.iter().find(|operator| {
+                    operator
+                        .chars()
+                        .enumerate()
+                        .all(|(offset, c)| chars.get(i + offset) == Some(&c))
+                }) else {
+                    return Err(error(start, format!("unexpected character `{c}`")));
+                };
+
+                i += operator.len();
+                tokens.push((Token::Operator(operator), start));
+                continue;
+            }
+        };
+
+        i += 1;
+        tokens.push((token, start));
+    }
+
+    tokens.push((Token::Eof, chars.len()));
+    Ok(tokens)
+}
+
+fn lex_number(chars: &[char], mut i: usize) -> usize {
+    if chars[i] == '0' && matches!(chars.get(i + 1), Some('x' | 'X')) {
+        i += 2;
+        while i < chars.len() && chars[i].is_ascii_hexdigit() {
+            i += 1;
+        }
+        return i;
+    }
+
+    while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+        i += 1;
+    }
+
+    if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+        let mut exponent = i + 1;
+        if matches!(chars.get(exponent), Some('+' | '-')) {
+            exponent += 1;
+        }
+        if chars.get(exponent).is_some_and(char::is_ascii_digit) {
+            i = exponent;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+    }
+
+    i
+}
+
+/// The precedence of the binary operators, from low to high.
+fn precedence(operator: &str) -> Option<u8> {
+    Some(match operator {
+        "or" => 1,
+        "and" | "unless" => 2,
+        "==" | "!=" | "<=" | "<" | ">=" | ">" => 3,
+        "+" | "-" => 4,
+        "*" | "/" | "%" | "atan2" => 5,
+        "^" => 6,
+        _ => return None,
+    })
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        let index = (self.position + offset).min(self.tokens.len() - 1);
+        &self.tokens[index].0
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if token != Token::Eof {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            position: self.tokens[self.position].1,
+            message,
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        if *self.peek() != expected {
+            return Err(self.error(format!("expected {expected}, found {}", self.peek())));
+        }
+        self.next();
+        Ok(())
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Identifier(identifier) if identifier.eq_ignore_ascii_case(keyword))
+    }
+
+    /// The binary operator at the current position, if any.
+    fn binary_operator(&self) -> Option<String> {
+        match self.peek() {
+            Token::Operator("=") => None,
+            Token::Operator(operator) => Some(operator.to_string()),
+            Token::Identifier(identifier) => {
+                let identifier = identifier.to_ascii_lowercase();
+                matches!(identifier.as_str(), "and" | "or" | "unless" | "atan2")
+                    .then_some(identifier)
+            }
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self, min_precedence: u8) -> Result<ValueType, ParseError> {
+        let mut lhs = self.unary()?;
+
+        while let Some(operator) = self.binary_operator() {
+            let Some(operator_precedence) = precedence(&operator) else {
+                return Err(self.error(format!("unexpected operator `{operator}`")));
+            };
+            if operator_precedence < min_precedence {
+                break;
+            }
+
+            let operator_position = self.position;
+            self.next();
+
+            let is_comparison = operator_precedence == 3;
+            let is_set_operator = matches!(operator.as_str(), "and" | "or" | "unless");
+
+            let mut return_bool = false;
+            if self.is_keyword("bool") {
+                if !is_comparison {
+                    return Err(
+                        self.error("`bool` can only be used with comparison operators".to_string())
+                    );
+                }
+                self.next();
+                return_bool = true;
+            }
+
+            let mut has_matching = false;
+            if self.is_keyword("on") || self.is_keyword("ignoring") {
+                self.next();
+                self.label_list()?;
+                has_matching = true;
+
+                if self.is_keyword("group_left") || self.is_keyword("group_right") {
+                    if is_set_operator {
+                        return Err(
+                            self.error(format!("no grouping allowed for `{operator}` operation"))
+                        );
+                    }
+                    self.next();
+                    if *self.peek() == Token::LeftParen {
+                        self.label_list()?;
+                    }
+                }
+            }
+
+            // `^` is right associative, all other operators are left
+            // associative.
+            let rhs = if operator == "^" {
+                self.expr(operator_precedence)?
+            } else {
+                self.expr(operator_precedence + 1)?
+            };
+
+            let error = |message: String| ParseError {
+                position: self.tokens[operator_position].1,
+                message,
+            };
+
+            for operand in [lhs, rhs] {
+                if !matches!(operand, ValueType::Scalar | ValueType::InstantVector) {
+                    return Err(error(format!(
+                        "binary expression must contain only scalar and instant vector types, found {operand}"
+                    )));
+                }
+            }
+
+            let both_scalars = lhs == ValueType::Scalar && rhs == ValueType::Scalar;
+            if is_set_operator && (lhs == ValueType::Scalar || rhs == ValueType::Scalar) {
+                return Err(error(format!(
+                    "set operator `{operator}` not allowed in binary scalar expression"
+                )));
+            }
+            if is_comparison && both_scalars && !return_bool {
+                return Err(error(
+                    "comparisons between scalars must use the `bool` modifier".to_string(),
+                ));
+            }
+            if has_matching && both_scalars {
+                return Err(error(
+                    "vector matching only allowed between instant vectors".to_string(),
+                ));
+            }
+
+            lhs = if both_scalars {
+                ValueType::Scalar
+            } else {
+                ValueType::InstantVector
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<ValueType, ParseError> {
+        if matches!(self.peek(), Token::Operator("+" | "-")) {
+            self.next();
+
+            // Unary operators bind weaker than `^`, so `-2 ^ 2` is `-(2 ^ 2)`.
+            let value_type = self.expr(precedence("^").unwrap())?;
+            if !matches!(value_type, ValueType::Scalar | ValueType::InstantVector) {
+                return Err(self.error(format!(
+                    "unary expression only allowed on expressions of type scalar or instant vector, found {value_type}"
+                )));
+            }
+            return Ok(value_type);
+        }
+
+        self.postfix()
+    }
+
+    /// A primary expression, followed by range selectors, subqueries and
+    /// modifiers.
+    fn postfix(&mut self) -> Result<ValueType, ParseError> {
+        let is_selector = self.is_selector();
+        let mut value_type = self.primary()?;
+        let mut can_select_range = is_selector;
+        let mut has_offset = false;
+        let mut has_at = false;
+
+        loop {
+            match self.peek() {
+                Token::LeftBracket => {
+                    self.next();
+                    self.expect(Token::Duration)?;
+
+                    if *self.peek() == Token::Colon {
+                        self.next();
+                        if *self.peek() == Token::Duration {
+                            self.next();
+                        }
+                        if value_type != ValueType::InstantVector {
+                            return Err(self.error(format!(
+                                "subquery is only allowed on instant vector, found {value_type}"
+                            )));
+                        }
+                    } else if !can_select_range || has_offset || has_at {
+                        return Err(
+                            self.error("ranges are only allowed for vector selectors".to_string())
+                        );
+                    }
+
+                    self.expect(Token::RightBracket)?;
+                    value_type = ValueType::RangeVector;
+                    can_select_range = false;
+                }
+                Token::Identifier(_) if self.is_keyword("offset") => {
+                    if has_offset {
+                        return Err(self.error("offset may not be set multiple times".to_string()));
+                    }
+                    self.check_modifiable(value_type, is_selector, "offset")?;
+                    self.next();
+                    if *self.peek() == Token::Operator("-") {
+                        self.next();
+                    }
+                    self.expect(Token::Duration)?;
+                    has_offset = true;
+                }
+                Token::At => {
+                    if has_at {
+                        return Err(self.error("@ may not be set multiple times".to_string()));
+                    }
+                    self.check_modifiable(value_type, is_selector, "@")?;
+                    self.next();
+                    if self.is_keyword("start") || self.is_keyword("end") {
+                        self.next();
+                        self.expect(Token::LeftParen)?;
+                        self.expect(Token::RightParen)?;
+                    } else {
+                        if matches!(self.peek(), Token::Operator("+" | "-")) {
+                            self.next();
+                        }
+                        self.expect(Token::Number)?;
+                    }
+                    has_at = true;
+                }
+                _ => return Ok(value_type),
+            }
+        }
+    }
+
+    /// Offsets and `@` modifiers can only be used on selectors and subqueries.
+    fn check_modifiable(
+        &self,
+        value_type: ValueType,
+        is_selector: bool,
+        modifier: &str,
+    ) -> Result<(), ParseError> {
+        if is_selector || value_type == ValueType::RangeVector {
+            return Ok(());
+        }
+
+        Err(self.error(format!(
+            "{modifier} modifier must be preceded by an instant vector selector or range vector selector or a subquery"
+        )))
+    }
+
+    /// Whether the current position is the start of a vector selector.
+    fn is_selector(&self) -> bool {
+        match self.peek() {
+            Token::LeftBrace => true,
+            Token::Identifier(identifier) => {
+                !is_number_literal(identifier)
+                    && *self.peek_at(1) != Token::LeftParen
+                    && !self.is_aggregation()
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the current position is the start of an aggregation, which
+    /// can have its grouping clause before the parentheses (ie.
+    /// `sum by (job) (up)`).
+    fn is_aggregation(&self) -> bool {
+        let Token::Identifier(identifier) = self.peek() else {
+            return false;
+        };
+
+        AGGREGATIONS.contains(&identifier.as_str())
+            && match self.peek_at(1) {
+                Token::LeftParen => true,
+                Token::Identifier(keyword) => {
+                    keyword.eq_ignore_ascii_case("by") || keyword.eq_ignore_ascii_case("without")
+                }
+                _ => false,
+            }
+    }
+
+    fn primary(&mut self) -> Result<ValueType, ParseError> {
+        match self.peek().clone() {
+            Token::Number => {
+                self.next();
+                Ok(ValueType::Scalar)
+            }
+            Token::String(_) => {
+                self.next();
+                Ok(ValueType::String)
+            }
+            Token::LeftParen => {
+                self.next();
+                let value_type = self.expr(0)?;
+                self.expect(Token::RightParen)?;
+                Ok(value_type)
+            }
+            Token::LeftBrace => {
+                self.matchers(false)?;
+                Ok(ValueType::InstantVector)
+            }
+            Token::Identifier(identifier) => {
+                if is_number_literal(&identifier) {
+                    self.next();
+                    return Ok(ValueType::Scalar);
+                }
+
+                if self.is_aggregation() {
+                    return self.aggregation(&identifier);
+                }
+
+                if *self.peek_at(1) == Token::LeftParen {
+                    return self.function_call(&identifier);
+                }
+
+                if matches!(
+                    identifier.to_ascii_lowercase().as_str(),
+                    "by" | "without"
+                        | "on"
+                        | "ignoring"
+                        | "group_left"
+                        | "group_right"
+                        | "bool"
+                        | "offset"
+                        | "and"
+                        | "or"
+                        | "unless"
+                        | "atan2"
+                ) {
+                    return Err(self.error(format!("unexpected keyword `{identifier}`")));
+                }
+
+                self.next();
+                if *self.peek() == Token::LeftBrace {
+                    self.matchers(true)?;
+                }
+                Ok(ValueType::InstantVector)
+            }
+            token => Err(self.error(format!("unexpected {token}"))),
+        }
+    }
+
+    /// Parse the label matchers of a vector selector, ie. `{job="api"}`.
+    fn matchers(&mut self, has_metric_name: bool) -> Result<(), ParseError> {
+        let start = self.position;
+        self.expect(Token::LeftBrace)?;
+
+        let mut matches_non_empty = has_metric_name;
+        while *self.peek() != Token::RightBrace {
+            self.label_name()?;
+
+            let operator = match self.next() {
+                Token::Operator(operator @ ("=" | "!=" | "=~" | "!~")) => operator,
+                token => {
+                    self.position -= 1;
+                    return Err(
+                        self.error(format!("expected label matching operator, found {token}"))
+                    );
+                }
+            };
+
+            let Token::String(value) = self.next() else {
+                self.position -= 1;
+                return Err(self.error(format!("expected string, found {}", self.peek())));
+            };
+            matches_non_empty |= match operator {
+                "=" => !value.is_empty(),
+                "!=" => value.is_empty(),
+                "=~" => !value.is_empty() && value != ".*",
+                _ => value == ".*",
+            };
+
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.next();
+        }
+
+        self.expect(Token::RightBrace)?;
+
+        if !matches_non_empty {
+            return Err(ParseError {
+                position: self.tokens[start].1,
+                message: "vector selector must contain at least one non-empty matcher".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn label_name(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            Token::Identifier(identifier) if !identifier.contains(':') => {
+                self.next();
+                Ok(())
+            }
+            token => Err(self.error(format!("expected label name, found {token}"))),
+        }
+    }
+
+    /// Parse a list of label names, ie. `(job, instance)`.
+    fn label_list(&mut self) -> Result<(), ParseError> {
+        self.expect(Token::LeftParen)?;
+        while *self.peek() != Token::RightParen {
+            self.label_name()?;
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.next();
+        }
+        self.expect(Token::RightParen)
+    }
+
+    fn aggregation(&mut self, name: &str) -> Result<ValueType, ParseError> {
+        self.next();
+
+        let mut has_grouping = false;
+        if self.is_keyword("by") || self.is_keyword("without") {
+            self.next();
+            self.label_list()?;
+            has_grouping = true;
+        }
+
+        self.expect(Token::LeftParen)?;
+
+        let parameter = match name {
+            "topk" | "bottomk" | "quantile" | "limitk" | "limit_ratio" => Some(ValueType::Scalar),
+            "count_values" => Some(ValueType::String),
+            _ => None,
+        };
+
+        if let Some(expected) = parameter {
+            let value_type = self.expr(0)?;
+            if value_type != expected {
+                return Err(self.error(format!(
+                    "expected type {expected} in aggregation parameter, got {value_type}"
+                )));
+            }
+            self.expect(Token::Comma)?;
+        }
+
+        let argument_position = self.position;
+        let value_type = self.expr(0)?;
+        if value_type != ValueType::InstantVector {
+            return Err(ParseError {
+                position: self.tokens[argument_position].1,
+                message: format!(
+                    "expected type instant vector in aggregation expression, got {value_type}"
+                ),
+            });
+        }
+
+        self.expect(Token::RightParen)?;
+
+        if self.is_keyword("by") || self.is_keyword("without") {
+            if has_grouping {
+                return Err(
+                    self.error("aggregation can only have a single grouping clause".to_string())
+                );
+            }
+            self.next();
+            self.label_list()?;
+        }
+
+        Ok(ValueType::InstantVector)
+    }
+
+    fn function_call(&mut self, name: &str) -> Result<ValueType, ParseError> {
+        let Some((_, parameters, optional, return_type)) =
+            FUNCTIONS.iter().find(|(function, ..)| *function == name)
+        else {
+            return Err(self.error(format!("unknown function `{name}`")));
+        };
+
+        self.next();
+        self.expect(Token::LeftParen)?;
+
+        let mut arguments = 0;
+        while *self.peek() != Token::RightParen {
+            let argument_position = self.position;
+            let value_type = self.expr(0)?;
+
+            let expected = parameters.get(arguments).or_else(|| {
+                VARIADIC_FUNCTIONS
+                    .contains(&name)
+                    .then(|| parameters.last())
+                    .flatten()
+            });
+            match expected {
+                Some(expected) if *expected != value_type => {
+                    return Err(ParseError {
+                        position: self.tokens[argument_position].1,
+                        message: format!(
+                        "expected type {expected} in call to function `{name}`, got {value_type}"
+                    ),
+                    })
+                }
+                Some(_) => {}
+                None => {
+                    return Err(ParseError {
+                        position: self.tokens[argument_position].1,
+                        message: format!(
+                            "expected at most {} argument(s) in call to `{name}`",
+                            parameters.len()
+                        ),
+                    })
+                }
+            }
+
+            arguments += 1;
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.next();
+        }
+
+        if arguments + optional < parameters.len() {
+            return Err(self.error(format!(
+                "expected at least {} argument(s) in call to `{name}`, got {arguments}",
+                parameters.len() - optional
+            )));
+        }
+
+        self.expect(Token::RightParen)?;
+        Ok(*return_type)
+    }
+}
+
+/// Whether `name` can be used as a metric name, ie. in a recording rule.
+pub fn is_valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Whether `name` can be used as a label name.
+pub fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `input` is a valid Prometheus duration, ie. `1h30m`. The units need
+/// to be in decreasing order and can only be used once.
+pub fn is_valid_duration(input: &str) -> bool {
+    const UNITS: [&str; 7] = ["y", "w", "d", "h", "m", "s", "ms"];
+
+    if input == "0" {
+        return true;
+    }
+
+    let mut rest = input;
+    let mut next_unit = 0;
+    while !rest.is_empty() {
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        if digits == 0 {
+            return false;
+        }
+        rest = &rest[digits..];
+
+        // `ms` needs to be checked before `m`.
+        let Some(unit) = (next_unit..UNITS.len())
+            .rev()
+            .find(|&unit| rest.starts_with(UNITS[unit]))
+        else {
+            return false;
+        };
+        rest = &rest[UNITS[unit].len()..];
+        next_unit = unit + 1;
+    }
+
+    !input.is_empty()
+}
+
+fn is_number_literal(identifier: &str) -> bool {
+    identifier.eq_ignore_ascii_case("inf") || identifier.eq_ignore_ascii_case("nan")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("up", ValueType::InstantVector)]
+    #[case("1 + 2 * 3", ValueType::Scalar)]
+    #[case("-2 ^ 2", ValueType::Scalar)]
+    #[case("time() > bool 5", ValueType::Scalar)]
+    #[case("\"text\"", ValueType::String)]
+    #[case("up[5m]", ValueType::RangeVector)]
+    #[case(
+        "rate(http_requests_total{job=~\"api|web\", code!=\"200\"}[5m] offset 1h)",
+        ValueType::InstantVector
+    )]
+    #[case("sum by (job) (rate(function_calls_total[5m])) / on (job) group_left sum without (instance) (up)", ValueType::InstantVector)]
+    #[case("histogram_quantile(0.99, sum(rate(function_calls_duration_seconds_bucket[1h])) by (le, function))", ValueType::InstantVector)]
+    #[case("topk(5, max_over_time(up[1h:5m]))", ValueType::InstantVector)]
+    #[case(
+        "label_join(up, \"new\", \",\", \"a\", \"b\", \"c\")",
+        ValueType::InstantVector
+    )]
+    #[case("{__name__=~\"function_.*\"} @ end()", ValueType::InstantVector)]
+    #[case("(up == 1) and on (job) absent(down)", ValueType::InstantVector)]
+    #[case("vector(1) unless up", ValueType::InstantVector)]
+    #[case(
+        "slo:sli_error:ratio_rate5m{slo=\"api\"} > (14.4 * 0.001)",
+        ValueType::InstantVector
+    )]
+    fn valid(#[case] expr: &str, #[case] expected: ValueType) {
+        assert_eq!(parse(expr), Ok(expected));
+    }
+
+    #[rstest]
+    #[case("", "unexpected end of expression")]
+    #[case("sum(up", "expected `)`, found end of expression")]
+    #[case(
+        "rate(up)",
+        "expected type range vector in call to function `rate`, got instant vector"
+    )]
+    #[case(
+        "up[5m] + 1",
+        "binary expression must contain only scalar and instant vector types, found range vector"
+    )]
+    #[case("1 > 2", "comparisons between scalars must use the `bool` modifier")]
+    #[case(
+        "1 and up",
+        "set operator `and` not allowed in binary scalar expression"
+    )]
+    #[case("rate(up[5m])[1h]", "ranges are only allowed for vector selectors")]
+    #[case("foo_bar(up)", "unknown function `foo_bar`")]
+    #[case(
+        "{job=\"\"}",
+        "vector selector must contain at least one non-empty matcher"
+    )]
+    #[case("up{job=api}", "expected string, found identifier `api`")]
+    #[case("sum(up) by (job) by (instance)", "unexpected identifier `by`")]
+    #[case("up[5x]", "invalid number or duration")]
+    fn invalid(#[case] expr: &str, #[case] message: &str) {
+        let err = parse(expr).expect_err("expected an error");
+        assert_eq!(err.message, message);
+    }
+
+    #[rstest]
+    #[case("rate(function_calls_total[5m])", &[])]
+    #[case("histogram_quantile(0.95, sum by (le, function) (rate(function_calls_duration_seconds_bucket[5m])))", &[])]
+    #[case("histogram_quantile(0.95, sum(rate(function_calls_duration_seconds_bucket[5m])) by (le))", &[])]
+    #[case("rate(slo:sli_error:ratio_rate5m[1h])", &[])]
+    #[case(
+        "rate(function_calls_concurrent[5m])",
+        &["`rate` should only be used with counters, but `function_calls_concurrent` doesn't look like one (at character 5)"]
+    )]
+    #[case(
+        "increase(sum(function_calls_total)[1h:])",
+        &["`increase` is applied to the result of `sum`, which hides counter resets; apply `increase` before aggregating instead (at character 9)"]
+    )]
+    #[case(
+        "histogram_quantile(0.95, sum by (function) (rate(function_calls_duration_seconds_bucket[5m])))",
+        &["`by` needs to keep the `le` label for `histogram_quantile` (at character 29)"]
+    )]
+    #[case(
+        "histogram_quantile(0.95, sum(rate(function_calls_duration_seconds_bucket[5m])))",
+        &["`sum` removes the `le` label that `histogram_quantile` needs, use `sum by (le)` (at character 25)"]
+    )]
+    #[case(
+        "histogram_quantile(0.95, rate(function_calls_duration_seconds_count[5m]))",
+        &["`histogram_quantile` expects the `_bucket` series of a histogram (at character 0)"]
+    )]
+    fn lints(#[case] expr: &str, #[case] expected: &[&str]) {
+        let lints: Vec<String> = lint(expr)
+            .expect("expected a valid expression")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(lints, expected);
+    }
+
+    #[rstest]
+    #[case("5m", true)]
+    #[case("1h30m", true)]
+    #[case("1d12h30m15s500ms", true)]
+    #[case("0", true)]
+    #[case("", false)]
+    #[case("5", false)]
+    #[case("30m1h", false)]
+    #[case("1.5h", false)]
+    fn duration(#[case] input: &str, #[case] valid: bool) {
+        assert_eq!(is_valid_duration(input), valid);
+    }
+}
@@ -0,0 +1,264 @@
+//! Checks for Prometheus rule files, similar to `promtool check rules`.
+
+use crate::promql::{self, ValueType};
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    groups: Vec<RuleGroup>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleGroup {
+    name: String,
+    interval: Option<String>,
+    /// This is only checked for its type.
+    #[serde(rename = "limit")]
+    _limit: Option<u64>,
+    query_offset: Option<String>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    record: Option<String>,
+    alert: Option<String>,
+    expr: String,
+    #[serde(rename = "for")]
+    for_duration: Option<String>,
+    keep_firing_for: Option<String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+}
+
+/// A problem with a rule or group in a rule file.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    /// The group and rule in which the problem was found, ie.
+    /// `group "slo-api", rule 3 (slo:sli_error:ratio_rate5m)`.
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// The result of checking a rule file.
+#[derive(Debug)]
+pub struct Report {
+    /// The amount of rules in the file.
+    pub rules: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// Check the contents of a rule file. This only returns an error if the file
+/// isn't a valid rule file at all, all other problems are part of the report.
+pub fn check(contents: &str) -> Result<Report> {
+    let file: RuleFile = serde_yaml::from_str(contents)?;
+
+    let mut report = Report {
+        rules: 0,
+        problems: vec![],
+    };
+    let mut group_names = BTreeSet::new();
+    let mut recording_rules = BTreeSet::new();
+
+    for group in &file.groups {
+        let location = format!("group {:?}", group.name);
+        let mut problem = |message: String| {
+            report.problems.push(Problem {
+                location: location.clone(),
+                message,
+            })
+        };
+
+        if group.name.is_empty() {
+            problem("group name cannot be empty".to_string());
+        } else if !group_names.insert(&group.name) {
+            problem("group name is used more than once in this file".to_string());
+        }
+
+        for (field, value) in [
+            ("interval", &group.interval),
+            ("query_offset", &group.query_offset),
+        ] {
+            if let Some(value) = value {
+                if !promql::is_valid_duration(value) {
+                    problem(format!("invalid {field} `{value}`"));
+                }
+            }
+        }
+
+        for (index, rule) in group.rules.iter().enumerate() {
+            report.rules += 1;
+
+            let name = rule.record.as_ref().or(rule.alert.as_ref());
+            let location = match name {
+                Some(name) => format!("group {:?}, rule {} ({name})", group.name, index + 1),
+                None => format!("group {:?}, rule {}", group.name, index + 1),
+            };
+
+            for message in check_rule(rule) {
+                report.problems.push(Problem {
+                    location: location.clone(),
+                    message,
+                });
+            }
+
+            // Prometheus would store the results of both rules in the same
+            // series.
+            if let Some(record) = &rule.record {
+                if !recording_rules.insert((record, &rule.labels)) {
+                    report.problems.push(Problem {
+                        location,
+                        message: "duplicate recording rule, another rule with the same name and labels exists".to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn check_rule(rule: &Rule) -> Vec<String> {
+    let mut problems = vec![];
+
+    match (&rule.record, &rule.alert) {
+        (Some(_), Some(_)) => {
+            problems.push("only one of `record` and `alert` can be set".to_string())
+        }
+        (None, None) => problems.push("one of `record` and `alert` must be set".to_string()),
+        (Some(record), None) => {
+            if !promql::is_valid_metric_name(record) {
+                problems.push(format!("invalid recording rule name `{record}`"));
+            }
+            if rule.for_duration.is_some() {
+                problems.push("`for` cannot be used in a recording rule".to_string());
+            }
+            if rule.keep_firing_for.is_some() {
+                problems.push("`keep_firing_for` cannot be used in a recording rule".to_string());
+            }
+            if !rule.annotations.is_empty() {
+                problems.push("`annotations` cannot be used in a recording rule".to_string());
+            }
+        }
+        (None, Some(alert)) => {
+            if alert.is_empty() {
+                problems.push("alert name cannot be empty".to_string());
+            }
+        }
+    }
+
+    for (field, value) in [
+        ("for", &rule.for_duration),
+        ("keep_firing_for", &rule.keep_firing_for),
+    ] {
+        if let Some(value) = value {
+            if !promql::is_valid_duration(value) {
+                problems.push(format!("invalid `{field}` duration `{value}`"));
+            }
+        }
+    }
+
+    for (kind, names) in [
+        ("label", rule.labels.keys()),
+        ("annotation", rule.annotations.keys()),
+    ] {
+        for name in names {
+            if !promql::is_valid_label_name(name) {
+                problems.push(format!("invalid {kind} name `{name}`"));
+            } else if name.starts_with("__") {
+                problems.push(format!("{kind} name `{name}` is reserved for internal use"));
+            }
+        }
+    }
+
+    if rule.expr.trim().is_empty() {
+        problems.push("`expr` cannot be empty".to_string());
+    } else {
+        match promql::parse(&rule.expr) {
+            Ok(ValueType::InstantVector | ValueType::Scalar) => {}
+            Ok(value_type) => problems.push(format!(
+                "`expr` needs to evaluate to an instant vector or scalar, found {value_type}"
+            )),
+            Err(err) => problems.push(format!("invalid `expr`: {err}")),
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slo::{generate_rules, SloDefinition};
+
+    #[test]
+    fn slo_rules_are_valid() {
+        let slo = SloDefinition {
+            name: "api".to_string(),
+            objective: 99.9,
+            function: Some("handler".to_string()),
+            module: None,
+            latency_threshold: Some(0.25),
+            description: Some("API latency".to_string()),
+        };
+        let contents = serde_yaml::to_string(&generate_rules(&[slo])).unwrap();
+
+        let report = check(&contents).unwrap();
+
+        assert_eq!(report.problems, vec![]);
+        assert_eq!(report.rules, 10);
+    }
+
+    #[test]
+    fn problems() {
+        let contents = r#"
+groups:
+  - name: example
+    rules:
+      - record: job:up:sum
+        expr: sum by (job) (up)
+      - record: job:up:sum
+        expr: sum by (job) (up)
+      - alert: Down
+        expr: rate(up) == 0
+        for: 5 minutes
+        labels:
+          severity-level: page
+"#;
+
+        let report = check(contents).unwrap();
+
+        let problems: Vec<String> = report.problems.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "group \"example\", rule 2 (job:up:sum): duplicate recording rule, another rule with the same name and labels exists",
+                "group \"example\", rule 3 (Down): invalid `for` duration `5 minutes`",
+                "group \"example\", rule 3 (Down): invalid label name `severity-level`",
+                "group \"example\", rule 3 (Down): invalid `expr`: expected type range vector in call to function `rate`, got instant vector (at character 5)",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_field() {
+        let contents = "groups:\n  - name: example\n    rules:\n      - record: up:sum\n        exp: sum(up)\n";
+
+        assert!(check(contents).is_err());
+    }
+}
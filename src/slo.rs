@@ -0,0 +1,292 @@
+use crate::prometheus::{Rule, RuleFile, RuleGroup};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// The windows over which the error ratio of every SLO is recorded.
+const WINDOWS: [&str; 7] = ["5m", "30m", "1h", "2h", "6h", "1d", "3d"];
+
+/// The multi-window, multi-burn-rate alerts from the Google SRE workbook, based
+/// on a 30 day SLO period. Each alert fires when the error budget is burning
+/// `factor` times faster than allowed in both the long and the short window.
+const BURN_RATE_ALERTS: [BurnRateAlert; 2] = [
+    BurnRateAlert {
+        severity: "page",
+        windows: [("1h", "5m", 14.4), ("6h", "30m", 6.0)],
+    },
+    BurnRateAlert {
+        severity: "ticket",
+        windows: [("1d", "2h", 3.0), ("3d", "6h", 1.0)],
+    },
+];
+
+struct BurnRateAlert {
+    severity: &'static str,
+    /// The long window, short window and burn rate factor.
+    windows: [(&'static str, &'static str, f64); 2],
+}
+
+/// A Service Level Objective for one or more autometrics instrumented
+/// functions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SloDefinition {
+    /// The name of the SLO, it will be added as the `slo` label to all
+    /// generated rules.
+    pub name: String,
+
+    /// The objective as a percentage, ie. `99.9`.
+    pub objective: f64,
+
+    /// Only include calls to the function with this name.
+    pub function: Option<String>,
+
+    /// Only include calls to functions in this module.
+    pub module: Option<String>,
+
+    /// Turn this into a latency SLO: calls that take longer than this many
+    /// seconds count as errors. This needs to match one of the histogram
+    /// buckets.
+    pub latency_threshold: Option<f64>,
+
+    /// A human readable description of the SLO.
+    pub description: Option<String>,
+}
+
+/// The format of a standalone SLO file (ie. `slo.yaml`).
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SloFile {
+    pub slos: Vec<SloDefinition>,
+}
+
+/// Combine the SLOs defined in the config file with the ones in `slo_file`
+/// (if any), and validate them.
+pub fn load(
+    slos: Option<Vec<SloDefinition>>,
+    slo_file: Option<&Path>,
+) -> Result<Vec<SloDefinition>> {
+    let mut slos = slos.unwrap_or_default();
+
+    if let Some(path) = slo_file {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read SLO file {}", path.display()))?;
+        let file: SloFile = serde_yaml::from_str(&contents)
+            .with_context(|| format!("SLO file {} is invalid", path.display()))?;
+        slos.extend(file.slos);
+    }
+
+    for slo in &slos {
+        slo.validate()?;
+    }
+
+    let mut names: Vec<_> = slos.iter().map(|slo| &slo.name).collect();
+    names.sort();
+    if let Some(name) = names.windows(2).find(|names| names[0] == names[1]) {
+        bail!("SLO `{}` is defined more than once", name[0]);
+    }
+
+    Ok(slos)
+}
+
+impl SloDefinition {
+    fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            bail!("SLO name cannot be empty");
+        }
+
+        if !(self.objective > 0.0 && self.objective < 100.0) {
+            bail!(
+                "objective of SLO `{}` needs to be between 0 and 100 (exclusive), got {}",
+                self.name,
+                self.objective
+            );
+        }
+
+        if let Some(threshold) = self.latency_threshold {
+            if threshold <= 0.0 {
+                bail!(
+                    "latency threshold of SLO `{}` needs to be positive",
+                    self.name
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether calls to `function` in `module` count towards this SLO.
+    pub fn matches(&self, function: &str, module: &str) -> bool {
+        self.function.as_deref().is_none_or(|f| f == function)
+            && self.module.as_deref().is_none_or(|m| m == module)
+    }
+
+    /// The ratio of calls that is allowed to fail.
+    pub fn error_budget(&self) -> f64 {
+        round((100.0 - self.objective) / 100.0)
+    }
+
+    fn selector(&self, extra: &[(&str, String)]) -> String {
+        let labels: Vec<String> = extra
+            .iter()
+            .map(|(label, value)| (*label, value))
+            .chain(self.function.iter().map(|function| ("function", function)))
+            .chain(self.module.iter().map(|module| ("module", module)))
+            .map(|(label, value)| format!("{label}=\"{}\"", value.replace('"', "\\\"")))
+            .collect();
+
+        format!("{{{}}}", labels.join(","))
+    }
+
+    /// The ratio of failed calls over `window`.
+    fn error_ratio(&self, window: &str) -> String {
+        match self.latency_threshold {
+            Some(threshold) => format!(
+                "1 - (sum(rate(function_calls_duration_seconds_bucket{}[{window}])) / sum(rate(function_calls_duration_seconds_count{}[{window}])))",
+                self.selector(&[("le", threshold.to_string())]),
+                self.selector(&[]),
+            ),
+            None => format!(
+                "sum(rate(function_calls_total{}[{window}])) / sum(rate(function_calls_total{}[{window}]))",
+                self.selector(&[("result", "error".to_string())]),
+                self.selector(&[]),
+            ),
+        }
+    }
+
+    fn rule_group(&self) -> RuleGroup {
+        let slo_labels = BTreeMap::from([("slo".to_string(), self.name.clone())]);
+        let slo_selector = format!("{{slo=\"{}\"}}", self.name.replace('"', "\\\""));
+
+        let mut rules: Vec<Rule> = WINDOWS
+            .iter()
+            .map(|window| Rule {
+                record: Some(format!("slo:sli_error:ratio_rate{window}")),
+                expr: self.error_ratio(window),
+                labels: slo_labels.clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        rules.push(Rule {
+            record: Some("slo:objective:ratio".to_string()),
+            expr: format!("vector({})", round(self.objective / 100.0)),
+            labels: slo_labels.clone(),
+            ..Default::default()
+        });
+
+        let error_budget = self.error_budget();
+        for alert in &BURN_RATE_ALERTS {
+            let expr = alert
+                .windows
+                .iter()
+                .map(|(long, short, factor)| {
+                    format!(
+                        "(slo:sli_error:ratio_rate{long}{slo_selector} > ({factor} * {error_budget}) and slo:sli_error:ratio_rate{short}{slo_selector} > ({factor} * {error_budget}))"
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" or ");
+
+            let mut labels = slo_labels.clone();
+            labels.insert("severity".to_string(), alert.severity.to_string());
+
+            let mut annotations = BTreeMap::from([(
+                "summary".to_string(),
+                format!("SLO `{}` is burning its error budget too fast", self.name),
+            )]);
+            if let Some(description) = &self.description {
+                annotations.insert("description".to_string(), description.clone());
+            }
+
+            rules.push(Rule {
+                alert: Some("SloErrorBudgetBurn".to_string()),
+                expr,
+                for_duration: Some(Duration::from_secs(60)),
+                labels,
+                annotations,
+                ..Default::default()
+            });
+        }
+
+        RuleGroup {
+            name: format!("slo-{}", self.name),
+            interval: None,
+            rules,
+        }
+    }
+}
+
+/// Round away floating point artifacts (ie. `0.0009999999999994`), so the
+/// generated rules stay readable.
+fn round(value: f64) -> f64 {
+    (value * 1e10).round() / 1e10
+}
+
+/// Generate the recording and alerting rules for `slos`.
+pub fn generate_rules(slos: &[SloDefinition]) -> RuleFile {
+    RuleFile {
+        groups: slos.iter().map(SloDefinition::rule_group).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slo() -> SloDefinition {
+        SloDefinition {
+            name: "api".to_string(),
+            objective: 99.9,
+            function: Some("handler".to_string()),
+            module: None,
+            latency_threshold: None,
+            description: None,
+        }
+    }
+
+    #[test]
+    fn error_ratio() {
+        assert_eq!(
+            slo().error_ratio("5m"),
+            "sum(rate(function_calls_total{result=\"error\",function=\"handler\"}[5m])) / sum(rate(function_calls_total{function=\"handler\"}[5m]))"
+        );
+    }
+
+    #[test]
+    fn latency_error_ratio() {
+        let slo = SloDefinition {
+            latency_threshold: Some(0.25),
+            ..slo()
+        };
+
+        assert_eq!(
+            slo.error_ratio("1h"),
+            "1 - (sum(rate(function_calls_duration_seconds_bucket{le=\"0.25\",function=\"handler\"}[1h])) / sum(rate(function_calls_duration_seconds_count{function=\"handler\"}[1h])))"
+        );
+    }
+
+    #[test]
+    fn rules_contain_recordings_and_alerts() {
+        let rules = generate_rules(&[slo()]);
+
+        assert_eq!(rules.groups.len(), 1);
+        let rules = &rules.groups[0].rules;
+        assert_eq!(
+            rules.iter().filter(|rule| rule.record.is_some()).count(),
+            WINDOWS.len() + 1
+        );
+        assert_eq!(rules.iter().filter(|rule| rule.alert.is_some()).count(), 2);
+    }
+
+    #[test]
+    fn invalid_objective() {
+        let slo = SloDefinition {
+            objective: 100.0,
+            ..slo()
+        };
+
+        assert!(load(Some(vec![slo]), None).is_err());
+    }
+}
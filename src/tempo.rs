@@ -0,0 +1,144 @@
+use crate::otel_collector::{Listener, OtlpProtocols, OtlpReceiver};
+use serde::Serialize;
+
+/// A Tempo configuration that runs all components in a single process,
+/// receives traces through OTLP and stores them on the local disk.
+///
+/// See: https://grafana.com/docs/tempo/latest/configuration/
+#[derive(Debug, Serialize)]
+pub struct Config {
+    /// The prefix of all HTTP endpoints, so Tempo can be proxied under a path.
+    pub http_api_prefix: String,
+    pub server: Server,
+    pub distributor: Distributor,
+    pub storage: Storage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Server {
+    /// The address of the HTTP and gRPC servers. An empty address listens on
+    /// all interfaces.
+    pub http_listen_address: String,
+    pub http_listen_port: u16,
+    pub grpc_listen_address: String,
+    pub grpc_listen_port: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Distributor {
+    pub receivers: Receivers,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Receivers {
+    pub otlp: OtlpReceiver,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Storage {
+    pub trace: TraceStorage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TraceStorage {
+    pub backend: String,
+    pub wal: StoragePath,
+    pub local: StoragePath,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StoragePath {
+    /// Relative paths are resolved against the working directory of Tempo.
+    pub path: String,
+}
+
+impl Config {
+    /// Create a config that serves the API under `http_api_prefix` on
+    /// `listen_address` and `http_port`, and receives OTLP on the
+    /// `grpc_endpoint` and `http_endpoint`. The gRPC server of Tempo itself
+    /// listens on a random port, since it is only used internally.
+    pub fn new(
+        http_api_prefix: &str,
+        listen_address: &str,
+        http_port: u16,
+        grpc_endpoint: &str,
+        http_endpoint: &str,
+    ) -> Self {
+        Self {
+            http_api_prefix: http_api_prefix.to_string(),
+            server: Server {
+                http_listen_address: listen_address.to_string(),
+                http_listen_port: http_port,
+                grpc_listen_address: listen_address.to_string(),
+                grpc_listen_port: 0,
+            },
+            distributor: Distributor {
+                receivers: Receivers {
+                    otlp: OtlpReceiver {
+                        protocols: OtlpProtocols {
+                            grpc: Listener {
+                                endpoint: grpc_endpoint.to_string(),
+                            },
+                            http: Listener {
+                                endpoint: http_endpoint.to_string(),
+                            },
+                        },
+                    },
+                },
+            },
+            storage: Storage {
+                trace: TraceStorage {
+                    backend: "local".to_string(),
+                    wal: StoragePath {
+                        path: "wal".to_string(),
+                    },
+                    local: StoragePath {
+                        path: "blocks".to_string(),
+                    },
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_config() {
+        let config = Config::new(
+            "/tempo",
+            "127.0.0.1",
+            3200,
+            "localhost:4317",
+            "localhost:4318",
+        );
+
+        assert_eq!(
+            serde_yaml::to_string(&config).unwrap(),
+            "http_api_prefix: /tempo
+server:
+  http_listen_address: 127.0.0.1
+  http_listen_port: 3200
+  grpc_listen_address: 127.0.0.1
+  grpc_listen_port: 0
+distributor:
+  receivers:
+    otlp:
+      protocols:
+        grpc:
+          endpoint: localhost:4317
+        http:
+          endpoint: localhost:4318
+storage:
+  trace:
+    backend: local
+    wal:
+      path: wal
+    local:
+      path: blocks
+"
+        );
+    }
+}